@@ -0,0 +1,184 @@
+//! C interface to the meter, for LabWindows/CVI, TestStand and other test executives that can
+//! call into a C library but not run the logger. Uses the logger's own polling and frame
+//! reassembly, see `ut61e_plus.h` for the API.
+
+// Shared with the logger, which uses more of them than this does
+#[path = "../../src/meter.rs"]
+#[allow(dead_code)]
+mod meter;
+#[path = "../../src/quirks.rs"]
+#[allow(dead_code)]
+mod quirks;
+
+use hidapi::HidApi;
+use meter::{GET_MEASUREMENT, Meter};
+use std::ffi::c_char;
+use ut61e_plus_logger::protocol::{self, FrameError, MEASUREMENT_LEN, Measurement, Reading};
+
+pub const UT61E_OK: i32 = 0;
+/// The meter didn't answer in time
+pub const UT61E_TIMEOUT: i32 = -1;
+/// Something arrived but it was corrupt or not a measurement
+pub const UT61E_BAD_FRAME: i32 = -2;
+/// The USB read or write failed, usually the cable was unplugged
+pub const UT61E_IO: i32 = -3;
+/// A NULL pointer was passed in
+pub const UT61E_INVALID: i32 = -4;
+
+/// An open meter, opaque to C
+pub struct Ut61e {
+    _api: HidApi,
+    meter: Meter,
+}
+
+/// `ut61e_reading` in the header
+#[repr(C)]
+pub struct Ut61eReading {
+    /// In `unit`, NaN when the display isn't a number (overload, dashes)
+    pub value: f64,
+    /// NUL terminated UTF-8, as on the LCD without padding, e.g. "-1.2345" or "OL"
+    pub display: [c_char; 8],
+    pub unit: [c_char; 8],
+    pub mode: [c_char; 48],
+    /// The raw mode and range bytes
+    pub mode_id: u8,
+    pub range_id: u8,
+    /// 1 for OL, -1 for -OL, 0 otherwise
+    pub overload: i8,
+    pub auto_range: u8,
+    pub hold: u8,
+    pub rel: u8,
+    pub min: u8,
+    pub max: u8,
+    pub low_battery: u8,
+    pub hv_warning: u8,
+    pub dc: u8,
+    pub peak_max: u8,
+    pub peak_min: u8,
+}
+
+/// Copies `s` in NUL terminated, cut short on a character boundary if it doesn't fit
+fn copy_str(dst: &mut [c_char], s: &str) {
+    let mut len = s.len().min(dst.len() - 1);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    for (d, &b) in dst.iter_mut().zip(&s.as_bytes()[..len]) {
+        *d = b as c_char;
+    }
+    dst[len] = 0;
+}
+
+fn fill(m: &Measurement, out: &mut Ut61eReading) {
+    let reading = m.reading();
+    out.value = reading.value().unwrap_or(f64::NAN);
+    copy_str(&mut out.display, m.display_digits().as_str());
+    copy_str(&mut out.unit, m.unit());
+    copy_str(&mut out.mode, m.mode_name());
+    out.mode_id = m.mode;
+    out.range_id = m.range;
+    out.overload = match reading {
+        Reading::Overload { negative: true } => -1,
+        Reading::Overload { negative: false } => 1,
+        _ => 0,
+    };
+    out.auto_range = m.auto_range as u8;
+    out.hold = m.hold as u8;
+    out.rel = m.rel as u8;
+    out.min = m.min as u8;
+    out.max = m.max as u8;
+    out.low_battery = m.low_battery as u8;
+    out.hv_warning = m.hv_warning as u8;
+    out.dc = m.dc as u8;
+    out.peak_max = m.peak_max as u8;
+    out.peak_min = m.peak_min as u8;
+}
+
+/// Opens the first meter found, NULL if there isn't one or it can't be opened
+#[unsafe(no_mangle)]
+pub extern "C" fn ut61e_open() -> *mut Ut61e {
+    let Ok(api) = HidApi::new() else {
+        return std::ptr::null_mut();
+    };
+    let meter = api
+        .device_list()
+        .filter(|info| quirks::DEVICE_IDS.contains(&(info.vendor_id(), info.product_id())))
+        .find_map(|info| {
+            let dev = api.open_path(info.path()).ok()?;
+            Some(Meter::new(dev, quirks::for_device(info.vendor_id(), info.product_id())))
+        });
+    let Some(meter) = meter else {
+        return std::ptr::null_mut();
+    };
+    if meter.quirks.init_on_open && meter.init_uart().is_err() {
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(Ut61e { _api: api, meter }))
+}
+
+/// Polls the meter for one reading, blocking for up to a second
+///
+/// # Safety
+///
+/// `meter` has to come from [`ut61e_open`] and not be closed, `out` has to point to a
+/// `ut61e_reading`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ut61e_read(meter: *mut Ut61e, out: *mut Ut61eReading) -> i32 {
+    let (Some(meter), Some(out)) = (unsafe { meter.as_mut() }, unsafe { out.as_mut() }) else {
+        return UT61E_INVALID;
+    };
+    let meter = &mut meter.meter;
+    let n = match meter.send_command(&GET_MEASUREMENT).and_then(|()| meter.read_frame()) {
+        Ok(n) => n,
+        Err(_) => return UT61E_IO,
+    };
+    if n == 0 {
+        return UT61E_TIMEOUT;
+    }
+    match protocol::find_frame(meter.frame()).ok().and_then(Measurement::decode) {
+        Some(m) => {
+            fill(&m, out);
+            UT61E_OK
+        }
+        None => UT61E_BAD_FRAME,
+    }
+}
+
+/// Decodes bytes captured some other way, a whole frame or just the payload, without a meter
+///
+/// # Safety
+///
+/// `bytes` has to point to `len` readable bytes and `out` to a `ut61e_reading`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ut61e_decode(bytes: *const u8, len: usize, out: *mut Ut61eReading) -> i32 {
+    let Some(out) = (unsafe { out.as_mut() }) else {
+        return UT61E_INVALID;
+    };
+    if bytes.is_null() {
+        return UT61E_INVALID;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(bytes, len) };
+    let payload = match protocol::find_frame(bytes) {
+        Err(FrameError::NoHeader) if bytes.len() >= MEASUREMENT_LEN => Ok(bytes),
+        payload => payload,
+    };
+    match payload.ok().and_then(Measurement::decode) {
+        Some(m) => {
+            fill(&m, out);
+            UT61E_OK
+        }
+        None => UT61E_BAD_FRAME,
+    }
+}
+
+/// Closes a meter from [`ut61e_open`], NULL is ignored
+///
+/// # Safety
+///
+/// `meter` has to come from [`ut61e_open`] and not already be closed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ut61e_close(meter: *mut Ut61e) {
+    if !meter.is_null() {
+        drop(unsafe { Box::from_raw(meter) });
+    }
+}
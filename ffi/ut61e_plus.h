@@ -0,0 +1,79 @@
+/*
+ * C interface to the UNI-T UT61E+ multimeter, from ut61e_plus_logger.
+ *
+ * Build with `cargo build --release -p ut61e_plus_ffi`, which leaves libut61e_plus.so (or
+ * ut61e_plus.dll) and the static libut61e_plus.a in target/release.
+ *
+ *     ut61e *meter = ut61e_open();
+ *     ut61e_reading r;
+ *     if (meter && ut61e_read(meter, &r) == UT61E_OK)
+ *         printf("%s %s (%s)\n", r.display, r.unit, r.mode);
+ *     ut61e_close(meter);
+ *
+ * Strings are NUL terminated UTF-8 (units like "kΩ" and "μA" aren't ASCII). A handle is not
+ * thread safe, use it from one thread at a time.
+ */
+#ifndef UT61E_PLUS_H
+#define UT61E_PLUS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define UT61E_OK 0
+/* The meter didn't answer in time */
+#define UT61E_TIMEOUT -1
+/* Something arrived but it was corrupt or not a measurement */
+#define UT61E_BAD_FRAME -2
+/* The USB read or write failed, usually the cable was unplugged */
+#define UT61E_IO -3
+/* A NULL pointer was passed in */
+#define UT61E_INVALID -4
+
+typedef struct ut61e ut61e;
+
+typedef struct {
+    /* In unit, NaN when the display isn't a number (overload, dashes) */
+    double value;
+    /* As on the LCD without padding, e.g. "-1.2345" or "OL" */
+    char display[8];
+    char unit[8];
+    char mode[48];
+    /* The raw mode and range bytes */
+    uint8_t mode_id;
+    uint8_t range_id;
+    /* 1 for OL, -1 for -OL, 0 otherwise */
+    int8_t overload;
+    /* The rest are 1 when the annunciator is on */
+    uint8_t auto_range;
+    uint8_t hold;
+    uint8_t rel;
+    uint8_t min;
+    uint8_t max;
+    uint8_t low_battery;
+    uint8_t hv_warning;
+    uint8_t dc;
+    uint8_t peak_max;
+    uint8_t peak_min;
+} ut61e_reading;
+
+/* Opens the first meter found, NULL if there isn't one or it can't be opened */
+ut61e *ut61e_open(void);
+
+/* Polls the meter for one reading, blocking for up to a second. Returns UT61E_OK or an error. */
+int ut61e_read(ut61e *meter, ut61e_reading *out);
+
+/* Decodes bytes captured some other way, a whole frame or just the payload, without a meter */
+int ut61e_decode(const uint8_t *bytes, size_t len, ut61e_reading *out);
+
+/* Closes a meter from ut61e_open, NULL is ignored */
+void ut61e_close(ut61e *meter);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,644 @@
+//! `--alarm`, levels the value shouldn't go past. Going past one is an event in the outputs, and
+//! so is coming back, so a reading hovering past the level doesn't repeat it every poll. The
+//! levels can be changed while logging with `--web-control`. Levels and rates are both in the
+//! unit without a prefix, like `--filter` and `--limit-low`, so `>0.005` on the mV range is 5 mV
+//! and a range change doesn't move the level.
+//!
+//! A noisy reading right at the level would still go past and back every few polls, so each alarm
+//! can have `hysteresis=0.1`, how far back past the level the value has to come for the alarm to
+//! clear (`>5` clears below 4.9), and `dwell=2s`, how long it has to stay past the level before it
+//! goes off and back before it clears. Both go after the level, `>5,hysteresis=0.1,dwell=2s`.
+//!
+//! `rate` in front makes the level one on how fast the value changes, per second, minute or hour:
+//! `rate<-2/s` for a probe falling off a 5 V rail, `rate>1/min` for a temperature running away.
+//! The rate is the slope of a straight line through the readings of the last `window` (2 s by
+//! default, `rate>1/min,window=5m`), and starts over when the meter changes mode or REL goes on or off.
+//!
+//! `--alarms FILE` has named alarms with more to them, an `[[alarm]]` each:
+//!
+//! ```toml
+//! [[alarm]]
+//! name = "Rail high"
+//! when = "value > 5.25 && mode == \"V_DC\""   # as --filter, or level = ">5.25,hysteresis=0.05"
+//! clear = "value < 5.2"                       # without it, once `when` isn't true any more
+//! dwell = "2s"
+//! severity = "critical"                       # info, warning (the default) or critical
+//! action = "stop"                             # log (the default), stop, or run with command = "..."
+//! rearm = "latch"                             # auto (the default), once, or latch
+//! ```
+//!
+//! `stop` ends logging with exit code 7 when the alarm goes off. `run` starts `command` with the
+//! shell when it goes off and when it clears, with `UT61E_ALARM` (the name), `UT61E_ALARM_STATE`
+//! (`on` or `off`), `UT61E_SEVERITY` and `UT61E_MESSAGE` set. An alarm with `rearm = "once"` goes
+//! off once a run, one with `latch` stays on after the value comes back until `rearm` (over
+//! `--web-control`, or `/rearm` with `--interactive`). Changing `--alarm` levels while logging
+//! leaves the named alarms alone.
+
+use crate::clock::Stamp;
+use crate::filter::Filter;
+use crate::config::{Section, Setting, Value, sections};
+use crate::schedule::Trigger;
+use crate::stats::significant;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::Duration;
+use std::{fmt, fs, io, thread};
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// For `rate` without `window=`
+const DEFAULT_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy)]
+pub struct Level {
+    pub trigger: Trigger,
+    /// In the same unit as the level, never negative
+    pub hysteresis: f64,
+    pub dwell: Duration,
+    /// For a level on the rate of change
+    pub rate: Option<Rate>,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rate {
+    /// The time unit the level is per, `s`, `min` or `h`
+    per: &'static str,
+    /// How far back the slope is fitted over
+    window: Duration,
+}
+
+impl Rate {
+    fn seconds(&self) -> f64 {
+        match self.per {
+            "min" => 60.0,
+            "h" => 3600.0,
+            _ => 1.0,
+        }
+    }
+}
+
+impl Level {
+    /// For clap and `alarm` on `--web-control`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = s.split(',').map(str::trim);
+        let first = parts.next().unwrap_or_default();
+        let (trigger, rate) = match first.strip_prefix("rate") {
+            Some(rest) => {
+                let (rest, per) = [("/s", "s"), ("/min", "min"), ("/h", "h")]
+                    .iter()
+                    .find_map(|&(suffix, per)| Some((rest.strip_suffix(suffix)?, per)))
+                    .unwrap_or((rest, "s"));
+                (Trigger::parse(rest.trim())?, Some(Rate { per, window: DEFAULT_WINDOW }))
+            }
+            None => (Trigger::parse(first)?, None),
+        };
+        let mut level = Level { trigger, hysteresis: 0.0, dwell: Duration::ZERO, rate };
+        for part in parts {
+            match (part.split_once('='), &mut level.rate) {
+                (Some(("hysteresis", value)), _) => {
+                    level.hysteresis = value.parse::<f64>().ok().filter(|h| *h >= 0.0).ok_or_else(|| format!("bad hysteresis in {:?}, expected e.g. 0.1", s))?;
+                }
+                (Some(("dwell", value)), _) => level.dwell = crate::clock::parse_duration(value).ok_or_else(|| format!("bad dwell in {:?}, expected e.g. 2s", s))?,
+                (Some(("window", value)), Some(rate)) => {
+                    rate.window = crate::clock::parse_duration(value).filter(|w| !w.is_zero()).ok_or_else(|| format!("bad window in {:?}, expected e.g. 5s", s))?;
+                }
+                (Some(("window", _)), None) => return Err(format!("window= goes with a rate, e.g. rate>0.5/s,window=5s, not {:?}", s)),
+                _ => return Err(format!("unknown alarm setting {:?} in {:?}, there's hysteresis=, dwell= and window=", part, s)),
+            }
+        }
+        Ok(level)
+    }
+
+    /// Just what it's a level on, `>5` or `rate<-2/s`
+    fn short(&self) -> String {
+        match self.rate {
+            Some(rate) => format!("rate{}/{}", self.trigger, rate.per),
+            None => self.trigger.to_string(),
+        }
+    }
+}
+
+/// As it's written on the command line, `>5,hysteresis=0.1`
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.short())?;
+        if let Some(rate) = self.rate
+            && rate.window != DEFAULT_WINDOW
+        {
+            write!(f, ",window={}s", rate.window.as_secs_f64())?;
+        }
+        if self.hysteresis > 0.0 {
+            write!(f, ",hysteresis={}", self.hysteresis)?;
+        }
+        if !self.dwell.is_zero() {
+            write!(f, ",dwell={}s", self.dwell.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+#[derive(Clone)]
+pub enum Action {
+    Log,
+    Stop,
+    Run(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rearm {
+    Auto,
+    Once,
+    Latch,
+}
+
+enum Condition {
+    Level(Level),
+    When { when: Filter, clear: Option<Filter> },
+}
+
+pub struct Alarm {
+    /// `None` for the `--alarm` levels
+    name: Option<String>,
+    condition: Condition,
+    dwell: Duration,
+    severity: Severity,
+    action: Action,
+    rearm: Rearm,
+}
+
+impl Alarm {
+    fn level(level: Level) -> Self {
+        Alarm { name: None, condition: Condition::Level(level), dwell: level.dwell, severity: Severity::Warning, action: Action::Log, rearm: Rearm::Auto }
+    }
+
+    fn name(&self) -> String {
+        match (&self.name, &self.condition) {
+            (Some(name), _) => name.clone(),
+            (None, Condition::Level(level)) => level.short(),
+            (None, Condition::When { .. }) => "alarm".to_owned(),
+        }
+    }
+}
+
+/// The `[[alarm]]` entries of an `--alarms` file
+pub fn load(path: &Path) -> Result<Vec<Alarm>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{} {}", path.display(), e))
+}
+
+/// An `[[alarm]]` as far as it's been read
+#[derive(Default)]
+struct Entry {
+    start: usize,
+    name: Option<String>,
+    level: Option<Level>,
+    when: Option<Filter>,
+    clear: Option<Filter>,
+    dwell: Option<Duration>,
+    severity: Option<Severity>,
+    action: Option<String>,
+    command: Option<String>,
+    rearm: Option<Rearm>,
+}
+
+fn parse(text: &str) -> Result<Vec<Alarm>, String> {
+    sections(text, "alarm")?.into_iter().map(|section| alarm(entry(section)?)).collect()
+}
+
+fn entry(section: Section) -> Result<Entry, String> {
+    let mut entry = Entry { start: section.start, ..Entry::default() };
+    for Setting { key, value, line } in section.settings {
+        let error = |e: String| format!("line {}: {}", line, e);
+        let key = key.as_str();
+        let text = match value {
+            Value::Text(text) => text,
+            Value::Number(secs) if key == "dwell" && secs >= 0.0 => {
+                entry.dwell = Some(Duration::from_secs_f64(secs));
+                continue;
+            }
+            Value::Number(_) => return Err(error(format!("{} has to be a string", key))),
+        };
+        match key {
+            "name" => entry.name = Some(text),
+            "level" => entry.level = Some(Level::parse(&text).map_err(error)?),
+            "when" => entry.when = Some(Filter::parse(&text).map_err(error)?),
+            "clear" => entry.clear = Some(Filter::parse(&text).map_err(error)?),
+            "dwell" => entry.dwell = Some(crate::clock::parse_duration(&text).ok_or_else(|| error(format!("bad dwell {:?}, expected e.g. \"2s\"", text)))?),
+            "severity" => {
+                entry.severity = Some(match text.as_str() {
+                    "info" => Severity::Info,
+                    "warning" => Severity::Warning,
+                    "critical" => Severity::Critical,
+                    _ => return Err(error(format!("severity is info, warning or critical, not {:?}", text))),
+                })
+            }
+            "action" => entry.action = Some(text),
+            "command" => entry.command = Some(text),
+            "rearm" => {
+                entry.rearm = Some(match text.as_str() {
+                    "auto" => Rearm::Auto,
+                    "once" => Rearm::Once,
+                    "latch" => Rearm::Latch,
+                    _ => return Err(error(format!("rearm is auto, once or latch, not {:?}", text))),
+                })
+            }
+            _ => return Err(error(format!("unknown setting {:?}", key))),
+        }
+    }
+    Ok(entry)
+}
+
+/// A complete `[[alarm]]`
+fn alarm(entry: Entry) -> Result<Alarm, String> {
+    let error = |e: &str| format!("line {}: {}", entry.start, e);
+    let name = entry.name.ok_or_else(|| error("the alarm has no name"))?;
+    let (condition, dwell) = match (entry.level, entry.when) {
+        (Some(level), None) if entry.clear.is_none() => (Condition::Level(level), level.dwell),
+        (Some(_), None) => return Err(error("clear goes with when, a level clears by itself (add hysteresis= to it)")),
+        (None, Some(when)) => (Condition::When { when, clear: entry.clear }, Duration::ZERO),
+        (Some(_), Some(_)) => return Err(error("the alarm has both a level and when, pick one")),
+        (None, None) => return Err(error("the alarm needs a level or when")),
+    };
+    let action = match (entry.action.as_deref(), entry.command) {
+        (None | Some("log"), None) => Action::Log,
+        (Some("stop"), None) => Action::Stop,
+        (Some("run") | None, Some(command)) => Action::Run(command),
+        (Some("run"), None) => return Err(error("action run needs a command")),
+        (Some("log" | "stop"), Some(_)) => return Err(error("command only goes with action run")),
+        (Some(other), _) => return Err(error(&format!("action is log, stop or run, not {:?}", other))),
+    };
+    Ok(Alarm {
+        name: Some(name),
+        condition,
+        dwell: entry.dwell.unwrap_or(dwell),
+        severity: entry.severity.unwrap_or(Severity::Warning),
+        action,
+        rearm: entry.rearm.unwrap_or(Rearm::Auto),
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Clear,
+    Firing,
+    /// The value came back but it stays on until rearmed
+    Latched,
+    /// Went off with `rearm = "once"` and cleared
+    Done,
+}
+
+/// An alarm going off or clearing
+pub struct Fired {
+    pub message: String,
+    pub severity: Severity,
+    /// Going off rather than clearing
+    pub on: bool,
+    name: String,
+    action: Action,
+}
+
+impl Fired {
+    /// Whether the logger should stop for it
+    pub fn stops(&self) -> bool {
+        self.on && matches!(self.action, Action::Stop)
+    }
+
+    /// Starts the alarm's command if it has one, without waiting for it
+    pub fn run(&self) -> io::Result<()> {
+        let Action::Run(command) = &self.action else {
+            return Ok(());
+        };
+        let mut child = crate::plugin::shell(command)
+            .env("UT61E_ALARM", &self.name)
+            .env("UT61E_ALARM_STATE", if self.on { "on" } else { "off" })
+            .env("UT61E_SEVERITY", self.severity.to_string())
+            .env("UT61E_MESSAGE", &self.message)
+            .spawn()?;
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// Where an alarm is at, for the dashboard
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub name: String,
+    pub severity: Severity,
+    /// `clear`, `on`, `latched` or `done`
+    pub state: &'static str,
+}
+
+struct Tracked {
+    alarm: Alarm,
+    state: State,
+    /// Since when the value has been on the other side, while that's shorter than the dwell
+    since: Option<Duration>,
+    /// For a rate, the mode and REL and the (elapsed s, value) of the readings in the window
+    recent: ((&'static str, bool), VecDeque<(f64, f64)>),
+}
+
+impl Tracked {
+    fn new(alarm: Alarm) -> Self {
+        Tracked { alarm, state: State::Clear, since: None, recent: (("", false), VecDeque::new()) }
+    }
+}
+
+/// Least squares slope through the readings of the window, per `rate.per`. `None` until they
+/// span half the window, so the first two readings can't make a rate of anything
+fn slope(recent: &mut ((&'static str, bool), VecDeque<(f64, f64)>), rate: Rate, stamp: &Stamp, m: &Measurement) -> Option<f64> {
+    let (baseline, points) = recent;
+    // REL going on or off is a jump that isn't the input changing
+    if *baseline != (m.mode_name(), m.rel) {
+        *baseline = (m.mode_name(), m.rel);
+        points.clear();
+    }
+    let now = stamp.elapsed.as_secs_f64();
+    points.push_back((now, m.reading_in(Units::Si).reading.value()?));
+    let window = rate.window.as_secs_f64();
+    while points.front().is_some_and(|&(t, _)| now - t > window) {
+        points.pop_front();
+    }
+    let first = points.front()?.0;
+    if now - first < window / 2.0 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let (mean_t, mean_v) = points.iter().fold((0.0, 0.0), |(t, v), &(pt, pv)| (t + pt / n, v + pv / n));
+    let (covariance, variance) = points.iter().fold((0.0, 0.0), |(c, var), &(t, v)| (c + (t - mean_t) * (v - mean_v), var + (t - mean_t).powi(2)));
+    (variance > 0.0).then(|| covariance / variance * rate.seconds())
+}
+
+#[derive(Default)]
+pub struct Alarms {
+    alarms: Vec<Tracked>,
+}
+
+impl Alarms {
+    pub fn new(levels: &[Level], named: Vec<Alarm>) -> Self {
+        let mut alarms = Alarms { alarms: named.into_iter().map(Tracked::new).collect() };
+        alarms.set(levels);
+        alarms
+    }
+
+    /// Replaces the `--alarm` levels, any of them that were going off are forgotten
+    pub fn set(&mut self, levels: &[Level]) {
+        self.alarms.retain(|tracked| tracked.alarm.name.is_some());
+        self.alarms.extend(levels.iter().map(|&level| Tracked::new(Alarm::level(level))));
+    }
+
+    /// Lets latched alarms go, how many there were
+    pub fn rearm(&mut self) -> usize {
+        let latched = self.alarms.iter_mut().filter(|tracked| tracked.state == State::Latched);
+        latched.map(|tracked| tracked.state = State::Clear).count()
+    }
+
+    pub fn statuses(&self) -> Vec<Status> {
+        let state = |state| match state {
+            State::Clear => "clear",
+            State::Firing => "on",
+            State::Latched => "latched",
+            State::Done => "done",
+        };
+        self.alarms.iter().map(|tracked| Status { name: tracked.alarm.name(), severity: tracked.alarm.severity, state: state(tracked.state) }).collect()
+    }
+
+    /// The alarms just gone off or cleared
+    pub fn check(&mut self, stamp: &Stamp, m: &Measurement) -> Vec<Fired> {
+        let value = m.reading_in(Units::Si).reading.value();
+        let mut fired = Vec::new();
+        for Tracked { alarm, state, since, recent } in &mut self.alarms {
+            // What a level is compared with, the value or how fast it's changing. An overload,
+            // or a rate without enough readings yet, leaves a level alarm as it is
+            let quantity = match &alarm.condition {
+                Condition::Level(Level { rate: Some(rate), .. }) => slope(recent, *rate, stamp, m),
+                Condition::Level(_) => value,
+                Condition::When { .. } => None,
+            };
+            let firing = match *state {
+                State::Clear => false,
+                State::Firing => true,
+                State::Latched | State::Done => continue,
+            };
+            let past = match &alarm.condition {
+                Condition::Level(_) if quantity.is_none() => continue,
+                Condition::Level(level) if firing => level.trigger.backed_off(level.hysteresis).matches(quantity.unwrap_or_default()),
+                Condition::Level(level) => level.trigger.matches(quantity.unwrap_or_default()),
+                Condition::When { clear: Some(clear), .. } if firing => !clear.matches(stamp, m),
+                Condition::When { when, .. } => when.matches(stamp, m),
+            };
+            if past == firing {
+                *since = None;
+                continue;
+            }
+            let started = *since.get_or_insert(stamp.elapsed);
+            if stamp.elapsed.saturating_sub(started) < alarm.dwell {
+                continue;
+            }
+            *since = None;
+            *state = match (past, alarm.rearm) {
+                (true, _) => State::Firing,
+                (false, Rearm::Auto) => State::Clear,
+                (false, Rearm::Once) => State::Done,
+                (false, Rearm::Latch) => State::Latched,
+            };
+            let scaled = m.reading_in(Units::Native);
+            let mut reading = format!("{} {}", scaled.reading, scaled.unit);
+            if let Condition::Level(Level { rate: Some(rate), .. }) = &alarm.condition {
+                let unit = m.reading_in(Units::Si).unit;
+                reading = format!("{}, changing {} {}/{}", reading, significant(quantity.unwrap_or_default()), unit, rate.per);
+            }
+            let message = match (&alarm.name, &alarm.condition, past) {
+                (None, Condition::Level(level @ Level { rate: None, .. }), true) => format!("Alarm: {} is {}", significant(value.unwrap_or_default()), level.trigger),
+                (None, Condition::Level(level @ Level { rate: None, .. }), false) => format!("Alarm {} cleared at {}", level.trigger, significant(value.unwrap_or_default())),
+                (_, _, true) => format!("Alarm {} ({}): {}", alarm.name(), alarm.severity, reading),
+                (_, _, false) if *state == State::Latched => format!("Alarm {} back to normal at {}, latched until rearmed", alarm.name(), reading),
+                (_, _, false) => format!("Alarm {} cleared at {}", alarm.name(), reading),
+            };
+            fired.push(Fired { message, severity: alarm.severity, on: past, name: alarm.name(), action: alarm.action.clone() });
+        }
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    const V_DC: u8 = 2;
+    const MV_DC: u8 = 3;
+
+    /// On the lowest range, 2.2000 V or 22.000 mV
+    fn reading(mode: u8, display: &[u8; 7]) -> Measurement {
+        Measurement {
+            mode,
+            range: 0x30,
+            display: *display,
+            bar: [0; 2],
+            rel: false,
+            hold: false,
+            min: false,
+            max: false,
+            auto_range: true,
+            low_battery: false,
+            hv_warning: false,
+            dc: true,
+            peak_max: false,
+            peak_min: false,
+        }
+    }
+
+    fn stamp(secs: u64) -> Stamp {
+        Stamp { wall: SystemTime::UNIX_EPOCH, elapsed: Duration::from_secs(secs), seq: secs }
+    }
+
+    /// Feeds V DC readings a second apart, what went off (true) or cleared (false) after each
+    fn feed(alarms: &mut Alarms, volts: &[&[u8; 7]]) -> Vec<Vec<bool>> {
+        volts.iter().enumerate().map(|(i, display)| alarms.check(&stamp(i as u64), &reading(V_DC, display)).iter().map(|fired| fired.on).collect()).collect()
+    }
+
+    fn levels(level: &str) -> Alarms {
+        Alarms::new(&[Level::parse(level).unwrap()], Vec::new())
+    }
+
+    #[test]
+    fn level_goes_off_and_clears() {
+        let mut alarms = levels(">5");
+        let fired = alarms.check(&stamp(0), &reading(V_DC, b" 6.0000"));
+        assert_eq!(fired.iter().map(|f| f.message.as_str()).collect::<Vec<_>>(), ["Alarm: 6 is >5"]);
+        assert_eq!(feed(&mut alarms, &[b" 6.0000", b" 4.0000", b" 4.0000"]), [vec![], vec![false], vec![]]);
+        assert_eq!(levels("<1").check(&stamp(0), &reading(V_DC, b" 0.5000")).len(), 1);
+    }
+
+    #[test]
+    fn overload_leaves_a_level_as_it_is() {
+        let mut alarms = levels(">5");
+        assert_eq!(feed(&mut alarms, &[b" 6.0000", b"   OL  ", b" 6.0000"]), [vec![true], vec![], vec![]]);
+    }
+
+    #[test]
+    fn hysteresis_holds_it_on_until_far_enough_back() {
+        let mut alarms = levels(">5,hysteresis=0.1");
+        assert_eq!(feed(&mut alarms, &[b" 5.5000", b" 4.9500", b" 5.0500", b" 4.8500"]), [vec![true], vec![], vec![], vec![false]]);
+    }
+
+    #[test]
+    fn dwell_waits_both_ways() {
+        let mut alarms = levels(">5,dwell=2s");
+        let fired = feed(&mut alarms, &[b" 6.0000", b" 6.0000", b" 6.0000", b" 4.0000", b" 6.0000", b" 4.0000", b" 4.0000", b" 4.0000"]);
+        assert_eq!(fired, [vec![], vec![], vec![true], vec![], vec![], vec![], vec![], vec![false]]);
+    }
+
+    #[test]
+    fn dwell_starts_over_when_it_goes_back() {
+        let mut alarms = levels(">5,dwell=2s");
+        assert_eq!(feed(&mut alarms, &[b" 6.0000", b" 6.0000", b" 4.0000", b" 6.0000", b" 6.0000"]), [vec![], vec![], vec![], vec![], vec![]]);
+    }
+
+    #[test]
+    fn rate_is_per_unit_without_prefix() {
+        // 1 mV a second up on the mV range is 0.001 V/s, not 1
+        let mut slow = levels("rate>0.0005/s");
+        let mut fast = levels("rate>0.5/s");
+        let mut per_minute = levels("rate>0.05/min");
+        for (secs, display) in [b" 10.000", b" 11.000", b" 12.000", b" 13.000"].iter().enumerate() {
+            let (stamp, m) = (stamp(secs as u64), reading(MV_DC, display));
+            assert_eq!(slow.check(&stamp, &m).iter().map(|f| f.on).collect::<Vec<_>>(), if secs == 1 { vec![true] } else { vec![] });
+            assert!(fast.check(&stamp, &m).is_empty());
+            assert_eq!(per_minute.check(&stamp, &m).len(), usize::from(secs == 1));
+        }
+    }
+
+    #[test]
+    fn rate_needs_half_a_window() {
+        let mut alarms = levels("rate>0.0005/s,window=10s");
+        let fired: Vec<usize> = (0..6).map(|secs| alarms.check(&stamp(secs), &reading(MV_DC, format!(" {:02}.000", 10 + secs).as_bytes().try_into().unwrap())).len()).collect();
+        assert_eq!(fired, [0, 0, 0, 0, 0, 1]);
+    }
+
+    const NAMED: &str = r#"
+[[alarm]]
+name = "Rail high"
+when = "value > 5.25 && mode == \"V_DC\""
+clear = "value < 5.2"
+severity = "critical"
+action = "stop"
+rearm = "latch"
+
+[[alarm]]
+name = "Rail low"
+level = "<4.75"
+rearm = "once"
+"#;
+
+    #[test]
+    fn named_alarms_from_a_file() {
+        let mut alarms = Alarms::new(&[], parse(NAMED).unwrap());
+        let fired = alarms.check(&stamp(0), &reading(V_DC, b" 5.3000"));
+        assert_eq!(fired.len(), 1);
+        assert_eq!((fired[0].message.as_str(), fired[0].severity, fired[0].stops()), ("Alarm Rail high (critical): 5.3000 V", Severity::Critical, true));
+        // Between when and clear it stays on
+        assert!(alarms.check(&stamp(1), &reading(V_DC, b" 5.2200")).is_empty());
+        let fired = alarms.check(&stamp(2), &reading(V_DC, b" 5.1000"));
+        assert_eq!(fired.iter().map(|f| (f.message.as_str(), f.on, f.stops())).collect::<Vec<_>>(), [("Alarm Rail high back to normal at 5.1000 V, latched until rearmed", false, false)]);
+    }
+
+    #[test]
+    fn latched_stays_on_until_rearmed() {
+        let mut alarms = Alarms::new(&[], parse(NAMED).unwrap());
+        assert_eq!(feed(&mut alarms, &[b" 5.3000", b" 5.0000", b" 5.3000", b" 5.0000"]), [vec![true], vec![false], vec![], vec![]]);
+        assert_eq!(alarms.statuses()[0].state, "latched");
+        assert_eq!(alarms.rearm(), 1);
+        assert_eq!(alarms.statuses()[0].state, "clear");
+        assert_eq!(alarms.check(&stamp(4), &reading(V_DC, b" 5.3000")).len(), 1);
+    }
+
+    #[test]
+    fn once_goes_off_once_a_run() {
+        let mut alarms = Alarms::new(&[], parse(NAMED).unwrap());
+        assert_eq!(feed(&mut alarms, &[b" 4.5000", b" 5.0000", b" 4.5000", b" 5.0000"]), [vec![true], vec![false], vec![], vec![]]);
+        assert_eq!(alarms.statuses()[1].state, "done");
+        // Rearming is for latched ones
+        assert_eq!(alarms.rearm(), 0);
+    }
+
+    #[test]
+    fn changing_the_levels_leaves_named_ones() {
+        let mut alarms = Alarms::new(&[Level::parse(">6").unwrap()], parse(NAMED).unwrap());
+        alarms.set(&[Level::parse(">7").unwrap(), Level::parse("<1").unwrap()]);
+        assert_eq!(alarms.statuses().iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), ["Rail high", "Rail low", ">7", "<1"]);
+    }
+
+    #[test]
+    fn bad_files_say_where() {
+        let error = |text: &str| parse(text).err().unwrap_or_default();
+        assert_eq!(error("[[alarm]]\nlevel = \">5\"\n"), "line 1: the alarm has no name");
+        assert_eq!(error("[[alarm]]\nname = \"a\"\nlevel = \">5\"\nwhen = \"value > 5\"\n"), "line 1: the alarm has both a level and when, pick one");
+        assert_eq!(error("[[alarm]]\nname = \"a\"\nlevel = \">5\"\nseverity = \"dire\"\n"), "line 4: severity is info, warning or critical, not \"dire\"");
+        assert_eq!(error("[[alarm]]\nname = \"a\"\nlevel = \">5\"\naction = \"run\"\n"), "line 1: action run needs a command");
+    }
+
+    #[test]
+    fn levels_print_as_given() {
+        for level in [">5", "<-0.5,hysteresis=0.1", "rate<-2/s,window=5s,dwell=1s", "rate>1/min"] {
+            assert_eq!(Level::parse(level).unwrap().to_string(), level);
+        }
+        assert!(Level::parse(">5,window=5s").is_err());
+    }
+}
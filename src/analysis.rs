@@ -0,0 +1,399 @@
+//! Number crunching for the report (spectra, histograms and the like, over the readings of one
+//! unit) and for fits the logger does as it goes.
+
+use clap::ValueEnum;
+use std::f64::consts::PI;
+
+/// Window applied before the FFT, trading frequency resolution against leakage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Window {
+    /// Good all-rounder
+    #[default]
+    Hann,
+    Hamming,
+    /// Lowest leakage, for spotting a small peak next to a big one
+    Blackman,
+    /// No window, sharpest peaks but the most leakage
+    Rectangular,
+}
+
+impl Window {
+    fn coefficients(self, n: usize) -> Vec<f64> {
+        let phase = |i: usize| 2.0 * PI * i as f64 / (n - 1) as f64;
+        (0..n)
+            .map(|i| match self {
+                Window::Hann => 0.5 - 0.5 * phase(i).cos(),
+                Window::Hamming => 0.54 - 0.46 * phase(i).cos(),
+                Window::Blackman => 0.42 - 0.5 * phase(i).cos() + 0.08 * (2.0 * phase(i)).cos(),
+                Window::Rectangular => 1.0,
+            })
+            .collect()
+    }
+}
+
+/// In-place radix-2 FFT, the length has to be a power of two
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let (br, bi) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - br;
+                im[b] = im[a] - bi;
+                re[a] += br;
+                im[a] += bi;
+            }
+        }
+        len *= 2;
+    }
+}
+
+/// Fewer readings than this don't make a spectrum worth looking at
+pub const SPECTRUM_MIN_SAMPLES: usize = 64;
+
+/// Caps the FFT length, a 6 Hz log has no more than this to say about slow drifts
+const SPECTRUM_MAX_SAMPLES: usize = 1 << 16;
+
+pub struct Spectrum {
+    /// Amplitude of each frequency bin from the first above DC up to Nyquist, in the unit of
+    /// the readings
+    pub amplitudes: Vec<f64>,
+    /// Hz per bin, or cycles per reading when the readings had no timestamps
+    pub bin_width: f64,
+    pub timed: bool,
+    /// How many readings went in, the newest power of two of them
+    pub samples: usize,
+}
+
+impl Spectrum {
+    pub fn frequency(&self, bin: usize) -> f64 {
+        (bin + 1) as f64 * self.bin_width
+    }
+
+    /// The strongest bin
+    pub fn peak(&self) -> Option<(usize, f64)> {
+        self.amplitudes.iter().copied().enumerate().max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// The amplitude spectrum of evenly spaced readings, `interval` seconds apart if known. The mean
+/// is taken off first so DC doesn't swamp everything else.
+pub fn spectrum(values: &[f64], window: Window, interval: Option<f64>) -> Option<Spectrum> {
+    if values.len() < SPECTRUM_MIN_SAMPLES {
+        return None;
+    }
+    let n = (1 << values.len().ilog2()).min(SPECTRUM_MAX_SAMPLES);
+    let values = &values[values.len() - n..];
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let coefficients = window.coefficients(n);
+    let gain: f64 = coefficients.iter().sum();
+    let mut re: Vec<f64> = values.iter().zip(&coefficients).map(|(v, w)| (v - mean) * w).collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    let amplitudes = (1..=n / 2).map(|k| 2.0 * re[k].hypot(im[k]) / gain).collect();
+    let timed = interval.is_some_and(|dt| dt > 0.0);
+    let rate = interval.filter(|_| timed).map_or(1.0, |dt| 1.0 / dt);
+    Some(Spectrum { amplitudes, bin_width: rate / n as f64, timed, samples: n })
+}
+
+/// Readings counted into equal bins
+pub struct Histogram {
+    /// Lower edge of the first bin
+    pub start: f64,
+    pub width: f64,
+    pub counts: Vec<usize>,
+}
+
+/// Readings taking no more distinct values than this get a bin each, the meter's resolution
+/// makes anything finer meaningless
+const DISTINCT_BINS: usize = 50;
+
+const MAX_BINS: usize = 200;
+
+/// `bins` equal bins from the smallest to the largest value, or picked to suit the data: one
+/// per resolution step when the readings only take a few values, the Freedman–Diaconis rule
+/// otherwise
+pub fn histogram(values: &[f64], bins: Option<usize>) -> Option<Histogram> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(f64::total_cmp);
+    let (min, max) = (sorted[0], sorted[sorted.len() - 1]);
+    let mut distinct = sorted.clone();
+    distinct.dedup();
+    let (start, width, bins) = match bins {
+        Some(bins) => (min, (max - min) / bins.max(1) as f64, bins.max(1)),
+        None if distinct.len() == 1 => (min - 0.5, 1.0, 1),
+        None if distinct.len() <= DISTINCT_BINS => {
+            let step = distinct.windows(2).map(|w| w[1] - w[0]).fold(f64::INFINITY, f64::min);
+            (min - step / 2.0, step, ((max - min) / step).round() as usize + 1)
+        }
+        None => {
+            let quartile = |q: f64| sorted[((sorted.len() - 1) as f64 * q) as usize];
+            let iqr = quartile(0.75) - quartile(0.25);
+            let width = 2.0 * iqr / (sorted.len() as f64).cbrt();
+            let bins = if width > 0.0 { ((max - min) / width).ceil() as usize } else { DISTINCT_BINS };
+            (min, (max - min) / bins.clamp(1, MAX_BINS) as f64, bins.clamp(1, MAX_BINS))
+        }
+    };
+    let width = if width > 0.0 { width } else { 1.0 };
+    let mut counts = vec![0; bins];
+    for v in sorted {
+        counts[(((v - start) / width) as usize).min(bins - 1)] += 1;
+    }
+    Some(Histogram { start, width, counts })
+}
+
+/// How far a distribution is from a normal one
+pub struct Shape {
+    pub skewness: f64,
+    /// 0 for a normal distribution, positive for heavier tails
+    pub excess_kurtosis: f64,
+    pub distinct: usize,
+}
+
+impl Shape {
+    pub fn of(values: &[f64]) -> Option<Shape> {
+        let n = values.len() as f64;
+        if values.len() < 3 {
+            return None;
+        }
+        let mean = values.iter().sum::<f64>() / n;
+        let moment = |k: i32| values.iter().map(|v| (v - mean).powi(k)).sum::<f64>() / n;
+        let var = moment(2);
+        let mut distinct = values.to_vec();
+        distinct.sort_by(f64::total_cmp);
+        distinct.dedup();
+        let (skewness, excess_kurtosis) = if var > 0.0 { (moment(3) / var.powf(1.5), moment(4) / (var * var) - 3.0) } else { (0.0, 0.0) };
+        Some(Shape { skewness, excess_kurtosis, distinct: distinct.len() })
+    }
+
+    /// A sentence on what the shape suggests, for someone judging a noise floor or a reference
+    pub fn hint(&self) -> String {
+        if self.distinct <= 3 {
+            return format!(
+                "The readings only take {} distinct values, the noise is at or below the meter's resolution so \
+                 the spread says more about the last digit than the source.",
+                self.distinct
+            );
+        }
+        let mut hints = Vec::new();
+        if self.skewness.abs() > 0.5 {
+            hints.push(format!(
+                "skewed to the {} (skewness {:.2}), often drift or a warm-up rather than noise",
+                if self.skewness > 0.0 { "high side" } else { "low side" },
+                self.skewness
+            ));
+        }
+        if self.excess_kurtosis > 1.0 {
+            hints.push(format!("heavier tails than normal (excess kurtosis {:.2}), look for spikes or interference", self.excess_kurtosis));
+        } else if self.excess_kurtosis < -1.0 {
+            hints.push(format!(
+                "flatter than normal (excess kurtosis {:.2}), e.g. a slow sweep or two levels mixed together",
+                self.excess_kurtosis
+            ));
+        }
+        if hints.is_empty() {
+            format!(
+                "Close to normal (skewness {:.2}, excess kurtosis {:.2}), consistent with random noise.",
+                self.skewness, self.excess_kurtosis
+            )
+        } else {
+            let mut hint = hints.join("; ");
+            hint[..1].make_ascii_uppercase();
+            hint + "."
+        }
+    }
+}
+
+/// One point of an Allan deviation curve
+pub struct Allan {
+    /// Averaging time, in seconds or readings like [`Spectrum::bin_width`]
+    pub tau: f64,
+    /// In the unit of the readings
+    pub deviation: f64,
+    /// Differences averaged for this point, the fewer the less it can be trusted
+    pub terms: usize,
+}
+
+/// Overlapping Allan deviation of evenly spaced readings at octave-spaced averaging times, from
+/// one reading up to a third of them. Where it bottoms out is the longest averaging that still
+/// helps, past that drift takes over from noise.
+pub fn allan_deviation(values: &[f64], interval: Option<f64>) -> Vec<Allan> {
+    let n = values.len();
+    let tau0 = interval.filter(|&dt| dt > 0.0).unwrap_or(1.0);
+    // Running sum, so any average is a difference of two of these
+    let mut phase = Vec::with_capacity(n + 1);
+    phase.push(0.0);
+    for v in values {
+        phase.push(phase[phase.len() - 1] + v);
+    }
+    let mut points = Vec::new();
+    let mut m = 1;
+    while 3 * m <= n {
+        let terms = n + 1 - 2 * m;
+        let sum: f64 = (0..terms).map(|i| (phase[i + 2 * m] - 2.0 * phase[i + m] + phase[i]).powi(2)).sum();
+        let m_f = m as f64;
+        points.push(Allan { tau: m_f * tau0, deviation: (sum / (2.0 * m_f * m_f * terms as f64)).sqrt(), terms });
+        m *= 2;
+    }
+    points
+}
+
+/// Shape of curve to fit for a trend
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Fit {
+    /// Straight line, drift as a slope
+    #[default]
+    Linear,
+    /// a·e^(b·t) through readings of one sign, drift as a rate, e.g. self-discharge
+    Exponential,
+}
+
+/// A least-squares fit of readings against time
+pub struct Trend {
+    pub fit: Fit,
+    /// Value at t = 0 for a line, a for an exponential
+    pub intercept: f64,
+    /// Per unit of time for a line, b (the fraction per unit of time) for an exponential
+    pub slope: f64,
+    /// Half-width of the slope's 95% confidence interval. Assumes independent noise, slow
+    /// wander makes the real interval wider.
+    pub slope_ci95: f64,
+    pub r_squared: f64,
+    pub samples: usize,
+}
+
+impl Trend {
+    pub fn at(&self, t: f64) -> f64 {
+        match self.fit {
+            Fit::Linear => self.intercept + self.slope * t,
+            Fit::Exponential => self.intercept * (self.slope * t).exp(),
+        }
+    }
+}
+
+/// Student's t for a two-sided 95% interval with `df` degrees of freedom, Cornish-Fisher
+/// expansion around the normal quantile, within a percent from 3 degrees of freedom up
+fn t95(df: usize) -> f64 {
+    const Z: f64 = 1.959964;
+    let df = df as f64;
+    Z + (Z.powi(3) + Z) / (4.0 * df) + (5.0 * Z.powi(5) + 16.0 * Z.powi(3) + 3.0 * Z) / (96.0 * df * df)
+}
+
+/// Fits `values` against `times`, `None` with fewer than 3 points, no spread in time, or for an
+/// exponential readings that change sign or touch zero
+pub fn trend(times: &[f64], values: &[f64], fit: Fit) -> Option<Trend> {
+    let n = times.len().min(values.len());
+    if n < 3 {
+        return None;
+    }
+    let sign = values[0].signum();
+    let ys: Vec<f64> = match fit {
+        Fit::Linear => values[..n].to_vec(),
+        Fit::Exponential => {
+            if values[..n].iter().any(|&v| v == 0.0 || v.signum() != sign) {
+                return None;
+            }
+            values[..n].iter().map(|v| v.abs().ln()).collect()
+        }
+    };
+    let mean_t = times[..n].iter().sum::<f64>() / n as f64;
+    let mean_y = ys.iter().sum::<f64>() / n as f64;
+    let sxx: f64 = times[..n].iter().map(|t| (t - mean_t).powi(2)).sum();
+    let sxy: f64 = times[..n].iter().zip(&ys).map(|(t, y)| (t - mean_t) * (y - mean_y)).sum();
+    let syy: f64 = ys.iter().map(|y| (y - mean_y).powi(2)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_t;
+    let residual = (syy - slope * sxy).max(0.0);
+    let slope_ci95 = t95(n - 2) * (residual / (n - 2) as f64 / sxx).sqrt();
+    let r_squared = if syy > 0.0 { 1.0 - residual / syy } else { 1.0 };
+    let intercept = match fit {
+        Fit::Linear => intercept,
+        Fit::Exponential => sign * intercept.exp(),
+    };
+    Some(Trend { fit, intercept, slope, slope_ci95, r_squared, samples: n })
+}
+
+/// A settling curve, `asymptote + amplitude·e^(-t/tau)`, like the current into a capacitor on
+/// a leakage test or the resistance of insulation while it charges
+pub struct Settling {
+    /// Where it's heading
+    pub asymptote: f64,
+    /// Time constant, in the unit of the times
+    pub tau: f64,
+    pub r_squared: f64,
+    pub samples: usize,
+}
+
+/// For a given time constant the curve is a straight line in e^(-t/tau), so that's a linear
+/// fit, and the squared residual that's left
+fn settling_for(times: &[f64], values: &[f64], tau: f64) -> Option<(f64, f64, f64)> {
+    let n = times.len() as f64;
+    let xs: Vec<f64> = times.iter().map(|t| (-t / tau).exp()).collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = values.iter().sum::<f64>() / n;
+    let sxx: f64 = xs.iter().map(|x| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = xs.iter().zip(values).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let syy: f64 = values.iter().map(|y| (y - mean_y).powi(2)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+    let amplitude = sxy / sxx;
+    Some((mean_y - amplitude * mean_x, amplitude, (syy - amplitude * sxy).max(0.0)))
+}
+
+/// Fits a settling curve to `values` against `times` (starting at 0), `None` with fewer than 5
+/// points or while it's still going as if it had a time constant longer than the readings, when
+/// where it ends up can't be told yet. The time constant is searched for on a log scale between
+/// the spacing of the readings and 10 times their span, then narrowed down.
+pub fn settling(times: &[f64], values: &[f64]) -> Option<Settling> {
+    let n = times.len().min(values.len());
+    let (times, values) = (&times[..n], &values[..n]);
+    let span = times.last()? - times.first()?;
+    if n < 5 || span <= 0.0 {
+        return None;
+    }
+    let residual = |tau: f64| settling_for(times, values, tau).map_or(f64::INFINITY, |(_, _, r)| r);
+    const STEPS: usize = 100;
+    let (low, high) = (span / n as f64, 10.0 * span);
+    let tau_at = |i: usize| low * (high / low).powf(i as f64 / STEPS as f64);
+    let best = (0..=STEPS).min_by(|&a, &b| residual(tau_at(a)).total_cmp(&residual(tau_at(b))))?;
+    // Still falling at the longest time constant tried, it hasn't bent over enough yet
+    if best == STEPS || tau_at(best) > span {
+        return None;
+    }
+    // Golden section between the neighbours
+    let (mut a, mut b) = (tau_at(best.saturating_sub(1)), tau_at(best + 1));
+    const PHI: f64 = 0.618_033_988_749_895;
+    for _ in 0..40 {
+        let (c, d) = (b - PHI * (b - a), a + PHI * (b - a));
+        if residual(c) < residual(d) {
+            b = d;
+        } else {
+            a = c;
+        }
+    }
+    let tau = (a + b) / 2.0;
+    let (asymptote, _, residual) = settling_for(times, values, tau)?;
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let total: f64 = values.iter().map(|v| (v - mean).powi(2)).sum();
+    let r_squared = if total > 0.0 { 1.0 - residual / total } else { 1.0 };
+    Some(Settling { asymptote, tau, r_squared, samples: n })
+}
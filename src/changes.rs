@@ -0,0 +1,38 @@
+//! Mode, range and REL changes, so what the logger works out from the readings doesn't quietly
+//! carry on across one, and with `--mark-changes` an `EVENT` for each so a capture that went
+//! through several says where. Most of it already starts over on a mode change by itself (the
+//! min, max and mean are per mode, `--settle` and `rate` alarms start again), but every reading
+//! after REL goes on or off is on another baseline, whether or not the mode changed with it. So
+//! then the logger's own min, max and mean for the mode and the `--settle` fit start over too,
+//! with or without `--mark-changes`.
+
+use ut61e_plus_logger::protocol::{self, Measurement};
+
+pub struct Change {
+    pub message: String,
+    /// Whether what's been worked out from the readings so far no longer goes with the new ones
+    pub rebaseline: bool,
+}
+
+#[derive(Default)]
+pub struct Changes {
+    /// Mode, range byte and REL of the reading before
+    last: Option<(&'static str, u8, bool)>,
+}
+
+impl Changes {
+    pub fn sample(&mut self, m: &Measurement) -> Option<Change> {
+        let (mode, range, rel) = (m.mode_name(), m.range, m.rel);
+        let last = self.last.replace((mode, range, rel))?;
+        let describe = |range: u8| protocol::range_info(m.mode, range).map_or_else(|| format!("range {}", range), |r| r.to_string());
+        let message = match last {
+            (last_mode, ..) if last_mode != mode => format!("Mode changed from {} to {}", last_mode, mode),
+            (_, _, last_rel) if last_rel != rel => format!("REL turned {} in {}", if rel { "on" } else { "off" }, mode),
+            (_, last_range, _) if last_range != range => {
+                format!("Range changed from {} to {}{}", describe(last_range), describe(range), if m.auto_range { " (auto)" } else { "" })
+            }
+            _ => return None,
+        };
+        Some(Change { rebaseline: last.2 != rel, message })
+    }
+}
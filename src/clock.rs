@@ -0,0 +1,104 @@
+//! Per-sample timestamps. The wall clock is what lines a capture up against other instruments,
+//! the monotonic clock is what can be trusted for intervals. NTP steps and suspend/resume move
+//! the wall clock without the monotonic one (which doesn't run while suspended), so comparing
+//! the two between samples tells us when that happened.
+
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Wall clock moving this much more or less than the monotonic clock between two samples
+/// counts as a jump
+const JUMP_THRESHOLD: f64 = 1.0;
+
+#[derive(Clone, Copy)]
+pub struct Stamp {
+    pub wall: SystemTime,
+    /// Monotonic time since logging started
+    pub elapsed: Duration,
+    /// Counts polls from 1. Rows written for the same poll (an event and the reading) share it,
+    /// so numbers missing downstream are rows that got lost on the way.
+    pub seq: u64,
+}
+
+pub struct Clock {
+    start: Instant,
+    last: Option<(Instant, SystemTime)>,
+    seq: u64,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock { start: Instant::now(), last: None, seq: 0 }
+    }
+
+    /// Takes a timestamp, along with how many seconds the wall clock jumped since the last one
+    /// if it moved differently from the monotonic clock
+    pub fn stamp(&mut self) -> (Stamp, Option<f64>) {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let jump = self.last.and_then(|(last_now, last_wall)| {
+            let mono = now.duration_since(last_now).as_secs_f64();
+            let wall = match wall.duration_since(last_wall) {
+                Ok(d) => d.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64(),
+            };
+            let jump = wall - mono;
+            (jump.abs() > JUMP_THRESHOLD).then_some(jump)
+        });
+        self.last = Some((now, wall));
+        self.seq += 1;
+        (Stamp { wall, elapsed: now.duration_since(self.start), seq: self.seq }, jump)
+    }
+}
+
+/// Days since the epoch to a (year, month, day) civil date, Howard Hinnant's algorithm
+pub fn civil(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// `500ms`, `30s`, `10m`, `2h` or `1d`, fractions allowed. `None` without a unit.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let units = [("ms", 0.001), ("s", 1.0), ("m", 60.0), ("h", 3600.0), ("d", 86400.0)];
+    let (number, scale) = units.iter().find_map(|(unit, scale)| Some((s.strip_suffix(unit)?, *scale)))?;
+    let number: f64 = number.parse().ok()?;
+    (number.is_finite() && number >= 0.0).then(|| Duration::from_secs_f64(number * scale))
+}
+
+/// Formats a wall clock time as ISO 8601 UTC with milliseconds, `2024-05-01T13:37:00.123Z`
+pub struct Utc(pub SystemTime);
+
+impl Utc {
+    /// Just the day, `2024-05-01`
+    pub fn date(&self) -> String {
+        let days = self.0.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 86400;
+        let (year, month, day) = civil(days as i64);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl fmt::Display for Utc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let since_epoch = self.0.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+        let (year, month, day) = civil(days);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            rem / 3600,
+            rem % 3600 / 60,
+            rem % 60,
+            since_epoch.subsec_millis()
+        )
+    }
+}
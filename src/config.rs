@@ -0,0 +1,82 @@
+//! The small piece of TOML the logger's own files are written in: `key = value` lines with
+//! strings in double quotes (`\"`, `\\` and `\n` in them) or plain numbers, `#` comments, and
+//! `[[name]]` headers to start the next entry of a list. That's all, no tables, arrays or dates.
+//! `procedure` files are read line by line with [`value_of`], since they have a `title` before the
+//! first step. `--alarms`, `--macros`, `--plugins` and `--smooth` files are a list of one kind of
+//! section each, read with [`sections`], and so is `--web-config` with its one `[[web]]`.
+
+/// A value, a string or a number
+pub enum Value {
+    Text(String),
+    Number(f64),
+}
+
+/// A string in double quotes (with `\"` and `\\`) or a number, and maybe a comment after it
+pub fn value_of(s: &str) -> Result<Value, String> {
+    let Some(quoted) = s.strip_prefix('"') else {
+        let number = s.split('#').next().unwrap_or_default().trim();
+        return number.parse().map(Value::Number).map_err(|_| format!("bad value {:?}, strings go in double quotes", number));
+    };
+    let mut text = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let rest = chars.as_str().trim();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(format!("unexpected {:?} after the string", rest));
+                }
+                return Ok(Value::Text(text));
+            }
+            '\\' => match chars.next() {
+                Some('"') => text.push('"'),
+                Some('\\') => text.push('\\'),
+                Some('n') => text.push('\n'),
+                other => return Err(format!("unknown escape \\{}", other.map(String::from).unwrap_or_default())),
+            },
+            c => text.push(c),
+        }
+    }
+    Err("the string isn't closed".to_owned())
+}
+
+/// One `[[section]]`, the line it starts on and its settings in the order they're given
+pub struct Section {
+    pub start: usize,
+    pub settings: Vec<Setting>,
+}
+
+pub struct Setting {
+    pub key: String,
+    pub value: Value,
+    /// For messages
+    pub line: usize,
+}
+
+/// The `[[name]]` sections of a file that's a list of something, with nothing allowed before
+/// the first. What the keys mean is up to the caller, a file without any section is an error.
+pub fn sections(text: &str, name: &str) -> Result<Vec<Section>, String> {
+    let header = format!("[[{}]]", name);
+    let mut sections: Vec<Section> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let error = |e: String| format!("line {}: {}", n + 1, e);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.split('#').next().unwrap_or_default().trim() == header {
+            sections.push(Section { start: n + 1, settings: Vec::new() });
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| error(format!("expected key = value or {}, not {:?}", header, line)))?;
+        let key = key.trim();
+        let Some(section) = sections.last_mut() else {
+            return Err(error(format!("unknown setting {:?} before the first {}", key, header)));
+        };
+        section.settings.push(Setting { key: key.to_owned(), value: value_of(value.trim()).map_err(error)?, line: n + 1 });
+    }
+    if sections.is_empty() {
+        return Err(format!("has no {}", header));
+    }
+    Ok(sections)
+}
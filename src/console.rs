@@ -0,0 +1,63 @@
+//! Commands typed into the terminal while logging, with `--interactive`. Lines are read on their
+//! own thread so waiting for a keypress never holds up polling.
+
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+pub enum Input {
+    /// Just Enter, copy the latest reading to the clipboard
+    Copy,
+    /// Any other line, a note like "connected load" to keep with the data
+    Annotate(String),
+    /// `/burst`, see `--burst`
+    Burst,
+    /// `/stats`, the logger's own min, max and mean so far
+    Stats,
+    /// `/reset`, start those over
+    Reset,
+    /// `/rearm`, let go of latched alarms
+    Rearm,
+    /// `/light`, press the meter's LIGHT key
+    Light,
+    /// `/macro NAME`, press the keys of one of the `--macros`
+    Macro(String),
+    /// Some other line starting with `/`
+    Unknown(String),
+}
+
+pub struct Console {
+    rx: Receiver<Input>,
+}
+
+impl Console {
+    pub fn start() -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { return };
+                let line = line.trim();
+                let input = match line {
+                    "" => Input::Copy,
+                    "/burst" => Input::Burst,
+                    "/stats" => Input::Stats,
+                    "/reset" => Input::Reset,
+                    "/rearm" => Input::Rearm,
+                    "/light" => Input::Light,
+                    _ if line.starts_with("/macro ") => Input::Macro(line["/macro ".len()..].trim().to_owned()),
+                    _ if line.starts_with('/') => Input::Unknown(line.to_owned()),
+                    _ => Input::Annotate(line.to_owned()),
+                };
+                if tx.send(input).is_err() {
+                    return;
+                }
+            }
+        });
+        Console { rx }
+    }
+
+    /// Whatever was typed since the last call, one at a time
+    pub fn poll(&self) -> Option<Input> {
+        self.rx.try_recv().ok()
+    }
+}
@@ -0,0 +1,54 @@
+//! `--web-control`, commands for a test executive or script to run the capture around its own
+//! test steps, sent as the body of `POST /control` or as text messages on the dashboard's
+//! WebSocket. One command per request or message:
+//!
+//! - `stop` stops polling the meter until `start`, with a gap in the outputs in between
+//! - `interval 500ms` changes the time between polls, like `--interval`
+//! - `alarm >5,hysteresis=0.1 <1` replaces the `--alarm` levels, `alarm off` clears them
+//! - `rearm` lets go of latched `--alarms`
+//! - `light` presses the meter's LIGHT key, turning the backlight on or off
+//! - `macro <name>` presses the keys of one of the `--macros`
+//! - `note <text>` keeps a note with the data, like typing it with `--interactive`
+//! - `burst` starts a `--burst`, like SIGUSR1
+//! - `reset` starts the logger's own min, max and mean over
+
+use crate::alarm::Level;
+use std::time::Duration;
+
+pub enum Command {
+    Stop,
+    Start,
+    Interval(Duration),
+    /// Empty to turn them off
+    Alarm(Vec<Level>),
+    Rearm,
+    Light,
+    Macro(String),
+    Note(String),
+    Burst,
+    Reset,
+}
+
+impl Command {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (name, rest) = s.split_once(char::is_whitespace).map_or((s, ""), |(name, rest)| (name, rest.trim()));
+        match (name, rest) {
+            ("stop", "") => Ok(Command::Stop),
+            ("start", "") => Ok(Command::Start),
+            ("burst", "") => Ok(Command::Burst),
+            ("reset", "") => Ok(Command::Reset),
+            ("rearm", "") => Ok(Command::Rearm),
+            ("light", "") => Ok(Command::Light),
+            ("macro", name) if !name.is_empty() => Ok(Command::Macro(name.to_owned())),
+            ("interval", interval) => crate::clock::parse_duration(interval)
+                .filter(|interval| !interval.is_zero())
+                .map(Command::Interval)
+                .ok_or_else(|| format!("expected a duration like 500ms or 2s after interval, got {:?}", interval)),
+            ("alarm", "off") => Ok(Command::Alarm(Vec::new())),
+            ("alarm", levels) if !levels.is_empty() => levels.split_whitespace().map(Level::parse).collect::<Result<_, _>>().map(Command::Alarm),
+            ("note", text) if !text.is_empty() => Ok(Command::Note(text.to_owned())),
+            _ => Err(format!("unknown command {:?}, try stop, start, interval, alarm, rearm, light, macro, note, burst or reset", s)),
+        }
+    }
+}
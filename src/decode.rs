@@ -0,0 +1,91 @@
+//! `decode`: hex dumps in on stdin, decoded measurements out, so reverse engineering the
+//! protocol and bug reports don't need the meter plugged in.
+//!
+//! Complete frames (`AB CD` header, length, payload, checksum) are found wherever they are in the
+//! input, even split across lines. Lines without a header that are long enough are taken as a
+//! bare payload, which is what `--show-raw` prints.
+
+use crate::clock::Clock;
+use crate::output::{self, Columns};
+use colored::*;
+use std::io::{self, BufRead, Write};
+use ut61e_plus_logger::protocol::{self, FrameError, MEASUREMENT_LEN, Measurement, Units};
+
+#[derive(Default)]
+struct Counts {
+    decoded: u64,
+    bad_checksums: u64,
+    undecodable: u64,
+}
+
+fn print(out: &mut impl Write, payload: &[u8], csv: bool, counts: &mut Counts) -> io::Result<()> {
+    let Some(m) = Measurement::decode(payload) else {
+        counts.undecodable += 1;
+        return writeln!(io::stderr(), "{}", format!("Payload too short: {:02x?}", payload).red());
+    };
+    counts.decoded += 1;
+    let columns = Columns { seq: false, timestamp: false, uncertainty: true, units: Units::Native, limits: None };
+    // Timestamps mean nothing here, they're left off
+    let (stamp, _) = Clock::new().stamp();
+    if csv {
+        output::write_csv(out, &stamp, &m, &columns)
+    } else {
+        output::write_pretty(out, &stamp, &m, &columns)?;
+        output::write_raw(out, payload)
+    }
+}
+
+pub fn run(csv: bool) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    if csv {
+        output::write_csv_header(&mut out, &Columns { seq: false, timestamp: false, uncertainty: true, units: Units::Native, limits: None })?;
+    }
+    let mut counts = Counts::default();
+    let mut pending: Vec<u8> = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let bytes: Vec<u8> = protocol::hex_bytes(&line?).collect();
+        let has_header = bytes.windows(2).any(|w| w == protocol::HEADER);
+        if pending.is_empty() && !has_header {
+            if bytes.len() >= MEASUREMENT_LEN {
+                print(&mut out, &bytes, csv, &mut counts)?;
+            }
+            continue;
+        }
+        pending.extend_from_slice(&bytes);
+        loop {
+            match protocol::locate_frame(&pending) {
+                Ok((start, total)) => {
+                    match protocol::find_frame(&pending[start..start + total]) {
+                        Ok(payload) => print(&mut out, payload, csv, &mut counts)?,
+                        Err(e) => {
+                            if matches!(e, FrameError::BadChecksum { .. }) {
+                                counts.bad_checksums += 1;
+                            }
+                            eprintln!("{}", format!("{}: {:02x?}", e, &pending[start..start + total]).red());
+                        }
+                    }
+                    pending.drain(..start + total);
+                }
+                // Wait for the rest of the frame on the next lines
+                Err(FrameError::Truncated) => break,
+                // Skip a bad header and look for the next one
+                Err(FrameError::BadLength(_)) => {
+                    let start = pending.windows(2).position(|w| w == protocol::HEADER).unwrap_or(0);
+                    pending.drain(..start + 2);
+                }
+                Err(_) => {
+                    pending.clear();
+                    break;
+                }
+            }
+        }
+    }
+    if !pending.is_empty() {
+        eprintln!("{}", format!("Incomplete frame at the end: {:02x?}", pending).yellow());
+    }
+    eprintln!(
+        "{} decoded, {} bad checksums, {} too short",
+        counts.decoded, counts.bad_checksums, counts.undecodable
+    );
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use crate::exit_code;
+#[cfg(unix)]
+use crate::ipc;
+use crate::meter::Meter;
+use crate::quirks::{self, DEVICE_IDS};
+use crate::sim::{Sim, SimTransport};
+use hidapi::{HidApi, HidError};
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{fmt, thread};
+
+/// How often to look for the meter again once it has gone away
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why no meter could be opened, with enough detail to tell the user what to do about it
+#[derive(Debug)]
+pub enum OpenError {
+    /// The HID library itself couldn't start
+    Api(HidError),
+    /// Nothing with a known VID/PID is plugged in
+    NotFound,
+    /// Found, but the device node isn't readable/writable by this user
+    PermissionDenied(Vec<String>),
+    /// Found, but opening it failed for another reason, usually another program holding it
+    Busy(Vec<(String, HidError)>),
+    /// Another logger is already reading it, these are its attach sockets (see ipc.rs)
+    Logging(Vec<PathBuf>),
+}
+
+impl OpenError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OpenError::Api(_) => exit_code::HID_UNAVAILABLE,
+            OpenError::NotFound => exit_code::DEVICE_NOT_FOUND,
+            OpenError::PermissionDenied(_) => exit_code::PERMISSION_DENIED,
+            OpenError::Busy(_) | OpenError::Logging(_) => exit_code::DEVICE_BUSY,
+        }
+    }
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::Api(e) => {
+                writeln!(f, "Could not initialise the HID API: {}", e)?;
+            }
+            OpenError::NotFound => {
+                write!(f, "UT61E+ not found, searched for")?;
+                for (vid, pid) in DEVICE_IDS {
+                    write!(f, " {:04x}:{:04x}", vid, pid)?;
+                }
+                writeln!(f)?;
+                writeln!(f, "Check the cable is plugged in and shows up in `lsusb`.")?;
+            }
+            OpenError::PermissionDenied(paths) => {
+                writeln!(f, "Found the UT61E+ but don't have permission to open it:")?;
+                for path in paths {
+                    writeln!(f, "  {}", path)?;
+                }
+                writeln!(f, "Install 99-ut61e-plus.rules to /etc/udev/rules.d/ (see the readme) or run as root.")?;
+            }
+            OpenError::Busy(devices) => {
+                writeln!(f, "Found the UT61E+ but could not open it:")?;
+                for (path, e) in devices {
+                    writeln!(f, "  {}: {}", path, e)?;
+                }
+                writeln!(f, "Another program (or another copy of this logger) may be using it.")?;
+            }
+            // Nothing wrong for doctor to find
+            OpenError::Logging(sockets) => {
+                writeln!(f, "The UT61E+ is already being read by another copy of this logger.")?;
+                for socket in sockets {
+                    writeln!(f, "Follow it with `ut61e_plus attach {}`.", socket.display())?;
+                }
+                return Ok(());
+            }
+        }
+        write!(f, "Run `ut61e_plus doctor` for a step by step check.")
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+/// On Linux the device path is the hidraw node, so the filesystem can tell a permission problem
+/// apart from a busy device. Elsewhere hidapi's error string is all there is.
+fn permission_denied(path: &str) -> bool {
+    #[cfg(target_os = "linux")]
+    if path.starts_with("/dev/") {
+        return matches!(
+            std::fs::OpenOptions::new().read(true).write(true).open(path),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied
+        );
+    }
+    let _ = path;
+    false
+}
+
+/// The attach socket of a logger already reading the meter with this serial number, if any.
+/// hidraw lets two processes open the same meter, and then both poll it and each gets half the
+/// answers.
+#[cfg(unix)]
+fn logging_elsewhere(serial: Option<&str>) -> Option<PathBuf> {
+    let serial = serial.filter(|serial| !serial.is_empty())?;
+    let path = ipc::path(Some(serial));
+    ipc::is_running(&path).then_some(path)
+}
+
+#[cfg(not(unix))]
+fn logging_elsewhere(_serial: Option<&str>) -> Option<PathBuf> {
+    None
+}
+
+/// `skip_logging` leaves out meters another logger is reading. Not when reconnecting, the
+/// socket for the meter this logger lost is its own.
+pub fn open_ut61eplus(api: &HidApi, skip_logging: bool) -> Result<Meter, OpenError> {
+    let mut denied = Vec::new();
+    let mut busy = Vec::new();
+    let mut logging = Vec::new();
+    for info in api.device_list() {
+        let (vid, pid) = (info.vendor_id(), info.product_id());
+        if !DEVICE_IDS.contains(&(vid, pid)) {
+            continue;
+        }
+        if skip_logging && let Some(socket) = logging_elsewhere(info.serial_number()) {
+            logging.push(socket);
+            continue;
+        }
+        match api.open_path(info.path()) {
+            Ok(dev) => {
+                let meter = Meter::new(dev, quirks::for_device(vid, pid));
+                if meter.quirks.init_on_open
+                    && let Err(e) = meter.init_uart()
+                {
+                    eprintln!("Could not set up the {} UART: {}", meter.quirks.name, e);
+                }
+                eprintln!(
+                    "Opened UT61E+ with VID=0x{:04x}, PID=0x{:04x} ({}{})",
+                    vid,
+                    pid,
+                    meter.quirks.name,
+                    meter.serial.as_deref().map_or(String::new(), |s| format!(", serial {}", s))
+                );
+                return Ok(meter);
+            }
+            Err(e) => {
+                let path = info.path().to_string_lossy().into_owned();
+                if permission_denied(&path) {
+                    denied.push(path);
+                } else {
+                    busy.push((path, e));
+                }
+            }
+        }
+    }
+
+    if !logging.is_empty() {
+        Err(OpenError::Logging(logging))
+    } else if !denied.is_empty() {
+        Err(OpenError::PermissionDenied(denied))
+    } else if !busy.is_empty() {
+        Err(OpenError::Busy(busy))
+    } else {
+        Err(OpenError::NotFound)
+    }
+}
+
+/// How hard to try before deciding a HID error means the meter is gone. Writes and reads fail
+/// now and then (EPIPE, timeouts in the CP2110) without the device actually going anywhere.
+pub struct RetryPolicy {
+    pub attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn run<T>(&self, mut op: impl FnMut() -> Result<T, HidError>) -> Result<T, HidError> {
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Ok(v) => return Ok(v),
+                Err(e) if attempt >= self.attempts => return Err(e),
+                Err(_) => {
+                    attempt += 1;
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+            }
+        }
+    }
+}
+
+/// Where meters are opened from: the USB bus, or `--simulate`
+pub enum Source {
+    Usb(HidApi),
+    Simulated(Sim),
+}
+
+impl Source {
+    pub fn open(&self) -> Result<Meter, OpenError> {
+        self.open_meter(true)
+    }
+
+    fn open_meter(&self, skip_logging: bool) -> Result<Meter, OpenError> {
+        match self {
+            Source::Usb(api) => open_ut61eplus(api, skip_logging),
+            Source::Simulated(sim) => {
+                eprintln!("Simulating a UT61E+ ({})", sim);
+                Ok(Meter::with_transport(Box::new(SimTransport::new(sim.clone())), &quirks::CP2110, None))
+            }
+        }
+    }
+
+    /// Keeps trying to reopen the meter until it comes back
+    pub fn reconnect(&mut self) -> Meter {
+        loop {
+            thread::sleep(RECONNECT_INTERVAL);
+            if let Source::Usb(api) = self
+                && api.refresh_devices().is_err()
+            {
+                continue;
+            }
+            if let Ok(meter) = self.open_meter(false) {
+                return meter;
+            }
+        }
+    }
+}
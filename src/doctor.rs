@@ -0,0 +1,162 @@
+//! `doctor` subcommand, walks through everything that has to work before a reading comes back
+//! and prints what to do about the first thing that doesn't.
+
+use crate::meter::Meter;
+use crate::quirks::{self, DEVICE_IDS};
+use colored::*;
+use hidapi::{DeviceInfo, HidApi};
+
+fn pass(msg: &str) {
+    println!("{} {}", "✓".green(), msg);
+}
+
+fn fail(msg: &str) {
+    println!("{} {}", "✗".red(), msg.red());
+}
+
+fn hint(msg: &str) {
+    for line in msg.lines() {
+        println!("    {}", line);
+    }
+}
+
+/// The udev rule that gives everyone read/write access to the cable and keeps ModemManager off
+/// it, same as `99-ut61e-plus.rules` but for whichever VID/PID was actually found.
+fn udev_rule(vid: u16, pid: u16) -> String {
+    format!(
+        "ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\", MODE=\"0666\", ENV{{ID_MM_DEVICE_IGNORE}}=\"1\", ENV{{ID_MM_PORT_IGNORE}}=\"1\"",
+        vid, pid
+    )
+}
+
+/// Runs every check, returns false if any of them failed
+pub fn run() -> bool {
+    println!("{}", "UT61E+ doctor".bold());
+
+    let api = match HidApi::new() {
+        Ok(api) => {
+            pass("HID API initialised");
+            api
+        }
+        Err(e) => {
+            fail(&format!("Could not initialise the HID API: {}", e));
+            #[cfg(target_os = "linux")]
+            hint("Check that the hidraw kernel module is loaded (`lsmod | grep hid`).");
+            return false;
+        }
+    };
+
+    let found: Vec<&DeviceInfo> = api
+        .device_list()
+        .filter(|d| DEVICE_IDS.contains(&(d.vendor_id(), d.product_id())))
+        .collect();
+
+    if found.is_empty() {
+        let searched = DEVICE_IDS
+            .iter()
+            .map(|(vid, pid)| format!("{:04x}:{:04x}", vid, pid))
+            .collect::<Vec<_>>()
+            .join(", ");
+        fail(&format!("No UT61E+ USB cable found (looked for {})", searched));
+        hint("Check the cable is plugged in and shows up in `lsusb`. If it has a different VID:PID\nplease open an issue with the `lsusb` line so it can be added.");
+        #[cfg(windows)]
+        hint("If Device Manager lists the cable under \"Universal Serial Bus devices\" (WinUSB or\nlibusb, e.g. after using Zadig) instead of \"Human Interface Devices\", roll the driver back\nto the built-in HID driver.");
+        return false;
+    }
+
+    for info in &found {
+        pass(&format!(
+            "Found {} (VID=0x{:04x}, PID=0x{:04x}) at {}",
+            info.product_string().unwrap_or("USB cable"),
+            info.vendor_id(),
+            info.product_id(),
+            info.path().to_string_lossy()
+        ));
+    }
+
+    let mut ok = true;
+    #[cfg(target_os = "linux")]
+    {
+        ok &= check_permissions(&found);
+    }
+    ok &= test_read(&api, found[0]);
+    ok
+}
+
+/// hidraw nodes are root-only by default, so without the udev rule opening them fails
+#[cfg(target_os = "linux")]
+fn check_permissions(found: &[&DeviceInfo]) -> bool {
+    use std::fs::OpenOptions;
+    use std::io::ErrorKind;
+
+    const RULES_PATH: &str = "/etc/udev/rules.d/99-ut61e-plus.rules";
+
+    let mut ok = true;
+    for info in found {
+        let path = info.path().to_string_lossy();
+        // The libusb backend hands out bus:device:interface paths, nothing to check there
+        if !path.starts_with("/dev/") {
+            continue;
+        }
+        match OpenOptions::new().read(true).write(true).open(&*path) {
+            Ok(_) => pass(&format!("{} is readable and writable", path)),
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                ok = false;
+                fail(&format!("No permission to open {}", path));
+                if std::path::Path::new(RULES_PATH).exists() {
+                    hint(&format!("{} exists but hasn't been applied, try replugging the cable or", RULES_PATH));
+                    hint("sudo udevadm control --reload && sudo udevadm trigger");
+                } else {
+                    hint(&format!("Install this udev rule to {}:\n", RULES_PATH));
+                    hint(&udev_rule(info.vendor_id(), info.product_id()));
+                    hint("\nthen run\n\nsudo udevadm control --reload\nsudo udevadm trigger");
+                }
+            }
+            Err(e) => {
+                ok = false;
+                fail(&format!("Could not open {}: {}", path, e));
+            }
+        }
+    }
+    ok
+}
+
+/// Asks the meter for one measurement, which proves the UART side and the meter itself work
+fn test_read(api: &HidApi, info: &DeviceInfo) -> bool {
+    let dev = match api.open_path(info.path()) {
+        Ok(dev) => dev,
+        Err(e) => {
+            fail(&format!("Could not open the device: {}", e));
+            hint("Another program (another logger, or UNI-T's own software) may be holding it.");
+            return false;
+        }
+    };
+
+    let mut meter = Meter::new(dev, quirks::for_device(info.vendor_id(), info.product_id()));
+    pass(&format!("Opened the cable, it uses a {}", meter.quirks.name));
+    if meter.quirks.init_on_open {
+        match meter.init_uart() {
+            Ok(()) => pass("Set up the UART"),
+            Err(e) => {
+                fail(&format!("Could not set up the UART: {}", e));
+                return false;
+            }
+        }
+    }
+
+    match meter.read_response() {
+        Ok(Some(payload)) => {
+            pass(&format!("Meter answered with a {} byte measurement", payload.len()));
+            true
+        }
+        Err(e) => {
+            fail(&format!("Could not read from the meter: {}", e));
+            false
+        }
+        Ok(None) => {
+            fail("The cable opened but the meter didn't answer");
+            hint("Make sure the meter is switched on and the cable is pushed fully into the meter.");
+            false
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! `--encrypt-to`, for prototypes whose numbers mustn't sit on a lab PC in the clear: `-o` files
+//! are written as age files to one or more public keys (`age1...` from `age-keygen`), and read back
+//! with `age -d -i key.txt capture.csv`. Nothing on the logging machine can decrypt them unless the
+//! private key is there too.
+//!
+//! age seals the data in 64 kB chunks, so the file trails the readings by up to that much, and it
+//! only decrypts in full once the logger has stopped and sealed the last chunk, like Parquet.
+
+use age::x25519::Recipient;
+use age::stream::StreamWriter;
+use colored::*;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// An `age1...` public key, for clap
+pub fn recipient(s: &str) -> Result<Recipient, String> {
+    s.trim().parse().map_err(|e| format!("{:?} isn't an age public key (age1...): {}", s, e))
+}
+
+pub struct Encrypted {
+    /// Taken to seal the last chunk when it's dropped
+    stream: Option<StreamWriter<File>>,
+}
+
+impl Encrypted {
+    pub fn create(path: &Path, recipients: &[Recipient]) -> io::Result<Self> {
+        let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient)).map_err(io::Error::other)?;
+        let stream = encryptor.wrap_output(File::create(path)?)?;
+        Ok(Encrypted { stream: Some(stream) })
+    }
+
+    fn stream(&mut self) -> &mut StreamWriter<File> {
+        self.stream.as_mut().expect("only taken on drop")
+    }
+}
+
+impl Write for Encrypted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream().flush()
+    }
+}
+
+/// The sinks only get to flush their output, sealing it has to happen when they let go of it
+impl Drop for Encrypted {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take()
+            && let Err(e) = stream.finish().and_then(|file| file.sync_all())
+        {
+            eprintln!("{}", format!("Finishing an encrypted output failed: {}", e).red());
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Process exit codes, so scripts wrapping the logger can tell failures apart. Anything else
+//! that goes wrong (writing the output, bad arguments) exits with 1, or 2 for clap's usage errors.
+
+pub const HID_UNAVAILABLE: i32 = 3;
+pub const DEVICE_NOT_FOUND: i32 = 4;
+pub const PERMISSION_DENIED: i32 = 5;
+pub const DEVICE_BUSY: i32 = 6;
+pub const ALARM_TRIPPED: i32 = 7;
+pub const CHECKSUM_STORM: i32 = 8;
+pub const TOO_MANY_ERRORS: i32 = 9;
+/// Ctrl-C, 128 + SIGINT like a shell would report it
+pub const USER_ABORT: i32 = 130;
+
+pub const ALL: &[(i32, &str)] = &[
+    (0, "success"),
+    (1, "other error, e.g. the output couldn't be written"),
+    (2, "bad command line arguments"),
+    (HID_UNAVAILABLE, "the HID library couldn't be initialised"),
+    (DEVICE_NOT_FOUND, "no UT61E+ cable found"),
+    (PERMISSION_DENIED, "found the cable but no permission to open it"),
+    (DEVICE_BUSY, "found the cable but it's in use"),
+    (ALARM_TRIPPED, "an alarm or limit test failed"),
+    (CHECKSUM_STORM, "too many corrupt frames in a row, usually a bad cable or interference"),
+    (TOO_MANY_ERRORS, "--max-errors failed polls in a row with --on-max-errors abort"),
+    (USER_ABORT, "stopped with Ctrl-C"),
+];
+
+/// The table above for the end of `--help`
+pub fn help() -> String {
+    let mut help = String::from("Exit codes:\n");
+    for (code, meaning) in ALL {
+        help.push_str(&format!("  {:>3}  {}\n", code, meaning));
+    }
+    help
+}
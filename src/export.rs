@@ -0,0 +1,335 @@
+//! `-o capture.mat` and `-o capture.npz` (or `.npy`), the readings as arrays for MATLAB and NumPy
+//! so nobody has to write another CSV parser. Both formats want the length up front, so the
+//! readings are kept in memory and the file is written when logging stops, like Parquet.
+//!
+//! The arrays are `time` (POSIX seconds), `elapsed`, `value` (NaN for OL), `unit` and `mode`, one
+//! element per reading, `event_time` and `event` for the events, and `meta` with the meter, the
+//! command line, the start, the logger's version and [`FORMAT_VERSION`]. A .mat is MATLAB v5 with
+//! `meta` as a struct and the text as char matrices (`cellstr(mode)`), a .npz one .npy per array
+//! with `meta` as a record, and a lone .npy a single record array of the readings without the
+//! events or `meta`.
+
+use crate::clock::{Stamp, Utc};
+use crate::output::{FORMAT_VERSION, Session};
+use crate::sink::Sink;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Mat,
+    Npy,
+    Npz,
+}
+
+pub struct Arrays {
+    out: BufWriter<File>,
+    kind: Kind,
+    session: Session,
+    units: Units,
+    time: Vec<f64>,
+    elapsed: Vec<f64>,
+    value: Vec<f64>,
+    unit: Vec<&'static str>,
+    mode: Vec<&'static str>,
+    event_time: Vec<f64>,
+    event: Vec<String>,
+}
+
+impl Arrays {
+    /// A .mat, or NumPy going by the extension, .npy for one array or else .npz
+    pub fn create(path: &Path, mat: bool, units: Units, session: Session) -> io::Result<Self> {
+        let kind = match (mat, path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("npy"))) {
+            (true, _) => Kind::Mat,
+            (false, true) => Kind::Npy,
+            (false, false) => Kind::Npz,
+        };
+        let out = BufWriter::new(File::create(path)?);
+        Ok(Arrays {
+            out,
+            kind,
+            session,
+            units,
+            time: Vec::new(),
+            elapsed: Vec::new(),
+            value: Vec::new(),
+            unit: Vec::new(),
+            mode: Vec::new(),
+            event_time: Vec::new(),
+            event: Vec::new(),
+        })
+    }
+
+    /// The metadata, as (name, text)
+    fn meta(&self) -> [(&'static str, String); 5] {
+        [
+            ("meter", self.session.meter.clone()),
+            ("command", self.session.command.clone()),
+            ("started", Utc(self.session.started).to_string()),
+            ("version", format!("ut61e_plus_logger {}", env!("CARGO_PKG_VERSION"))),
+            ("format_version", FORMAT_VERSION.to_string()),
+        ]
+    }
+}
+
+fn posix(stamp: &Stamp) -> f64 {
+    stamp.wall.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+impl Sink for Arrays {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        let scaled = m.reading_in(self.units);
+        self.time.push(posix(stamp));
+        self.elapsed.push(stamp.elapsed.as_secs_f64());
+        self.value.push(scaled.reading.value().unwrap_or(f64::NAN));
+        self.unit.push(scaled.unit);
+        self.mode.push(m.mode_name());
+        Ok(())
+    }
+
+    fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.event_time.push(posix(stamp));
+        self.event.push(message.to_owned());
+        Ok(())
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        self.event(stamp, &format!("gap: {}", reason))
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        let file = match self.kind {
+            Kind::Mat => self.mat(),
+            Kind::Npy => self.npy(),
+            Kind::Npz => self.npz(),
+        };
+        self.out.write_all(&file)?;
+        self.out.flush()
+    }
+}
+
+// MATLAB level 5 MAT-file, as in "MAT-File Format" (MathWorks), uncompressed
+
+const MI_INT8: u32 = 1;
+const MI_UINT16: u32 = 4;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_DOUBLE: u32 = 9;
+const MI_MATRIX: u32 = 14;
+const MX_STRUCT_CLASS: u32 = 2;
+const MX_CHAR_CLASS: u32 = 4;
+const MX_DOUBLE_CLASS: u32 = 6;
+/// Longest field name of a struct, with the NUL
+const FIELD_NAME: usize = 32;
+
+/// A data element: tag, data, padding to 8 bytes
+fn element(out: &mut Vec<u8>, data_type: u32, data: &[u8]) {
+    out.extend(data_type.to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend(data);
+    out.resize(out.len().next_multiple_of(8), 0);
+}
+
+/// An array, its name empty for the fields of a struct
+fn matrix(out: &mut Vec<u8>, name: &str, class: u32, dims: [usize; 2], body: &[u8]) {
+    let mut inner = Vec::new();
+    element(&mut inner, MI_UINT32, &[class.to_le_bytes(), [0; 4]].concat());
+    element(&mut inner, MI_INT32, &dims.iter().flat_map(|&d| (d as i32).to_le_bytes()).collect::<Vec<_>>());
+    element(&mut inner, MI_INT8, name.as_bytes());
+    inner.extend(body);
+    element(out, MI_MATRIX, &inner);
+}
+
+fn doubles(out: &mut Vec<u8>, name: &str, values: &[f64]) {
+    let mut body = Vec::new();
+    element(&mut body, MI_DOUBLE, &values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>());
+    matrix(out, name, MX_DOUBLE_CLASS, [values.len(), 1], &body);
+}
+
+/// A row per string padded with spaces, column-major like everything in MATLAB
+fn chars<S: AsRef<str>>(out: &mut Vec<u8>, name: &str, rows: &[S]) {
+    let rows: Vec<Vec<u16>> = rows.iter().map(|row| row.as_ref().encode_utf16().collect()).collect();
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut data = Vec::with_capacity(rows.len() * width * 2);
+    for column in 0..width {
+        for row in &rows {
+            data.extend(row.get(column).copied().unwrap_or(u16::from(b' ')).to_le_bytes());
+        }
+    }
+    let mut body = Vec::new();
+    element(&mut body, MI_UINT16, &data);
+    matrix(out, name, MX_CHAR_CLASS, [rows.len(), width], &body);
+}
+
+impl Arrays {
+    fn mat(&self) -> Vec<u8> {
+        let mut file = format!("MATLAB 5.0 MAT-file, Platform: {}, Created on: {}, ut61e_plus_logger", std::env::consts::OS, Utc(self.session.started)).into_bytes();
+        file.resize(116, b' ');
+        file.extend([0; 8]);
+        file.extend(0x0100u16.to_le_bytes());
+        file.extend(b"IM");
+
+        doubles(&mut file, "time", &self.time);
+        doubles(&mut file, "elapsed", &self.elapsed);
+        doubles(&mut file, "value", &self.value);
+        chars(&mut file, "unit", &self.unit);
+        chars(&mut file, "mode", &self.mode);
+        doubles(&mut file, "event_time", &self.event_time);
+        chars(&mut file, "event", &self.event);
+
+        let meta = self.meta();
+        let mut body = Vec::new();
+        element(&mut body, MI_INT32, &(FIELD_NAME as i32).to_le_bytes());
+        let mut names = Vec::new();
+        for (name, _) in &meta {
+            names.extend(name.as_bytes());
+            names.resize(names.len().next_multiple_of(FIELD_NAME), 0);
+        }
+        element(&mut body, MI_INT8, &names);
+        for (_, text) in &meta {
+            chars(&mut body, "", &[text]);
+        }
+        matrix(&mut file, "meta", MX_STRUCT_CLASS, [1, 1], &body);
+        file
+    }
+}
+
+// NumPy .npy format version 1.0, and .npz as a zip of them without compression
+
+/// The magic, the header padded to a multiple of 64 bytes, and no data yet
+fn npy_header(descr: &str, shape: &str) -> Vec<u8> {
+    let mut header = format!("{{'descr': {}, 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    let total = (10 + header.len() + 1).next_multiple_of(64);
+    while 10 + header.len() + 1 < total {
+        header.push(' ');
+    }
+    header.push('\n');
+    let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    npy.extend((header.len() as u16).to_le_bytes());
+    npy.extend(header.as_bytes());
+    npy
+}
+
+/// Fixed width UTF-32, `<U{width}`, padded with NULs
+fn utf32(out: &mut Vec<u8>, s: &str, width: usize) {
+    let mut n = 0;
+    for c in s.chars().take(width) {
+        out.extend((c as u32).to_le_bytes());
+        n += 1;
+    }
+    out.resize(out.len() + (width - n) * 4, 0);
+}
+
+fn width<S: AsRef<str>>(strings: &[S]) -> usize {
+    strings.iter().map(|s| s.as_ref().chars().count()).max().unwrap_or(0).max(1)
+}
+
+fn npy_doubles(values: &[f64]) -> Vec<u8> {
+    let mut npy = npy_header("'<f8'", &format!("({},)", values.len()));
+    npy.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+    npy
+}
+
+fn npy_strings<S: AsRef<str>>(strings: &[S]) -> Vec<u8> {
+    let width = width(strings);
+    let mut npy = npy_header(&format!("'<U{}'", width), &format!("({},)", strings.len()));
+    for s in strings {
+        utf32(&mut npy, s.as_ref(), width);
+    }
+    npy
+}
+
+impl Arrays {
+    /// One record per reading
+    fn npy(&self) -> Vec<u8> {
+        let (unit, mode) = (width(&self.unit), width(&self.mode));
+        let descr = format!("[('time', '<f8'), ('elapsed', '<f8'), ('value', '<f8'), ('unit', '<U{}'), ('mode', '<U{}')]", unit, mode);
+        let mut npy = npy_header(&descr, &format!("({},)", self.time.len()));
+        for i in 0..self.time.len() {
+            for v in [self.time[i], self.elapsed[i], self.value[i]] {
+                npy.extend(v.to_le_bytes());
+            }
+            utf32(&mut npy, self.unit[i], unit);
+            utf32(&mut npy, self.mode[i], mode);
+        }
+        npy
+    }
+
+    fn npz(&self) -> Vec<u8> {
+        let meta = self.meta();
+        let widths: Vec<usize> = meta.iter().map(|(_, text)| width(&[text])).collect();
+        let descr = meta.iter().zip(&widths).map(|((name, _), width)| format!("('{}', '<U{}')", name, width)).collect::<Vec<_>>().join(", ");
+        let mut record = npy_header(&format!("[{}]", descr), "()");
+        for ((_, text), &width) in meta.iter().zip(&widths) {
+            utf32(&mut record, text, width);
+        }
+        zip(&[
+            ("time.npy", npy_doubles(&self.time)),
+            ("elapsed.npy", npy_doubles(&self.elapsed)),
+            ("value.npy", npy_doubles(&self.value)),
+            ("unit.npy", npy_strings(&self.unit)),
+            ("mode.npy", npy_strings(&self.mode)),
+            ("event_time.npy", npy_doubles(&self.event_time)),
+            ("event.npy", npy_strings(&self.event)),
+            ("meta.npy", record),
+        ])
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A zip with every file stored as is, which is all `numpy.load` needs
+fn zip(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    // 1980-01-01 00:00, the earliest a zip can say
+    const DATE: u16 = 0x21;
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in files {
+        let (crc, size, offset) = (crc32(data), data.len() as u32, out.len() as u32);
+        let common = |out: &mut Vec<u8>| {
+            out.extend(20u16.to_le_bytes()); // version needed, 2.0
+            out.extend(0u16.to_le_bytes()); // flags
+            out.extend(0u16.to_le_bytes()); // stored
+            out.extend(0u16.to_le_bytes()); // time
+            out.extend(DATE.to_le_bytes());
+            out.extend(crc.to_le_bytes());
+            out.extend(size.to_le_bytes());
+            out.extend(size.to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend(0u16.to_le_bytes()); // extra field
+        };
+        out.extend(0x0403_4b50u32.to_le_bytes());
+        common(&mut out);
+        out.extend(name.as_bytes());
+        out.extend(data);
+
+        directory.extend(0x0201_4b50u32.to_le_bytes());
+        directory.extend(20u16.to_le_bytes()); // made by
+        common(&mut directory);
+        directory.extend([0; 10]); // comment length, disk, internal and external attributes
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+    let (start, length) = (out.len() as u32, directory.len() as u32);
+    out.extend(directory);
+    out.extend(0x0605_4b50u32.to_le_bytes());
+    out.extend([0; 4]); // this disk, the disk the directory starts on
+    out.extend((files.len() as u16).to_le_bytes());
+    out.extend((files.len() as u16).to_le_bytes());
+    out.extend(length.to_le_bytes());
+    out.extend(start.to_le_bytes());
+    out.extend(0u16.to_le_bytes()); // comment length
+    out
+}
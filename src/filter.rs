@@ -0,0 +1,524 @@
+//! `--filter 'value > 0.5 && mode == "V_DC"'`, which readings get written. Readings it turns
+//! down are still counted in the summary and checked by `--alarm`, they're just left out of the
+//! outputs together with anything derived from them, so the idle stretches between touching the
+//! probes to test points don't fill the capture. Events and gaps always go through.
+//!
+//! The expression has the fields `value` (in the unit without a prefix, like `--limit-low`),
+//! `elapsed` (seconds since logging started), `unit`, `mode`, `display`, `range` and `minmax` as
+//! in the CSV, and `rel`, `hold` and `ol` (an overload or dashes, where `value` has no number).
+//! They go with numbers, strings in double quotes (`\"` and `\\` escaped), `true`/`false`,
+//! `+ - * / ^`, `< <= > >= == !=`, `&& || !` and brackets. `^` goes right to left and before a
+//! minus in front, so `-2^2` is -4 and `2^3^2` is 512 as on paper. It's checked when it's given,
+//! so `mode > 3` is an error up front rather than nothing ever being written. Comparing `value`
+//! of an overload with a number is false.
+
+use crate::clock::Stamp;
+use std::fmt;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Type {
+    Number,
+    Text,
+    Bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Value,
+    Elapsed,
+    Unit,
+    Mode,
+    Display,
+    Range,
+    MinMax,
+    Rel,
+    Hold,
+    Ol,
+}
+
+impl Field {
+    fn named(name: &str) -> Option<Field> {
+        Some(match name {
+            "value" => Field::Value,
+            "elapsed" => Field::Elapsed,
+            "unit" => Field::Unit,
+            "mode" => Field::Mode,
+            "display" => Field::Display,
+            "range" => Field::Range,
+            "minmax" => Field::MinMax,
+            "rel" => Field::Rel,
+            "hold" => Field::Hold,
+            "ol" => Field::Ol,
+            _ => return None,
+        })
+    }
+
+    fn kind(self) -> Type {
+        match self {
+            Field::Value | Field::Elapsed => Type::Number,
+            Field::Unit | Field::Mode | Field::Display | Field::Range | Field::MinMax => Type::Text,
+            Field::Rel | Field::Hold | Field::Ol => Type::Bool,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Field(Field),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+/// What an expression comes to for one reading, `None` for the value of an overload
+#[derive(PartialEq, Debug)]
+enum Value {
+    Number(Option<f64>),
+    Text(String),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: Expr,
+    /// As it was given, for messages
+    text: String,
+}
+
+impl Filter {
+    /// For clap
+    pub fn parse(s: &str) -> Result<Filter, String> {
+        let tokens = tokens(s)?;
+        let mut parser = Parser { tokens: &tokens, at: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {} in {:?}", token, s));
+        }
+        match kind(&expr)? {
+            Type::Bool => Ok(Filter { expr, text: s.trim().to_owned() }),
+            other => Err(format!("{:?} is a {}, not true or false", s, name(other))),
+        }
+    }
+
+    pub fn matches(&self, stamp: &Stamp, m: &Measurement) -> bool {
+        eval(&self.expr, stamp, m) == Value::Bool(true)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn name(kind: Type) -> &'static str {
+    match kind {
+        Type::Number => "number",
+        Type::Text => "string",
+        Type::Bool => "true/false",
+    }
+}
+
+/// Checks the types fit together, and what the expression comes to
+fn kind(expr: &Expr) -> Result<Type, String> {
+    let expect = |expr: &Expr, wanted: Type, what: &str| match kind(expr)? {
+        found if found == wanted => Ok(()),
+        found => Err(format!("{} needs a {}, got a {}", what, name(wanted), name(found))),
+    };
+    Ok(match expr {
+        Expr::Number(_) => Type::Number,
+        Expr::Text(_) => Type::Text,
+        Expr::Bool(_) => Type::Bool,
+        Expr::Field(field) => field.kind(),
+        Expr::Not(inner) => {
+            expect(inner, Type::Bool, "!")?;
+            Type::Bool
+        }
+        Expr::Neg(inner) => {
+            expect(inner, Type::Number, "-")?;
+            Type::Number
+        }
+        Expr::Binary(op, a, b) => match op {
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                expect(a, Type::Number, "arithmetic")?;
+                expect(b, Type::Number, "arithmetic")?;
+                Type::Number
+            }
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+                expect(a, Type::Number, "<, <=, > and >=")?;
+                expect(b, Type::Number, "<, <=, > and >=")?;
+                Type::Bool
+            }
+            Op::Eq | Op::Ne => {
+                let (a, b) = (kind(a)?, kind(b)?);
+                if a != b {
+                    return Err(format!("can't compare a {} with a {}", name(a), name(b)));
+                }
+                Type::Bool
+            }
+            Op::And | Op::Or => {
+                expect(a, Type::Bool, "&& and ||")?;
+                expect(b, Type::Bool, "&& and ||")?;
+                Type::Bool
+            }
+        },
+    })
+}
+
+fn field(field: Field, stamp: &Stamp, m: &Measurement) -> Value {
+    match field {
+        Field::Value => Value::Number(m.reading_in(Units::Si).reading.value()),
+        Field::Elapsed => Value::Number(Some(stamp.elapsed.as_secs_f64())),
+        Field::Unit => Value::Text(m.reading_in(Units::Si).unit.to_owned()),
+        Field::Mode => Value::Text(m.mode_name().to_owned()),
+        Field::Display => Value::Text(m.reading().to_string()),
+        Field::Range => Value::Text(m.range_mode().to_owned()),
+        Field::MinMax => Value::Text(m.min_max().to_owned()),
+        Field::Rel => Value::Bool(m.rel),
+        Field::Hold => Value::Bool(m.hold),
+        Field::Ol => Value::Bool(m.reading().value().is_none()),
+    }
+}
+
+/// Only called on expressions that passed [`kind`]
+fn eval(expr: &Expr, stamp: &Stamp, m: &Measurement) -> Value {
+    let number = |expr: &Expr| match eval(expr, stamp, m) {
+        Value::Number(n) => n,
+        _ => None,
+    };
+    let truth = |expr: &Expr| eval(expr, stamp, m) == Value::Bool(true);
+    match expr {
+        Expr::Number(n) => Value::Number(Some(*n)),
+        Expr::Text(text) => Value::Text(text.clone()),
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Field(f) => field(*f, stamp, m),
+        Expr::Not(inner) => Value::Bool(!truth(inner)),
+        Expr::Neg(inner) => Value::Number(number(inner).map(|n| -n)),
+        Expr::Binary(Op::And, a, b) => Value::Bool(truth(a) && truth(b)),
+        Expr::Binary(Op::Or, a, b) => Value::Bool(truth(a) || truth(b)),
+        Expr::Binary(op @ (Op::Eq | Op::Ne), a, b) => {
+            let same = eval(a, stamp, m) == eval(b, stamp, m);
+            Value::Bool(same == (*op == Op::Eq))
+        }
+        Expr::Binary(op, a, b) => {
+            let (Some(a), Some(b)) = (number(a), number(b)) else {
+                // Anything with an overload in it
+                return match op {
+                    Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => Value::Number(None),
+                    _ => Value::Bool(false),
+                };
+            };
+            match op {
+                Op::Add => Value::Number(Some(a + b)),
+                Op::Sub => Value::Number(Some(a - b)),
+                Op::Mul => Value::Number(Some(a * b)),
+                Op::Div => Value::Number(Some(a / b)),
+                Op::Pow => Value::Number(Some(a.powf(b))),
+                Op::Lt => Value::Bool(a < b),
+                Op::Le => Value::Bool(a <= b),
+                Op::Gt => Value::Bool(a > b),
+                _ => Value::Bool(a >= b),
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Word(String),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Text(text) => write!(f, "{:?}", text),
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 17] = ["&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "+", "-", "*", "/", "^", "(", ")", "="];
+
+fn tokens(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() || c == '.' {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_')).unwrap_or(rest.len());
+            // 1e-3 has a sign in it
+            let end = match rest[..end].ends_with(['e', 'E']) && rest[end..].starts_with(['-', '+']) {
+                true => end + 1 + rest[end + 1..].find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len() - end - 1),
+                false => end,
+            };
+            let number = &rest[..end];
+            tokens.push(Token::Number(number.parse().map_err(|_| format!("bad number {:?}", number))?));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_owned()));
+            rest = &rest[end..];
+        } else if c == '"' {
+            let mut text = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c @ ('"' | '\\'))) => text.push(c),
+                        _ => return Err("only \\\" and \\\\ can be escaped in a string".to_owned()),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => return Err(format!("a string isn't closed in {:?}", s)),
+                }
+            };
+            tokens.push(Token::Text(text));
+            rest = &rest[end..];
+        } else {
+            let symbol = SYMBOLS.iter().find(|&&symbol| rest.starts_with(symbol)).ok_or_else(|| format!("unexpected {:?} in {:?}", c, s))?;
+            if *symbol == "=" {
+                return Err("use == to compare".to_owned());
+            }
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent, loosest first: ||, &&, comparisons, + -, * /, ! -, ^, then what's left
+struct Parser<'a> {
+    tokens: &'a [Token],
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.at)
+    }
+
+    /// Takes the next token if it's one of these symbols
+    fn symbol(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                self.at += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, symbols: &[&'static str], next: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        let mut expr = next(self)?;
+        while let Some(symbol) = self.symbol(symbols) {
+            let op = match symbol {
+                "||" => Op::Or,
+                "&&" => Op::And,
+                "+" => Op::Add,
+                "-" => Op::Sub,
+                "*" => Op::Mul,
+                _ => Op::Div,
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(next(self)?));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(&["||"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&["&&"], Self::comparison)
+    }
+
+    /// Only one, `1 < value < 2` doesn't mean what it looks like
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let a = self.sum()?;
+        let Some(symbol) = self.symbol(&["<", "<=", ">", ">=", "==", "!="]) else {
+            return Ok(a);
+        };
+        let op = match symbol {
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "==" => Op::Eq,
+            _ => Op::Ne,
+        };
+        Ok(Expr::Binary(op, Box::new(a), Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(&["+", "-"], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        self.binary(&["*", "/"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.symbol(&["!", "-"]) {
+            Some("!") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(_) => Ok(Expr::Neg(Box::new(self.unary()?))),
+            None => self.power(),
+        }
+    }
+
+    /// The exponent can have a minus of its own, and another `^`, which is what makes it go
+    /// right to left
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.atom()?;
+        match self.symbol(&["^"]) {
+            Some(_) => Ok(Expr::Binary(Op::Pow, Box::new(base), Box::new(self.unary()?))),
+            None => Ok(base),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned().ok_or("the expression ends too soon")?;
+        self.at += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Text(text) => Ok(Expr::Text(text)),
+            Token::Word(word) => match word.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                _ => Field::named(&word).map(Expr::Field).ok_or_else(|| {
+                    format!("unknown field {:?}, there's value, elapsed, unit, mode, display, range, minmax, rel, hold and ol", word)
+                }),
+            },
+            Token::Symbol("(") => {
+                let expr = self.or()?;
+                match self.symbol(&[")"]) {
+                    Some(_) => Ok(expr),
+                    None => Err("a bracket isn't closed".to_owned()),
+                }
+            }
+            Token::Symbol(symbol) => Err(format!("unexpected {}", symbol)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// V DC on the 2.2000 V range
+    fn reading(display: &[u8; 7]) -> Measurement {
+        Measurement {
+            mode: 2,
+            range: 0x30,
+            display: *display,
+            bar: [0; 2],
+            rel: false,
+            hold: false,
+            min: false,
+            max: false,
+            auto_range: true,
+            low_battery: false,
+            hv_warning: false,
+            dc: true,
+            peak_max: false,
+            peak_min: false,
+        }
+    }
+
+    fn matches(expr: &str, m: &Measurement) -> bool {
+        let stamp = Stamp { wall: SystemTime::UNIX_EPOCH, elapsed: Duration::from_secs(10), seq: 1 };
+        Filter::parse(expr).unwrap_or_else(|e| panic!("{}: {}", expr, e)).matches(&stamp, m)
+    }
+
+    fn is_true(expr: &str) -> bool {
+        matches(expr, &reading(b" 1.2345"))
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(is_true("1 + 2 * 3 == 7"));
+        assert!(is_true("(1 + 2) * 3 == 9"));
+        assert!(is_true("2 * 3 ^ 2 == 18"));
+        assert!(is_true("-2 ^ 2 == -4"));
+        assert!(is_true("2 ^ -1 == 0.5"));
+        assert!(is_true("value * 1000 > 1234 && value < 1.3"));
+        assert!(is_true("true || true && false"));
+        assert!(!is_true("!false && false"));
+        assert!(is_true("-(1 - 3) == 2"));
+    }
+
+    #[test]
+    fn associativity() {
+        assert!(is_true("10 - 4 - 3 == 3"));
+        assert!(is_true("8 / 4 / 2 == 1"));
+        assert!(is_true("2 ^ 3 ^ 2 == 512"));
+        assert!(is_true("1e-3 * 1e3 == 1"));
+        assert!(Filter::parse("1 < value < 2").is_err());
+    }
+
+    #[test]
+    fn strings() {
+        assert!(is_true(r#"mode == "V_DC" && unit == "V" && display == "1.2345""#));
+        assert!(is_true(r#"mode != "mV_DC""#));
+        assert_eq!(tokens(r#""a\"b\\c""#), Ok(vec![Token::Text(r#"a"b\c"#.to_owned())]));
+        assert!(is_true(r#""say \"hi\"" == "say \"hi\"""#));
+        assert!(Filter::parse(r#"mode == "V\n""#).is_err());
+        assert!(Filter::parse(r#"mode == "V_DC"#).is_err());
+    }
+
+    #[test]
+    fn type_errors() {
+        for expr in ["mode > 3", "value", "value + \"x\" > 1", "!value", "-mode == \"x\"", "rel == 1", "value && true", "mode = \"V_DC\"", "", "(value > 1", "value > 1 )", "value >"] {
+            assert!(Filter::parse(expr).is_err(), "{:?} should be an error", expr);
+        }
+    }
+
+    #[test]
+    fn overloads() {
+        for display in [b"  OL.  ", b"  -OL  ", b" ----  "] {
+            let m = reading(display);
+            assert!(matches("ol", &m));
+            assert!(!matches("value > 0", &m));
+            assert!(!matches("value < 0", &m));
+            assert!(!matches("value + 1 > 0", &m));
+            assert!(!matches("-value <= 0", &m));
+            assert!(matches("!(value > 0)", &m));
+            assert!(matches("ol || value > 0", &m));
+        }
+        assert!(!is_true("ol"));
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let e = Filter::parse("voltage > 1").unwrap_err();
+        assert!(e.contains("unknown field \"voltage\""), "{}", e);
+        assert!(Filter::parse("value > 1 && Mode == \"V_DC\"").is_err());
+        assert!(Filter::parse("value > 1 # comment").is_err());
+    }
+}
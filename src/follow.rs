@@ -0,0 +1,79 @@
+//! The readings of another logger, for values that need a second meter like the power with
+//! `--power-with`. A thread follows the other logger's CSV over its attach socket (see `ipc`)
+//! and keeps its latest reading, connecting again whenever that logger stops or isn't running
+//! yet.
+
+#[cfg(unix)]
+use std::io::{BufRead, BufReader};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use ut61e_plus_logger::protocol::without_prefix;
+
+/// The other logger is tried again this often while it isn't running
+#[cfg(unix)]
+const RETRY: Duration = Duration::from_secs(1);
+
+/// A reading in the unit without a prefix
+#[derive(Clone, Copy)]
+pub struct Latest {
+    pub value: f64,
+    pub unit: &'static str,
+    at: Instant,
+}
+
+pub struct Follow {
+    latest: Arc<Mutex<Option<Latest>>>,
+}
+
+impl Follow {
+    /// `other` is the other logger's socket, or the serial number of its meter
+    pub fn start(other: &str) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let (other, shared) = (other.to_owned(), latest.clone());
+        thread::spawn(move || follow(&other, &shared));
+        Follow { latest }
+    }
+
+    /// The other meter's reading, unless there's none from the last `max_age`
+    pub fn latest(&self, max_age: Duration) -> Option<Latest> {
+        self.latest.lock().unwrap().filter(|latest| latest.at.elapsed() <= max_age)
+    }
+}
+
+#[cfg(unix)]
+fn follow(other: &str, latest: &Mutex<Option<Latest>>) {
+    let path = match other.contains('/') {
+        true => std::path::PathBuf::from(other),
+        false => crate::ipc::path(Some(other)),
+    };
+    loop {
+        if let Ok(stream) = UnixStream::connect(&path) {
+            let mut lines = BufReader::new(stream).lines().map_while(Result::ok);
+            let header = lines.next().unwrap_or_default();
+            let column = |name: &str| header.split(',').position(|c| c == name);
+            let (value, unit, range) = (column("value"), column("unit"), column("range"));
+            for line in lines {
+                let fields: Vec<&str> = line.split(',').collect();
+                let get = |i: Option<usize>| i.and_then(|i| fields.get(i)).copied().unwrap_or("");
+                match get(value) {
+                    _ if get(range) == "DERIVED" => {}
+                    "EVENT" => {}
+                    v => {
+                        let reading = v.parse::<f64>().ok().zip(without_prefix(get(unit)));
+                        *latest.lock().unwrap() = reading.map(|(v, (exp, unit))| Latest { value: v * 10f64.powi(exp), unit, at: Instant::now() });
+                    }
+                }
+            }
+            *latest.lock().unwrap() = None;
+        }
+        thread::sleep(RETRY);
+    }
+}
+
+/// There's no following another logger without Unix sockets
+#[cfg(not(unix))]
+fn follow(_other: &str, _latest: &Mutex<Option<Latest>>) {}
@@ -0,0 +1,66 @@
+//! The last so many minutes of readings kept in memory, looked up by time. For anything showing
+//! more than the newest reading (a plot being zoomed out, `/history` on the web dashboard)
+//! without going back to the log file.
+
+use alloc::collections::VecDeque;
+use core::ops::{Bound, RangeBounds};
+use core::time::Duration;
+
+/// Entries of any kind `T`, each at a time since some fixed start (e.g. when logging started),
+/// oldest first
+pub struct History<T> {
+    entries: VecDeque<(Duration, T)>,
+    keep: Duration,
+}
+
+impl<T> History<T> {
+    /// Keeps entries up to `keep` older than the newest one
+    pub fn new(keep: Duration) -> Self {
+        History { entries: VecDeque::new(), keep }
+    }
+
+    /// Adds an entry and forgets what's fallen out of the window. Times have to come in order,
+    /// an entry older than the newest is put at the end anyway.
+    pub fn push(&mut self, time: Duration, entry: T) {
+        let time = self.entries.back().map_or(time, |&(newest, _)| time.max(newest));
+        self.entries.push_back((time, entry));
+        while let Some(&(oldest, _)) = self.entries.front()
+            && time.saturating_sub(oldest) > self.keep
+        {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Entries with their times in `range`, e.g. `history.range(t0..t1)`, oldest first
+    pub fn range(&self, range: impl RangeBounds<Duration>) -> impl DoubleEndedIterator<Item = &(Duration, T)> {
+        let start = match range.start_bound() {
+            Bound::Included(t) => self.entries.partition_point(|(time, _)| time < t),
+            Bound::Excluded(t) => self.entries.partition_point(|(time, _)| time <= t),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(t) => self.entries.partition_point(|(time, _)| time <= t),
+            Bound::Excluded(t) => self.entries.partition_point(|(time, _)| time < t),
+            Bound::Unbounded => self.entries.len(),
+        };
+        self.entries.range(start..end.max(start))
+    }
+
+    /// The newest `n` entries, oldest first
+    pub fn latest(&self, n: usize) -> impl DoubleEndedIterator<Item = &(Duration, T)> {
+        self.entries.range(self.entries.len().saturating_sub(n)..)
+    }
+
+    /// Removes the oldest `n` entries and hands them over, e.g. to move them somewhere else
+    pub fn take_oldest(&mut self, n: usize) -> impl Iterator<Item = (Duration, T)> + '_ {
+        self.entries.drain(..n.min(self.entries.len()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
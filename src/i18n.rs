@@ -0,0 +1,250 @@
+//! Translations of the messages printed for a person at the terminal. The English text is the
+//! key, like gettext, so a message nobody has translated yet just comes out in English. What
+//! goes into captures (events, gap reasons, column names) stays English so logs from anywhere
+//! read the same.
+
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    En,
+    De,
+    Zh,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// From `LC_ALL`, `LC_MESSAGES` or `LANG` the way the C library picks, e.g. `de_DE.UTF-8`
+pub fn from_env() -> Lang {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_default();
+    match locale.get(..2) {
+        Some("de") => Lang::De,
+        Some("zh") => Lang::Zh,
+        _ => Lang::En,
+    }
+}
+
+/// Picks the language for the rest of the run, the environment's if this is never called
+pub fn set(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+fn catalog() -> &'static [(&'static str, &'static str)] {
+    match LANG.get_or_init(from_env) {
+        Lang::En => &[],
+        Lang::De => DE,
+        Lang::Zh => ZH,
+    }
+}
+
+/// `english` in the chosen language
+pub fn translate(english: &'static str) -> &'static str {
+    catalog().iter().find(|(en, _)| *en == english).map_or(english, |(_, translated)| translated)
+}
+
+/// Puts `args` into the `{}`s of a translated template in order, since `format!` only takes
+/// literals. Numbers that need a precision are formatted before they're passed in.
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut parts = template.split("{}");
+    out.push_str(parts.next().unwrap_or_default());
+    for part in parts {
+        if let Some(arg) = args.next() {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+/// `tr!("Copied {}", text)` is the translated message with `text` filled in
+macro_rules! tr {
+    ($english:literal) => {
+        $crate::i18n::translate($english)
+    };
+    ($english:literal, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::fill($crate::i18n::translate($english), &[$(&$arg),+])
+    };
+}
+pub(crate) use tr;
+
+const DE: &[(&str, &str)] = &[
+    ("No logger is running.", "Es läuft kein Logger."),
+    ("More than one logger is running, pick one:", "Es läuft mehr als ein Logger, bitte einen auswählen:"),
+    ("no answer, {}", "keine Antwort, {}"),
+    ("Attach to {} read-only instead? [Y/n] ", "Stattdessen {} nur lesend folgen? [J/n] "),
+    ("Can't listen for attach at {}: {}", "Kann auf {} nicht zum Anhängen lauschen: {}"),
+    ("Can't open the kiosk browser: {}", "Kann den Browser für den Kioskmodus nicht öffnen: {}"),
+    (
+        "Nothing for --mdns to advertise, --web, --modbus or --scpi on an address other machines can reach",
+        "Nichts für --mdns anzukündigen, --web, --modbus oder --scpi auf einer Adresse, die andere Rechner erreichen",
+    ),
+    ("Can't advertise over mDNS: {}", "Kann nicht per mDNS ankündigen: {}"),
+    ("Stopped over the API", "Über die API angehalten"),
+    ("Started over the API", "Über die API gestartet"),
+    ("UT61E+ connected. Writing {}...", "UT61E+ verbunden. Schreibe {}..."),
+    ("UT61E+ connected. Reading measurements...", "UT61E+ verbunden. Lese Messwerte..."),
+    ("The schedule never matches, nothing to do.", "Der Zeitplan trifft nie zu, nichts zu tun."),
+    ("Next burst at {}", "Nächste Messreihe um {}"),
+    ("No reading to copy yet", "Noch kein Messwert zum Kopieren"),
+    ("No clipboard: {}", "Keine Zwischenablage: {}"),
+    ("Copied {}", "Kopiert: {}"),
+    ("Couldn't copy: {}", "Kopieren fehlgeschlagen: {}"),
+    ("{} (built without the clipboard feature, so not copied)", "{} (ohne Zwischenablage gebaut, daher nicht kopiert)"),
+    ("Unknown command {}, try /burst, /stats, /reset, /rearm, /light or /macro", "Unbekannter Befehl {}, versuche /burst, /stats, /reset, /rearm, /light oder /macro"),
+    ("No readings to go on yet", "Noch keine Messwerte"),
+    ("{}: min {}, max {}, mean {} over {} readings", "{}: Min. {}, Max. {}, Mittel {} aus {} Messwerten"),
+    ("min at {}, max at {}", "Min. um {}, Max. um {}"),
+    ("PASS: all {} readings within the limits", "BESTANDEN: alle {} Messwerte innerhalb der Grenzen"),
+    ("FAIL: no readings to test", "DURCHGEFALLEN: keine Messwerte zum Prüfen"),
+    ("FAIL: {} of {} readings outside the limits", "DURCHGEFALLEN: {} von {} Messwerten außerhalb der Grenzen"),
+    ("Step {} of {}:", "Schritt {} von {}:"),
+    ("Enter to measure, s to skip, q to stop: ", "Enter zum Messen, s zum Überspringen, q zum Beenden: "),
+    ("Enter to go on, r to repeat the step, q to stop: ", "Enter zum Weitermachen, r zum Wiederholen, q zum Beenden: "),
+    ("PASS: all {} steps", "BESTANDEN: alle {} Schritte"),
+    ("FAIL: {} of {} steps passed", "NICHT BESTANDEN: {} von {} Schritten bestanden"),
+    ("Report written to {}", "Bericht geschrieben nach {}"),
+    ("Energy {} Wh, charge {} mAh over {} s", "Energie {} Wh, Ladung {} mAh über {} s"),
+    ("Charge {} mAh over {} s", "Ladung {} mAh über {} s"),
+    (
+        "{} settles to {} {} with a time constant of {} s (R² {}, {} readings), within 1% after {} s",
+        "{} läuft gegen {} {} mit einer Zeitkonstante von {} s (R² {}, {} Messwerte), auf 1% genau nach {} s",
+    ),
+    ("Step {} of {}: set the current to {} A", "Schritt {} von {}: Strom auf {} A einstellen"),
+    ("Step {}", "Schritt {}"),
+    ("Enter to measure or type a note first, s to skip, q to stop: ", "Enter zum Messen oder erst eine Notiz eingeben, s zum Überspringen, q zum Beenden: "),
+    ("No reading, open or OL? Skipped.", "Kein Messwert, offen oder OL? Übersprungen."),
+    ("No current in A from the other meter", "Kein Strom in A vom anderen Messgerät"),
+    ("Plot written to {}", "Diagramm geschrieben nach {}"),
+    ("{}: unchanged", "{}: unverändert"),
+    ("Can't run the alarm's command: {}", "Kann den Befehl des Alarms nicht ausführen: {}"),
+    ("Stopping, {} at {} {}", "Beende, {} bei {} {}"),
+    ("Nothing's changed for {}, pausing the outputs until it does", "Seit {} keine Änderung, Ausgaben pausieren bis sich etwas tut"),
+    ("The reading moved, logging again", "Der Messwert ändert sich, es wird wieder aufgezeichnet"),
+    ("Can't press a key to keep the meter on: {}", "Kann keine Taste drücken, um das Messgerät eingeschaltet zu lassen: {}"),
+    ("The meter is answering again", "Das Messgerät antwortet wieder"),
+    ("The meter has stopped answering, has it turned itself off? --keep-alive stops auto power-off", "Das Messgerät antwortet nicht mehr, hat es sich selbst ausgeschaltet? --keep-alive verhindert die automatische Abschaltung"),
+    ("Can't press LIGHT: {}", "Kann LIGHT nicht drücken: {}"),
+    ("No macro called {}", "Kein Makro namens {}"),
+    ("Macro {} stopped: {}", "Makro {} abgebrochen: {}"),
+    ("Spikes left out of the min, max and mean: {}", "{} Ausreißer bei Min, Max und Mittelwert ausgelassen"),
+    ("Polled {} times/s, the reading changed {} times/s ({}% repeats)", "{}-mal/s abgefragt, der Messwert hat sich {}-mal/s geändert ({}% Wiederholungen)"),
+    (". Oversampling unless the input was steady, --interval {}ms would keep up", ". Überabtastung, falls das Eingangssignal nicht konstant war, --interval {}ms würde mithalten"),
+    (". Every reading was new, the meter may be updating faster than it's polled, try a shorter --interval", ". Jeder Messwert war neu, das Messgerät aktualisiert womöglich schneller als abgefragt wird, ein kürzeres --interval versuchen"),
+    ("Settling: started over in {}", "Einschwingen: neu begonnen in {}"),
+    ("Settled within 1% of {} {} (τ {} s)", "Auf 1% von {} {} eingeschwungen (τ {} s)"),
+    ("Power: {}", "Leistung: {}"),
+    ("Power: working it out again", "Leistung: wird wieder berechnet"),
+    ("no number from this meter", "kein Zahlenwert von diesem Messgerät"),
+    ("--shunt needs this meter on volts, it's on {}", "--shunt braucht dieses Messgerät auf Volt, es steht auf {}"),
+    ("no reading from the other meter", "kein Messwert vom anderen Messgerät"),
+    ("needs volts on one meter and amps on the other, not {} and {}", "braucht Volt an einem Messgerät und Ampere am anderen, nicht {} und {}"),
+    ("Lost the meter ({}), reconnecting...", "Verbindung zum Messgerät verloren ({}), verbinde neu..."),
+    ("{} corrupt frames in a row, giving up. Check the cable.", "{} fehlerhafte Frames in Folge, gebe auf. Bitte das Kabel prüfen."),
+    (
+        "{} samples in {} s, {} errors ({} timeouts, {} bad checksums, {} parse errors, {} read errors), {} reconnects",
+        "{} Messwerte in {} s, {} Fehler ({} Zeitüberschreitungen, {} falsche Prüfsummen, {} Formatfehler, {} Lesefehler), {} Neuverbindungen",
+    ),
+    (
+        "{} meter updates missed because polls came late, up to {} s apart",
+        "{} Aktualisierungen des Messgeräts verpasst, weil Abfragen zu spät kamen, bis zu {} s auseinander",
+    ),
+    ("{} records left out for {}, it couldn't keep up", "{} Datensätze für {} ausgelassen, es kam nicht hinterher"),
+    ("Attached to {}", "Angehängt an {}"),
+    ("The logger stopped", "Der Logger wurde beendet"),
+    ("nothing in {} s, paused or waiting for a schedule", "nichts seit {} s, pausiert oder wartet auf den Zeitplan"),
+    ("stopping", "wird beendet"),
+    ("meter not answering ({})", "Messgerät antwortet nicht ({})"),
+];
+
+const ZH: &[(&str, &str)] = &[
+    ("No logger is running.", "没有正在运行的记录器。"),
+    ("More than one logger is running, pick one:", "有多个记录器正在运行，请选择一个："),
+    ("no answer, {}", "无应答，{}"),
+    ("Attach to {} read-only instead? [Y/n] ", "改为以只读方式连接到 {}？[Y/n] "),
+    ("Can't listen for attach at {}: {}", "无法在 {} 上监听连接：{}"),
+    ("Can't open the kiosk browser: {}", "无法打开展台模式浏览器：{}"),
+    ("Nothing for --mdns to advertise, --web, --modbus or --scpi on an address other machines can reach", "--mdns 没有可通告的服务，需要在其他机器可访问的地址上启用 --web、--modbus 或 --scpi"),
+    ("Can't advertise over mDNS: {}", "无法通过 mDNS 通告：{}"),
+    ("Stopped over the API", "已通过 API 停止"),
+    ("Started over the API", "已通过 API 启动"),
+    ("UT61E+ connected. Writing {}...", "UT61E+ 已连接。正在写入 {}..."),
+    ("UT61E+ connected. Reading measurements...", "UT61E+ 已连接。正在读取测量值..."),
+    ("The schedule never matches, nothing to do.", "计划永远不会触发，无事可做。"),
+    ("Next burst at {}", "下一组测量在 {}"),
+    ("No reading to copy yet", "还没有可复制的读数"),
+    ("No clipboard: {}", "没有剪贴板：{}"),
+    ("Copied {}", "已复制 {}"),
+    ("Couldn't copy: {}", "复制失败：{}"),
+    ("{} (built without the clipboard feature, so not copied)", "{}（构建时未启用剪贴板功能，未复制）"),
+    ("Unknown command {}, try /burst, /stats, /reset, /rearm, /light or /macro", "未知命令 {}，试试 /burst、/stats、/reset、/rearm、/light 或 /macro"),
+    ("No readings to go on yet", "还没有读数"),
+    ("{}: min {}, max {}, mean {} over {} readings", "{}：最小 {}，最大 {}，平均 {}，共 {} 个读数"),
+    ("min at {}, max at {}", "最小值出现于 {}，最大值出现于 {}"),
+    ("PASS: all {} readings within the limits", "合格：全部 {} 个读数都在限值内"),
+    ("FAIL: no readings to test", "不合格：没有可测试的读数"),
+    ("FAIL: {} of {} readings outside the limits", "不合格：{} 个读数超出限值（共 {} 个）"),
+    ("Step {} of {}:", "第 {} 步，共 {} 步："),
+    ("Enter to measure, s to skip, q to stop: ", "按 Enter 测量，s 跳过，q 停止："),
+    ("Enter to go on, r to repeat the step, q to stop: ", "按 Enter 继续，r 重做此步，q 停止："),
+    ("PASS: all {} steps", "合格：全部 {} 步"),
+    ("FAIL: {} of {} steps passed", "不合格：{} 步合格（共 {} 步）"),
+    ("Report written to {}", "报告已写入 {}"),
+    ("Energy {} Wh, charge {} mAh over {} s", "能量 {} Wh，电荷 {} mAh，用时 {} 秒"),
+    ("Charge {} mAh over {} s", "电荷 {} mAh，用时 {} 秒"),
+    (
+        "{} settles to {} {} with a time constant of {} s (R² {}, {} readings), within 1% after {} s",
+        "{} 趋于 {} {}，时间常数 {} 秒（R² {}，{} 个读数），{} 秒后误差在 1% 以内",
+    ),
+    ("Step {} of {}: set the current to {} A", "第 {} 步，共 {} 步：将电流设为 {} A"),
+    ("Step {}", "第 {} 步"),
+    ("Enter to measure or type a note first, s to skip, q to stop: ", "按 Enter 测量，或先输入备注，s 跳过，q 停止："),
+    ("No reading, open or OL? Skipped.", "没有读数，开路或 OL？已跳过。"),
+    ("No current in A from the other meter", "另一台万用表没有以 A 为单位的电流"),
+    ("Plot written to {}", "图表已写入 {}"),
+    ("{}: unchanged", "{}：未更改"),
+    ("Can't run the alarm's command: {}", "无法运行报警的命令：{}"),
+    ("Stopping, {} at {} {}", "停止，{}，读数 {} {}"),
+    ("Nothing's changed for {}, pausing the outputs until it does", "{} 内读数没有变化，暂停输出直到读数变化"),
+    ("The reading moved, logging again", "读数已变化，重新开始记录"),
+    ("Can't press a key to keep the meter on: {}", "无法按键以保持万用表开启：{}"),
+    ("The meter is answering again", "万用表已恢复响应"),
+    ("The meter has stopped answering, has it turned itself off? --keep-alive stops auto power-off", "万用表停止响应，是否已自动关机？--keep-alive 可防止自动关机"),
+    ("Can't press LIGHT: {}", "无法按下 LIGHT 键：{}"),
+    ("No macro called {}", "没有名为 {} 的宏"),
+    ("Macro {} stopped: {}", "宏 {} 已中止：{}"),
+    ("Spikes left out of the min, max and mean: {}", "最小值、最大值和平均值中排除了 {} 个尖峰"),
+    ("Polled {} times/s, the reading changed {} times/s ({}% repeats)", "每秒轮询 {} 次，读数每秒变化 {} 次（{}% 重复）"),
+    (". Oversampling unless the input was steady, --interval {}ms would keep up", "。除非输入保持不变，否则属于过采样，--interval {}ms 即可跟上"),
+    (". Every reading was new, the meter may be updating faster than it's polled, try a shorter --interval", "。每个读数都是新的，万用表的更新可能比轮询更快，请尝试更短的 --interval"),
+    ("Settling: started over in {}", "稳定过程：已在 {} 中重新开始"),
+    ("Settled within 1% of {} {} (τ {} s)", "已稳定在 {} {} 的 1% 以内（τ {} 秒）"),
+    ("Power: {}", "功率：{}"),
+    ("Power: working it out again", "功率：重新开始计算"),
+    ("no number from this meter", "此万用表没有数值"),
+    ("--shunt needs this meter on volts, it's on {}", "--shunt 需要此万用表处于电压档，当前为 {}"),
+    ("no reading from the other meter", "另一台万用表没有读数"),
+    ("needs volts on one meter and amps on the other, not {} and {}", "需要一台万用表测电压、另一台测电流，而不是 {} 和 {}"),
+    ("Lost the meter ({}), reconnecting...", "与万用表断开连接（{}），正在重新连接..."),
+    ("{} corrupt frames in a row, giving up. Check the cable.", "连续 {} 个损坏的数据帧，已放弃。请检查数据线。"),
+    (
+        "{} samples in {} s, {} errors ({} timeouts, {} bad checksums, {} parse errors, {} read errors), {} reconnects",
+        "{} 个读数，用时 {} 秒，{} 个错误（{} 次超时，{} 次校验和错误，{} 次解析错误，{} 次读取错误），{} 次重新连接",
+    ),
+    (
+        "{} meter updates missed because polls came late, up to {} s apart",
+        "由于轮询过晚，错过了 {} 次万用表更新，间隔最长 {} 秒",
+    ),
+    ("{} records left out for {}, it couldn't keep up", "{} 条记录未发送到 {}，它跟不上"),
+    ("Attached to {}", "已连接到 {}"),
+    ("The logger stopped", "记录器已停止"),
+    ("nothing in {} s, paused or waiting for a schedule", "{} 秒内无数据，已暂停或在等待计划"),
+    ("stopping", "正在停止"),
+    ("meter not answering ({})", "万用表无应答（{}）"),
+];
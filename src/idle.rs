@@ -0,0 +1,57 @@
+//! `--idle 10m`, for leaving the logger running overnight next to intermittent work: once the
+//! reading has stayed put for that long (within `--idle-band` of where it settled, in the unit
+//! without a prefix, so a meter sitting at 0.000 V with a digit of noise counts), the outputs
+//! get a gap and nothing more until it moves again. The meter is still polled the whole time, the
+//! summary, `--alarm` and the dashboard carry on as usual, it's only the files and network outputs
+//! that are spared hours of the same number. A mode change counts as moving.
+
+use crate::clock::Stamp;
+use std::time::Duration;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+pub enum Change {
+    Paused,
+    Resumed,
+}
+
+pub struct Idle {
+    after: Duration,
+    band: f64,
+    /// The mode and value the quiet stretch started with (no value for OL), and when
+    quiet: Option<(&'static str, Option<f64>, Duration)>,
+    paused: bool,
+}
+
+impl Idle {
+    pub fn new(after: Duration, band: f64) -> Self {
+        Idle { after, band, quiet: None, paused: false }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Whether this reading paused or resumed the outputs
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> Option<Change> {
+        let value = m.reading_in(Units::Si).reading.value();
+        let still = self.quiet.is_some_and(|(mode, start, _)| {
+            mode == m.mode_name()
+                && match (start, value) {
+                    (Some(start), Some(value)) => (value - start).abs() <= self.band,
+                    (start, value) => start.is_none() && value.is_none(),
+                }
+        });
+        if !still {
+            self.quiet = Some((m.mode_name(), value, stamp.elapsed));
+            return std::mem::take(&mut self.paused).then_some(Change::Resumed);
+        }
+        let since = self.quiet.map_or(stamp.elapsed, |(_, _, since)| since);
+        match !self.paused && stamp.elapsed.saturating_sub(since) >= self.after {
+            true => {
+                self.paused = true;
+                Some(Change::Paused)
+            }
+            false => None,
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! `--hash`, for captures that end up in a test report and have to be shown to be the ones that
+//! were logged. Each output file gets a `.sha256` next to it when it's closed, with who logged
+//! what and when as comments, the file's SHA-256 in the format `sha256sum -c` reads, and a last
+//! line with the SHA-256 of everything above it so the comments can't be edited either.
+//! `ut61e_plus verify` checks both.
+//!
+//! It shows a file hasn't changed since, not who wrote it: anyone can write a new sidecar for an
+//! edited file, so keep a copy of it (or its last line) somewhere the file can't go.
+
+use crate::clock::{Stamp, Utc};
+use crate::output::{Derived, FORMAT_VERSION, Session};
+use crate::sink::Sink;
+use crate::stats::Tracked;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use ut61e_plus_logger::protocol::Measurement;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// FIPS 180-4 SHA-256, small enough not to be worth a dependency
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    filled: usize,
+    length: u64,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256 {
+            state: [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19],
+            block: [0; 64],
+            filled: 0,
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.filled).min(data.len());
+            self.block[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+            if self.filled == 64 {
+                self.compress();
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, word) in self.block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (k, w) in K.iter().zip(w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+        for (state, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(v);
+        }
+    }
+
+    /// The digest as lowercase hex, like `sha256sum` prints it
+    pub fn finish(mut self) -> String {
+        let bits = self.length * 8;
+        self.update(&[0x80]);
+        while self.filled != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+        self.state.iter().map(|word| format!("{:08x}", word)).collect()
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn of_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hash = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hash.finish()),
+            n => hash.update(&buffer[..n]),
+        }
+    }
+}
+
+fn of_str(s: &str) -> String {
+    let mut hash = Sha256::new();
+    hash.update(s.as_bytes());
+    hash.finish()
+}
+
+/// For a comment line, with newlines and other control characters escaped so an argument with one
+/// in it can't end the comment and pass for the digest line
+fn comment(s: &str) -> String {
+    s.chars().map(|c| if c.is_control() { c.escape_default().to_string() } else { c.to_string() }).collect()
+}
+
+/// `capture.csv.sha256` for `capture.csv`
+pub fn sidecar(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// An output file that gets its sidecar written once it's finished
+pub struct Sealed<S> {
+    inner: S,
+    path: PathBuf,
+    session: Session,
+    readings: u64,
+    events: u64,
+}
+
+impl<S> Sealed<S> {
+    pub fn new(inner: S, path: &Path, session: Session) -> Self {
+        Sealed { inner, path: path.to_owned(), session, readings: 0, events: 0 }
+    }
+}
+
+impl<S: Sink> Sink for Sealed<S> {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.readings += 1;
+        self.inner.sample(stamp, m)
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        self.inner.gap(stamp, reason)
+    }
+
+    fn missed(&mut self) -> io::Result<()> {
+        self.inner.missed()
+    }
+
+    fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.events += 1;
+        self.inner.event(stamp, message)
+    }
+
+    fn notice(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.events += 1;
+        self.inner.notice(stamp, message)
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        self.inner.derived(stamp, derived)
+    }
+
+    fn stats(&mut self, current: Option<&Tracked>) -> io::Result<()> {
+        self.inner.stats(current)
+    }
+
+    fn tick(&mut self) -> io::Result<()> {
+        self.inner.tick()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let Sealed { inner, path, session, readings, events } = *self;
+        Box::new(inner).finish()?;
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+        let mut text = format!("# ut61e_plus_logger {}, format version {}\n", env!("CARGO_PKG_VERSION"), FORMAT_VERSION);
+        text += &format!("# meter: {}\n", comment(&session.meter));
+        text += &format!("# command: {}\n", comment(&session.command));
+        text += &format!("# started: {}\n", Utc(session.started));
+        text += &format!("# finished: {}\n", Utc(SystemTime::now()));
+        text += &format!("# records: {} readings, {} events\n", readings, events);
+        text += &format!("{}  {}\n", of_file(&path)?, name);
+        text += &format!("# sha256 of the lines above: {}\n", of_str(&text));
+        fs::write(sidecar(&path), text)
+    }
+}
+
+/// Checks a file against its sidecar, `Err` saying what doesn't match
+pub fn verify(path: &Path) -> Result<(), String> {
+    let (path, sidecar) = match path.extension().is_some_and(|ext| ext == "sha256") {
+        true => (path.with_extension(""), path.to_owned()),
+        false => (path.to_owned(), sidecar(path)),
+    };
+    let text = fs::read_to_string(&sidecar).map_err(|e| format!("can't read {}: {}", sidecar.display(), e))?;
+    let (above, last) = text.trim_end_matches('\n').rsplit_once('\n').ok_or("the sidecar is cut short")?;
+    let sealed = last.strip_prefix("# sha256 of the lines above: ").ok_or("the sidecar is cut short")?;
+    if of_str(&format!("{}\n", above)) != sealed {
+        return Err(format!("{} has been edited", sidecar.display()));
+    }
+    let digest = above.lines().find(|line| !line.starts_with('#')).and_then(|line| line.split_once("  ")).ok_or("no digest in the sidecar")?.0;
+    let actual = of_file(&path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    match actual == digest {
+        true => Ok(()),
+        false => Err(format!("{} has changed since it was logged", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::time::Duration;
+
+    fn of(data: &[u8]) -> String {
+        let mut hash = Sha256::new();
+        hash.update(data);
+        hash.finish()
+    }
+
+    // The examples in FIPS 180-4 and the NIST test vectors, taken across the padding boundaries
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(of(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(of(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // 56 bytes, the length no longer fits in the block
+        assert_eq!(of(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        assert_eq!(of(&[b'a'; 55]), "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318");
+        assert_eq!(of(&[b'a'; 56]), "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a");
+        assert_eq!(of(&[b'a'; 64]), "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb");
+    }
+
+    #[test]
+    fn sha256_of_a_million_in_pieces() {
+        let mut hash = Sha256::new();
+        // Pieces that don't line up with the blocks
+        for _ in 0..1000 {
+            hash.update(&[b'a'; 999]);
+        }
+        hash.update(&[b'a'; 1000]);
+        assert_eq!(hash.finish(), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+    }
+
+    fn reading() -> Measurement {
+        Measurement {
+            mode: 2,
+            range: 0x30,
+            display: *b" 1.2345",
+            bar: [0; 2],
+            rel: false,
+            hold: false,
+            min: false,
+            max: false,
+            auto_range: true,
+            low_battery: false,
+            hv_warning: false,
+            dc: true,
+            peak_max: false,
+            peak_min: false,
+        }
+    }
+
+    /// Writes a line per reading to the file, like the text outputs
+    struct Lines(File);
+
+    impl Sink for Lines {
+        fn sample(&mut self, stamp: &Stamp, _m: &Measurement) -> io::Result<()> {
+            writeln!(self.0, "{}", stamp.seq)
+        }
+    }
+
+    fn sealed(name: &str, command: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ut61e-integrity-{}-{}.csv", std::process::id(), name));
+        let session = Session { meter: "B123".to_owned(), command: command.to_owned(), started: SystemTime::UNIX_EPOCH };
+        let mut sink = Box::new(Sealed::new(Lines(File::create(&path).unwrap()), &path, session));
+        for seq in 0..3 {
+            let stamp = Stamp { wall: SystemTime::UNIX_EPOCH, elapsed: Duration::from_secs(seq), seq };
+            sink.sample(&stamp, &reading()).unwrap();
+        }
+        sink.finish().unwrap();
+        path
+    }
+
+    fn tidy(path: &Path) {
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(sidecar(path));
+    }
+
+    #[test]
+    fn sealed_files_verify_until_edited() {
+        let path = sealed("data", "ut61e_plus_logger --hash -o capture.csv");
+        assert_eq!(verify(&path), Ok(()));
+        assert_eq!(verify(&sidecar(&path)), Ok(()));
+        fs::write(&path, "0\n1\n3\n").unwrap();
+        assert_eq!(verify(&path), Err(format!("{} has changed since it was logged", path.display())));
+        tidy(&path);
+    }
+
+    #[test]
+    fn editing_a_comment_is_caught() {
+        let path = sealed("comment", "ut61e_plus_logger --hash -o capture.csv");
+        let text = fs::read_to_string(sidecar(&path)).unwrap();
+        assert!(text.contains("# meter: B123\n"));
+        fs::write(sidecar(&path), text.replace("# meter: B123", "# meter: B124")).unwrap();
+        assert_eq!(verify(&path), Err(format!("{} has been edited", sidecar(&path).display())));
+        tidy(&path);
+    }
+
+    #[test]
+    fn newlines_in_the_command_stay_in_the_comment() {
+        let path = sealed("newline", "ut61e_plus_logger --hash --note 'a\nb' -o capture.csv");
+        let text = fs::read_to_string(sidecar(&path)).unwrap();
+        assert!(text.contains("# command: ut61e_plus_logger --hash --note 'a\\nb' -o capture.csv\n"));
+        assert_eq!(text.lines().filter(|line| !line.starts_with('#')).count(), 1);
+        assert_eq!(verify(&path), Ok(()));
+        tidy(&path);
+    }
+}
@@ -0,0 +1,98 @@
+//! Attaching to a logger that's already running. Every logger listens on a Unix socket named
+//! after the cable's serial number in `$XDG_RUNTIME_DIR/ut61e_plus/`, streaming the same CSV as
+//! `--unix-socket`, so a viewer started later can follow the capture without stopping it or
+//! opening the meter itself. `ut61e_plus attach` is the simplest such viewer.
+
+use crate::i18n::tr;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Only readable by this user, like the runtime directory itself
+pub fn dir() -> PathBuf {
+    let base = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(std::env::temp_dir);
+    base.join("ut61e_plus")
+}
+
+/// Where the logger for the meter with `serial` listens. Meters without a serial number (and
+/// `--simulate`) get one per process instead.
+pub fn path(serial: Option<&str>) -> PathBuf {
+    let name = match serial {
+        Some(serial) if !serial.is_empty() => serial.replace(['/', '\0'], "_"),
+        _ => format!("pid{}", std::process::id()),
+    };
+    dir().join(format!("{}.sock", name))
+}
+
+pub fn create_dir() -> io::Result<()> {
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir())
+}
+
+/// Sockets with a logger behind them. Ones left behind by a logger that was killed are skipped.
+pub fn running() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir()) else {
+        return Vec::new();
+    };
+    let mut sockets: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_socket()))
+        .map(|entry| entry.path())
+        .filter(|path| is_running(path))
+        .collect();
+    sockets.sort();
+    sockets
+}
+
+/// A socket file is left behind when a logger is killed, so only answering counts
+pub fn is_running(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+/// Copies a running logger's CSV to stdout until it stops
+pub fn attach(path: &Path) -> io::Result<()> {
+    let mut stream = UnixStream::connect(path)?;
+    eprintln!("{}", tr!("Attached to {}", path.display()));
+    let mut stdout = io::stdout().lock();
+    let mut buf = [0; 4096];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            eprintln!("{}", tr!("The logger stopped"));
+            return Ok(());
+        }
+        stdout.write_all(&buf[..n])?;
+        stdout.flush()?;
+    }
+}
+
+/// What a running logger is doing, from the next record it writes: `1.2345 V (V_DC)`, or why
+/// there's no reading. Events are skipped, a logger that writes nothing within `timeout` is
+/// reported as quiet.
+pub fn status(path: &Path, timeout: Duration) -> io::Result<String> {
+    let stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(timeout))?;
+    let mut lines = BufReader::new(stream).lines();
+    let header = lines.next().transpose()?.unwrap_or_default();
+    let column = |name: &str| header.split(',').position(|c| c == name);
+    let (value, unit, mode, range) = (column("value"), column("unit"), column("mode"), column("range"));
+    loop {
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                return Ok(tr!("nothing in {} s, paused or waiting for a schedule", timeout.as_secs()));
+            }
+            Some(Err(e)) => return Err(e),
+            None => return Ok(tr!("stopping").to_string()),
+        };
+        let fields: Vec<&str> = line.split(',').collect();
+        let get = |i: Option<usize>| i.and_then(|i| fields.get(i)).copied().unwrap_or("");
+        match get(value) {
+            _ if get(range) == "DERIVED" => continue,
+            "EVENT" => continue,
+            "GAP" => return Ok(tr!("meter not answering ({})", get(mode))),
+            v => return Ok(format!("{} {} ({})", v, get(unit), get(mode))),
+        }
+    }
+}
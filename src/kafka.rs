@@ -0,0 +1,43 @@
+//! Publishing measurements to Kafka (or Redpanda, anything speaking the protocol) as JSON,
+//! keyed by the cable's serial number so readings from one meter stay in order on one partition.
+
+use rdkafka::config::ClientConfig;
+use rdkafka::error::KafkaResult;
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+use std::time::Duration;
+
+pub struct Kafka {
+    producer: BaseProducer,
+    topic: String,
+    /// So a broker outage doesn't print an error three times a second
+    warned: bool,
+}
+
+impl Kafka {
+    /// `brokers` is a comma separated `host:port` list
+    pub fn connect(brokers: &str, topic: &str) -> KafkaResult<Self> {
+        let producer = ClientConfig::new().set("bootstrap.servers", brokers).create()?;
+        Ok(Kafka { producer, topic: topic.to_owned(), warned: false })
+    }
+
+    /// Queues the message and returns straight away, librdkafka sends it in the background
+    pub fn send(&mut self, key: &str, payload: &[u8]) {
+        match self.producer.send(BaseRecord::to(&self.topic).key(key).payload(payload)) {
+            Ok(()) => self.warned = false,
+            Err((e, _)) if !self.warned => {
+                eprintln!("Kafka publish to {} failed: {}", self.topic, e);
+                self.warned = true;
+            }
+            Err(_) => {}
+        }
+        // Delivery reports are only needed to free their memory
+        self.producer.poll(Duration::ZERO);
+    }
+}
+
+impl Drop for Kafka {
+    /// Gives whatever is still queued a chance to go out before exiting
+    fn drop(&mut self) {
+        let _ = self.producer.flush(Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,14 @@
+//! Decoding for the UNI-T UT61E+ multimeter's USB protocol, shared by the logger binary and
+//! anything else that wants to talk to the meter.
+
+#![no_std]
+
+// Not on wasm32, the browser decoder has no allocator
+#[cfg(not(target_arch = "wasm32"))]
+extern crate alloc;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod history;
+pub mod protocol;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tiles;
@@ -0,0 +1,135 @@
+//! Streaming CSV records to other programs on the same machine through a named pipe or a Unix
+//! socket. The logger never waits for them: a reader that isn't there or can't keep up just
+//! misses lines.
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// A FIFO at `path`, created if it isn't there yet. It's reopened whenever there's no reader,
+/// so consumers can come and go.
+pub struct Fifo {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Fifo {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.file_type().is_fifo() => {}
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and isn't a FIFO", path.display()))),
+            Err(_) => {
+                let c_path = CString::new(path.as_os_str().as_encoded_bytes())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(Fifo { path: path.to_owned(), file: None })
+    }
+
+    /// `header` is sent first to every new reader
+    fn send(&mut self, header: &[u8], line: &[u8]) {
+        if self.file.is_none() {
+            // Non-blocking so opening fails straight away (ENXIO) when nobody is reading
+            self.file = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+                .ok();
+            if let Some(file) = &mut self.file
+                && file.write_all(header).is_err()
+            {
+                self.file = None;
+            }
+        }
+        // Lines are well under PIPE_BUF, so each goes into the pipe whole or not at all
+        if let Some(file) = &mut self.file {
+            match file.write_all(line) {
+                Ok(()) => {}
+                // Reader is behind and the pipe is full, it misses this one
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                // Reader went away
+                Err(_) => self.file = None,
+            }
+        }
+    }
+}
+
+/// A listening Unix socket at `path`, any number of clients get every record
+pub struct Socket {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<UnixStream>,
+}
+
+impl Socket {
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        // A socket file left behind by a previous run would make bind fail
+        if std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Socket { path: path.to_owned(), listener, clients: Vec::new() })
+    }
+
+    fn send(&mut self, header: &[u8], line: &[u8]) {
+        while let Ok((mut client, _)) = self.listener.accept() {
+            if client.set_nonblocking(true).is_ok() && client.write_all(header).is_ok() {
+                self.clients.push(client);
+            }
+        }
+        // A client that can't take a whole line is dropped rather than sent half of one
+        self.clients.retain_mut(|client| client.write_all(line).is_ok());
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// All the local outputs, written to like any other output and split into lines for them
+pub struct Local {
+    pub fifo: Option<Fifo>,
+    /// `--unix-socket` and the attach socket, see ipc.rs
+    pub sockets: Vec<Socket>,
+    header: Vec<u8>,
+    pending: Vec<u8>,
+}
+
+impl Local {
+    /// `header` is the CSV header, sent to each consumer when it connects
+    pub fn new(header: Vec<u8>) -> Self {
+        Local { fifo: None, sockets: Vec::new(), header, pending: Vec::new() }
+    }
+}
+
+impl Write for Local {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.fifo.is_none() && self.sockets.is_empty() {
+            return Ok(buf.len());
+        }
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            if let Some(fifo) = &mut self.fifo {
+                fifo.send(&self.header, &line);
+            }
+            for socket in &mut self.sockets {
+                socket.send(&self.header, &line);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
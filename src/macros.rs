@@ -0,0 +1,102 @@
+//! `--macros FILE`, named sequences of key presses for setting the meter up the same way every
+//! time, so "switch to mA, fix the range, zero it" is one command rather than a card of
+//! instructions taped to the bench. One `[[macro]]` each:
+//!
+//! ```toml
+//! [[macro]]
+//! name = "setup-current-logging"
+//! keys = "select, range, range, wait 1s, rel"
+//! ```
+//!
+//! `keys` are the names in `protocol::KEYS` (`minmax`, `exit-minmax`, `range`, `auto`, `rel`,
+//! `hz`, `hold`, `light`, `select`, `peak`, `exit-peak`) and `wait <duration>` for the meter to
+//! settle in between, e.g. after a mode change. A macro runs with `ut61e_plus_logger --macros
+//! FILE macro NAME`, or while logging with `/macro NAME`, `macro NAME` over `--web-control` or
+//! its button on the dashboard. Polling stops while it runs.
+//!
+//! The meter doesn't say which of its keys were pressed, only what it's showing, so a macro can't
+//! be recorded off the front panel and has to be written down.
+
+use crate::meter::Meter;
+use crate::config::{Setting, Value, sections};
+use hidapi::HidError;
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, thread};
+use ut61e_plus_logger::protocol::KEYS;
+
+/// Between presses, the meter misses one sent straight after another
+const KEY_GAP: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy)]
+enum Step {
+    Press(u8),
+    Wait(Duration),
+}
+
+pub struct Macro {
+    pub name: String,
+    steps: Vec<Step>,
+}
+
+impl Macro {
+    /// Presses the keys one after another, stopping at the first that can't be sent
+    pub fn run(&self, meter: &mut Meter) -> Result<(), HidError> {
+        for (i, step) in self.steps.iter().enumerate() {
+            match *step {
+                Step::Press(key) => {
+                    if i > 0 {
+                        thread::sleep(KEY_GAP);
+                    }
+                    meter.press(key)?;
+                }
+                Step::Wait(wait) => thread::sleep(wait),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `[[macro]]` entries of a file
+pub fn load(path: &Path) -> Result<Vec<Macro>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{} {}", path.display(), e))
+}
+
+fn parse(text: &str) -> Result<Vec<Macro>, String> {
+    let mut macros: Vec<Macro> = Vec::new();
+    for section in sections(text, "macro")? {
+        let (mut name, mut steps) = (None, None);
+        for Setting { key, value, line } in section.settings {
+            let error = |e: String| format!("line {}: {}", line, e);
+            match (key.as_str(), value) {
+                ("name", Value::Text(text)) if !text.is_empty() && !text.contains(char::is_whitespace) => name = Some(text),
+                ("name", Value::Text(text)) => return Err(error(format!("bad name {:?}, it's typed after /macro so it can't be empty or have spaces", text))),
+                ("keys", Value::Text(text)) => steps = Some(text.split(',').map(step).collect::<Result<Vec<_>, _>>().map_err(error)?),
+                ("name" | "keys", Value::Number(_)) => return Err(error(format!("{} has to be a string", key))),
+                _ => return Err(error(format!("unknown setting {:?}", key))),
+            }
+        }
+        let found = match (name, steps) {
+            (Some(name), Some(steps)) if !steps.is_empty() => Macro { name, steps },
+            (None, _) => return Err(format!("line {}: the macro has no name", section.start)),
+            (Some(_), _) => return Err(format!("line {}: the macro has no keys", section.start)),
+        };
+        if macros.iter().any(|other| other.name == found.name) {
+            return Err(format!("has two macros called {}", found.name));
+        }
+        macros.push(found);
+    }
+    Ok(macros)
+}
+
+fn step(s: &str) -> Result<Step, String> {
+    let s = s.trim();
+    if let Some(wait) = s.strip_prefix("wait") {
+        return crate::clock::parse_duration(wait.trim()).map(Step::Wait).ok_or_else(|| format!("bad {:?}, expected e.g. wait 1s", s));
+    }
+    match KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+        Some(&(_, key)) => Ok(Step::Press(key)),
+        None => Err(format!("no key called {:?}, there's {} and wait", s, KEYS.map(|(name, _)| name).join(", "))),
+    }
+}
@@ -1,296 +1,1722 @@
-use hidapi::{HidApi, HidDevice};
+use hidapi::HidApi;
+use device::OpenError;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{thread, time};
-use clap::Parser;
+use clap::builder::TypedValueParser as _;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::*;
 
+mod alarm;
+mod analysis;
+mod changes;
+mod clock;
+mod config;
+mod console;
+mod control;
+mod decode;
+mod device;
+mod doctor;
+#[cfg(feature = "encrypt")]
+mod encrypt;
+mod exit_code;
+mod export;
+mod filter;
+mod follow;
+mod i18n;
+mod idle;
+mod integrity;
+#[cfg(unix)]
+mod ipc;
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(unix)]
+mod local;
+mod macros;
+mod mains;
+#[cfg(unix)]
+mod mdns;
+mod ntc;
+mod meter;
+mod modbus;
+#[cfg(feature = "opcua")]
+mod opcua;
+mod osc;
+mod output;
+#[cfg(target_os = "linux")]
+mod panel;
+#[cfg(feature = "parquet")]
+mod parquet_file;
+mod plugin;
+mod power;
+mod procedure;
+mod quirks;
+mod report;
+mod rollup;
+mod schedule;
+#[cfg(feature = "scripting")]
+mod script;
+mod scpi;
+mod settle;
+mod sim;
+mod sink;
+mod smooth;
+#[cfg(feature = "sonify")]
+mod sonify;
+mod spill;
+mod spike;
+mod stats;
+mod summary;
+mod sweep;
+mod tdms;
+mod udp;
+mod watchdog;
+mod wav;
+mod web;
+mod zabbix;
+
+use device::{RetryPolicy, Source};
+use i18n::tr;
+use meter::GET_MEASUREMENT;
+use ut61e_plus_logger::protocol::{self, Measurement, Units};
+
 /// UT61E+ USB multimeter reader
 /// with help from https://github.com/ljakob/unit_ut61eplus/
 #[derive(Parser)]
+#[command(after_help = exit_code::help())]
+#[command(group(clap::ArgGroup::new("dashboard").args(["web", "kiosk"]).multiple(true)))]
 struct Args {
-    /// Output as CSV
+    /// Read commands from the terminal while logging: Enter copies the latest reading to the
+    /// clipboard, /burst starts a --burst, anything else typed is added to the log as a note
+    #[arg(short, long)]
+    interactive: bool,
+
+    /// Print every payload to stderr as hex, coloured by field and decoded, for working out the
+    /// bytes that aren't understood yet
     #[arg(long)]
+    show_raw: bool,
+
+    /// Language for messages at the terminal, from LANG by default. Captures stay in English.
+    #[arg(long, value_enum, global = true)]
+    lang: Option<i18n::Lang>,
+
+    /// Pretend to be a meter instead of opening one, to try things out without the hardware,
+    /// e.g. mode=V_DC,value=5,amplitude=0.1,period=10s,noise=0.001. Flags hold, rel, min, max,
+    /// low-battery and hv, corrupt=0.01 and dropout=0.01 for the chance of bad or missing frames,
+    /// scenario=file.csv to script the readings step by step instead.
+    #[arg(long, value_name = "SETTINGS", num_args = 0..=1, default_missing_value = "", value_parser = sim::Sim::parse)]
+    simulate: Option<sim::Sim>,
+
+    /// Output as CSV, short for --format csv
+    #[arg(long, conflicts_with = "format")]
     csv: bool,
-}
 
-const DEVICE_IDS: &[(u16, u16)] = &[
-    (0x1A86, 0xE429), // QinHeng
-    (0x10C4, 0xEA80), // Silicon Labs CP2110
-];
+    /// Print the JSON Schema of the records of --format json, --udp and --kafka, and exit
+    #[arg(long)]
+    emit_schema: bool,
+
+    /// What to write: coloured lines for a human (the default), CSV, or Parquet (needs
+    /// --output). Without it each --output goes by its extension, .csv or .parquet
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Write the output to this file instead of stdout. Give it more than once to write several
+    /// files at the same time, e.g. a CSV to tail and a Parquet file for later
+    #[arg(short, long)]
+    output: Vec<std::path::PathBuf>,
+
+    /// Seconds without a valid frame before re-initialising the UART, and after another period
+    /// reopening the device
+    #[arg(long, default_value_t = 5.0)]
+    stall_timeout: f64,
+
+    /// Press LIGHT twice every so often (10m if no time is given, and once on start) so the meter's
+    /// auto power-off doesn't end a long capture. Turning it on with SELECT held disables it for good
+    #[arg(long, value_name = "EVERY", value_parser = duration, num_args = 0..=1, default_missing_value = "10m")]
+    keep_alive: Option<time::Duration>,
+
+    /// Add the wall clock time and monotonic elapsed seconds to every reading
+    #[arg(long)]
+    timestamp: bool,
+
+    /// Number every row with the poll it came from, so rows lost downstream can be spotted
+    #[arg(long)]
+    seq: bool,
+
+    /// Add the datasheet ± uncertainty of each reading to the output
+    #[arg(long)]
+    uncertainty: bool,
+
+    /// Limit test: every reading below this fails (in the unit without a prefix, V not mV). Each
+    /// reading gets a PASS or FAIL, and the run a verdict and exit code 7 if any failed
+    #[arg(long, value_name = "VALUE", allow_hyphen_values = true)]
+    limit_low: Option<f64>,
+
+    /// Limit test: every reading above this fails, see --limit-low
+    #[arg(long, value_name = "VALUE", allow_hyphen_values = true)]
+    limit_high: Option<f64>,
+
+    /// Stop after this many readings
+    #[arg(long, value_name = "N")]
+    count: Option<u64>,
+
+    /// Note every mode, range and REL change as an event. The logger's own min, max and mean and
+    /// the --settle fit start over when REL goes on or off either way (see the readme)
+    #[arg(long)]
+    mark_changes: bool,
+
+    /// Stop once a reading makes this true, e.g. 'value < 3.0' for a battery down to its cutoff.
+    /// Same fields as --filter, the reading that does it is the last one written
+    #[arg(long, value_name = "EXPRESSION", value_parser = filter::Filter::parse)]
+    stop_when: Option<filter::Filter>,
+
+    /// Units for values: as the meter shows them (native), with the SI prefix that keeps the
+    /// number between 1 and 1000 (engineering, 500.0 mV), or without a prefix (si, 4653 Ω) so a
+    /// column keeps one unit across range changes
+    #[arg(long, default_value = "native", value_parser = clap::builder::PossibleValuesParser::new(["native", "engineering", "si"]).map(|s| units(&s)))]
+    units: protocol::Units,
+
+    /// How many times to retry a failed read or write before treating the meter as disconnected
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    /// Delay before the first retry in milliseconds, doubled after each failed attempt
+    #[arg(long, default_value_t = 50)]
+    retry_backoff: u64,
+
+    /// Give up after this many failed polls in a row (timeouts, bad checksums, parse or read
+    /// errors) instead of retrying forever
+    #[arg(long)]
+    max_errors: Option<u32>,
+
+    /// What to do once --max-errors is reached
+    #[arg(long, value_enum, default_value_t = OnMaxErrors::Abort)]
+    on_max_errors: OnMaxErrors,
+
+    /// Flush output every N lines (e.g. 10) or this often (e.g. 2s, 500ms). Without it every line
+    /// is flushed as it's written, also when piped into tee or grep
+    #[arg(long, value_name = "N|DURATION")]
+    flush_every: Option<output::FlushEvery>,
+
+    /// How readings get to an output, by kind (stdout, file, local, udp, osc, modbus, opcua, scpi,
+    /// panel, web, zabbix, kafka, plugin) or file or plugin name: inline in the logging loop, or through a
+    /// queue on a thread of its own that drops readings or blocks when full, e.g. web=drop:100 or
+    /// capture.csv=block. Network outputs and plugins default to drop:1000, the rest to inline
+    #[arg(long, value_name = "OUTPUT=POLICY", value_parser = queue_policy)]
+    queue: Vec<(String, sink::Policy)>,
+
+    /// Also stream CSV records into this named pipe, created if needed
+    #[cfg(unix)]
+    #[arg(long)]
+    fifo: Option<std::path::PathBuf>,
+
+    /// Also stream CSV records to every client of a Unix socket listening here
+    #[cfg(unix)]
+    #[arg(long)]
+    unix_socket: Option<std::path::PathBuf>,
+
+    /// Don't listen for `ut61e_plus attach` and other viewers
+    #[cfg(unix)]
+    #[arg(long)]
+    no_attach: bool,
+
+    /// Also send every measurement as a JSON datagram to this host:port (unicast, broadcast or
+    /// multicast)
+    #[arg(long, value_name = "HOST:PORT")]
+    udp: Option<String>,
+
+    /// Serve the latest reading as Modbus TCP registers on this address, e.g. 0.0.0.0:1502
+    #[arg(long, value_name = "ADDR:PORT")]
+    modbus: Option<String>,
+
+    /// Serve the latest reading to OPC UA clients on this address, e.g. 0.0.0.0:4840 (anonymous,
+    /// no security), as Objects/UT61E+/Value with its unit and range
+    #[cfg(feature = "opcua")]
+    #[arg(long, value_name = "ADDR:PORT")]
+    opcua: Option<String>,
+
+    /// Answer SCPI queries (*IDN?, READ?, ...) on a raw socket on this address, e.g. 0.0.0.0:5025,
+    /// like a bench meter on the network. With --web that serves LXI identification at /lxi too
+    #[arg(long, value_name = "ADDR:PORT")]
+    scpi: Option<String>,
+
+    /// Serve a live dashboard for any browser on this address, e.g. 0.0.0.0:8080, with the
+    /// readings streamed as JSON over a WebSocket at /ws
+    #[arg(long, value_name = "ADDR:PORT", default_value_if("kiosk", "true", "127.0.0.1:0"))]
+    web: Option<String>,
+
+    /// Advertise --web, --modbus and --scpi on the local network over mDNS as _ut61e._tcp (and
+    /// _http._tcp, _modbus._tcp, _scpi-raw._tcp, _lxi._tcp), so other machines can find the
+    /// logger without its address
+    #[cfg(unix)]
+    #[arg(long)]
+    mdns: bool,
+
+    /// The dashboard's settings, e.g. a token every request has to come with as a bearer token
+    /// or ?token=... on the page's address (the format is in the readme)
+    #[arg(long, value_name = "FILE", requires = "dashboard")]
+    web_config: Option<std::path::PathBuf>,
+
+    /// Take commands over the dashboard's HTTP and WebSocket API (POST /control): stop, start,
+    /// interval 500ms, alarm >5 <1, rearm, light, note <text>, burst
+    #[arg(long, requires = "dashboard")]
+    web_control: bool,
+
+    /// Show the reading on a screen of its own: an SSD1306 OLED on an I²C bus (/dev/i2c-1, or
+    /// /dev/i2c-1@0x3d for the other address) or a framebuffer (/dev/fb1)
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_name = "DEVICE", value_parser = panel::Target::parse)]
+    panel: Option<panel::Target>,
+
+    /// Show the dashboard fullscreen in Chromium's (or Firefox's) kiosk mode with big buttons
+    /// and the cursor hidden, for a touchscreen such as a Raspberry Pi's. Serves it on a free
+    /// local port unless --web is given too
+    #[arg(long)]
+    kiosk: bool,
+
+    /// Keep this much of the capture in memory, e.g. 1h, for /history on --web and for the
+    /// dashboard to fill its plot from when opened
+    #[arg(long, value_parser = duration, requires = "dashboard")]
+    history: Option<time::Duration>,
+
+    /// How much of --history to keep in memory (e.g. 500k, 64M, 1G), older readings go to
+    /// temporary files
+    #[arg(long, value_name = "SIZE", value_parser = size, default_value = "32M", requires = "history")]
+    history_memory: usize,
+
+    /// Also publish every measurement as JSON to these Kafka brokers (host:port,...), keyed by the
+    /// cable's serial number
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_name = "BROKERS", requires = "kafka_topic")]
+    kafka: Option<String>,
+
+    /// Topic for --kafka
+    #[cfg(feature = "kafka")]
+    #[arg(long, requires = "kafka")]
+    kafka_topic: Option<String>,
+
+    /// Also push readings to this Zabbix server or proxy (host or host:port) as trapper items
+    #[arg(long, value_name = "SERVER", requires = "zabbix_host")]
+    zabbix: Option<String>,
+
+    /// Host name the trapper items belong to in Zabbix
+    #[arg(long, requires = "zabbix")]
+    zabbix_host: Option<String>,
+
+    /// Seconds between batches sent to Zabbix
+    #[arg(long, default_value_t = 10.0)]
+    zabbix_interval: f64,
+
+    /// Also send every value as an OSC message (one float) to this host:port
+    #[arg(long, value_name = "HOST:PORT")]
+    osc: Option<String>,
+
+    /// OSC address pattern for --osc
+    #[arg(long, default_value = "/ut61e/value", value_parser = osc_address)]
+    osc_address: String,
+
+    /// Play a tone whose pitch follows the value
+    #[cfg(feature = "sonify")]
+    #[arg(long)]
+    sonify: bool,
+
+    /// Values for the lowest and highest pitch, e.g. 4.9:5.1. Without it the pitch follows the
+    /// magnitude from 0 to the full scale of the current range
+    #[cfg(feature = "sonify")]
+    #[arg(long, value_name = "LOW:HIGH", value_parser = pair, allow_hyphen_values = true, requires = "sonify")]
+    sonify_range: Option<(f64, f64)>,
+
+    /// Lowest and highest pitch in Hz
+    #[cfg(feature = "sonify")]
+    #[arg(long, value_name = "LOW:HIGH", value_parser = pair, default_value = "220:1760")]
+    sonify_pitch: (f64, f64),
+
+    #[cfg(feature = "sonify")]
+    #[arg(long, value_enum, default_value_t = sonify::Scale::Continuous)]
+    sonify_scale: sonify::Scale,
+
+    /// Only sample in bursts starting at these times, cron style in local time, e.g. "*/10 * * * *"
+    /// for every 10 minutes
+    #[arg(long, value_parser = schedule::Schedule::parse, requires = "burst")]
+    schedule: Option<schedule::Schedule>,
+
+    /// Time between polls, e.g. 1s. The meter updates its display a few times a second
+    #[arg(long, value_parser = duration)]
+    interval: Option<time::Duration>,
+
+    /// A burst of sampling: a duration like 30s, or a number of samples at a rate like 100@10Hz
+    /// (or 100@max). With --schedule each scheduled window is one burst, otherwise bursts are
+    /// started with /burst in --interactive mode, SIGUSR1 or --burst-trigger
+    #[arg(long, value_parser = schedule::Burst::parse)]
+    burst: Option<schedule::Burst>,
+
+    /// Start a burst when the value goes above (>LEVEL) or below (<LEVEL) a level
+    #[arg(long, value_parser = schedule::Trigger::parse, requires = "burst", allow_hyphen_values = true)]
+    burst_trigger: Option<schedule::Trigger>,
+
+    /// Note it in the outputs when the value goes above (>LEVEL) or below (<LEVEL) a level, and
+    /// when it comes back. Levels and rates are in the unit without a prefix (>0.005 is 5 mV on
+    /// the mV range). Against noise add how far back it has to come to clear and how long it
+    /// has to stay either way, e.g. >5,hysteresis=0.1,dwell=2s. rate>0.5/s (or /min, /h) is a
+    /// level on how fast it changes, over the last 2 s or window=10s. Can be given more than once
+    #[arg(long, value_name = "LEVEL", value_parser = alarm::Level::parse, allow_hyphen_values = true)]
+    alarm: Vec<alarm::Level>,
+
+    /// Named alarms from this file, each with a level or an expression like --filter's, a
+    /// severity, what to do (log, stop or run a command) and when to rearm (see the readme)
+    #[arg(long, value_name = "FILE")]
+    alarms: Option<std::path::PathBuf>,
+
+    /// Work out power, energy and charge with the current or voltage from the meter of another
+    /// logger, given by its socket or the meter's serial number
+    #[cfg(unix)]
+    #[arg(long, value_name = "LOGGER")]
+    power_with: Option<String>,
+
+    /// This meter reads the voltage across a shunt of this many ohms, log the current through it
+    /// and the charge
+    #[arg(long, value_name = "OHMS", value_parser = shunt)]
+    shunt: Option<f64>,
+
+    /// Log the temperature of an NTC thermistor on the resistance range, from its beta and
+    /// resistance at 25 °C (3950:10k), at another temperature (3950:10k:0), or Steinhart–Hart
+    /// coefficients (sh:A:B:C)
+    #[arg(long, value_name = "MODEL")]
+    ntc: Option<ntc::Ntc>,
+
+    /// Write the --output files encrypted with age to these public keys (age1..., from
+    /// age-keygen), comma separated or given more than once. Needs the encrypt feature
+    #[cfg(feature = "encrypt")]
+    #[arg(long, value_name = "RECIPIENT", env = "UT61E_ENCRYPT_TO", value_delimiter = ',', value_parser = encrypt::recipient)]
+    encrypt_to: Vec<age::x25519::Recipient>,
+
+    /// Write a FILE.sha256 next to each output file when it's closed, with the file's SHA-256 and
+    /// the session (meter, command line, start and end), for `verify` to check later
+    #[arg(long)]
+    hash: bool,
+
+    /// Also write a line per day and series with the min, max and mean, and the Ah or Wh for
+    /// currents and powers, appending to this file (CSV, or JSON lines for .json or .jsonl)
+    #[arg(long, value_name = "FILE")]
+    rollup: Option<std::path::PathBuf>,
+
+    /// Fit the readings as they settle, e.g. the current on a capacitor leakage test or the
+    /// resistance of insulation, and log where they're heading and the time constant
+    #[arg(long)]
+    settle: bool,
+
+    /// Leave single readings more than this many standard deviations out of line (6 is a good
+    /// start) out of the min, max and mean and log them as Spike, with ,remove out of the
+    /// outputs' readings too (see the readme)
+    #[arg(long, value_name = "SIGMAS", value_parser = spike::Settings::parse)]
+    despike: Option<spike::Settings>,
+
+    /// Median, moving average and outlier stages from this file, each logged as a derived value
+    /// next to the reading and drawn as a trace of its own (the format is in the readme)
+    #[arg(long, value_name = "FILE")]
+    smooth: Option<std::path::PathBuf>,
 
-const GET_MEASUREMENT: [u8; 6] = [0xAB, 0xCD, 0x03, 0x5E, 0x01, 0xD9];
+    /// Only write readings this is true for, e.g. 'value > 0.5 && mode == "V_DC"', with value in
+    /// the unit without a prefix (the fields are in the readme). The rest are still counted
+    #[arg(long, value_name = "EXPRESSION", value_parser = filter::Filter::parse)]
+    filter: Option<filter::Filter>,
 
-fn open_ut61eplus(api: &HidApi) -> Option<HidDevice> {
-    for (vid, pid) in DEVICE_IDS {
-        if let Ok(dev) = api.open(*vid, *pid) {
-            println!("Opened UT61E+ with VID=0x{:04x}, PID=0x{:04x}", vid, pid);
-            return Some(dev);
+    /// Pause the outputs once the reading hasn't moved for this long, e.g. 10m, and carry on when
+    /// it does. The meter is still polled and the summary and alarms keep going
+    #[arg(long, value_parser = duration)]
+    idle: Option<time::Duration>,
+
+    /// How far the reading can wander and still not count as moving for --idle, in the unit
+    /// without a prefix, e.g. 0.002 for a couple of digits of noise on the 2 V range
+    #[arg(long, value_name = "AMOUNT", default_value_t = 0.0, requires = "idle")]
+    idle_band: f64,
+
+    /// Run each reading through fn reading(r) in this Rhai script, which can leave it out, log
+    /// derived values and raise alarms (see the readme). Needs the scripting feature
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "FILE")]
+    script: Option<std::path::PathBuf>,
+
+    /// Also hand the records to the programs in this file, as JSON lines on their stdin, for
+    /// outputs that aren't built in (the format is in the readme)
+    #[arg(long, value_name = "FILE")]
+    plugins: Option<std::path::PathBuf>,
+
+    /// Named sequences of key presses in this file, e.g. to put the meter on the mA range with
+    /// REL on, run with the macro subcommand, /macro NAME or macro NAME over --web-control (the
+    /// format is in the readme)
+    #[arg(long, value_name = "FILE")]
+    macros: Option<std::path::PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Pretty,
+    Csv,
+    /// A JSON object a line, as in schema/records.schema.json (see --emit-schema)
+    Json,
+    /// Typed columns for pandas/Polars, needs the parquet feature
+    Parquet,
+    /// MATLAB v5 arrays with the metadata as a struct
+    Mat,
+    /// NumPy arrays, a .npz or a record array in a .npy going by the extension
+    Numpy,
+    /// LabVIEW/DIAdem, a channel per mode
+    Tdms,
+    /// The values as sound at the rate they came in, for Audacity or sox
+    Wav,
+}
+
+impl Format {
+    /// `--format`, or else what the file is called, or else pretty
+    fn of(given: Option<Format>, path: Option<&std::path::Path>) -> Format {
+        let extension = path.and_then(|p| p.extension()).and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        match (given, extension.as_deref()) {
+            (Some(format), _) => format,
+            (None, Some("csv")) => Format::Csv,
+            (None, Some("json" | "jsonl")) => Format::Json,
+            (None, Some("parquet")) => Format::Parquet,
+            (None, Some("mat")) => Format::Mat,
+            (None, Some("npy" | "npz")) => Format::Numpy,
+            (None, Some("tdms")) => Format::Tdms,
+            (None, Some("wav")) => Format::Wav,
+            (None, _) => Format::Pretty,
         }
     }
-    None
+
+    /// Formats that are written as a whole at the end, not line by line, so only to a file
+    fn file_only(self) -> bool {
+        matches!(self, Format::Parquet | Format::Mat | Format::Numpy | Format::Tdms | Format::Wav)
+    }
 }
 
-fn send_command(dev: &HidDevice, cmd: &[u8]) -> Result<(), hidapi::HidError> {
-    let mut buf = Vec::with_capacity(cmd.len() + 1);
-    buf.push(cmd.len() as u8);
-    buf.extend_from_slice(cmd);
-    dev.write(&buf)?;
-    Ok(())
+#[derive(Clone, Copy, ValueEnum)]
+enum OnMaxErrors {
+    /// Print the summary and exit
+    Abort,
+    /// Reopen the device and carry on
+    Reconnect,
 }
 
-fn read_response(dev: &HidDevice) -> Option<Vec<u8>> {
-    let mut buf = [0u8; 64];
-    loop {
-        match dev.read(&mut buf) {
-            Ok(n) if n > 0 => {
-                // Skip first byte (length), look for 0xAB 0xCD header
-                let data = &buf[1..n];
-                if data.len() > 3 && data[0] == 0xAB && data[1] == 0xCD {
-                    // Length is data[2], payload is data[3..]
-                    let payload_len = data[2] as usize;
-                    if data.len() >= 3 + payload_len {
-                        // Drop last 2 bytes (checksum)
-                        return Some(data[3..3 + payload_len - 2].to_vec());
-                    }
-                }
+/// This many corrupt frames in a row means something is wrong with the link, not a one-off
+const CHECKSUM_STORM_FRAMES: u32 = 50;
+
+/// How often the min, max and mean go out to the outputs that show them
+const STATS_EVERY: time::Duration = time::Duration::from_secs(1);
+
+/// How long the meter has to stay silent before it's reported as off
+const SILENT_ALERT: time::Duration = time::Duration::from_secs(10);
+
+/// Polls without `--interval`, about as often as the meter updates its display
+const DEFAULT_INTERVAL: time::Duration = time::Duration::from_millis(1000 / 6);
+
+/// Set by the first Ctrl-C, logging stops cleanly at the end of the current poll
+static ABORT: AtomicBool = AtomicBool::new(false);
+
+/// Set by SIGUSR1 to start a burst
+static BURST: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_sigusr1(_: libc::c_int) {
+    BURST.store(true, Ordering::SeqCst);
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that the meter can be found, opened and read, and suggest fixes if not
+    Doctor,
+    /// Decode hex dumps read from stdin (frames or bare payloads, e.g. from a logic analyser
+    /// export or --show-raw) without a meter attached
+    Decode {
+        /// Print CSV instead of the decoded fields
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Render a CSV capture into an HTML report with a plot, statistics and events
+    Report {
+        /// CSV file written with --csv
+        input: std::path::PathBuf,
+        /// Where to write the report, defaults to the input with an .html extension
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+        /// Another capture to overlay for before/after comparisons, with a difference plot
+        #[arg(long)]
+        compare: Option<std::path::PathBuf>,
+        /// Window for the spectrum of each series
+        #[arg(long, value_enum, default_value_t)]
+        window: analysis::Window,
+        /// Bins for the histogram of each series, picked from the data by default
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..=1000))]
+        bins: Option<u16>,
+        /// Curve to fit for the trend of each series
+        #[arg(long, value_enum, default_value_t)]
+        fit: analysis::Fit,
+        /// Fit only from this many seconds into the capture (records without --timestamp)
+        #[arg(long)]
+        fit_from: Option<f64>,
+        /// Fit only up to this many seconds into the capture
+        #[arg(long)]
+        fit_to: Option<f64>,
+        /// Rename a series, picked by unit or mode, e.g. --name V_DC="5 V rail"
+        #[arg(long, value_name = "SERIES=NAME", value_parser = key_value)]
+        name: Vec<(String, String)>,
+        /// Colour for a series' plots, any CSS colour, e.g. --color V=#d62728
+        #[arg(long, value_name = "SERIES=COLOR", value_parser = key_value)]
+        color: Vec<(String, String)>,
+        /// Leave a series out of the report, by unit or mode
+        #[arg(long, value_name = "SERIES")]
+        hide: Vec<String>,
+        /// Only report these series, by unit or mode
+        #[arg(long, value_name = "SERIES", conflicts_with = "hide")]
+        solo: Vec<String>,
+        /// Colours for the plots
+        #[arg(long, value_enum, default_value_t)]
+        palette: report::Palette,
+    },
+    /// Guide someone through a test procedure step by step, checking each measurement against its
+    /// limits, and write a report of the run (Markdown)
+    Procedure {
+        /// The procedure, a small TOML file with a [[step]] for each measurement
+        file: std::path::PathBuf,
+        /// Where to write the report, stdout by default
+        #[arg(short, long)]
+        report: Option<std::path::PathBuf>,
+    },
+    /// Step a diode or LED through currents set by hand or read from a second meter and record
+    /// the forward voltage at each, or one part after another at the diode test current
+    Sweep {
+        /// Currents to ask for one at a time, e.g. 1m,2m,5m,10m,20m
+        #[arg(long, value_delimiter = ',', value_parser = sweep::amps)]
+        current: Vec<f64>,
+        /// Read the current from the meter of another logger, given by its socket or the meter's
+        /// serial number
+        #[cfg(unix)]
+        #[arg(long, value_name = "LOGGER", conflicts_with = "current")]
+        current_from: Option<String>,
+        /// How long to read at each step
+        #[arg(long, value_parser = duration, default_value = "1s")]
+        duration: time::Duration,
+        /// Where to write the steps as CSV, stdout by default
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+        /// Also draw them as an SVG, current against voltage or the voltage of each step
+        #[arg(long)]
+        plot: Option<std::path::PathBuf>,
+    },
+    /// Log a mains socket with the meter on V AC for as long as it takes: timestamps, the RMS
+    /// over a rolling window, sags and swells as events and a summary for each day
+    MonitorMains {
+        /// The nominal voltage
+        #[arg(long, default_value_t = 230.0)]
+        nominal: f64,
+        /// Percent below nominal that's a sag
+        #[arg(long, value_name = "PERCENT", default_value_t = 10.0)]
+        sag: f64,
+        /// Percent above nominal that's a swell
+        #[arg(long, value_name = "PERCENT", default_value_t = 10.0)]
+        swell: f64,
+        /// How far back the rolling RMS goes
+        #[arg(long, value_parser = duration, default_value = "1m")]
+        window: time::Duration,
+    },
+    /// Check that a file written with --hash hasn't changed since, against its .sha256
+    Verify {
+        /// The output file or its .sha256
+        file: std::path::PathBuf,
+    },
+    /// Follow a logger that's already running, printing its CSV as it's written
+    #[cfg(unix)]
+    Attach {
+        /// The logger's socket, only needed when more than one is running
+        socket: Option<std::path::PathBuf>,
+    },
+    /// One line per running logger with what it's reading right now
+    #[cfg(unix)]
+    Status,
+    /// Press the meter's LIGHT key, for one mounted somewhere dim. The meter only has the one key
+    /// for it and doesn't say whether the backlight is on, so this turns it on if it was off and
+    /// off if it was on. While logging use /light or light over --web-control instead
+    Backlight,
+    /// Press the keys of one of the --macros and exit. While logging use /macro NAME or macro NAME
+    /// over --web-control instead
+    Macro {
+        name: String,
+    },
+}
+
+/// Whether the script keeps the reading, and what it derived from it
+#[cfg(feature = "scripting")]
+fn run_script(script: Option<&mut script::Script>, sinks: &mut sink::Fanout, stamp: &clock::Stamp, m: &Measurement) -> std::io::Result<(bool, Vec<output::Derived>)> {
+    match script.and_then(|script| script.reading(stamp, m)) {
+        Some(Ok(outcome)) => {
+            for message in outcome.alarms.iter().map(|text| format!("Alarm: {}", text)) {
+                eprintln!("{}", message.yellow());
+                sinks.notice(stamp, &message)?;
             }
-            _ => return None,
+            Ok((outcome.keep, outcome.derived))
         }
+        Some(Err(e)) => {
+            let message = format!("The script failed, carrying on without it: {}", e);
+            eprintln!("{}", message.red());
+            sinks.notice(stamp, &message)?;
+            Ok((true, Vec::new()))
+        }
+        None => Ok((true, Vec::new())),
     }
 }
 
-fn parse_display_ascii(payload: &[u8]) -> String {
-    // Digits are at payload[2..9] (see Python code)
-    payload
-        .get(2..9)
-        .map(|slice| String::from_utf8_lossy(slice).replace(' ', ""))
-        .unwrap_or_else(|| "?".to_string())
-}
-
-fn parse_mode(mode: u8) -> &'static str {
-    match mode {
-        0 => "V_AC",
-        24 => "V_AC_LPF",
-        2 => "V_DC",
-        25 => "V_AC_DC",
-        1 => "mV_AC",
-        3 => "mV_DC",
-        6 => "Resistance Ω",
-        7 => "Continuity 🕪",
-        8 => "Diode 𜰏",
-        9 => "Capacitance 𜰓",
-        4 => "Hz",
-        5 => "%",
-        18 => "Transistor gain 𜰐 β hFE",
-        12 => "μA_DC",
-        13 => "μA_AC",
-        14 => "mA_DC",
-        15 => "mA_AC",
-        16 => "A_DC",
-        17 => "A_AC",
-        20 => "NCV",
-        _ => "?",
+/// An output file, with a sidecar when it's closed for --hash
+fn add_file(sinks: &mut sink::Fanout, path: &std::path::Path, file: impl sink::Sink + Send + 'static, session: Option<&output::Session>) {
+    let name = path.display().to_string();
+    match session {
+        Some(session) => sinks.add("file", name, integrity::Sealed::new(file, path, session.clone())),
+        None => sinks.add("file", name, file),
     }
 }
 
-fn parse_unit(mode: u8, range: u8) -> &'static str {
-    match mode {
-        0 => match range { // VAC
-            0x30 => "V",
-            0x31 => "V",
-            0x32 => "V",
-            0x33 => "V",
-            _ => "?",
-        },
-        24 => match range { // VAC LPF
-            0x30 => "V",
-            0x31 => "V",
-            0x32 => "V",
-            0x33 => "V",
-            _ => "?",
-        },
-        2 => match range { // VDC
-            0x30 => "V",
-            0x31 => "V",
-            0x32 => "V",
-            0x33 => "V",
-            _ => "?",
-        },
-        25 => match range { // VACDC
-            0x30 => "V",
-            0x31 => "V",
-            0x32 => "V",
-            0x33 => "V",
-            _ => "?",
-        },
-        1 => match range { // mVAC
-            0x30 => "mV",
-            _ => "?",
-        },
-        3 => match range { // mVDC
-            0x30 => "mV",
-            _ => "?",
-        },
-        6 => match range { // Resistance
-            0x30 => "Ω",
-            0x31 => "kΩ",
-            0x32 => "kΩ",
-            0x33 => "kΩ",
-            0x34 => "MΩ",
-            0x35 => "MΩ",
-            0x36 => "MΩ",
-            _ => "?",
-        },
-        7 => match range { // Continuity
-            0x30..=0x36 => "Ω",
-            _ => "?",
-        },
-        8 => match range { // Diode
-            0x30 => "V",
-            _ => "?",
-        },
-        9 => match range { // Capacitance
-            0x30 => "nF",
-            0x31 => "nF",
-            0x32 => "μF",
-            0x33 => "μF",
-            0x34 => "μF",
-            0x35 => "mF",
-            0x36 => "mF",
-            _ => "?",
-        },
-        4 => match range { // Hz
-            0x30 => "Hz",
-            0x31 => "Hz",
-            0x32 => "kHz",
-            0x33 => "kHz",
-            0x34 => "kHz",
-            0x35 => "MHz",
-            0x36 => "MHz",
-            0x37 => "MHz",
-            _ => "?",
-        },
-        5 => match range { // %
-            0x30 => "%",
-            _ => "?",
-        },
-        18 => match range { // hFE
-            0x30 => "β",
-            _ => "?",
-        },
-        12 => match range { // μA_DC
-            0x30 => "μA",
-            0x31 => "μA",
-            _ => "?",
-        },
-        13 => match range { // μA_AC
-            0x30 => "μA",
-            0x31 => "μA",
-            _ => "?",
+/// When the meter couldn't be opened because another logger has it, asks whether to follow
+/// that one instead. Only asked at a terminal, scripts get the error.
+#[cfg(unix)]
+fn offer_attach(e: &OpenError) -> Option<std::path::PathBuf> {
+    use std::io::IsTerminal;
+    let socket = match e {
+        OpenError::Logging(sockets) => sockets.first().cloned(),
+        // Meters without a serial number can't be matched to their logger, but if there's only
+        // one logger running it's a good guess
+        OpenError::Busy(_) => match ipc::running().as_slice() {
+            [socket] => Some(socket.clone()),
+            _ => None,
         },
-        14 => match range { // mA_DC
-            0x30 => "mA",
-            0x31 => "mA",
-            _ => "?",
-        },
-        15 => match range { // mA_AC
-            0x30 => "mA",
-            0x31 => "mA",
-            _ => "?",
-        },
-        16 => match range { // A_DC
-            0x31 => "A",
-            _ => "?",
-        },
-        17 => match range { // A_AC
-            0x31 => "A",
-            _ => "?",
-        },
-        20 => match range { // NCV
-            0x30 => "NCV",
-            _ => "?",
-        },
-        _ => "?",
+        _ => None,
+    }?;
+    if !std::io::stdin().is_terminal() {
+        return None;
     }
+    eprint!("{}", tr!("Attach to {} read-only instead? [Y/n] ", socket.display()));
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok()?;
+    matches!(answer.trim(), "" | "y" | "Y" | "yes" | "j" | "J" | "ja" | "是").then_some(socket)
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+/// `low:high`, low has to be below high
+#[cfg_attr(not(feature = "sonify"), allow(dead_code))]
+fn pair(s: &str) -> Result<(f64, f64), String> {
+    let (low, high) = s.split_once(':').ok_or("expected LOW:HIGH")?;
+    let low: f64 = low.parse().map_err(|_| format!("{:?} isn't a number", low))?;
+    let high: f64 = high.parse().map_err(|_| format!("{:?} isn't a number", high))?;
+    if low < high { Ok((low, high)) } else { Err("LOW has to be less than HIGH".to_owned()) }
+}
+
+fn shunt(s: &str) -> Result<f64, String> {
+    s.parse().ok().filter(|&ohms: &f64| ohms > 0.0 && ohms.is_finite()).ok_or_else(|| format!("expected a resistance in ohms like 0.1, got {:?}", s))
+}
+
+fn duration(s: &str) -> Result<time::Duration, String> {
+    clock::parse_duration(s).ok_or_else(|| format!("expected a duration like 500ms, 30s, 10m or 2h, got {:?}", s))
+}
+
+/// Bytes, with an optional k, M or G (powers of 1024)
+fn size(s: &str) -> Result<usize, String> {
+    let (number, scale) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("expected a size like 500k, 64M or 1G, got {:?}", s))
+}
+
+fn units(s: &str) -> protocol::Units {
+    match s {
+        "engineering" => protocol::Units::Engineering,
+        "si" => protocol::Units::Si,
+        _ => protocol::Units::Native,
+    }
+}
 
-    let api = HidApi::new()?;
-    let dev =
-        open_ut61eplus(&api).expect("UT61E+ device not found (tried all known VID/PID pairs)");
+fn key_value(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected SERIES=VALUE, got {:?}", s))
+}
 
-    // Enable UART, set baudrate, purge FIFOs
-    // dev.send_feature_report(&[0x41, 0x01])?;
-    // dev.send_feature_report(&[0x50, 0x00, 0x00, 0x25, 0x80, 0x00, 0x00, 0x03, 0x00, 0x00])?;
-    // dev.send_feature_report(&[0x43, 0x02])?;
+fn queue_policy(s: &str) -> Result<(String, sink::Policy), String> {
+    let (output, policy) = s.split_once('=').ok_or_else(|| format!("expected OUTPUT=POLICY, got {:?}", s))?;
+    Ok((output.to_owned(), policy.parse()?))
+}
 
-    if args.csv {
-        println!("value,unit,mode,range,rel,hold,minmax");
+fn osc_address(s: &str) -> Result<String, String> {
+    if s.starts_with('/') && !s.contains(' ') {
+        Ok(s.to_owned())
     } else {
-        println!("{}", "UT61E+ connected. Reading measurements...".bold().green());
-    }
-
-    loop {
-        send_command(&dev, &GET_MEASUREMENT)?;
-        if let Some(payload) = read_response(&dev) {
-            let display = parse_display_ascii(&payload);
-            let mode = payload.get(0).copied().unwrap_or(0);
-            let range = payload.get(1).copied().unwrap_or(0);
-            let unit = parse_unit(mode, range);
-            let mode_str = parse_mode(mode);
-
-            // Extract auto/manual status from second last byte
-            let auto_manual_byte = payload.get(payload.len().saturating_sub(2)).copied().unwrap_or(0);
-            let auto_manual = match auto_manual_byte {
-                48 => "AUTO",
-                52 => "MANUAL",
-                _ => "?",
-            };
+        Err("OSC addresses start with / and have no spaces".to_owned())
+    }
+}
 
-            // Extract REL status from third last byte
-            let flags_byte = payload.get(payload.len().saturating_sub(3)).copied().unwrap_or(0);
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = Args::parse();
+    i18n::set(args.lang.unwrap_or_else(i18n::from_env));
+    if args.emit_schema {
+        print!("{}", output::SCHEMA);
+        return Ok(());
+    }
 
-            // Bitwise flags
-            let rel = if flags_byte & 0x01 != 0 { "REL" } else { "" };
-            let hold = if flags_byte & 0x02 != 0 { "HOLD" } else { "" };
-            let minmax = match flags_byte {
-                56 => "MAX",
-                52 => "MIN",
-                _ => "",
+    match &args.command {
+        Some(Command::Doctor) => {
+            if !doctor::run() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Verify { file }) => {
+            match integrity::verify(file) {
+                Ok(()) => println!("{}", tr!("{}: unchanged", file.display()).green()),
+                Err(e) => {
+                    eprintln!("{}", e.red());
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Decode { csv }) => {
+            decode::run(*csv)?;
+            return Ok(());
+        }
+        Some(Command::Report { input, output, compare, window, bins, fit, fit_from, fit_to, name, color, hide, solo, palette }) => {
+            let options = report::Options {
+                window: *window,
+                bins: bins.map(usize::from),
+                fit: *fit,
+                fit_from: *fit_from,
+                fit_to: *fit_to,
+                names: name.clone(),
+                colors: color.clone(),
+                hide: hide.clone(),
+                solo: solo.clone(),
+                palette: *palette,
+            };
+            report::run(input, output.as_deref(), compare.as_deref(), &options)?;
+            return Ok(());
+        }
+        #[cfg(unix)]
+        Some(Command::Status) => {
+            let sockets = ipc::running();
+            if sockets.is_empty() {
+                eprintln!("{}", tr!("No logger is running.").red());
+                std::process::exit(1);
+            }
+            for socket in sockets {
+                let name = socket.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                match ipc::status(&socket, time::Duration::from_secs(5)) {
+                    Ok(status) => println!("{}: {}", name.bold(), status),
+                    Err(e) => println!("{}: {}", name.bold(), tr!("no answer, {}", e).red()),
+                }
+            }
+            return Ok(());
+        }
+        #[cfg(unix)]
+        Some(Command::Attach { socket }) => {
+            let socket = match socket {
+                Some(socket) => socket.clone(),
+                None => match ipc::running().as_slice() {
+                    [] => {
+                        eprintln!("{}", tr!("No logger is running.").red());
+                        std::process::exit(1);
+                    }
+                    [socket] => socket.clone(),
+                    sockets => {
+                        eprintln!("{}", tr!("More than one logger is running, pick one:").yellow());
+                        for socket in sockets {
+                            eprintln!("  ut61e_plus attach {}", socket.display());
+                        }
+                        std::process::exit(1);
+                    }
+                },
             };
+            ipc::attach(&socket)?;
+            return Ok(());
+        }
+        // A preset for the logger as usual
+        Some(Command::MonitorMains { .. }) => args.timestamp = true,
+        Some(Command::Procedure { .. } | Command::Sweep { .. } | Command::Backlight | Command::Macro { .. }) | None => {}
+    }
+    // Checked before the meter is opened, so a typo doesn't wait for the cable
+    let procedure = match &args.command {
+        Some(Command::Procedure { file, .. }) => match procedure::Procedure::load(file) {
+            Ok(procedure) => Some(procedure),
+            Err(e) => {
+                eprintln!("{}", e.red());
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    let named_alarms = match args.alarms.as_deref().map(alarm::load).transpose() {
+        Ok(alarms) => alarms.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{}", e.red());
+            std::process::exit(1);
+        }
+    };
+    let plugins = match args.plugins.as_deref().map(plugin::load).transpose() {
+        Ok(plugins) => plugins.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{}", e.red());
+            std::process::exit(1);
+        }
+    };
+    let mut smooth = match args.smooth.as_deref().map(smooth::Smooth::load).transpose() {
+        Ok(smooth) => smooth,
+        Err(e) => {
+            eprintln!("{}", e.red());
+            std::process::exit(1);
+        }
+    };
+    let macros = match args.macros.as_deref().map(macros::load).transpose() {
+        Ok(macros) => macros.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{}", e.red());
+            std::process::exit(1);
+        }
+    };
+    let web_config = match args.web_config.as_deref().map(web::Config::load).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("{}", e.red());
+            std::process::exit(1);
+        }
+    };
+    if let Some(Command::Macro { name }) = &args.command
+        && !macros.iter().any(|m| &m.name == name)
+    {
+        Args::command().error(clap::error::ErrorKind::InvalidValue, format!("no macro called {} in --macros", name)).exit();
+    }
+    #[cfg(feature = "scripting")]
+    let mut script = match args.script.as_deref().map(script::Script::load).transpose() {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("{}", e.red());
+            std::process::exit(1);
+        }
+    };
+
+    let given = if args.csv { Some(Format::Csv) } else { args.format };
+    let outputs: Vec<(&std::path::Path, Format)> = args.output.iter().map(|path| (path.as_path(), Format::of(given, Some(path)))).collect();
+    let parquet_output = outputs.iter().any(|&(_, format)| format == Format::Parquet);
+    if parquet_output && !cfg!(feature = "parquet") {
+        Args::command().error(clap::error::ErrorKind::InvalidValue, "built without the parquet feature").exit();
+    }
+    #[cfg(feature = "encrypt")]
+    if !args.encrypt_to.is_empty() && (outputs.iter().any(|&(_, format)| format.file_only()) || args.rollup.is_some()) {
+        Args::command().error(clap::error::ErrorKind::ArgumentConflict, "--encrypt-to only covers CSV and text outputs, not Parquet, .mat, NumPy, TDMS, WAV or --rollup").exit();
+    }
+    if given.is_some_and(Format::file_only) && outputs.is_empty() {
+        Args::command().error(clap::error::ErrorKind::MissingRequiredArgument, "--format parquet, mat, numpy, tdms and wav need --output <file>").exit();
+    }
+
+    let opened = match &args.simulate {
+        Some(sim) => Ok(Source::Simulated(sim.clone())),
+        None => HidApi::new().map(Source::Usb).map_err(OpenError::Api),
+    }
+    .and_then(|source| source.open().map(|meter| (source, meter)));
+    let (mut source, mut meter) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            eprintln!("{}", e.to_string().red());
+            #[cfg(unix)]
+            if args.command.is_none()
+                && let Some(socket) = offer_attach(&e)
+            {
+                ipc::attach(&socket)?;
+                return Ok(());
+            }
+            std::process::exit(e.exit_code());
+        }
+    };
+    if let (Some(procedure), Some(Command::Procedure { report, .. })) = (&procedure, &args.command) {
+        let interval = args.interval.unwrap_or(DEFAULT_INTERVAL);
+        std::process::exit(procedure::run(procedure, report.as_deref(), &mut meter, interval)?);
+    }
+    if let Some(Command::Sweep { current, #[cfg(unix)] current_from, duration, output, plot, .. }) = &args.command {
+        let options = sweep::Options {
+            currents: current,
+            #[cfg(unix)]
+            current_from: current_from.as_deref(),
+            #[cfg(not(unix))]
+            current_from: None,
+            duration: *duration,
+            output: output.as_deref(),
+            plot: plot.as_deref(),
+        };
+        sweep::run(&options, &mut meter, args.interval.unwrap_or(DEFAULT_INTERVAL))?;
+        return Ok(());
+    }
+    if let Some(Command::Backlight) = &args.command {
+        meter.press(protocol::CMD_LIGHT)?;
+        return Ok(());
+    }
+    if let Some(Command::Macro { name }) = &args.command
+        && let Some(m) = macros.iter().find(|m| &m.name == name)
+    {
+        m.run(&mut meter)?;
+        return Ok(());
+    }
+    let retry = RetryPolicy {
+        attempts: args.retries,
+        backoff: time::Duration::from_millis(args.retry_backoff),
+    };
+
+    // Bridges that don't need the UART set up on open (see quirks.rs) only get it from the
+    // watchdog when the cable seems to have stalled
+    let mut watchdog = watchdog::Watchdog::new(time::Duration::from_secs_f64(args.stall_timeout));
 
-            if args.csv {
-                println!("{},{},{},{},{},{},{}", display, unit, mode_str, auto_manual, rel, hold, minmax);
-            } else {
-                println!(
-                    "{} {} {} {} {} {} {}",
-                    display.bold().yellow(),
-                    unit.cyan(),
-                    format!("({})", mode_str).blue(),
-                    format!("[{}]", auto_manual).magenta(),
-                    rel.red(),
-                    hold.red(),
-                    minmax.red()
-                );
+    let columns = output::Columns {
+        seq: args.seq,
+        timestamp: args.timestamp,
+        uncertainty: args.uncertainty,
+        units: args.units,
+        limits: (args.limit_low.is_some() || args.limit_high.is_some()).then_some(output::Limits { low: args.limit_low, high: args.limit_high }),
+    };
+    let flush_every = args.flush_every.unwrap_or(output::FlushEvery::Lines(1));
+    let mut sinks = sink::Fanout::new(args.queue.clone());
+    let session = output::Session {
+        meter: meter.serial.clone().unwrap_or_else(|| "no serial number".to_owned()),
+        command: std::env::args().collect::<Vec<_>>().join(" "),
+        started: time::SystemTime::now(),
+    };
+    let sealed = args.hash.then_some(&session);
+    // stdout unless there are files to write instead
+    let files = outputs.iter().map(|&(path, format)| (Some(path), format));
+    let stdout = outputs.is_empty().then_some((None, Format::of(given, None)));
+    for (path, format) in stdout.into_iter().chain(files) {
+        if format == Format::Parquet {
+            #[cfg(feature = "parquet")]
+            if let Some(path) = path {
+                add_file(&mut sinks, path, parquet_file::ParquetFile::create(path, columns.units)?, sealed);
+            }
+            continue;
+        }
+        match (format, path) {
+            (Format::Mat | Format::Numpy, Some(path)) => {
+                add_file(&mut sinks, path, export::Arrays::create(path, format == Format::Mat, columns.units, session.clone())?, sealed);
+                continue;
             }
+            (Format::Tdms, Some(path)) => {
+                add_file(&mut sinks, path, tdms::Tdms::create(path, columns.units, session.clone())?, sealed);
+                continue;
+            }
+            (Format::Wav, Some(path)) => {
+                add_file(&mut sinks, path, wav::Wav::create(path, session.clone())?, sealed);
+                continue;
+            }
+            _ => {}
+        }
+        let target: Box<dyn Write + Send> = match path {
+            #[cfg(feature = "encrypt")]
+            Some(path) if !args.encrypt_to.is_empty() => Box::new(encrypt::Encrypted::create(path, &args.encrypt_to)?),
+            Some(path) => Box::new(std::fs::File::create(path)?),
+            None => Box::new(std::io::stdout()),
+        };
+        let mut out = output::Flushing::new(target, flush_every);
+        if format == Format::Json {
+            let json = sink::JsonLines::new(out, columns);
+            match path {
+                Some(path) => add_file(&mut sinks, path, json, sealed),
+                None => sinks.add("stdout", "stdout", json),
+            }
+            continue;
+        }
+        if format == Format::Csv {
+            output::write_csv_header(&mut out, &columns)?;
         } else {
-            if !args.csv {
-                println!("{}", "No response or parse error.".red());
+            writeln!(out, "{}", tr!("UT61E+ connected. Reading measurements...").bold().green())?;
+        }
+        out.flush()?;
+        let text = sink::Text::new(out, columns, format == Format::Csv);
+        match path {
+            Some(path) => add_file(&mut sinks, path, text, sealed),
+            None => sinks.add("stdout", "stdout", text),
+        }
+    }
+    if let Some(path) = &args.rollup {
+        add_file(&mut sinks, path, rollup::Rollup::create(path)?, sealed);
+    }
+    if !outputs.is_empty() && outputs.iter().all(|&(_, format)| format.file_only()) {
+        let names: Vec<String> = outputs.iter().map(|(path, _)| path.display().to_string()).collect();
+        eprintln!("{}", tr!("UT61E+ connected. Writing {}...", names.join(", ")));
+    }
+
+    // Local consumers always get CSV, whatever goes to stdout
+    #[cfg(unix)]
+    {
+        let mut header = Vec::new();
+        output::write_csv_header(&mut header, &columns)?;
+        let mut local = local::Local::new(header);
+        if let Some(path) = &args.fifo {
+            local.fifo = Some(local::Fifo::create(path)?);
+        }
+        if let Some(path) = &args.unix_socket {
+            local.sockets.push(local::Socket::bind(path)?);
+        }
+        if !args.no_attach {
+            let path = ipc::path(meter.serial.as_deref());
+            match ipc::create_dir().and_then(|()| local::Socket::bind(&path)) {
+                Ok(socket) => local.sockets.push(socket),
+                Err(e) => eprintln!("{}", tr!("Can't listen for attach at {}: {}", path.display(), e).yellow()),
+            }
+        }
+        let local: Box<dyn Write + Send> = Box::new(local);
+        sinks.add("local", "local consumers", sink::Text::new(output::Flushing::new(local, output::FlushEvery::Lines(1)), columns, true));
+    }
+    if let Some(target) = &args.udp {
+        sinks.add("udp", target.clone(), sink::Datagrams { udp: udp::Udp::connect(target)?, columns });
+    }
+    #[cfg(feature = "sonify")]
+    if args.sonify {
+        let mapping = sonify::Mapping {
+            values: args.sonify_range,
+            pitch: (args.sonify_pitch.0 as f32, args.sonify_pitch.1 as f32),
+            scale: args.sonify_scale,
+        };
+        sinks.add_inline("sonify", sink::Sonify { sonify: sonify::Sonify::start()?, mapping });
+    }
+    let console = args.interactive.then(console::Console::start);
+    #[cfg(feature = "clipboard")]
+    let mut clipboard = None;
+    let mut latest = None;
+    if let Some(target) = &args.osc {
+        sinks.add("osc", target.clone(), sink::Osc { udp: udp::Udp::connect(target)?, address: args.osc_address.clone() });
+    }
+    // Commands from --web-control
+    let control = std::sync::mpsc::channel();
+    // Servers for --mdns to advertise
+    let mut services = Vec::new();
+    if let Some(addr) = &args.modbus {
+        let modbus = modbus::Server::serve(addr)?;
+        services.push(("_ut61e._tcp", modbus.addr(), vec!["proto=modbus".to_owned()]));
+        services.push(("_modbus._tcp", modbus.addr(), vec![]));
+        sinks.add("modbus", format!("Modbus on {}", addr), modbus);
+    }
+    #[cfg(feature = "opcua")]
+    if let Some(addr) = &args.opcua {
+        let opcua = opcua::Server::serve(addr)?;
+        sinks.add("opcua", format!("OPC UA on opc.tcp://{}", opcua.addr()), opcua);
+    }
+    let mut identity = None;
+    if let Some(addr) = &args.scpi {
+        let scpi = scpi::Server::serve(addr, meter.serial.clone())?;
+        services.push(("_scpi-raw._tcp", scpi.identity().scpi, vec![]));
+        identity = Some(scpi.identity().clone());
+        sinks.add("scpi", format!("SCPI on {}", addr), scpi);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(target) = &args.panel {
+        sinks.add("panel", "the panel", sink::Panel { panel: panel::Panel::start(target)?, units: columns.units });
+    }
+    if let Some(addr) = &args.web {
+        let token = web_config.token;
+        let auth = if token.is_some() { "token" } else { "none" };
+        let history = args.history.map(|keep| (keep, args.history_memory));
+        let web = web::Web::serve(addr, columns, history, token, args.web_control.then(|| control.0.clone()))?;
+        let txt = vec!["proto=http".to_owned(), "path=/".to_owned(), "ws=/ws".to_owned(), format!("history={}", args.history.is_some()), format!("auth={}", auth)];
+        // The dashboard is the _ut61e._tcp one when there's Modbus too
+        services.retain(|(kind, _, _)| *kind != "_ut61e._tcp");
+        services.push(("_ut61e._tcp", web.addr(), txt));
+        services.push(("_http._tcp", web.addr(), vec!["path=/".to_owned()]));
+        if let Some(identity) = identity {
+            let txt = vec![
+                format!("Manufacturer={}", scpi::Identity::MANUFACTURER),
+                format!("Model={}", scpi::Identity::MODEL),
+                format!("SerialNumber={}", identity.serial()),
+                format!("FirmwareVersion={}", scpi::Identity::VERSION),
+            ];
+            services.push(("_lxi._tcp", web.addr(), txt));
+            web.identify(identity);
+        }
+        if args.web_control {
+            web.offer_macros(macros.iter().map(|m| m.name.clone()).collect());
+        }
+        if args.kiosk
+            && let Err(e) = web.kiosk()
+        {
+            eprintln!("{}", tr!("Can't open the kiosk browser: {}", e).yellow());
+        }
+        sinks.add("web", format!("the dashboard on {}", addr), web);
+    }
+    #[cfg(unix)]
+    if args.mdns {
+        // Nobody else could connect to these
+        services.retain(|(_, addr, _)| !addr.ip().is_loopback());
+        let services = services.into_iter().map(|(kind, addr, txt)| mdns::Service { kind, port: addr.port(), txt }).collect::<Vec<_>>();
+        if services.is_empty() {
+            eprintln!("{}", tr!("Nothing for --mdns to advertise, --web, --modbus or --scpi on an address other machines can reach").yellow());
+        } else if let Err(e) = mdns::start(meter.serial.as_deref(), services) {
+            eprintln!("{}", tr!("Can't advertise over mDNS: {}", e).yellow());
+        }
+    }
+    if let (Some(server), Some(host)) = (&args.zabbix, &args.zabbix_host) {
+        sinks.add("zabbix", format!("Zabbix at {}", server), zabbix::Zabbix::start(server, host, time::Duration::from_secs_f64(args.zabbix_interval)));
+    }
+    #[cfg(feature = "kafka")]
+    if let (Some(brokers), Some(topic)) = (&args.kafka, &args.kafka_topic) {
+        let key = meter.serial.clone().unwrap_or_else(|| "unknown".to_owned());
+        sinks.add("kafka", format!("Kafka topic {}", topic), sink::Kafka { kafka: kafka::Kafka::connect(brokers, topic)?, key, columns });
+    }
+    for spec in &plugins {
+        sinks.add("plugin", spec.name.clone(), plugin::Plugin::start(spec, &session.meter, columns)?);
+    }
+
+    // Set once a gap has been written, so a run of failed polls is marked once
+    let mut in_gap = false;
+    // When --keep-alive last pressed a key
+    let mut kept_alive: Option<time::Instant> = None;
+    // Since when the meter hasn't answered at all, and whether that's been reported
+    let mut silent: Option<(time::Instant, bool)> = None;
+    let mut bad_checksums = 0;
+    let mut summary = summary::Summary::new();
+    summary.limits = columns.limits;
+    // A second Ctrl-C doesn't wait, e.g. while stuck reconnecting
+    ctrlc::set_handler(|| {
+        if ABORT.swap(true, Ordering::SeqCst) {
+            std::process::exit(exit_code::USER_ABORT);
+        }
+    })?;
+    #[cfg(unix)]
+    if args.burst.is_some() && args.schedule.is_none() {
+        unsafe { libc::signal(libc::SIGUSR1, on_sigusr1 as *const () as libc::sighandler_t) };
+    }
+    let mut interval = args.interval.unwrap_or(DEFAULT_INTERVAL);
+    let mut alarms = alarm::Alarms::new(&args.alarm, named_alarms);
+    // So the dashboard hears of it straight away rather than with the next stats
+    let mut alarms_changed = false;
+    // By an alarm with action stop
+    let mut alarm_stop = false;
+    // By --stop-when
+    let mut condition_met = false;
+    let mut changes = changes::Changes::default();
+    let mut despike = args.despike.map(spike::Despike::new);
+    let mut spikes = 0;
+    let mut idle = args.idle.map(|after| idle::Idle::new(after, args.idle_band.abs()));
+    #[cfg(unix)]
+    let power_with = args.power_with.as_deref();
+    #[cfg(not(unix))]
+    let power_with = None;
+    let mut power = (power_with.is_some() || args.shunt.is_some()).then(|| power::Power::new(power_with, args.shunt));
+    let mut settle = args.settle.then(settle::Settle::default);
+    let mut mains = match args.command {
+        Some(Command::MonitorMains { nominal, sag, swell, window }) => Some(mains::Mains::new(mains::Settings { nominal, sag, swell, window })),
+        _ => None,
+    };
+    // By a stop command, nothing is polled until start
+    let mut stopped = false;
+    let mut stats_sent = None;
+    let mut clock = clock::Clock::new();
+    // The burst underway, with --schedule None until the first window
+    let mut active: Option<schedule::Active> = None;
+    // So --burst-trigger fires when the level is crossed, not on every reading past it
+    let mut triggered = false;
+    let code = loop {
+        if ABORT.load(Ordering::SeqCst) {
+            break exit_code::USER_ABORT;
+        }
+
+        if let (Some(schedule), Some(burst)) = (&args.schedule, args.burst)
+            && active.as_ref().is_none_or(schedule::Active::done)
+        {
+            let Some(next) = schedule.next_after(time::SystemTime::now()) else {
+                eprintln!("{}", tr!("The schedule never matches, nothing to do.").red());
+                break 1;
+            };
+            // Nothing between bursts isn't missing data, but plots still shouldn't join the dots
+            if active.is_some() {
+                let stamp = clock.stamp().0;
+                sinks.gap(&stamp, "schedule")?;
+                sinks.tick()?;
+            }
+            eprintln!("{}", tr!("Next burst at {}", clock::Utc(next)));
+            while time::SystemTime::now() < next && !ABORT.load(Ordering::SeqCst) {
+                thread::sleep(time::Duration::from_millis(200));
+            }
+            if ABORT.load(Ordering::SeqCst) {
+                continue;
+            }
+            active = Some(schedule::Active::start(burst));
+            // The meter wasn't stalled, just not asked
+            watchdog.feed();
+            summary.pause();
+        }
+
+        // Written with this poll's timestamp
+        let mut events = Vec::new();
+        let mut reset_stats = false;
+        let mut rearm = false;
+        let mut light = false;
+        let mut run = Vec::new();
+        while let Some(input) = console.as_ref().and_then(console::Console::poll) {
+            match input {
+                console::Input::Copy => {
+                    let Some((stamp, m)) = &latest else {
+                        eprintln!("{}", tr!("No reading to copy yet").yellow());
+                        continue;
+                    };
+                    let text = output::plain(stamp, m, columns.units);
+                    #[cfg(feature = "clipboard")]
+                    {
+                        // Opened on first use, and kept so the copy outlives the call on X11
+                        if clipboard.is_none() {
+                            clipboard = arboard::Clipboard::new().map_err(|e| eprintln!("{}", tr!("No clipboard: {}", e).red())).ok();
+                        }
+                        if let Some(clipboard) = &mut clipboard {
+                            match clipboard.set_text(&text) {
+                                Ok(()) => eprintln!("{}", tr!("Copied {}", text)),
+                                Err(e) => eprintln!("{}", tr!("Couldn't copy: {}", e).red()),
+                            }
+                        }
+                    }
+                    #[cfg(not(feature = "clipboard"))]
+                    eprintln!("{}", tr!("{} (built without the clipboard feature, so not copied)", text));
+                }
+                console::Input::Annotate(text) => events.push(format!("Note: {}", text)),
+                console::Input::Burst => {
+                    BURST.store(true, Ordering::SeqCst);
+                }
+                console::Input::Stats => match summary.stats.all() {
+                    [] => eprintln!("{}", tr!("No readings to go on yet")),
+                    all => all.iter().for_each(|tracked| eprintln!("{}\n  {}", summary::describe(tracked), summary::peaks(tracked))),
+                },
+                console::Input::Reset => reset_stats = true,
+                console::Input::Rearm => rearm = true,
+                console::Input::Light => light = true,
+                console::Input::Macro(name) => run.push(name),
+                console::Input::Unknown(line) => eprintln!("{}", tr!("Unknown command {}, try /burst, /stats, /reset, /rearm, /light or /macro", line).yellow()),
+            }
+        }
+        while let Ok(command) = control.1.try_recv() {
+            match command {
+                control::Command::Stop if !stopped => {
+                    eprintln!("{}", tr!("Stopped over the API"));
+                    let stamp = clock.stamp().0;
+                    for message in events.drain(..).chain(["Logging stopped".to_owned()]) {
+                        sinks.event(&stamp, &message)?;
+                    }
+                    sinks.gap(&stamp, "stopped")?;
+                    stopped = true;
+                }
+                control::Command::Start if stopped => {
+                    eprintln!("{}", tr!("Started over the API"));
+                    events.push("Logging started".to_owned());
+                    // The meter wasn't stalled, just not asked
+                    watchdog.feed();
+                    summary.pause();
+                    stopped = false;
+                }
+                control::Command::Stop | control::Command::Start => {}
+                control::Command::Interval(new) => {
+                    events.push(format!("Interval changed to {:.3} s", new.as_secs_f64()));
+                    interval = new;
+                }
+                control::Command::Alarm(levels) => {
+                    let levels_text: Vec<String> = levels.iter().map(ToString::to_string).collect();
+                    events.push(match levels.is_empty() {
+                        true => "Alarms off".to_owned(),
+                        false => format!("Alarms set to {}", levels_text.join(" ")),
+                    });
+                    alarms.set(&levels);
+                    alarms_changed = true;
+                }
+                control::Command::Rearm => rearm = true,
+                control::Command::Light => light = true,
+                control::Command::Macro(name) => run.push(name),
+                control::Command::Note(text) => events.push(format!("Note: {}", text)),
+                control::Command::Burst => BURST.store(true, Ordering::SeqCst),
+                control::Command::Reset => reset_stats = true,
+            }
+        }
+        if light {
+            match meter.press(protocol::CMD_LIGHT) {
+                Ok(()) => events.push("Pressed LIGHT".to_owned()),
+                Err(e) => eprintln!("{}", tr!("Can't press LIGHT: {}", e).red()),
+            }
+            // It may have taken a read timeout
+            summary.pause();
+        }
+        for name in run {
+            let Some(m) = macros.iter().find(|m| m.name == name) else {
+                eprintln!("{}", tr!("No macro called {}", name).yellow());
+                continue;
+            };
+            match m.run(&mut meter) {
+                Ok(()) => events.push(format!("Ran macro {}", name)),
+                Err(e) => {
+                    eprintln!("{}", tr!("Macro {} stopped: {}", name, e).red());
+                    events.push(format!("Macro {} stopped: {}", name, e));
+                }
+            }
+            // Not polled while it ran, on purpose
+            watchdog.feed();
+            summary.pause();
+        }
+        if rearm {
+            events.push(format!("Rearmed {} latched alarms", alarms.rearm()));
+            alarms_changed = true;
+        }
+        if std::mem::take(&mut reset_stats) {
+            summary.stats.reset();
+            if let Some(settle) = &mut settle {
+                settle.reset();
+            }
+            sinks.stats(None)?;
+            events.push("Min, max and mean reset".to_owned());
+        }
+        if stopped {
+            // Notes still go in, with nothing to poll they get a timestamp of their own
+            if !events.is_empty() {
+                let stamp = clock.stamp().0;
+                for message in &events {
+                    sinks.event(&stamp, message)?;
+                }
+            }
+            sinks.tick()?;
+            thread::sleep(interval.min(time::Duration::from_millis(200)));
+            continue;
+        }
+
+        // Scheduled bursts are finished off at the top of the loop, they end with a gap
+        if args.schedule.is_none() && active.as_ref().is_some_and(schedule::Active::done) {
+            active = None;
+            events.push("Burst done".to_owned());
+        }
+        if BURST.swap(false, Ordering::SeqCst)
+            && let (None, Some(burst)) = (&args.schedule, args.burst)
+        {
+            if active.is_none() {
+                events.push("Burst started".to_owned());
+            }
+            active = Some(schedule::Active::start(burst));
+        }
+
+        if let Some(every) = args.keep_alive
+            && kept_alive.is_none_or(|pressed| pressed.elapsed() >= every)
+        {
+            // Twice leaves the backlight as it was
+            if let Err(e) = meter.press(protocol::CMD_LIGHT).and_then(|_| meter.press(protocol::CMD_LIGHT)) {
+                eprintln!("{}", tr!("Can't press a key to keep the meter on: {}", e).yellow());
+            }
+            kept_alive = Some(time::Instant::now());
+            summary.pause();
+        }
+        if let Some((missed, apart)) = summary.poll() {
+            events.push(format!("Missed {} meter updates (polls {:.2} s apart)", missed, apart.as_secs_f64()));
+        }
+        let exchange = retry.run(|| {
+            meter.send_command(&GET_MEASUREMENT)?;
+            meter.read_frame()
+        });
+        let n = match exchange {
+            Ok(n) => n,
+            Err(e) => {
+                eprintln!("{}", tr!("Lost the meter ({}), reconnecting...", e).red());
+                let stamp = clock.stamp().0;
+                sinks.gap(&stamp, "reconnect")?;
+                in_gap = true;
+                summary.error(summary::Error::Read);
+                summary.reconnects += 1;
+                summary.pause();
+                meter = source.reconnect();
+                continue;
+            }
+        };
+
+        let payload = match n {
+            0 => Err(protocol::FrameError::NoHeader),
+            _ => protocol::find_frame(meter.frame()),
+        };
+        let (stamp, jump) = clock.stamp();
+        if let Some(jump) = jump {
+            events.push(format!("Wall clock jumped {:+.3} s (NTP step or suspend/resume)", jump));
+        }
+        for message in &events {
+            sinks.event(&stamp, message)?;
+        }
+
+        if args.show_raw {
+            match payload {
+                Ok(payload) => output::write_raw(&mut std::io::stderr(), payload)?,
+                Err(e) if n > 0 => eprintln!("raw: {:02x?} ({})", meter.frame(), e),
+                Err(_) => {}
+            }
+        }
+        match payload {
+            Err(protocol::FrameError::BadChecksum { .. }) => bad_checksums += 1,
+            Ok(_) => bad_checksums = 0,
+            Err(_) => {}
+        }
+        if bad_checksums >= CHECKSUM_STORM_FRAMES {
+            eprintln!("{}", tr!("{} corrupt frames in a row, giving up. Check the cable.", bad_checksums).red());
+            break exit_code::CHECKSUM_STORM;
+        }
+        let measurement = payload.ok().and_then(Measurement::decode);
+        // A reading out of line waits for the next to tell a spike from the input moving, so a
+        // poll can have the one held back the poll before to handle as well as its own, or neither
+        let (released, hold) = match (&mut despike, &measurement) {
+            (Some(despike), Some(m)) => despike.sample(&stamp, m),
+            _ => (None, false),
+        };
+        let released = released.map(|held| match held {
+            spike::Held::Spike(stamp, m) => (stamp, m, true),
+            spike::Held::Step(stamp, m) => (stamp, m, false),
+        });
+        // Two at most, so no Vec to allocate on every poll
+        let readings = [released, measurement.filter(|_| !hold).map(|m| (stamp, m, false))];
+        match (measurement, payload) {
+            (Some(_), _) => {}
+            (None, Err(protocol::FrameError::BadChecksum { .. })) => summary.error(summary::Error::BadChecksum),
+            (None, _) if n == 0 => summary.error(summary::Error::Timeout),
+            (None, _) => summary.error(summary::Error::Parse),
+        }
+
+        let recovery = match watchdog.check() {
+            watchdog::Action::Nothing => None,
+            watchdog::Action::Reinit => {
+                let result = meter.init_uart();
+                Some(format!(
+                    "No valid frame for {:.1} s, re-initialised the UART{}",
+                    args.stall_timeout,
+                    result.err().map_or(String::new(), |e| format!(" (failed: {})", e))
+                ))
+            }
+            watchdog::Action::Reopen => {
+                drop(meter);
+                meter = source.reconnect();
+                summary.reconnects += 1;
+                summary.pause();
+                Some(format!("Still no valid frame after {:.1} s, reopened the device", 2.0 * args.stall_timeout))
+            }
+        };
+        if let Some(message) = recovery {
+            eprintln!("{}", message.yellow());
+            sinks.notice(&stamp, &message)?;
+        }
+
+        // With the cable still there, nothing at all coming back is usually the meter having
+        // switched itself off, which otherwise only shows as a gap in a log nobody's watching
+        if measurement.is_some() {
+            if silent.take().is_some_and(|(_, reported)| reported) {
+                eprintln!("{}", tr!("The meter is answering again").green());
+                sinks.notice(&stamp, "Meter answering again")?;
+            }
+        } else if n == 0 {
+            let (since, reported) = silent.get_or_insert((time::Instant::now(), false));
+            if !*reported && since.elapsed() >= SILENT_ALERT {
+                *reported = true;
+                eprintln!("\x07{}", tr!("The meter has stopped answering, has it turned itself off? --keep-alive stops auto power-off").red().bold());
+                sinks.notice(&stamp, "Meter stopped answering (auto power-off?)")?;
+            }
+        }
+
+        if let Some(max) = args.max_errors
+            && summary.consecutive >= max
+        {
+            let message = format!("{} failed polls in a row", summary.consecutive);
+            match args.on_max_errors {
+                OnMaxErrors::Abort => {
+                    eprintln!("{}", format!("{}, giving up.", message).red());
+                    break exit_code::TOO_MANY_ERRORS;
+                }
+                OnMaxErrors::Reconnect => {
+                    let message = format!("{}, reopened the device", message);
+                    eprintln!("{}", message.yellow());
+                    sinks.notice(&stamp, &message)?;
+                    drop(meter);
+                    meter = source.reconnect();
+                    summary.reconnects += 1;
+                    summary.pause();
+                    summary.consecutive = 0;
+                }
+            }
+        }
+
+        if readings.iter().all(Option::is_none) && !hold {
+            if !in_gap {
+                sinks.gap(&stamp, if n == 0 { "no response" } else { "parse error" })?;
+                in_gap = true;
+            }
+            sinks.missed()?;
+        }
+        for (stamp, m, spike) in readings.into_iter().flatten() {
+            in_gap = false;
+            watchdog.feed();
+            // For each reading in the order they're handled, one held back from before the change
+            // is still counted on the old baseline. Before it's counted, so after a rebaseline
+            // it's the first on the new one.
+            if let Some(change) = changes.sample(&m) {
+                if args.mark_changes {
+                    sinks.notice(&stamp, &change.message)?;
+                }
+                if change.rebaseline {
+                    summary.stats.restart(&m);
+                    if let Some(settle) = &mut settle {
+                        settle.reset();
+                    }
+                    sinks.notice(&stamp, "Min/max/mean and fits started over on the new baseline")?;
+                }
+            }
+            // Nothing to do with the input, so it's only written, and only where a reading would be
+            if spike {
+                spikes += 1;
+                let keep = args.filter.as_ref().is_none_or(|filter| filter.matches(&stamp, &m)) && !idle.as_ref().is_some_and(idle::Idle::paused);
+                if keep && !args.despike.is_some_and(|despike| despike.remove) {
+                    sinks.sample(&stamp, &m)?;
+                }
+                if keep && let Some(derived) = spike::derived(&m) {
+                    sinks.derived(&stamp, &derived)?;
+                }
+                continue;
+            }
+            // Here rather than for the whole poll, so one left unhandled after a stop isn't counted
+            summary.sample(&stamp, &m);
+            latest = Some((stamp, m));
+            if let Some(active) = &mut active {
+                active.taken += 1;
+            }
+            for fired in alarms.check(&stamp, &m) {
+                match fired.severity {
+                    alarm::Severity::Info => eprintln!("{}", fired.message),
+                    alarm::Severity::Warning => eprintln!("{}", fired.message.yellow()),
+                    alarm::Severity::Critical => eprintln!("{}", fired.message.red().bold()),
+                }
+                sinks.notice(&stamp, &fired.message)?;
+                if let Err(e) = fired.run() {
+                    eprintln!("{}", tr!("Can't run the alarm's command: {}", e).red());
+                }
+                alarm_stop |= fired.stops();
+                alarms_changed = true;
+            }
+            if let Some(trigger) = args.burst_trigger {
+                let matches = m.reading().value().is_some_and(|v| trigger.matches(v));
+                if matches && !triggered {
+                    BURST.store(true, Ordering::SeqCst);
+                }
+                triggered = matches;
+            }
+            // Readings left out still feed the running totals, only nothing of them is written
+            #[cfg(feature = "scripting")]
+            let (keep, scripted) = run_script(script.as_mut(), &mut sinks, &stamp, &m)?;
+            #[cfg(not(feature = "scripting"))]
+            let (keep, scripted) = (true, Vec::new());
+            match idle.as_mut().and_then(|idle| idle.sample(&stamp, &m)) {
+                Some(idle::Change::Paused) => {
+                    eprintln!("{}", tr!("Nothing's changed for {}, pausing the outputs until it does", format!("{:?}", args.idle.unwrap_or_default())).yellow());
+                    sinks.notice(&stamp, "Outputs paused while idle")?;
+                    sinks.gap(&stamp, "idle")?;
+                }
+                Some(idle::Change::Resumed) => {
+                    eprintln!("{}", tr!("The reading moved, logging again").green());
+                    sinks.notice(&stamp, "Outputs resumed")?;
+                }
+                None => {}
+            }
+            let keep = keep && args.filter.as_ref().is_none_or(|filter| filter.matches(&stamp, &m));
+            let keep = keep && !idle.as_ref().is_some_and(idle::Idle::paused);
+            if keep {
+                sinks.sample(&stamp, &m)?;
+            }
+            let power = power.as_mut().map(|power| power.sample(&stamp, &m));
+            let settled = settle.as_mut().map(|settle| settle.sample(&stamp, &m));
+            let temperature = args.ntc.map(|ntc| (ntc.derived(&m).into_iter().collect(), None));
+            let derived = power.into_iter().chain(settled).chain(temperature).map(|(derived, message)| (derived, Vec::from_iter(message)));
+            let derived = derived.chain(mains.as_mut().map(|mains| mains.sample(&stamp, &m))).chain([(scripted, Vec::new())]);
+            let derived = derived.chain(smooth.as_mut().map(|smooth| (smooth.sample(&stamp, &m), Vec::new())));
+            for (derived, messages) in derived {
+                for message in messages {
+                    eprintln!("{}", message.yellow());
+                    sinks.notice(&stamp, &message)?;
+                }
+                for derived in derived.iter().filter(|_| keep) {
+                    sinks.derived(&stamp, derived)?;
+                }
+            }
+            if let Some(when) = &args.stop_when
+                && when.matches(&stamp, &m)
+            {
+                let scaled = m.reading_in(Units::Native);
+                eprintln!("{}", tr!("Stopping, {} at {} {}", when, scaled.reading, scaled.unit).green());
+                sinks.notice(&stamp, &format!("Stopped: {} at {} {}", when, scaled.reading, scaled.unit))?;
+                condition_met = true;
+            }
+            if stats_sent.is_none_or(|sent: time::Instant| sent.elapsed() >= STATS_EVERY) {
+                sinks.stats(summary.stats.current())?;
+                stats_sent = Some(time::Instant::now());
+                alarms_changed = true;
+            }
+            // The reading that stopped it is the last one written, also with another after it
+            if condition_met || alarm_stop {
+                break;
             }
         }
 
+        if std::mem::take(&mut alarms_changed) {
+            sinks.alarms(&alarms.statuses())?;
+        }
+        sinks.tick()?;
+        if alarm_stop {
+            break exit_code::ALARM_TRIPPED;
+        }
+        if condition_met || args.count.is_some_and(|count| summary.samples >= count) {
+            break 0;
+        }
         // UT61 display updates around 3 times
         // per second but this code is not particularly fast either and I'm not sure what the limit
         // is on the USB
-        thread::sleep(time::Duration::from_millis(1000/6));
+        let pause = active.as_ref().filter(|active| !active.done()).map_or(interval, |active| active.interval(interval));
+        summary.interval = pause;
+        thread::sleep(pause);
+    };
+
+    // Exiting skips destructors, so everything that buffers is finished off here, and the
+    // socket files removed
+    if let Some(day) = mains.as_ref().and_then(mains::Mains::summary) {
+        sinks.event(&clock.stamp().0, &day)?;
     }
-    #[cfg(debug_assertions)]
-    {
-        let hex_string = payload.iter()
-            .map(|b| format!("{:02x}", b))
-            .collect::<Vec<_>>()
-            .join(" ");
-        println!("DEBUG: payload hex: {}", hex_string);
+    // Only when the run was over anyway (--count, Ctrl-C): after --stop-when or an alarm the
+    // reading that stopped it stays the last one written
+    let ended = match code {
+        0 => !condition_met,
+        code => code == exit_code::USER_ABORT,
+    };
+    if let Some((stamp, m)) = despike.as_mut().and_then(spike::Despike::take_held).filter(|_| ended) {
+        summary.sample(&stamp, &m);
+        if args.filter.as_ref().is_none_or(|filter| filter.matches(&stamp, &m)) && !idle.as_ref().is_some_and(idle::Idle::paused) {
+            sinks.sample(&stamp, &m)?;
+        }
     }
+    sinks.finish()?;
+    summary.print();
+    if despike.is_some() {
+        eprintln!("{}", tr!("Spikes left out of the min, max and mean: {}", spikes));
+    }
+    if let Some(power) = &power {
+        eprintln!("{}", power.summary());
+    }
+    if let Some(summary) = settle.as_ref().and_then(settle::Settle::summary) {
+        eprintln!("{}", summary);
+    }
+    if let Some(day) = mains.as_ref().and_then(mains::Mains::summary) {
+        eprintln!("{}", day);
+    }
+    // The verdict is what a test script wants to know, also when it stopped the logger with Ctrl-C
+    let code = match code {
+        0 | exit_code::USER_ABORT if summary.limits.is_some() => {
+            if summary.failed() { exit_code::ALARM_TRIPPED } else { 0 }
+        }
+        code => code,
+    };
+    std::process::exit(code);
 }
@@ -0,0 +1,161 @@
+//! `ut61e_plus monitor-mains`, for leaving the meter on a socket for days: the logger as usual
+//! with timestamps, plus the RMS of the readings over a rolling window as a derived value, sags
+//! and swells past a percentage of the nominal voltage as events, and a summary for each day
+//! (UTC) as an event when the day is over and at the end.
+
+use crate::clock::{Stamp, Utc};
+use crate::output::Derived;
+use crate::stats::significant;
+use std::collections::VecDeque;
+use std::time::Duration;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// V
+    pub nominal: f64,
+    /// % below the nominal voltage that's a sag
+    pub sag: f64,
+    /// % above it that's a swell
+    pub swell: f64,
+    pub window: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Normal,
+    Sag,
+    Swell,
+}
+
+/// A sag or swell in progress
+struct Excursion {
+    state: State,
+    start: Duration,
+    /// The lowest for a sag, the highest for a swell
+    worst: f64,
+}
+
+/// One day's worth
+struct Day {
+    date: String,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u64,
+    sags: u32,
+    swells: u32,
+    outside: Duration,
+}
+
+impl Day {
+    fn new(date: String) -> Self {
+        Day { date, min: f64::INFINITY, max: f64::NEG_INFINITY, sum: 0.0, count: 0, sags: 0, swells: 0, outside: Duration::ZERO }
+    }
+
+    fn summary(&self, settings: &Settings) -> String {
+        format!(
+            "Mains {}: min {} V, max {} V, mean {} V over {} readings, {} sags below -{}%, {} swells above +{}%, {:.1} s outside",
+            self.date,
+            significant(self.min),
+            significant(self.max),
+            significant(self.sum / self.count as f64),
+            self.count,
+            self.sags,
+            settings.sag,
+            self.swells,
+            settings.swell,
+            self.outside.as_secs_f64()
+        )
+    }
+}
+
+pub struct Mains {
+    settings: Settings,
+    /// (elapsed, V) within the window
+    window: VecDeque<(Duration, f64)>,
+    excursion: Option<Excursion>,
+    day: Option<Day>,
+    /// Whether it's been said that the meter isn't on AC volts
+    wrong_mode: bool,
+}
+
+impl Mains {
+    pub fn new(settings: Settings) -> Self {
+        Mains { settings, window: VecDeque::new(), excursion: None, day: None, wrong_mode: false }
+    }
+
+    /// The rolling RMS, and events for sags, swells, days gone by and the meter on another mode
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> (Vec<Derived>, Vec<String>) {
+        let mut events = Vec::new();
+        let scaled = m.reading_in(Units::Si);
+        let ac = m.mode_name().starts_with("V_AC");
+        if ac == self.wrong_mode {
+            self.wrong_mode = !ac;
+            events.push(match ac {
+                true => "Mains: back on AC volts".to_owned(),
+                false => format!("Mains: the meter is on {}, turn it to V AC", m.mode_name()),
+            });
+        }
+        let Some(volts) = scaled.reading.value().filter(|_| ac && scaled.unit == "V") else {
+            return (Vec::new(), events);
+        };
+
+        let date = Utc(stamp.wall).date();
+        if self.day.as_ref().is_some_and(|day| day.date != date)
+            && let Some(day) = self.day.take()
+        {
+            events.push(day.summary(&self.settings));
+        }
+        let day = self.day.get_or_insert_with(|| Day::new(date));
+        day.min = day.min.min(volts);
+        day.max = day.max.max(volts);
+        day.sum += volts;
+        day.count += 1;
+
+        let percent = (volts / self.settings.nominal - 1.0) * 100.0;
+        let state = if percent < -self.settings.sag {
+            State::Sag
+        } else if percent > self.settings.swell {
+            State::Swell
+        } else {
+            State::Normal
+        };
+        let was = self.excursion.as_ref().map_or(State::Normal, |excursion| excursion.state);
+        if state != was {
+            if let Some(excursion) = self.excursion.take() {
+                let lasted = stamp.elapsed.saturating_sub(excursion.start);
+                day.outside += lasted;
+                let (name, extreme) = if excursion.state == State::Sag { ("Sag", "lowest") } else { ("Swell", "highest") };
+                events.push(format!("{} over after {:.1} s, {} {} V", name, lasted.as_secs_f64(), extreme, significant(excursion.worst)));
+            }
+            if state != State::Normal {
+                let name = if state == State::Sag { "Sag" } else { "Swell" };
+                match state {
+                    State::Sag => day.sags += 1,
+                    _ => day.swells += 1,
+                }
+                events.push(format!("{}: {} V ({:+.1}% of {} V)", name, significant(volts), percent, self.settings.nominal));
+                self.excursion = Some(Excursion { state, start: stamp.elapsed, worst: volts });
+            }
+        }
+        if let Some(excursion) = &mut self.excursion {
+            excursion.worst = match excursion.state {
+                State::Sag => excursion.worst.min(volts),
+                _ => excursion.worst.max(volts),
+            };
+        }
+
+        self.window.push_back((stamp.elapsed, volts));
+        while self.window.front().is_some_and(|&(at, _)| stamp.elapsed.saturating_sub(at) > self.settings.window) {
+            self.window.pop_front();
+        }
+        let rms = (self.window.iter().map(|(_, v)| v * v).sum::<f64>() / self.window.len() as f64).sqrt();
+        (vec![Derived { name: "Rolling RMS", value: rms, unit: "V" }], events)
+    }
+
+    /// The day so far, for the end of the run
+    pub fn summary(&self) -> Option<String> {
+        self.day.as_ref().map(|day| day.summary(&self.settings))
+    }
+}
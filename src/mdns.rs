@@ -0,0 +1,302 @@
+//! `--mdns`, telling the local network about the servers that are running (multicast DNS with
+//! DNS-SD, RFC 6762 and 6763) so other machines find the logger by browsing for `_ut61e._tcp`
+//! instead of being given an address. The dashboard is also listed as `_http._tcp`, which is what
+//! browsers and `avahi-browse -a` look for, Modbus as `_modbus._tcp` and SCPI as `_scpi-raw._tcp`
+//! (with `_lxi._tcp` for the dashboard's LXI identification).
+//!
+//! Only enough of a responder for that: announcing at start, then answering questions for the
+//! service types, the instance names and the host name. It shares port 5353 with Avahi or
+//! mDNSResponder if they're running.
+
+use std::ffi::CStr;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::os::fd::FromRawFd;
+use std::thread;
+use std::time::Duration;
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+/// Short, so a logger that was stopped (exiting doesn't say goodbye) drops out of browsers soon
+const TTL: u32 = 120;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on records only this host answers for, so caches replace rather than add to them
+const CACHE_FLUSH: u16 = 0x8000;
+const SERVICES: &str = "_services._dns-sd._udp.local";
+
+/// One server to advertise, e.g. `_http._tcp` on port 8080
+pub struct Service {
+    pub kind: &'static str,
+    pub port: u16,
+    /// `key=value` strings
+    pub txt: Vec<String>,
+}
+
+struct Responder {
+    /// `UT61E+ 4D3A5B on benchpc`, the same for every service
+    instance: String,
+    /// `benchpc.local`
+    host: String,
+    ip: Ipv4Addr,
+    services: Vec<Service>,
+}
+
+/// Starts answering on a thread of its own and announces the services straight away. `serial`
+/// tells loggers on the same machine apart.
+pub fn start(serial: Option<&str>, services: Vec<Service>) -> io::Result<()> {
+    let socket = bind()?;
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(255)?;
+    let ip = local_ip()?;
+    let host = hostname().unwrap_or_else(|| "ut61e".to_owned());
+    let instance = match serial {
+        Some(serial) => format!("UT61E+ {} on {}", serial, host),
+        None => format!("UT61E+ on {}", host),
+    };
+    let responder = Responder { instance, host: format!("{}.local", host), ip, services };
+    eprintln!("Advertising {} over mDNS", responder.instance);
+    thread::spawn(move || {
+        let group = SocketAddr::from((GROUP, PORT));
+        // Twice, a second apart, in case the first one gets lost
+        for _ in 0..2 {
+            let _ = socket.send_to(&responder.answer(None, &responder.everything()), group);
+            thread::sleep(Duration::from_secs(1));
+        }
+        let mut buf = [0; 1500];
+        while let Ok((n, from)) = socket.recv_from(&mut buf) {
+            let Some((questions, end)) = questions(&buf[..n]) else {
+                continue;
+            };
+            let wanted: Vec<Record> = questions.iter().flat_map(|(name, kind)| responder.records(name, *kind)).collect();
+            if wanted.is_empty() {
+                continue;
+            }
+            // A plain resolver asking from some other port gets a plain unicast DNS reply, with
+            // its id and questions
+            let _ = match from.port() {
+                PORT => socket.send_to(&responder.answer(None, &wanted), group),
+                _ => socket.send_to(&responder.answer(Some(&buf[..end]), &wanted), from),
+            };
+        }
+    });
+    Ok(())
+}
+
+/// 0.0.0.0:5353 with the address shareable, which std can't set before binding
+fn bind() -> io::Result<UdpSocket> {
+    unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = UdpSocket::from_raw_fd(fd);
+        let one: libc::c_int = 1;
+        for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+            let size = std::mem::size_of_val(&one) as libc::socklen_t;
+            if libc::setsockopt(fd, libc::SOL_SOCKET, option, &one as *const _ as *const libc::c_void, size) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let addr = libc::sockaddr_in {
+            sin_family: libc::AF_INET as libc::sa_family_t,
+            sin_port: PORT.to_be(),
+            sin_addr: libc::in_addr { s_addr: 0 },
+            ..std::mem::zeroed()
+        };
+        let size = std::mem::size_of_val(&addr) as libc::socklen_t;
+        if libc::bind(fd, &addr as *const _ as *const libc::sockaddr, size) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(socket)
+    }
+}
+
+/// The address other machines reach this one on, the one multicast would go out from
+fn local_ip() -> io::Result<Ipv4Addr> {
+    let probe = UdpSocket::bind("0.0.0.0:0")?;
+    probe.connect(SocketAddrV4::new(GROUP, PORT))?;
+    match probe.local_addr()? {
+        SocketAddr::V4(addr) if !addr.ip().is_unspecified() => Ok(*addr.ip()),
+        _ => Err(io::Error::new(io::ErrorKind::AddrNotAvailable, "no IPv4 address to advertise")),
+    }
+}
+
+/// Up to the first dot, `benchpc` of `benchpc.lab.example`
+fn hostname() -> Option<String> {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().ok()?;
+    name.split('.').next().filter(|n| !n.is_empty()).map(str::to_owned)
+}
+
+enum Data {
+    Ptr(String),
+    Srv(u16, String),
+    Txt(Vec<String>),
+    A(Ipv4Addr),
+}
+
+struct Record {
+    name: String,
+    data: Data,
+}
+
+impl Responder {
+    fn instance_of(&self, service: &Service) -> String {
+        format!("{}.{}.local", self.instance, service.kind)
+    }
+
+    /// PTR, SRV and TXT for every service and the host's address, for announcing
+    fn everything(&self) -> Vec<Record> {
+        let mut records: Vec<Record> = self.services.iter().flat_map(|s| self.service(s)).collect();
+        records.push(Record { name: self.host.clone(), data: Data::A(self.ip) });
+        records
+    }
+
+    fn service(&self, service: &Service) -> Vec<Record> {
+        let instance = self.instance_of(service);
+        vec![
+            Record { name: format!("{}.local", service.kind), data: Data::Ptr(instance.clone()) },
+            Record { name: instance.clone(), data: Data::Srv(service.port, self.host.clone()) },
+            Record { name: instance, data: Data::Txt(service.txt.clone()) },
+        ]
+    }
+
+    /// What answers a question, nothing if it's about someone else
+    fn records(&self, name: &str, kind: u16) -> Vec<Record> {
+        let is = |other: &str| name.eq_ignore_ascii_case(other);
+        let mut records = Vec::new();
+        if is(SERVICES) && matches!(kind, TYPE_PTR | TYPE_ANY) {
+            for service in &self.services {
+                records.push(Record { name: SERVICES.to_owned(), data: Data::Ptr(format!("{}.local", service.kind)) });
+            }
+        }
+        for service in &self.services {
+            if is(&format!("{}.local", service.kind)) && matches!(kind, TYPE_PTR | TYPE_ANY) {
+                records.extend(self.service(service));
+                records.push(Record { name: self.host.clone(), data: Data::A(self.ip) });
+            } else if is(&self.instance_of(service)) && matches!(kind, TYPE_SRV | TYPE_TXT | TYPE_ANY) {
+                records.extend(self.service(service).into_iter().skip(1));
+                records.push(Record { name: self.host.clone(), data: Data::A(self.ip) });
+            }
+        }
+        if is(&self.host) && matches!(kind, TYPE_A | TYPE_ANY) {
+            records.push(Record { name: self.host.clone(), data: Data::A(self.ip) });
+        }
+        records
+    }
+
+    /// `query` is the header and questions of a unicast query to repeat, `None` for multicast
+    fn answer(&self, query: Option<&[u8]>, records: &[Record]) -> Vec<u8> {
+        let mut out = Vec::new();
+        // The id, then a response, authoritative
+        out.extend_from_slice(query.map_or(&[0, 0], |q| &q[..2]));
+        out.extend_from_slice(&0x8400u16.to_be_bytes());
+        out.extend_from_slice(query.map_or(&[0, 0], |q| &q[4..6]));
+        out.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(query.map_or(&[], |q| &q[12..]));
+        for record in records {
+            name(&mut out, &record.name);
+            let mut data = Vec::new();
+            let (kind, class) = match &record.data {
+                Data::Ptr(target) => {
+                    name(&mut data, target);
+                    (TYPE_PTR, CLASS_IN)
+                }
+                Data::Srv(port, target) => {
+                    data.extend_from_slice(&[0; 4]);
+                    data.extend_from_slice(&port.to_be_bytes());
+                    name(&mut data, target);
+                    (TYPE_SRV, CLASS_IN | CACHE_FLUSH)
+                }
+                Data::Txt(strings) => {
+                    for s in strings {
+                        data.push(s.len().min(255) as u8);
+                        data.extend_from_slice(&s.as_bytes()[..s.len().min(255)]);
+                    }
+                    if strings.is_empty() {
+                        data.push(0);
+                    }
+                    (TYPE_TXT, CLASS_IN | CACHE_FLUSH)
+                }
+                Data::A(ip) => {
+                    data.extend_from_slice(&ip.octets());
+                    (TYPE_A, CLASS_IN | CACHE_FLUSH)
+                }
+            };
+            out.extend_from_slice(&kind.to_be_bytes());
+            out.extend_from_slice(&class.to_be_bytes());
+            out.extend_from_slice(&TTL.to_be_bytes());
+            out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            out.extend_from_slice(&data);
+        }
+        out
+    }
+}
+
+/// Written out in full, no compression. The instance name is one label even with dots in it.
+fn name(out: &mut Vec<u8>, name: &str) {
+    let labels: Vec<&str> = match name.find("._") {
+        Some(at) if !name.starts_with('_') => {
+            let (instance, rest) = name.split_at(at);
+            std::iter::once(instance).chain(rest[1..].split('.')).collect()
+        }
+        _ => name.split('.').collect(),
+    };
+    for label in labels {
+        out.push(label.len().min(63) as u8);
+        out.extend_from_slice(&label.as_bytes()[..label.len().min(63)]);
+    }
+    out.push(0);
+}
+
+/// The questions of a query and where they end, `None` for responses and anything malformed
+fn questions(packet: &[u8]) -> Option<(Vec<(String, u16)>, usize)> {
+    let word = |at: usize| Some(u16::from_be_bytes([*packet.get(at)?, *packet.get(at + 1)?]));
+    if word(2)? & 0x8000 != 0 {
+        return None;
+    }
+    let mut at = 12;
+    let mut questions = Vec::new();
+    for _ in 0..word(4)? {
+        let (name, next) = read_name(packet, at)?;
+        // The class only matters for its unicast-response bit, which isn't heeded
+        word(next + 2)?;
+        questions.push((name, word(next)?));
+        at = next + 4;
+    }
+    Some((questions, at))
+}
+
+/// A possibly compressed name starting at `at`, and where whatever follows it starts
+fn read_name(packet: &[u8], mut at: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Pointers only go backwards in a sane packet, this stops loops in the rest
+    for _ in 0..128 {
+        let len = *packet.get(at)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(at + 1)));
+            }
+            0xc0.. => {
+                let pointer = (len & 0x3f) << 8 | *packet.get(at + 1)? as usize;
+                end.get_or_insert(at + 2);
+                at = pointer;
+            }
+            _ => {
+                labels.push(String::from_utf8_lossy(packet.get(at + 1..at + 1 + len)?).into_owned());
+                at += 1 + len;
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,147 @@
+//! An open meter: polling it and putting frames back together from the HID reports its cable
+//! splits them into. Only needs hidapi and the protocol, so the C library in `ffi/` builds it
+//! too.
+
+use crate::quirks::Quirks;
+use hidapi::{HidDevice, HidError};
+use ut61e_plus_logger::protocol::{self, CMD_GET_MEASUREMENT, FrameError, HEADER};
+
+pub const GET_MEASUREMENT: [u8; 6] = protocol::command(CMD_GET_MEASUREMENT);
+
+/// How long to wait for the meter to answer a command before giving up on that poll
+const READ_TIMEOUT_MS: i32 = 1000;
+
+/// What a [`Meter`] talks through, the cable's HID device or the logger's simulator. The
+/// methods are hidapi's, so everything above this works the same either way.
+pub trait Transport {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError>;
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, HidError>;
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError>;
+}
+
+impl Transport for HidDevice {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        HidDevice::write(self, data)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        HidDevice::read_timeout(self, buf, timeout_ms)
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), HidError> {
+        HidDevice::send_feature_report(self, data)
+    }
+}
+
+/// An open meter: the HID device, how its bridge chip frames reports, and the buffers frames
+/// are reassembled in. The buffers are reused for every poll so polling doesn't allocate.
+pub struct Meter {
+    dev: Box<dyn Transport>,
+    pub quirks: &'static Quirks,
+    /// The USB bridge's serial number, if it has one, to tell several meters apart
+    pub serial: Option<String>,
+    /// UART bytes received but not yet part of a complete frame
+    rx: [u8; 128],
+    rx_len: usize,
+    /// The last complete frame, header to checksum
+    frame: [u8; 64],
+    frame_len: usize,
+}
+
+impl Meter {
+    pub fn new(dev: HidDevice, quirks: &'static Quirks) -> Self {
+        let serial = dev.get_serial_number_string().ok().flatten().filter(|s| !s.is_empty());
+        Self::with_transport(Box::new(dev), quirks, serial)
+    }
+
+    pub fn with_transport(dev: Box<dyn Transport>, quirks: &'static Quirks, serial: Option<String>) -> Self {
+        Meter { dev, quirks, serial, rx: [0; 128], rx_len: 0, frame: [0; 64], frame_len: 0 }
+    }
+
+    /// Sets up the bridge's UART, see [`Quirks::init`]
+    pub fn init_uart(&self) -> Result<(), HidError> {
+        (self.quirks.init)(&*self.dev)
+    }
+
+    pub fn send_command(&self, cmd: &[u8]) -> Result<(), HidError> {
+        // First byte is the length, built on the stack so polling doesn't allocate
+        let mut buf = [0u8; 64];
+        for chunk in cmd.chunks(self.quirks.max_payload) {
+            buf[0] = (self.quirks.length_byte)(chunk.len());
+            buf[1..=chunk.len()].copy_from_slice(chunk);
+            self.dev.write(&buf[..=chunk.len()])?;
+        }
+        Ok(())
+    }
+
+    /// Presses a key, e.g. [`protocol::CMD_LIGHT`]. Whatever the meter sends back is read and
+    /// dropped so the next poll doesn't take it for a measurement, which can take up to a read
+    /// timeout if it sends nothing
+    pub fn press(&mut self, key: u8) -> Result<(), HidError> {
+        self.send_command(&protocol::command(key))?;
+        self.read_frame()?;
+        Ok(())
+    }
+
+    /// Drops the first `n` buffered bytes
+    fn consume(&mut self, n: usize) {
+        self.rx.copy_within(n..self.rx_len, 0);
+        self.rx_len -= n;
+    }
+
+    /// Reads reports until a complete frame (valid or not) has arrived and returns its length,
+    /// or 0 if the meter didn't answer in time. The frame is then in [`Meter::frame`].
+    pub fn read_frame(&mut self) -> Result<usize, HidError> {
+        let mut report = [0u8; 64];
+        loop {
+            match protocol::locate_frame(&self.rx[..self.rx_len]) {
+                Ok((start, total)) => {
+                    self.frame_len = total.min(self.frame.len());
+                    self.frame[..self.frame_len].copy_from_slice(&self.rx[start..start + self.frame_len]);
+                    self.consume(start + total);
+                    return Ok(self.frame_len);
+                }
+                // Keep a trailing 0xAB, it might be the start of the next header
+                Err(FrameError::NoHeader) => {
+                    let keep = self.rx_len > 0 && self.rx[self.rx_len - 1] == HEADER[0];
+                    self.consume(self.rx_len - keep as usize);
+                }
+                Err(FrameError::BadLength(_)) => {
+                    let start = self.rx[..self.rx_len].windows(2).position(|w| w == HEADER).unwrap_or(0);
+                    self.consume(start + HEADER.len());
+                    continue;
+                }
+                // Wait for the rest, dropping whatever came before the header
+                Err(_) => {
+                    let start = self.rx[..self.rx_len].windows(2).position(|w| w == HEADER).unwrap_or(0);
+                    self.consume(start);
+                }
+            }
+
+            let n = self.dev.read_timeout(&mut report, READ_TIMEOUT_MS)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let data = self.quirks.report_data(&report[..n]);
+            if self.rx_len + data.len() > self.rx.len() {
+                // Nothing sensible fills this much without a frame, start over
+                self.rx_len = 0;
+            }
+            self.rx[self.rx_len..self.rx_len + data.len()].copy_from_slice(data);
+            self.rx_len += data.len();
+        }
+    }
+
+    /// The last frame returned by [`Meter::read_frame`]
+    pub fn frame(&self) -> &[u8] {
+        &self.frame[..self.frame_len]
+    }
+
+    /// Polls for one measurement and returns its payload, `None` if the meter didn't answer or
+    /// the frame was corrupt
+    pub fn read_response(&mut self) -> Result<Option<&[u8]>, HidError> {
+        self.send_command(&GET_MEASUREMENT)?;
+        let n = self.read_frame()?;
+        Ok(if n == 0 { None } else { protocol::find_frame(self.frame()).ok() })
+    }
+}
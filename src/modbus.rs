@@ -0,0 +1,180 @@
+//! A small Modbus TCP server so a PLC or SCADA system can poll the meter like any other
+//! transducer. Only reading is supported, the same map answers function 0x04 (input registers)
+//! and 0x03 (holding registers) since plenty of masters only do the latter.
+//!
+//! | register | contents |
+//! |----------|----------|
+//! | 0-1      | value, float32 (high word first), NaN for OL or dashes |
+//! | 2-3      | range full scale, float32 |
+//! | 4-5      | range resolution, float32 |
+//! | 6-7      | value mantissa, int32 (high word first), for masters without floats |
+//! | 8        | value decimals, value = mantissa / 10^decimals |
+//! | 9        | mode code as sent by the meter |
+//! | 10       | range code as sent by the meter |
+//! | 11       | flags, see the `FLAG_` constants |
+//! | 12       | sample counter, wraps at 65535 |
+//! | 13       | age of the reading in 0.1 s, saturates |
+//! | 14-17    | unit, UTF-8 padded with zeros, 2 bytes per register |
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use ut61e_plus_logger::protocol::{Measurement, Reading};
+
+pub const REGISTERS: usize = 18;
+
+pub const FLAG_REL: u16 = 1 << 0;
+pub const FLAG_HOLD: u16 = 1 << 1;
+pub const FLAG_MIN: u16 = 1 << 2;
+pub const FLAG_MAX: u16 = 1 << 3;
+pub const FLAG_AUTO_RANGE: u16 = 1 << 4;
+pub const FLAG_LOW_BATTERY: u16 = 1 << 5;
+pub const FLAG_HV_WARNING: u16 = 1 << 6;
+pub const FLAG_OVERLOAD: u16 = 1 << 7;
+/// Cleared while the meter isn't answering, the rest of the registers keep the last reading
+pub const FLAG_VALID: u16 = 1 << 8;
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+struct State {
+    registers: [u16; REGISTERS],
+    updated: Option<Instant>,
+}
+
+pub struct Server {
+    state: Arc<Mutex<State>>,
+    addr: SocketAddr,
+}
+
+fn float(registers: &mut [u16], value: f64) {
+    let bits = (value as f32).to_bits();
+    registers[0] = (bits >> 16) as u16;
+    registers[1] = bits as u16;
+}
+
+impl Server {
+    /// Starts listening on `addr` (502 is the standard port but needs root) and answers every
+    /// connection on its own thread
+    pub fn serve(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State { registers: [0; REGISTERS], updated: None }));
+        let shared = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = shared.clone();
+                thread::spawn(move || {
+                    let _ = handle(stream, &state);
+                });
+            }
+        });
+        Ok(Server { state, addr })
+    }
+
+    /// Where it ended up listening, with the port filled in if it was 0
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn update(&self, m: &Measurement) {
+        let mut state = self.state.lock().unwrap();
+        let r = &mut state.registers;
+        let reading = m.reading();
+        float(&mut r[0..2], reading.value().unwrap_or(f64::NAN));
+        let range = m.range_info();
+        float(&mut r[2..4], range.as_ref().map_or(f64::NAN, |r| r.full_scale()));
+        float(&mut r[4..6], range.as_ref().map_or(f64::NAN, |r| r.resolution()));
+        let (mantissa, decimals) = match reading {
+            Reading::Value { mantissa, decimals } => (mantissa, decimals),
+            _ => (0, 0),
+        };
+        r[6] = (mantissa >> 16) as u16;
+        r[7] = mantissa as u16;
+        r[8] = decimals as u16;
+        r[9] = m.mode as u16;
+        r[10] = m.range as u16;
+        let flags = [
+            (m.rel, FLAG_REL),
+            (m.hold, FLAG_HOLD),
+            (m.min, FLAG_MIN),
+            (m.max, FLAG_MAX),
+            (m.auto_range, FLAG_AUTO_RANGE),
+            (m.low_battery, FLAG_LOW_BATTERY),
+            (m.hv_warning, FLAG_HV_WARNING),
+            (matches!(reading, Reading::Overload { .. }), FLAG_OVERLOAD),
+            (true, FLAG_VALID),
+        ];
+        r[11] = flags.iter().filter(|(set, _)| *set).fold(0, |acc, (_, flag)| acc | flag);
+        r[12] = r[12].wrapping_add(1);
+        let mut unit = [0u8; 8];
+        let bytes = m.unit().as_bytes();
+        let len = bytes.len().min(unit.len());
+        unit[..len].copy_from_slice(&bytes[..len]);
+        for (register, pair) in r[14..18].iter_mut().zip(unit.chunks(2)) {
+            *register = u16::from_be_bytes([pair[0], pair[1]]);
+        }
+        state.updated = Some(Instant::now());
+    }
+
+    /// The meter stopped answering
+    pub fn gap(&self) {
+        self.state.lock().unwrap().registers[11] &= !FLAG_VALID;
+    }
+}
+
+fn handle(mut stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    loop {
+        // MBAP header: transaction id, protocol id (0), length of what follows, unit id
+        let mut header = [0u8; 7];
+        stream.read_exact(&mut header)?;
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if !(2..=254).contains(&len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad MBAP length"));
+        }
+        let mut pdu = [0u8; 253];
+        let pdu = &mut pdu[..len - 1];
+        stream.read_exact(pdu)?;
+
+        let response = respond(pdu, state);
+        let mut reply = Vec::with_capacity(7 + response.len());
+        reply.extend_from_slice(&header[..4]);
+        reply.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        reply.push(header[6]);
+        reply.extend_from_slice(&response);
+        stream.write_all(&reply)?;
+    }
+}
+
+fn respond(pdu: &[u8], state: &Mutex<State>) -> Vec<u8> {
+    let function = pdu[0];
+    let exception = |code| vec![function | 0x80, code];
+    if function != READ_INPUT_REGISTERS && function != READ_HOLDING_REGISTERS {
+        return exception(ILLEGAL_FUNCTION);
+    }
+    if pdu.len() != 5 {
+        return exception(ILLEGAL_DATA_VALUE);
+    }
+    let start = u16::from_be_bytes([pdu[1], pdu[2]]) as usize;
+    let count = u16::from_be_bytes([pdu[3], pdu[4]]) as usize;
+    if !(1..=125).contains(&count) {
+        return exception(ILLEGAL_DATA_VALUE);
+    }
+    if start + count > REGISTERS {
+        return exception(ILLEGAL_DATA_ADDRESS);
+    }
+
+    let mut state = state.lock().unwrap();
+    let age = state.updated.map_or(u16::MAX, |t| (t.elapsed().as_millis() / 100).min(u16::MAX as u128) as u16);
+    state.registers[13] = age;
+    let mut response = vec![function, (count * 2) as u8];
+    for register in &state.registers[start..start + count] {
+        response.extend_from_slice(&register.to_be_bytes());
+    }
+    response
+}
@@ -0,0 +1,73 @@
+//! `--ntc`, a thermistor on the resistance range as a thermometer: each reading in Ω is turned
+//! into a temperature through the thermistor's beta, or its Steinhart–Hart coefficients for the
+//! whole range, and logged as a derived value alongside.
+
+use crate::output::Derived;
+use std::str::FromStr;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+const KELVIN: f64 = 273.15;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Ntc {
+    /// `1/T = 1/T0 + ln(R/R0)/B`, good for a few tens of degrees around T0
+    Beta { beta: f64, r0: f64, t0: f64 },
+    /// `1/T = A + B·ln(R) + C·ln(R)³`, from the datasheet or a three point calibration
+    SteinhartHart { a: f64, b: f64, c: f64 },
+}
+
+impl Ntc {
+    /// In °C, `None` if it comes out as no temperature at all (a short, a negative reading)
+    pub fn celsius(&self, ohms: f64) -> Option<f64> {
+        if ohms <= 0.0 {
+            return None;
+        }
+        let inverse = match *self {
+            Ntc::Beta { beta, r0, t0 } => 1.0 / (t0 + KELVIN) + (ohms / r0).ln() / beta,
+            Ntc::SteinhartHart { a, b, c } => a + b * ohms.ln() + c * ohms.ln().powi(3),
+        };
+        (inverse > 0.0).then(|| 1.0 / inverse - KELVIN)
+    }
+
+    /// The temperature for a reading, if it's a resistance
+    pub fn derived(&self, m: &Measurement) -> Option<Derived> {
+        let scaled = m.reading_in(Units::Si);
+        if scaled.unit != "Ω" {
+            return None;
+        }
+        let celsius = self.celsius(scaled.reading.value()?)?;
+        Some(Derived { name: "Temperature", value: celsius, unit: "°C" })
+    }
+}
+
+/// Ohms with an optional k or M, `10k`
+fn ohms(s: &str) -> Result<f64, String> {
+    let (number, scale) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1e3),
+        Some((i, 'M')) => (&s[..i], 1e6),
+        _ => (s, 1.0),
+    };
+    number.parse::<f64>().ok().filter(|r| *r > 0.0).map(|r| r * scale).ok_or_else(|| format!("{:?} isn't a resistance like 10k", s))
+}
+
+impl FromStr for Ntc {
+    type Err = String;
+
+    /// `3950:10k` for the beta and the resistance at 25 °C, `3950:10k:0` for the resistance at
+    /// another temperature, or `sh:A:B:C` for Steinhart–Hart
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |s: &str| s.parse::<f64>().map_err(|_| format!("{:?} isn't a number", s));
+        match s.split(':').collect::<Vec<_>>().as_slice() {
+            ["sh", a, b, c] => Ok(Ntc::SteinhartHart { a: number(a)?, b: number(b)?, c: number(c)? }),
+            [beta, r0, t0 @ ..] if t0.len() <= 1 => {
+                let beta = number(beta)?;
+                if beta <= 0.0 {
+                    return Err("the beta has to be positive, e.g. 3950".to_owned());
+                }
+                let t0 = t0.first().map_or(Ok(25.0), |t0| number(t0))?;
+                Ok(Ntc::Beta { beta, r0: ohms(r0)?, t0 })
+            }
+            _ => Err(format!("expected BETA:R25 like 3950:10k, BETA:R:T or sh:A:B:C, got {:?}", s)),
+        }
+    }
+}
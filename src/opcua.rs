@@ -0,0 +1,201 @@
+//! `--opcua`, an OPC UA server for test stands that talk OPC UA rather than Modbus. The reading is
+//! `Objects/UT61E+/Value`, an `AnalogItemType` variable in the unit without a prefix whose
+//! `EngineeringUnits` (UNECE codes, so V is `VLT`, as OPC UA clients expect) and `EURange` follow
+//! the meter's mode and range. `EURange` is the range's full scale either way, or from 0 for
+//! resistance, capacitance, frequency and duty cycle, which only go negative with REL on. Next to
+//! it are `Mode` and `Unit` as in the CSV, `Range` (`22.000 V`), `RangeMode` (`AUTO` or `MANUAL`),
+//! and `Rel`, `Hold` and `LowBattery`.
+//!
+//! An overload or dashes is NaN with the status `UncertainEngineeringUnitsExceeded`, and while the
+//! meter isn't answering the last value stays with `UncertainLastUsableValue`. Only the unsecured
+//! endpoint with anonymous access is offered, like `--modbus` it's meant for the bench network.
+//! The rest of the logger isn't async, so the server gets a thread and a tokio runtime of its own.
+
+use opcua::nodes::{ObjectBuilder, VariableBuilder};
+use opcua::server::diagnostics::NamespaceMetadata;
+use opcua::server::node_manager::memory::{SimpleNodeManager, simple_node_manager};
+use opcua::server::{ANONYMOUS_USER_TOKEN_ID, ServerBuilder, ServerEndpoint, SubscriptionCache};
+use opcua::types::{DataTypeId, DataValue, DateTime, EUInformation, ExtensionObject, NodeId, ObjectId, Range, StatusCode, Variant, VariableTypeId};
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// The namespace of the nodes below, the server itself is `urn:ut61e_plus_logger:server`
+const NAMESPACE: &str = "urn:ut61e_plus_logger";
+
+/// Where the UNECE unit codes in `EngineeringUnits` are from
+const UNECE: &str = "http://www.opcfoundation.org/UA/units/un/cefact";
+
+/// The UNECE common code of each unit the meter's readings come in without a prefix
+const UNITS: [(&str, &str, &str); 6] = [
+    ("V", "VLT", "volt"),
+    ("A", "AMP", "ampere"),
+    ("Ω", "OHM", "ohm"),
+    ("F", "FAR", "farad"),
+    ("Hz", "HTZ", "hertz"),
+    ("%", "P1", "percent"),
+];
+
+struct Nodes {
+    value: NodeId,
+    units: NodeId,
+    range: NodeId,
+    mode: NodeId,
+    unit: NodeId,
+    range_name: NodeId,
+    range_mode: NodeId,
+    rel: NodeId,
+    hold: NodeId,
+    low_battery: NodeId,
+}
+
+pub struct Server {
+    manager: Arc<SimpleNodeManager>,
+    subscriptions: Arc<SubscriptionCache>,
+    nodes: Nodes,
+    addr: SocketAddr,
+    /// The unit and range `EngineeringUnits` and `EURange` were last set to, they only change
+    /// with the mode, range and REL
+    described: Option<(&'static str, f64, f64)>,
+    last: Option<f64>,
+}
+
+fn engineering_units(unit: &str) -> EUInformation {
+    let (code, name) = UNITS.iter().find(|(u, ..)| *u == unit).map_or(("", unit), |(_, code, name)| (*code, *name));
+    // The code's characters packed into an integer, -1 for none
+    let unit_id = match code {
+        "" => -1,
+        code => code.bytes().fold(0, |id, c| id << 8 | c as i32),
+    };
+    EUInformation { namespace_uri: UNECE.into(), unit_id, display_name: unit.into(), description: name.into() }
+}
+
+impl Server {
+    /// Starts serving on `addr` (4840 is the standard port) on a thread of its own
+    pub fn serve(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(2).enable_all().build()?;
+        // Building the server already starts tasks on the runtime
+        let entered = runtime.enter();
+        let anonymous = [ANONYMOUS_USER_TOKEN_ID.to_owned()];
+        let (server, handle) = ServerBuilder::new()
+            .application_name("UT61E+ logger")
+            .application_uri(format!("{}:server", NAMESPACE))
+            .product_uri(NAMESPACE)
+            .host(addr.ip().to_string())
+            .port(addr.port())
+            .add_endpoint("none", ServerEndpoint::new_none("/", &anonymous))
+            .discovery_urls(vec!["/".to_owned()])
+            .create_sample_keypair(false)
+            .pki_dir(std::env::temp_dir().join("ut61e_plus_logger-pki"))
+            .with_node_manager(simple_node_manager(NamespaceMetadata { namespace_uri: NAMESPACE.to_owned(), ..Default::default() }, "ut61e"))
+            .build()
+            .map_err(io::Error::other)?;
+        let manager = handle.node_managers().get_of_type::<SimpleNodeManager>().expect("the node manager was just added");
+        let ns = handle.get_namespace_index(NAMESPACE).expect("the namespace was just added");
+        let id = |name: &str| NodeId::new(ns, name);
+        let nodes = Nodes {
+            value: id("Value"),
+            units: id("Value.EngineeringUnits"),
+            range: id("Value.EURange"),
+            mode: id("Mode"),
+            unit: id("Unit"),
+            range_name: id("Range"),
+            range_mode: id("RangeMode"),
+            rel: id("Rel"),
+            hold: id("Hold"),
+            low_battery: id("LowBattery"),
+        };
+        {
+            let mut space = manager.address_space().write();
+            let meter = id("UT61E+");
+            ObjectBuilder::new(&meter, "UT61E+", "UT61E+").organized_by(ObjectId::ObjectsFolder).insert(&mut *space);
+            VariableBuilder::new(&nodes.value, "Value", "Value")
+                .data_type(DataTypeId::Double)
+                .value(f64::NAN)
+                .has_type_definition(VariableTypeId::AnalogItemType)
+                .component_of(meter.clone())
+                .insert(&mut *space);
+            VariableBuilder::new(&nodes.units, "EngineeringUnits", "EngineeringUnits")
+                .data_type(DataTypeId::EUInformation)
+                .value(ExtensionObject::from_message(engineering_units("")))
+                .has_type_definition(VariableTypeId::PropertyType)
+                .property_of(nodes.value.clone())
+                .insert(&mut *space);
+            VariableBuilder::new(&nodes.range, "EURange", "EURange")
+                .data_type(DataTypeId::Range)
+                .value(ExtensionObject::from_message(Range { low: 0.0, high: 0.0 }))
+                .has_type_definition(VariableTypeId::PropertyType)
+                .property_of(nodes.value.clone())
+                .insert(&mut *space);
+            for (node, name) in [(&nodes.mode, "Mode"), (&nodes.unit, "Unit"), (&nodes.range_name, "Range"), (&nodes.range_mode, "RangeMode")] {
+                VariableBuilder::new(node, name, name).data_type(DataTypeId::String).value("").component_of(meter.clone()).insert(&mut *space);
+            }
+            for (node, name) in [(&nodes.rel, "Rel"), (&nodes.hold, "Hold"), (&nodes.low_battery, "LowBattery")] {
+                VariableBuilder::new(node, name, name).data_type(DataTypeId::Boolean).value(false).component_of(meter.clone()).insert(&mut *space);
+            }
+        }
+        let subscriptions = handle.subscriptions().clone();
+        drop(entered);
+        thread::spawn(move || {
+            runtime.block_on(async move {
+                let result = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => server.run_with(listener).await,
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(e) = result {
+                    eprintln!("OPC UA server stopped: {}", e);
+                }
+            })
+        });
+        Ok(Server { manager, subscriptions, nodes, addr, described: None, last: None })
+    }
+
+    /// Where it ended up listening, with the port filled in if it was 0
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn update(&mut self, m: &Measurement) {
+        let scaled = m.reading_in(Units::Si);
+        let full_scale = m.range_info().map_or(f64::NAN, |range| scaled.scale(range.full_scale()));
+        // Nothing below 0 but a REL offset
+        let low = match scaled.unit {
+            "Ω" | "F" | "Hz" | "%" if !m.rel => 0.0,
+            _ => -full_scale,
+        };
+        let now = DateTime::now();
+        let at = |value: Variant| DataValue::new_at(value, now);
+        let mut values = vec![
+            (&self.nodes.mode, at(m.mode_name().into())),
+            (&self.nodes.unit, at(scaled.unit.into())),
+            (&self.nodes.range_name, at(m.range_info().map(|range| range.to_string()).unwrap_or_default().into())),
+            (&self.nodes.range_mode, at(m.range_mode().into())),
+            (&self.nodes.rel, at(m.rel.into())),
+            (&self.nodes.hold, at(m.hold.into())),
+            (&self.nodes.low_battery, at(m.low_battery.into())),
+        ];
+        if self.described != Some((scaled.unit, low, full_scale)) {
+            self.described = Some((scaled.unit, low, full_scale));
+            values.push((&self.nodes.units, at(ExtensionObject::from_message(engineering_units(scaled.unit)).into())));
+            values.push((&self.nodes.range, at(ExtensionObject::from_message(Range { low, high: full_scale }).into())));
+        }
+        let value = match scaled.reading.value() {
+            Some(value) => at(value.into()),
+            None => DataValue { status: Some(StatusCode::UncertainEngineeringUnitsExceeded), ..at(f64::NAN.into()) },
+        };
+        self.last = scaled.reading.value();
+        values.push((&self.nodes.value, value));
+        let _ = self.manager.set_values(&self.subscriptions, values.into_iter().map(|(node, value)| (node, None, value)));
+    }
+
+    /// The meter stopped answering
+    pub fn gap(&mut self) {
+        let value = DataValue { status: Some(StatusCode::UncertainLastUsableValue), ..DataValue::new_now(self.last.unwrap_or(f64::NAN)) };
+        let _ = self.manager.set_value(&self.subscriptions, &self.nodes.value, None, value);
+    }
+}
@@ -0,0 +1,18 @@
+//! Open Sound Control messages, so Max/MSP, Pure Data or an installation can be driven by
+//! whatever the meter is measuring. Sent over UDP like any other OSC source.
+
+/// Strings are NUL terminated and padded to a multiple of 4 bytes
+fn push_str(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    packet.extend(std::iter::repeat_n(0, padding));
+}
+
+/// A message with a single float32 argument
+pub fn message(address: &str, value: f32) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(address.len() + 12);
+    push_str(&mut packet, address);
+    push_str(&mut packet, ",f");
+    packet.extend_from_slice(&value.to_be_bytes());
+    packet
+}
@@ -0,0 +1,415 @@
+//! Turning measurements into lines of output
+
+use crate::clock::{Stamp, Utc, parse_duration};
+use crate::stats::significant;
+use colored::*;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+use ut61e_plus_logger::protocol::{Measurement, Reading, Units};
+
+/// What goes into each record besides the basics
+#[derive(Clone, Copy)]
+pub struct Columns {
+    /// The poll number, first of all
+    pub seq: bool,
+    /// Wall clock time and monotonic seconds since the start, in front of everything else
+    pub timestamp: bool,
+    /// ± datasheet uncertainty of the value
+    pub uncertainty: bool,
+    /// What unit values (and full scale, resolution and uncertainty with them) are written in
+    pub units: Units,
+    /// `--limit-low` and `--limit-high`, for a PASS or FAIL after each reading
+    pub limits: Option<Limits>,
+}
+
+/// A limit test. The limits are in the unit without a prefix (V, Ω, A) whatever `--units` is, so
+/// a range change doesn't move them.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+}
+
+impl Limits {
+    /// Within both limits. Overloads and dashes fail, they're no number at all.
+    pub fn pass(&self, m: &Measurement) -> bool {
+        m.reading_in(Units::Si).reading.value().is_some_and(|v| self.low.is_none_or(|low| v >= low) && self.high.is_none_or(|high| v <= high))
+    }
+}
+
+/// A value the logger works out from the readings rather than reads off the meter, like the power
+/// with `--power-with`
+#[derive(Clone, Copy)]
+pub struct Derived {
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+fn verdict(pass: bool) -> &'static str {
+    if pass { "PASS" } else { "FAIL" }
+}
+
+/// Who logged what and when, for the files that keep it: the `--hash` sidecars and the metadata of
+/// .mat and .npz files
+#[derive(Clone)]
+pub struct Session {
+    pub meter: String,
+    pub command: String,
+    pub started: SystemTime,
+}
+
+/// How often buffered output is pushed out, see `--flush-every`
+#[derive(Clone, Copy, Debug)]
+pub enum FlushEvery {
+    Lines(u32),
+    Interval(Duration),
+}
+
+impl FromStr for FlushEvery {
+    type Err = String;
+
+    /// `10` for every 10 lines, `2s` or `500ms` for time based
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(interval) = parse_duration(s) {
+            return Ok(FlushEvery::Interval(interval));
+        }
+        match s.parse() {
+            Ok(0) | Err(_) => Err(format!("expected a line count like 10 or a duration like 2s or 500ms, got {:?}", s)),
+            Ok(n) => Ok(FlushEvery::Lines(n)),
+        }
+    }
+}
+
+/// Buffers output and flushes it every so many lines or so often, so whatever is reading a pipe
+/// gets samples as they come (or in batches if asked) instead of in 4 kB lumps
+pub struct Flushing<W: Write> {
+    inner: BufWriter<W>,
+    every: FlushEvery,
+    lines: u32,
+    last_flush: Instant,
+}
+
+impl<W: Write> Flushing<W> {
+    pub fn new(inner: W, every: FlushEvery) -> Self {
+        Self {
+            inner: BufWriter::new(inner),
+            every,
+            lines: 0,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// Flushes if it's due, call this between polls so time based flushing happens even when
+    /// nothing is being written
+    pub fn tick(&mut self) -> io::Result<()> {
+        let due = match self.every {
+            FlushEvery::Lines(n) => self.lines >= n,
+            FlushEvery::Interval(interval) => self.lines > 0 && self.last_flush.elapsed() >= interval,
+        };
+        if due { self.flush() } else { Ok(()) }
+    }
+}
+
+impl<W: Write> Write for Flushing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.lines += buf[..n].iter().filter(|&&b| b == b'\n').count() as u32;
+        self.tick()?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lines = 0;
+        self.last_flush = Instant::now();
+        self.inner.flush()
+    }
+}
+
+fn write_csv_stamp(out: &mut impl Write, stamp: &Stamp, columns: &Columns) -> io::Result<()> {
+    if columns.seq {
+        write!(out, "{},", stamp.seq)?;
+    }
+    if columns.timestamp {
+        write!(out, "{},{:.3},", Utc(stamp.wall), stamp.elapsed.as_secs_f64())?;
+    }
+    Ok(())
+}
+
+pub fn write_csv_header(out: &mut impl Write, columns: &Columns) -> io::Result<()> {
+    if columns.seq {
+        write!(out, "seq,")?;
+    }
+    if columns.timestamp {
+        write!(out, "time,elapsed,")?;
+    }
+    write!(out, "value,unit,mode,range,rel,hold,minmax,full_scale,resolution")?;
+    if columns.uncertainty {
+        write!(out, ",uncertainty")?;
+    }
+    if columns.limits.is_some() {
+        write!(out, ",verdict")?;
+    }
+    writeln!(out)
+}
+
+pub fn write_csv(out: &mut impl Write, stamp: &Stamp, m: &Measurement, columns: &Columns) -> io::Result<()> {
+    write_csv_stamp(out, stamp, columns)?;
+    let scaled = m.reading_in(columns.units);
+    write!(
+        out,
+        "{},{},{},{},{},{},{},",
+        scaled.reading,
+        scaled.unit,
+        m.mode_name(),
+        m.range_mode(),
+        if m.rel { "REL" } else { "" },
+        if m.hold { "HOLD" } else { "" },
+        m.min_max()
+    )?;
+    match m.range_info() {
+        Some(r) => write!(out, "{},{}", scaled.scale(r.full_scale()), scaled.scale(r.resolution()))?,
+        None => write!(out, ",")?,
+    }
+    if columns.uncertainty {
+        write!(out, ",")?;
+        if let Some(u) = m.uncertainty() {
+            write!(out, "{}", scaled.scale(u))?;
+        }
+    }
+    if let Some(limits) = columns.limits {
+        write!(out, ",{}", verdict(limits.pass(m)))?;
+    }
+    writeln!(out)
+}
+
+/// Marks missing samples (timeouts, reconnects) with a `GAP` row and the reason in the mode
+/// column, so nothing downstream draws a line straight across the hole
+pub fn write_csv_gap(out: &mut impl Write, stamp: &Stamp, columns: &Columns, reason: &str) -> io::Result<()> {
+    write_csv_stamp(out, stamp, columns)?;
+    writeln!(out, "GAP,,{}", reason)
+}
+
+/// Something that happened during the capture (clock jumps etc.), as an `EVENT` row with the
+/// message in the mode column. Commas would split the message so they become semicolons.
+pub fn write_csv_event(out: &mut impl Write, stamp: &Stamp, columns: &Columns, message: &str) -> io::Result<()> {
+    write_csv_stamp(out, stamp, columns)?;
+    writeln!(out, "EVENT,,{}", message.replace(',', ";"))
+}
+
+/// A derived value as a row of its own after the reading it came from, with its name in the mode
+/// column and `DERIVED` in the range column, so `report` plots it as a series of its own and
+/// nothing takes it for a reading
+pub fn write_csv_derived(out: &mut impl Write, stamp: &Stamp, columns: &Columns, derived: &Derived) -> io::Result<()> {
+    write_csv_stamp(out, stamp, columns)?;
+    writeln!(out, "{},{},{},DERIVED", significant(derived.value), derived.unit, derived.name)
+}
+
+/// A JSON string, the mode and unit names are plain but events could be anything
+pub fn write_json_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+fn write_json_number(out: &mut impl Write, n: Option<f64>) -> io::Result<()> {
+    match n {
+        Some(n) if n.is_finite() => write!(out, "{}", n),
+        _ => write!(out, "null"),
+    }
+}
+
+/// The JSON Schema of the records below, for `--emit-schema`
+pub const SCHEMA: &str = include_str!("../schema/records.schema.json");
+/// The version of what the outputs write, in every JSON record (`"version"`) and in the metadata
+/// of the files that have some (`format_version` in Parquet, .mat, .npz, TDMS and the `--hash`
+/// sidecars). It goes up when a field changes meaning or goes away, not when one is added, and
+/// `report` reads every version up to this one. CSV goes by its header instead, columns are only
+/// ever added and everything reading it goes by name.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// What every JSON record starts with, the object left open
+fn write_json_head(out: &mut impl Write, stamp: &Stamp) -> io::Result<()> {
+    write!(
+        out,
+        "{{\"schema\":\"ut61e_plus\",\"version\":{},\"seq\":{},\"time\":\"{}\",\"elapsed\":{:.3}",
+        FORMAT_VERSION,
+        stamp.seq,
+        Utc(stamp.wall),
+        stamp.elapsed.as_secs_f64()
+    )
+}
+
+/// One measurement as a single line JSON object. `value` is null for overloads and dashes,
+/// `display` always has what the meter showed. The time and poll number are always included
+/// since whatever receives these can't rely on the order or timing they arrive in, or on all of
+/// them arriving.
+pub fn write_json(out: &mut impl Write, stamp: &Stamp, m: &Measurement, columns: &Columns) -> io::Result<()> {
+    write_json_head(out, stamp)?;
+    write!(out, ",\"value\":")?;
+    let scaled = m.reading_in(columns.units);
+    write_json_number(out, scaled.reading.value())?;
+    write!(out, ",\"display\":")?;
+    write_json_str(out, &scaled.reading.to_string())?;
+    write!(out, ",\"unit\":")?;
+    write_json_str(out, scaled.unit)?;
+    write!(out, ",\"mode\":")?;
+    write_json_str(out, m.mode_name())?;
+    write!(
+        out,
+        ",\"range\":\"{}\",\"rel\":{},\"hold\":{},\"minmax\":\"{}\",\"full_scale\":",
+        m.range_mode(),
+        m.rel,
+        m.hold,
+        m.min_max()
+    )?;
+    let range = m.range_info();
+    write_json_number(out, range.as_ref().map(|r| scaled.scale(r.full_scale())))?;
+    write!(out, ",\"resolution\":")?;
+    write_json_number(out, range.as_ref().map(|r| scaled.scale(r.resolution())))?;
+    if columns.uncertainty {
+        write!(out, ",\"uncertainty\":")?;
+        write_json_number(out, m.uncertainty().map(|u| scaled.scale(u)))?;
+    }
+    if let Some(limits) = columns.limits {
+        write!(out, ",\"verdict\":\"{}\"", verdict(limits.pass(m)))?;
+    }
+    writeln!(out, "}}")
+}
+
+/// A derived value as a JSON object of its own, told apart from readings by `"derived"`
+pub fn write_json_derived(out: &mut impl Write, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+    write_json_head(out, stamp)?;
+    write!(out, ",\"derived\":")?;
+    write_json_str(out, derived.name)?;
+    write!(out, ",\"value\":")?;
+    write_json_number(out, Some(derived.value))?;
+    write!(out, ",\"unit\":")?;
+    write_json_str(out, derived.unit)?;
+    writeln!(out, "}}")
+}
+
+/// A gap or an event, `{..., "gap": "reconnect"}` or `{..., "event": "..."}`
+pub fn write_json_note(out: &mut impl Write, stamp: &Stamp, kind: &str, text: &str) -> io::Result<()> {
+    write_json_head(out, stamp)?;
+    write!(out, ",\"{}\":", kind)?;
+    write_json_str(out, text)?;
+    writeln!(out, "}}")
+}
+
+/// One reading as plain text to paste into lab notes, e.g. `1.2345 V (V_DC) at 2024-05-01T12:00:00.000Z`
+pub fn plain(stamp: &Stamp, m: &Measurement, units: Units) -> String {
+    let scaled = m.reading_in(units);
+    format!("{} {} ({}) at {}", scaled.reading, scaled.unit, m.mode_name(), Utc(stamp.wall))
+}
+
+/// A payload as hex, coloured by field, with what each field decodes to and any flag bits
+/// nobody knows the meaning of yet. For `--show-raw`.
+pub fn write_raw(out: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+    let Some(m) = Measurement::decode(payload) else {
+        return writeln!(out, "raw: {} (too short to decode)", hex(payload).dimmed());
+    };
+    writeln!(
+        out,
+        "raw: {} {} {} {} {} {}",
+        hex(&payload[0..1]).blue(),
+        hex(&payload[1..2]).magenta(),
+        hex(&payload[2..9]).yellow(),
+        hex(&payload[9..11]).cyan(),
+        hex(&payload[11..14]).red(),
+        hex(&payload[14..]).dimmed()
+    )?;
+    // Bits decode() knows about in each flag byte
+    const KNOWN: [u8; 3] = [0x0f, 0x07, 0x0e];
+    let unknown: Vec<String> = payload[11..14]
+        .iter()
+        .zip(KNOWN)
+        .enumerate()
+        .filter(|(_, (b, known))| *b & !known != 0)
+        .map(|(i, (b, known))| format!("flags{}&{:02x}", i + 1, b & !known))
+        .collect();
+    let flags: Vec<&str> = [
+        (true, m.range_mode()),
+        (m.rel, "REL"),
+        (m.hold, "HOLD"),
+        (m.min || m.max, m.min_max()),
+        (m.low_battery, "LOW_BAT"),
+        (m.hv_warning, "HV"),
+        (m.dc, "DC"),
+        (m.peak_max, "P_MAX"),
+        (m.peak_min, "P_MIN"),
+    ]
+    .into_iter()
+    .filter_map(|(set, name)| set.then_some(name))
+    .collect();
+    writeln!(
+        out,
+        "     {} {} {} {} {}{}",
+        format!("mode {} ({})", m.mode, m.mode_name()).blue(),
+        format!("range {:#04x} ({})", m.range, m.range_info().map_or("?".to_owned(), |r| r.to_string())).magenta(),
+        format!("display {:?}", m.display_digits().as_str()).yellow(),
+        format!("bar {:02x}{:02x}", m.bar[0], m.bar[1]).cyan(),
+        format!("flags {}", flags.join(" ")).red(),
+        if unknown.is_empty() { String::new() } else { format!(" unknown bits {}", unknown.join(" ")) }
+    )
+}
+
+pub fn write_pretty_event(out: &mut impl Write, message: &str) -> io::Result<()> {
+    writeln!(out, "{}", message.yellow())
+}
+
+pub fn write_pretty_derived(out: &mut impl Write, derived: &Derived) -> io::Result<()> {
+    writeln!(out, "{}", format!("  {}: {} {}", derived.name, significant(derived.value), derived.unit).cyan())
+}
+
+pub fn write_pretty(out: &mut impl Write, stamp: &Stamp, m: &Measurement, columns: &Columns) -> io::Result<()> {
+    if columns.timestamp {
+        write!(out, "{} ", Utc(stamp.wall).to_string().dimmed())?;
+    }
+    let auto_manual = m.range_mode();
+    let range_str = match m.range_info() {
+        Some(r) => {
+            let (res, res_unit) = r.resolution_prefixed();
+            format!("[{} {}, {} {} resolution]", auto_manual, r, res, res_unit)
+        }
+        None => format!("[{}]", auto_manual),
+    };
+    let scaled = m.reading_in(columns.units);
+    // One digit more than the display so small uncertainties don't round to zero
+    let uncertainty = match (m.uncertainty(), scaled.reading) {
+        (Some(u), Reading::Value { decimals, .. }) if columns.uncertainty => {
+            format!("±{:.*} ", decimals as usize + 1, scaled.scale(u))
+        }
+        _ => String::new(),
+    };
+
+    let verdict = match columns.limits.map(|limits| limits.pass(m)) {
+        Some(true) => " PASS".bold().green(),
+        Some(false) => " FAIL".bold().red(),
+        None => "".normal(),
+    };
+
+    // colored allocates for each styled piece, the CSV path is the lean one
+    writeln!(
+        out,
+        "{} {}{} {} {} {} {} {}{}",
+        scaled.reading.to_string().bold().yellow(),
+        uncertainty.yellow(),
+        scaled.unit.cyan(),
+        format!("({})", m.mode_name()).blue(),
+        range_str.magenta(),
+        if m.rel { "REL" } else { "" }.red(),
+        if m.hold { "HOLD" } else { "" }.red(),
+        m.min_max().red(),
+        verdict
+    )
+}
@@ -0,0 +1,315 @@
+//! The reading on a small screen of its own, for a Raspberry Pi (a Zero is plenty) next to the
+//! meter: an SSD1306 OLED on I²C or anything Linux has a framebuffer for, e.g. an SPI LCD with
+//! the fbtft driver or the HDMI console. Drawn on a background thread with a built-in font, so
+//! there's no graphics stack to install and a slow I²C bus never holds up logging.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// Where the panel is, from `--panel`
+#[derive(Clone, Debug)]
+pub enum Target {
+    /// An I²C bus like `/dev/i2c-1` and the display's 7-bit address
+    Ssd1306(PathBuf, u16),
+    /// Like `/dev/fb0`
+    Framebuffer(PathBuf),
+}
+
+impl Target {
+    /// `/dev/i2c-1` (address 0x3c), `/dev/i2c-1@0x3d` or `/dev/fb1`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (path, address) = s.split_once('@').map_or((s, None), |(path, address)| (path, Some(address)));
+        let name = Path::new(path).file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if name.starts_with("i2c-") {
+            let address = match address {
+                Some(a) => u16::from_str_radix(a.trim_start_matches("0x"), 16).map_err(|_| format!("bad I²C address {}", a))?,
+                None => SSD1306_ADDRESS,
+            };
+            Ok(Target::Ssd1306(path.into(), address))
+        } else if name.starts_with("fb") && address.is_none() {
+            Ok(Target::Framebuffer(path.into()))
+        } else {
+            Err(format!("{} is neither an I²C bus (/dev/i2c-N) nor a framebuffer (/dev/fbN)", s))
+        }
+    }
+}
+
+/// What's shown: the reading big, the unit under it and the mode and flags small at the bottom
+struct Screen {
+    reading: String,
+    unit: String,
+    detail: String,
+}
+
+pub struct Panel {
+    tx: Sender<Screen>,
+}
+
+impl Panel {
+    /// Opens and clears the display here, so a wrong path or address is reported straight away
+    pub fn start(target: &Target) -> io::Result<Self> {
+        let mut device = match target {
+            Target::Ssd1306(bus, address) => Device::Ssd1306(Ssd1306::open(bus, *address)?),
+            Target::Framebuffer(path) => Device::Framebuffer(Framebuffer::open(path)?),
+        };
+        let (width, height) = device.size();
+        device.show(&Canvas::new(width, height))?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || draw(device, rx));
+        Ok(Panel { tx })
+    }
+
+    pub fn sample(&self, m: &Measurement, units: Units) {
+        let scaled = m.reading_in(units);
+        // V_DC as V DC, and the pictograms after some mode names aren't in the font
+        let mode = m.mode_name().split(' ').next().unwrap_or_default().replace('_', " ");
+        let flags = [(m.hold, "HOLD"), (m.rel, "REL"), (m.min || m.max, m.min_max())];
+        let detail = std::iter::once(mode.as_str()).chain(flags.into_iter().filter_map(|(set, name)| set.then_some(name))).collect::<Vec<_>>();
+        let _ = self.tx.send(Screen { reading: scaled.reading.to_string(), unit: scaled.unit.to_owned(), detail: detail.join(" ") });
+    }
+
+    pub fn gap(&self, reason: &str) {
+        let _ = self.tx.send(Screen { reading: "----".to_owned(), unit: String::new(), detail: reason.to_owned() });
+    }
+}
+
+fn draw(mut device: Device, rx: Receiver<Screen>) {
+    let (width, height) = device.size();
+    let mut warned = false;
+    while let Ok(mut screen) = rx.recv() {
+        // Only the newest matters when the display is slower than the meter
+        while let Ok(newer) = rx.try_recv() {
+            screen = newer;
+        }
+        let mut canvas = Canvas::new(width, height);
+        canvas.layout(&screen);
+        match device.show(&canvas) {
+            Ok(()) => warned = false,
+            Err(e) if !warned => {
+                eprintln!("Can't draw on the panel: {}", e);
+                warned = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// One bit a pixel, true is lit
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Canvas { width, height, pixels: vec![false; width * height] }
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Each character is 6 by 8 pixels times `scale`, including the gap after it
+    fn text(&mut self, x: usize, y: usize, text: &str, scale: usize) {
+        for (i, c) in text.chars().enumerate() {
+            for (column, bits) in glyph(c).iter().enumerate() {
+                for row in 0..8 {
+                    if bits & (1 << row) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let (px, py) = (x + (i * 6 + column) * scale + dx, y + row * scale + dy);
+                            if px < self.width && py < self.height {
+                                self.pixels[py * self.width + px] = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The reading as large as fits the width and half the height, the rest shrinking with it
+    fn layout(&mut self, screen: &Screen) {
+        let chars = screen.reading.chars().count().max(1);
+        let big = (self.width / (6 * chars)).min(self.height / 16).max(1);
+        let medium = (big * 2 / 3).max(1);
+        let small = (big / 3).max(1);
+        self.text(0, 0, &screen.reading, big);
+        self.text(0, 8 * big + big, &screen.unit, medium);
+        self.text(0, self.height.saturating_sub(8 * small), &screen.detail, small);
+    }
+}
+
+enum Device {
+    Ssd1306(Ssd1306),
+    Framebuffer(Framebuffer),
+}
+
+impl Device {
+    fn size(&self) -> (usize, usize) {
+        match self {
+            Device::Ssd1306(_) => (SSD1306_WIDTH, SSD1306_HEIGHT),
+            Device::Framebuffer(fb) => (fb.width, fb.height),
+        }
+    }
+
+    fn show(&mut self, canvas: &Canvas) -> io::Result<()> {
+        match self {
+            Device::Ssd1306(oled) => oled.show(canvas),
+            Device::Framebuffer(fb) => fb.show(canvas),
+        }
+    }
+}
+
+/// What most of the little modules come set to, the other choice is 0x3d
+const SSD1306_ADDRESS: u16 = 0x3c;
+const SSD1306_WIDTH: usize = 128;
+const SSD1306_HEIGHT: usize = 64;
+/// From linux/i2c-dev.h, sets the address the following reads and writes go to
+const I2C_SLAVE: libc::Ioctl = 0x0703;
+
+struct Ssd1306 {
+    bus: File,
+}
+
+impl Ssd1306 {
+    fn open(bus: &Path, address: u16) -> io::Result<Self> {
+        let bus = OpenOptions::new().read(true).write(true).open(bus)?;
+        if unsafe { libc::ioctl(bus.as_raw_fd(), I2C_SLAVE, libc::c_ulong::from(address)) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut oled = Ssd1306 { bus };
+        // Display off, clock, 64 rows, no offset, start line 0, charge pump on, horizontal
+        // addressing, flipped to read the right way up with the pins at the top, COM pins,
+        // contrast, precharge, VCOM level, show RAM, not inverted, display on
+        oled.commands(&[
+            0xae, 0xd5, 0x80, 0xa8, 0x3f, 0xd3, 0x00, 0x40, 0x8d, 0x14, 0x20, 0x00, 0xa1, 0xc8, 0xda, 0x12, 0x81, 0xcf, 0xd9,
+            0xf1, 0xdb, 0x40, 0xa4, 0xa6, 0xaf,
+        ])?;
+        Ok(oled)
+    }
+
+    fn commands(&mut self, commands: &[u8]) -> io::Result<()> {
+        let mut message = vec![0x00];
+        message.extend_from_slice(commands);
+        self.bus.write_all(&message)
+    }
+
+    /// The display's memory is 8 pages of 8 rows, a byte is a column of a page with the top
+    /// row in bit 0
+    fn show(&mut self, canvas: &Canvas) -> io::Result<()> {
+        self.commands(&[0x21, 0x00, (SSD1306_WIDTH - 1) as u8, 0x22, 0x00, (SSD1306_HEIGHT / 8 - 1) as u8])?;
+        let mut data = Vec::with_capacity(SSD1306_WIDTH * SSD1306_HEIGHT / 8);
+        for page in 0..SSD1306_HEIGHT / 8 {
+            for x in 0..SSD1306_WIDTH {
+                data.push((0..8).filter(|bit| canvas.get(x, page * 8 + bit)).fold(0, |byte, bit| byte | 1 << bit));
+            }
+        }
+        // Small writes, some I²C adapters can't do more in one go
+        for chunk in data.chunks(32) {
+            let mut message = vec![0x40];
+            message.extend_from_slice(chunk);
+            self.bus.write_all(&message)?;
+        }
+        Ok(())
+    }
+}
+
+struct Framebuffer {
+    file: File,
+    width: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+    stride: usize,
+}
+
+impl Framebuffer {
+    /// The size and layout from sysfs, `/dev/fb1` is described in `/sys/class/graphics/fb1`
+    fn open(path: &Path) -> io::Result<Self> {
+        let name = path.file_name().unwrap_or_default();
+        let sysfs = Path::new("/sys/class/graphics").join(name);
+        let read = |attribute: &str| fs::read_to_string(sysfs.join(attribute)).map(|s| s.trim().to_owned());
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("can't make sense of {}'s {}", path.display(), what));
+        let size = read("virtual_size")?;
+        let (width, height) = size
+            .split_once(',')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .ok_or_else(|| invalid("virtual_size"))?;
+        let bits: usize = read("bits_per_pixel")?.parse().map_err(|_| invalid("bits_per_pixel"))?;
+        let stride = read("stride")?.parse().map_err(|_| invalid("stride"))?;
+        let file = OpenOptions::new().write(true).open(path)?;
+        Ok(Framebuffer { file, width, height, bytes_per_pixel: bits.div_ceil(8), stride })
+    }
+
+    /// White on black, all bits set is white whatever the pixel format
+    fn show(&mut self, canvas: &Canvas) -> io::Result<()> {
+        let mut frame = vec![0; self.stride * self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if canvas.get(x, y) {
+                    let at = y * self.stride + x * self.bytes_per_pixel;
+                    frame[at..at + self.bytes_per_pixel].fill(0xff);
+                }
+            }
+        }
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&frame)
+    }
+}
+
+/// Columns of a 5 by 8 glyph with the top row in bit 0, printable ASCII and the few other
+/// characters in units. Anything else is a question mark.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        ' '..='~' => FONT[c as usize - ' ' as usize],
+        'Ω' => [0x58, 0x64, 0x04, 0x64, 0x58],
+        'μ' | 'µ' => [0xfc, 0x40, 0x40, 0x20, 0x7c],
+        '°' => [0x00, 0x06, 0x09, 0x09, 0x06],
+        'β' => [0xfe, 0x49, 0x49, 0x4e, 0x30],
+        _ => FONT['?' as usize - ' ' as usize],
+    }
+}
+
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5f, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], // space ! "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], [0x24, 0x2a, 0x7f, 0x2a, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], // # $ %
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1c, 0x22, 0x41, 0x00], // & ' (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], [0x08, 0x2a, 0x1c, 0x2a, 0x08], [0x08, 0x08, 0x3e, 0x08, 0x08], // ) * +
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], // , - .
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3e, 0x51, 0x49, 0x45, 0x3e], [0x00, 0x42, 0x7f, 0x40, 0x00], // / 0 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4b, 0x31], [0x18, 0x14, 0x12, 0x7f, 0x10], // 2 3 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3c, 0x4a, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03], // 5 6 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1e], [0x00, 0x36, 0x36, 0x00, 0x00], // 8 9 :
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], // ; < =
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3e], // > ? @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], [0x7f, 0x49, 0x49, 0x49, 0x36], [0x3e, 0x41, 0x41, 0x41, 0x22], // A B C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], [0x7f, 0x49, 0x49, 0x49, 0x41], [0x7f, 0x09, 0x09, 0x01, 0x01], // D E F
+    [0x3e, 0x41, 0x41, 0x51, 0x32], [0x7f, 0x08, 0x08, 0x08, 0x7f], [0x00, 0x41, 0x7f, 0x41, 0x00], // G H I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], [0x7f, 0x08, 0x14, 0x22, 0x41], [0x7f, 0x40, 0x40, 0x40, 0x40], // J K L
+    [0x7f, 0x02, 0x04, 0x02, 0x7f], [0x7f, 0x04, 0x08, 0x10, 0x7f], [0x3e, 0x41, 0x41, 0x41, 0x3e], // M N O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], [0x3e, 0x41, 0x51, 0x21, 0x5e], [0x7f, 0x09, 0x19, 0x29, 0x46], // P Q R
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7f, 0x01, 0x01], [0x3f, 0x40, 0x40, 0x40, 0x3f], // S T U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], [0x7f, 0x20, 0x18, 0x20, 0x7f], [0x63, 0x14, 0x08, 0x14, 0x63], // V W X
+    [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7f, 0x41, 0x41, 0x00], // Y Z [
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7f, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], // \ ] ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], // _ ` a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7f], // b c d
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7e, 0x09, 0x01, 0x02], [0x08, 0x54, 0x54, 0x54, 0x3c], // e f g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7d, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3d, 0x00], // h i j
+    [0x00, 0x7f, 0x10, 0x28, 0x44], [0x00, 0x41, 0x7f, 0x40, 0x00], [0x7c, 0x04, 0x18, 0x04, 0x78], // k l m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7c, 0x14, 0x14, 0x14, 0x08], // n o p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], [0x7c, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20], // q r s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], [0x3c, 0x40, 0x40, 0x20, 0x7c], [0x1c, 0x20, 0x40, 0x20, 0x1c], // t u v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0c, 0x50, 0x50, 0x50, 0x3c], // w x y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7f, 0x00, 0x00], // z { |
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x02, 0x01, 0x02, 0x04, 0x02],                                 // } ~
+];
@@ -0,0 +1,174 @@
+//! Parquet output for long captures: typed columns (a real timestamp, f64 values, booleans) and
+//! dictionary encoded mode/unit strings, so it loads straight into pandas or Polars and ends up
+//! a fraction of the size of the CSV. Gaps and events are rows too, told apart by `kind`.
+
+use crate::clock::Stamp;
+use crate::output::FORMAT_VERSION;
+use arrow_array::types::Int16Type;
+use arrow_array::{
+    ArrayRef, BooleanArray, DictionaryArray, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::Result;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// Rows are handed to the writer in batches of this many, it makes row groups out of them
+const BATCH_ROWS: usize = 1000;
+
+#[derive(Default)]
+struct Rows {
+    seq: Vec<u64>,
+    time: Vec<i64>,
+    elapsed: Vec<f64>,
+    /// `sample`, `gap` or `event`
+    kind: Vec<&'static str>,
+    value: Vec<Option<f64>>,
+    display: Vec<Option<String>>,
+    unit: Vec<Option<&'static str>>,
+    mode: Vec<Option<&'static str>>,
+    range: Vec<Option<&'static str>>,
+    rel: Vec<Option<bool>>,
+    hold: Vec<Option<bool>>,
+    minmax: Vec<Option<&'static str>>,
+    full_scale: Vec<Option<f64>>,
+    resolution: Vec<Option<f64>>,
+    uncertainty: Vec<Option<f64>>,
+    /// Gap reason or event message
+    note: Vec<Option<String>>,
+}
+
+fn dictionary(strings: &[Option<&'static str>]) -> ArrayRef {
+    Arc::new(strings.iter().copied().collect::<DictionaryArray<Int16Type>>())
+}
+
+pub struct ParquetFile {
+    writer: ArrowWriter<File>,
+    schema: SchemaRef,
+    rows: Rows,
+    units: Units,
+}
+
+impl ParquetFile {
+    pub fn create(path: &Path, units: Units) -> Result<Self> {
+        let dict = || DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("seq", DataType::UInt64, false),
+            Field::new("time", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false),
+            Field::new("elapsed", DataType::Float64, false),
+            Field::new("kind", dict(), false),
+            Field::new("value", DataType::Float64, true),
+            Field::new("display", DataType::Utf8, true),
+            Field::new("unit", dict(), true),
+            Field::new("mode", dict(), true),
+            Field::new("range", dict(), true),
+            Field::new("rel", DataType::Boolean, true),
+            Field::new("hold", DataType::Boolean, true),
+            Field::new("minmax", dict(), true),
+            Field::new("full_scale", DataType::Float64, true),
+            Field::new("resolution", DataType::Float64, true),
+            Field::new("uncertainty", DataType::Float64, true),
+            Field::new("note", DataType::Utf8, true),
+        ]));
+        let version = KeyValue::new("format_version".to_owned(), FORMAT_VERSION.to_string());
+        let props = WriterProperties::builder().set_compression(Compression::SNAPPY).set_key_value_metadata(Some(vec![version])).build();
+        let writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(props))?;
+        Ok(ParquetFile { writer, schema, rows: Rows::default(), units })
+    }
+
+    /// Fills in everything but the measurement columns
+    fn row(&mut self, stamp: &Stamp, kind: &'static str, note: Option<String>) -> Result<()> {
+        let rows = &mut self.rows;
+        let millis = stamp.wall.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64);
+        rows.seq.push(stamp.seq);
+        rows.time.push(millis);
+        rows.elapsed.push(stamp.elapsed.as_secs_f64());
+        rows.kind.push(kind);
+        rows.note.push(note);
+        if kind != "sample" {
+            rows.value.push(None);
+            rows.display.push(None);
+            rows.unit.push(None);
+            rows.mode.push(None);
+            rows.range.push(None);
+            rows.rel.push(None);
+            rows.hold.push(None);
+            rows.minmax.push(None);
+            rows.full_scale.push(None);
+            rows.resolution.push(None);
+            rows.uncertainty.push(None);
+        }
+        if rows.time.len() >= BATCH_ROWS {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, stamp: &Stamp, m: &Measurement) -> Result<()> {
+        let rows = &mut self.rows;
+        let scaled = m.reading_in(self.units);
+        let range = m.range_info();
+        rows.value.push(scaled.reading.value());
+        rows.display.push(Some(scaled.reading.to_string()));
+        rows.unit.push(Some(scaled.unit));
+        rows.mode.push(Some(m.mode_name()));
+        rows.range.push(Some(m.range_mode()));
+        rows.rel.push(Some(m.rel));
+        rows.hold.push(Some(m.hold));
+        rows.minmax.push(Some(m.min_max()));
+        rows.full_scale.push(range.as_ref().map(|r| scaled.scale(r.full_scale())));
+        rows.resolution.push(range.as_ref().map(|r| scaled.scale(r.resolution())));
+        rows.uncertainty.push(m.uncertainty().map(|u| scaled.scale(u)));
+        self.row(stamp, "sample", None)
+    }
+
+    pub fn write_gap(&mut self, stamp: &Stamp, reason: &str) -> Result<()> {
+        self.row(stamp, "gap", Some(reason.to_owned()))
+    }
+
+    pub fn write_event(&mut self, stamp: &Stamp, message: &str) -> Result<()> {
+        self.row(stamp, "event", Some(message.to_owned()))
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        if self.rows.time.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let kind: Vec<_> = rows.kind.into_iter().map(Some).collect();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(rows.seq)),
+            Arc::new(TimestampMillisecondArray::from(rows.time).with_timezone("UTC")),
+            Arc::new(Float64Array::from(rows.elapsed)),
+            dictionary(&kind),
+            Arc::new(Float64Array::from(rows.value)),
+            Arc::new(StringArray::from(rows.display)),
+            dictionary(&rows.unit),
+            dictionary(&rows.mode),
+            dictionary(&rows.range),
+            Arc::new(BooleanArray::from(rows.rel)),
+            Arc::new(BooleanArray::from(rows.hold)),
+            dictionary(&rows.minmax),
+            Arc::new(Float64Array::from(rows.full_scale)),
+            Arc::new(Float64Array::from(rows.resolution)),
+            Arc::new(Float64Array::from(rows.uncertainty)),
+            Arc::new(StringArray::from(rows.note)),
+        ];
+        self.writer.write(&RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+
+    /// Writes what's left and the footer. Without this the file can't be read, so it has to
+    /// happen however logging stops.
+    pub fn close(mut self) -> Result<()> {
+        self.write_batch()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
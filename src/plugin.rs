@@ -0,0 +1,142 @@
+//! `--plugins FILE`, outputs that aren't built in: each plugin is a program the logger starts and
+//! writes the records to on its stdin, a JSON object a line exactly as `--format json` (see
+//! schema/records.schema.json), so a sink for an in-house database is a short script in whatever
+//! language it has a client for, without forking the crate. The file has a `[[plugin]]` for each:
+//!
+//! ```toml
+//! [[plugin]]
+//! name = "lab-db"
+//! command = "python3 /opt/lab/ut61e_to_db.py --table bench3"
+//! ```
+//!
+//! `command` is run by the shell (`sh -c`, `cmd /C` on Windows), `name` is what it's called in
+//! messages and picked by in `--queue` (the command if there's none). A plugin gets its records
+//! through a queue like the network outputs, `UT61E_METER` and `UT61E_FORMAT_VERSION` in its
+//! environment, and EOF on stdin when logging stops, after which it has a few seconds to finish
+//! up before it's killed. Its stderr goes to the logger's, its stdout is thrown away. A plugin that
+//! exits early is reported and dropped like any other output that fails.
+
+use crate::clock::Stamp;
+use crate::output::{self, Columns, Derived, FORMAT_VERSION};
+use crate::config::{Setting, Value, sections};
+use crate::sink::Sink;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+use ut61e_plus_logger::protocol::Measurement;
+
+/// How long a plugin gets to exit after its stdin is closed
+const EXIT_GRACE: Duration = Duration::from_secs(5);
+
+pub struct Spec {
+    pub name: String,
+    pub command: String,
+}
+
+/// The `[[plugin]]` entries of a file
+pub fn load(path: &Path) -> Result<Vec<Spec>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{} {}", path.display(), e))
+}
+
+fn parse(text: &str) -> Result<Vec<Spec>, String> {
+    let mut plugins = Vec::new();
+    for section in sections(text, "plugin")? {
+        let (mut name, mut command) = (None, None);
+        for Setting { key, value, line } in section.settings {
+            let error = |e: String| format!("line {}: {}", line, e);
+            match (key.as_str(), value) {
+                ("name", Value::Text(text)) => name = Some(text),
+                ("command", Value::Text(text)) => command = Some(text),
+                ("name" | "command", Value::Number(_)) => return Err(error(format!("{} has to be a string", key))),
+                _ => return Err(error(format!("unknown setting {:?}", key))),
+            }
+        }
+        match command {
+            Some(command) if !command.trim().is_empty() => plugins.push(Spec { name: name.unwrap_or_else(|| command.clone()), command }),
+            _ => return Err(format!("line {}: the plugin has no command", section.start)),
+        }
+    }
+    Ok(plugins)
+}
+
+/// `command` run by the shell, out of the terminal's process group so Ctrl-C stops the logger
+/// and what it started finishes up rather than being interrupted halfway through
+pub fn shell(command: &str) -> Command {
+    let mut shell = match cfg!(windows) {
+        true => Command::new("cmd"),
+        false => Command::new("sh"),
+    };
+    shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(command);
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut shell, 0);
+    shell
+}
+
+pub struct Plugin {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    columns: Columns,
+}
+
+impl Plugin {
+    pub fn start(spec: &Spec, meter: &str, columns: Columns) -> io::Result<Self> {
+        let mut child = shell(&spec.command)
+            .env("UT61E_METER", meter)
+            .env("UT61E_FORMAT_VERSION", FORMAT_VERSION.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("can't start plugin {}: {}", spec.name, e)))?;
+        let stdin = BufWriter::new(child.stdin.take().expect("stdin is piped"));
+        Ok(Plugin { child, stdin, columns })
+    }
+}
+
+impl Sink for Plugin {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        output::write_json(&mut self.stdin, stamp, m, &self.columns)
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        output::write_json_note(&mut self.stdin, stamp, "gap", reason)
+    }
+
+    fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        output::write_json_note(&mut self.stdin, stamp, "event", message)
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        output::write_json_derived(&mut self.stdin, stamp, derived)
+    }
+
+    /// A poll's records go over together
+    fn tick(&mut self) -> io::Result<()> {
+        self.stdin.flush()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let Plugin { mut child, mut stdin, .. } = *self;
+        let flushed = stdin.flush();
+        // EOF tells it to finish up
+        drop(stdin);
+        let deadline = Instant::now() + EXIT_GRACE;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() > deadline {
+                child.kill()?;
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("still running {:?} after its input ended, killed", EXIT_GRACE)));
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+        flushed?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(io::Error::other(format!("exited with {}", status))),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Power and energy from two meters, one on the voltage and one on the current, each with a
+//! logger of its own. `--power-with` follows the other logger (see `follow`), pairs each reading
+//! here with the latest one there, and logs the power and what's been used so far as derived
+//! values. With `--shunt` this meter reads the voltage across a shunt
+//! resistor instead, and that's turned into the current first, which on its own already gives
+//! the charge.
+//!
+//! The energy and charge add up each power and current times the time since the reading
+//! before, skipping steps longer than [`MAX_STEP`] so a gap doesn't count as a constant load.
+//! AC readings are RMS, so for AC the power is the apparent power.
+
+use crate::clock::Stamp;
+use crate::i18n::tr;
+use crate::output::Derived;
+use crate::stats::significant;
+use crate::follow::Follow;
+use std::time::Duration;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// The other meter's reading is too old to pair with after this long
+const STALE: Duration = Duration::from_secs(2);
+
+/// Longer than this between two readings is a gap, not a step to integrate over
+const MAX_STEP: Duration = Duration::from_secs(5);
+
+pub struct Power {
+    /// The other meter's latest reading
+    other: Option<Follow>,
+    /// Ω, with this meter across a shunt
+    shunt: Option<f64>,
+    /// J
+    energy: f64,
+    /// C
+    charge: f64,
+    /// When the last value was integrated
+    last: Option<Duration>,
+    /// How long there was something to integrate
+    integrated: Duration,
+    /// Why there's no power at the moment, so it's only said once
+    problem: Option<String>,
+}
+
+impl Power {
+    /// `other` is the other logger's socket, or the serial number of its meter
+    pub fn new(other: Option<&str>, shunt: Option<f64>) -> Self {
+        let other = other.map(Follow::start);
+        Power { other, shunt, energy: 0.0, charge: 0.0, last: None, integrated: Duration::ZERO, problem: None }
+    }
+
+    /// The derived values for a reading from this meter, and a message when the power stops or
+    /// starts being worked out
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> (Vec<Derived>, Option<String>) {
+        let result = self.pair(m);
+        let message = match (&result, &self.problem) {
+            (Err(problem), Some(was)) if problem == was => None,
+            (Err(problem), _) => Some(tr!("Power: {}", problem)),
+            (Ok(_), Some(_)) => Some(tr!("Power: working it out again").to_owned()),
+            (Ok(_), None) => None,
+        };
+        self.problem = result.as_ref().err().cloned();
+        let Ok((volts, amps)) = result else {
+            self.last = None;
+            return (Vec::new(), message);
+        };
+        let step = self.last.map(|last| stamp.elapsed.saturating_sub(last)).filter(|&step| step <= MAX_STEP);
+        self.last = Some(stamp.elapsed);
+        if let Some(step) = step {
+            self.charge += amps * step.as_secs_f64();
+            self.integrated += step;
+        }
+        let mut derived = Vec::new();
+        if self.shunt.is_some() {
+            derived.push(Derived { name: "Current", value: amps, unit: "A" });
+        }
+        if let Some(volts) = volts {
+            if let Some(step) = step {
+                self.energy += volts * amps * step.as_secs_f64();
+            }
+            derived.push(Derived { name: "Power", value: volts * amps, unit: "W" });
+            derived.push(Derived { name: "Energy", value: self.energy / 3600.0, unit: "Wh" });
+        }
+        derived.push(Derived { name: "Charge", value: self.charge / 3.6, unit: "mAh" });
+        (derived, message)
+    }
+
+    /// The voltage if there's one and the current, from whichever meter has each
+    fn pair(&self, m: &Measurement) -> Result<(Option<f64>, f64), String> {
+        let scaled = m.reading_in(Units::Si);
+        let Some(mut here) = scaled.reading.value() else {
+            return Err(tr!("no number from this meter").to_owned());
+        };
+        let mut unit = scaled.unit;
+        if let Some(shunt) = self.shunt {
+            if unit != "V" {
+                return Err(tr!("--shunt needs this meter on volts, it's on {}", unit));
+            }
+            (here, unit) = (here / shunt, "A");
+        }
+        let Some(other) = &self.other else {
+            return Ok((None, here));
+        };
+        let other = other.latest(STALE).ok_or_else(|| tr!("no reading from the other meter").to_owned())?;
+        match (unit, other.unit) {
+            ("V", "A") => Ok((Some(here), other.value)),
+            ("A", "V") => Ok((Some(other.value), here)),
+            (a, b) => Err(tr!("needs volts on one meter and amps on the other, not {} and {}", a, b)),
+        }
+    }
+
+    /// For the end of the run
+    pub fn summary(&self) -> String {
+        let secs = format!("{:.1}", self.integrated.as_secs_f64());
+        let charge = significant(self.charge / 3.6);
+        match self.other {
+            Some(_) => tr!("Energy {} Wh, charge {} mAh over {} s", significant(self.energy / 3600.0), charge, secs),
+            None => tr!("Charge {} mAh over {} s", charge, secs),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! `ut61e_plus procedure FILE`, a test procedure someone can follow at the bench: each step says
+//! where to put the probes, takes readings for a while and checks them against limits, and at
+//! the end there's a report of the whole run. The file is a small piece of TOML:
+//!
+//! ```toml
+//! title = "PSU board bring-up"
+//!
+//! [[step]]
+//! prompt = "Connect the probes to TP3 and GND"
+//! mode = "V_DC"
+//! low = 4.75
+//! high = 5.25
+//! duration = "2s"
+//! ```
+//!
+//! It's the TOML subset of [`crate::config`], a `title` and then `[[step]]` for each step. A step
+//! takes `prompt`, and optionally `name` for the report, `mode` (as in the CSV, e.g. `V_DC`,
+//! `Resistance`), `low` and `high` in the unit without a prefix like `--limit-low`, and either
+//! `duration` (seconds or e.g. `500ms`) or `samples`. Without either it reads for a second.
+
+use crate::clock::{Utc, parse_duration};
+use crate::config::{Value, value_of};
+use crate::i18n::tr;
+use crate::meter::Meter;
+use crate::output::Limits;
+use crate::stats::significant;
+use colored::*;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+use std::{fs, thread};
+use ut61e_plus_logger::protocol::{Measurement, Reading, Units};
+
+/// How long a step waits for the meter to answer at all before it gives up on the step
+const NO_READING: Duration = Duration::from_secs(10);
+
+pub struct Procedure {
+    pub title: Option<String>,
+    pub steps: Vec<Step>,
+}
+
+pub struct Step {
+    pub name: Option<String>,
+    pub prompt: String,
+    pub mode: Option<String>,
+    pub limits: Limits,
+    pub capture: Capture,
+}
+
+#[derive(Clone, Copy)]
+pub enum Capture {
+    For(Duration),
+    Samples(u32),
+}
+
+impl Procedure {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        Procedure::parse(&text).map_err(|e| format!("{} {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut title = None;
+        let mut steps: Vec<Step> = Vec::new();
+        // Steps are checked once they're complete, when the next one starts or the file ends
+        let mut started = None;
+        for (n, line) in text.lines().enumerate() {
+            let error = |e: String| format!("line {}: {}", n + 1, e);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.split('#').next().unwrap_or_default().trim() == "[[step]]" {
+                if let Some(start) = started {
+                    check(steps.last().expect("a step was started")).map_err(|e| format!("line {}: {}", start, e))?;
+                }
+                steps.push(Step { name: None, prompt: String::new(), mode: None, limits: Limits { low: None, high: None }, capture: Capture::For(Duration::from_secs(1)) });
+                started = Some(n + 1);
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error(format!("expected key = value or [[step]], not {:?}", line)))?;
+            let key = key.trim();
+            let value = value_of(value.trim()).map_err(error)?;
+            let Some(step) = steps.last_mut() else {
+                match (key, value) {
+                    ("title", Value::Text(text)) => title = Some(text),
+                    ("title", Value::Number(_)) => return Err(error("title has to be a string".to_owned())),
+                    _ => return Err(error(format!("unknown setting {:?} before the first [[step]]", key))),
+                }
+                continue;
+            };
+            match (key, value) {
+                ("name", Value::Text(text)) => step.name = Some(text),
+                ("prompt", Value::Text(text)) => step.prompt = text,
+                ("mode", Value::Text(text)) => step.mode = Some(text),
+                ("low", Value::Number(low)) => step.limits.low = Some(low),
+                ("high", Value::Number(high)) => step.limits.high = Some(high),
+                ("samples", Value::Number(samples)) if samples >= 1.0 && samples.fract() == 0.0 => step.capture = Capture::Samples(samples as u32),
+                ("samples", _) => return Err(error("samples has to be a whole number, at least 1".to_owned())),
+                ("duration", Value::Number(secs)) if secs > 0.0 => step.capture = Capture::For(Duration::from_secs_f64(secs)),
+                ("duration", Value::Text(text)) if parse_duration(&text).is_some() => step.capture = Capture::For(parse_duration(&text).unwrap_or_default()),
+                ("duration", _) => return Err(error("bad duration, expected seconds or e.g. \"500ms\"".to_owned())),
+                ("name" | "prompt" | "mode", Value::Number(_)) => return Err(error(format!("{} has to be a string", key))),
+                ("low" | "high", Value::Text(_)) => return Err(error(format!("{} has to be a number", key))),
+                _ => return Err(error(format!("unknown setting {:?}", key))),
+            }
+        }
+        match (started, steps.last()) {
+            (Some(start), Some(last)) => check(last).map_err(|e| format!("line {}: {}", start, e))?,
+            _ => return Err("has no [[step]]".to_owned()),
+        }
+        Ok(Procedure { title, steps })
+    }
+}
+
+fn check(step: &Step) -> Result<(), String> {
+    if step.prompt.is_empty() {
+        return Err("the step has no prompt".to_owned());
+    }
+    if let (Some(low), Some(high)) = (step.limits.low, step.limits.high)
+        && low > high
+    {
+        return Err(format!("low {} is above high {}", low, high));
+    }
+    Ok(())
+}
+
+/// What one step measured
+struct Captured {
+    /// In the unit without a prefix, only those that were a number
+    values: Vec<f64>,
+    unit: &'static str,
+    readings: usize,
+    /// Outside the limits, overloads and dashes, or in the wrong mode
+    failed: usize,
+    /// The mode the meter was in when it wasn't the step's
+    wrong_mode: Option<&'static str>,
+}
+
+impl Captured {
+    fn pass(&self) -> bool {
+        self.readings > 0 && self.failed == 0
+    }
+
+    fn mean(&self) -> Option<f64> {
+        (!self.values.is_empty()).then(|| self.values.iter().sum::<f64>() / self.values.len() as f64)
+    }
+
+    fn min(&self) -> Option<f64> {
+        self.values.iter().copied().reduce(f64::min)
+    }
+
+    fn max(&self) -> Option<f64> {
+        self.values.iter().copied().reduce(f64::max)
+    }
+
+    fn why(&self) -> String {
+        if self.readings == 0 {
+            "no readings".to_owned()
+        } else if let Some(mode) = self.wrong_mode {
+            format!("meter in {}", mode)
+        } else {
+            format!("{} of {} outside the limits", self.failed, self.readings)
+        }
+    }
+}
+
+enum Outcome {
+    Measured(Captured),
+    Skipped,
+    NotRun,
+}
+
+/// `V_DC` for the step's mode matches V_DC, `Resistance` matches "Resistance Ω"
+fn mode_matches(wanted: &str, m: &Measurement) -> bool {
+    let name = m.mode_name();
+    name.eq_ignore_ascii_case(wanted) || name.split_whitespace().next().is_some_and(|first| first.eq_ignore_ascii_case(wanted))
+}
+
+fn capture(meter: &mut Meter, step: &Step, interval: Duration) -> io::Result<Captured> {
+    let mut captured = Captured { values: Vec::new(), unit: "", readings: 0, failed: 0, wrong_mode: None };
+    read_for(meter, step.capture, interval, |m| {
+        let in_mode = step.mode.as_deref().is_none_or(|mode| mode_matches(mode, m));
+        if !in_mode {
+            captured.wrong_mode = Some(m.mode_name());
+        }
+        if !in_mode || !step.limits.pass(m) {
+            captured.failed += 1;
+        }
+        captured.readings += 1;
+        let scaled = m.reading_in(Units::Si);
+        captured.unit = scaled.unit;
+        captured.values.extend(scaled.reading.value());
+    })?;
+    Ok(captured)
+}
+
+/// Polls for as long as `capture` says, showing each reading on one line as it comes in and
+/// handing it to `each`. Gives up after [`NO_READING`] with nothing from the meter.
+pub fn read_for(meter: &mut Meter, capture: Capture, interval: Duration, mut each: impl FnMut(&Measurement)) -> io::Result<()> {
+    let start = Instant::now();
+    let (mut last, mut readings) = (start, 0u32);
+    loop {
+        let done = match capture {
+            Capture::For(duration) => start.elapsed() >= duration,
+            Capture::Samples(samples) => readings >= samples,
+        };
+        if done || last.elapsed() >= NO_READING {
+            break;
+        }
+        let polled = Instant::now();
+        let payload = meter.read_response().map_err(io::Error::other)?;
+        if let Some(m) = payload.and_then(Measurement::decode) {
+            last = Instant::now();
+            readings += 1;
+            each(&m);
+            let scaled = m.reading_in(Units::Si);
+            let shown = match scaled.reading {
+                Reading::Overload { negative: false } => "OL".to_owned(),
+                Reading::Overload { negative: true } => "-OL".to_owned(),
+                reading => reading.value().map_or("----".to_owned(), |value| format!("{} {}", significant(value), scaled.unit)),
+            };
+            eprint!("\r  {}          ", shown);
+        }
+        thread::sleep(interval.saturating_sub(polled.elapsed()));
+    }
+    eprintln!();
+    Ok(())
+}
+
+/// Reads a line of what the operator typed, nothing when stdin is closed
+pub fn ask(question: &str) -> io::Result<String> {
+    eprint!("{}", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_owned())
+}
+
+/// Goes through the steps one at a time and writes the report to `report` or stdout. The exit
+/// code is `ALARM_TRIPPED` unless every step was measured and passed.
+pub fn run(procedure: &Procedure, report: Option<&Path>, meter: &mut Meter, interval: Duration) -> Result<i32, Box<dyn std::error::Error>> {
+    let started = SystemTime::now();
+    if let Some(title) = &procedure.title {
+        eprintln!("{}", title.bold());
+    }
+    let total = procedure.steps.len();
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    'steps: for (i, step) in procedure.steps.iter().enumerate() {
+        eprintln!("\n{} {}", tr!("Step {} of {}:", i + 1, total).bold(), step.prompt);
+        let outcome = loop {
+            match ask(tr!("Enter to measure, s to skip, q to stop: "))?.to_lowercase().as_str() {
+                "s" => break Outcome::Skipped,
+                "q" => break 'steps,
+                _ => {}
+            }
+            let captured = capture(meter, step, interval)?;
+            let range = match (captured.min(), captured.mean(), captured.max()) {
+                (Some(min), Some(mean), Some(max)) => format!(" ({} {} .. {} {} .. {} {})", significant(min), captured.unit, significant(mean), captured.unit, significant(max), captured.unit),
+                _ => String::new(),
+            };
+            if captured.pass() {
+                eprintln!("{}{}", "PASS".green().bold(), range);
+                break Outcome::Measured(captured);
+            }
+            eprintln!("{} {}{}", "FAIL".red().bold(), captured.why(), range);
+            match ask(tr!("Enter to go on, r to repeat the step, q to stop: "))?.to_lowercase().as_str() {
+                "r" => continue,
+                "q" => {
+                    outcomes.push(Outcome::Measured(captured));
+                    break 'steps;
+                }
+                _ => break Outcome::Measured(captured),
+            }
+        };
+        outcomes.push(outcome);
+    }
+    outcomes.resize_with(total, || Outcome::NotRun);
+
+    let pass = outcomes.iter().all(|outcome| matches!(outcome, Outcome::Measured(captured) if captured.pass()));
+    let mut out: Box<dyn Write> = match report {
+        Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    write_report(&mut out, procedure, &outcomes, started, meter.serial.as_deref(), pass)?;
+    out.flush()?;
+    let passed = outcomes.iter().filter(|outcome| matches!(outcome, Outcome::Measured(captured) if captured.pass())).count();
+    match pass {
+        true => eprintln!("\n{}", tr!("PASS: all {} steps", total).green().bold()),
+        false => eprintln!("\n{}", tr!("FAIL: {} of {} steps passed", passed, total).red().bold()),
+    }
+    if let Some(path) = report {
+        eprintln!("{}", tr!("Report written to {}", path.display()));
+    }
+    Ok(if pass { 0 } else { crate::exit_code::ALARM_TRIPPED })
+}
+
+/// `4.75 .. 5.25 V`, `≥ 1000`, or `any` without limits
+fn expected(step: &Step, unit: &str) -> String {
+    let limits = match (step.limits.low, step.limits.high) {
+        (Some(low), Some(high)) => format!("{} .. {} {}", low, high, unit),
+        (Some(low), None) => format!("≥ {} {}", low, unit),
+        (None, Some(high)) => format!("≤ {} {}", high, unit),
+        (None, None) => "any".to_owned(),
+    };
+    match &step.mode {
+        Some(mode) => format!("{}, {}", mode, limits.trim_end()),
+        None => limits.trim_end().to_owned(),
+    }
+}
+
+/// Markdown, a heading and a table with a row per step, for pasting into a ticket or a wiki
+fn write_report(out: &mut dyn Write, procedure: &Procedure, outcomes: &[Outcome], started: SystemTime, serial: Option<&str>, pass: bool) -> io::Result<()> {
+    writeln!(out, "# {}\n", procedure.title.as_deref().unwrap_or("Test procedure"))?;
+    writeln!(out, "- Started: {}", Utc(started))?;
+    writeln!(out, "- Finished: {}", Utc(SystemTime::now()))?;
+    writeln!(out, "- Meter: UT61E+{}", serial.map_or(String::new(), |serial| format!(" {}", serial)))?;
+    writeln!(out, "- Result: **{}**\n", if pass { "PASS" } else { "FAIL" })?;
+    writeln!(out, "| # | Step | Expected | Readings | Min | Mean | Max | Result |")?;
+    writeln!(out, "|---|------|----------|----------|-----|------|-----|--------|")?;
+    for (i, (step, outcome)) in procedure.steps.iter().zip(outcomes).enumerate() {
+        let name = step.name.as_deref().unwrap_or(&step.prompt).replace('|', "\\|").replace('\n', " ");
+        let row = match outcome {
+            Outcome::Measured(captured) => {
+                let value = |v: Option<f64>| v.map_or("-".to_owned(), |v| format!("{} {}", significant(v), captured.unit));
+                let result = if captured.pass() { "PASS".to_owned() } else { format!("FAIL, {}", captured.why()) };
+                format!("{} | {} | {} | {} | {} | {} | {}", name, expected(step, captured.unit), captured.readings, value(captured.min()), value(captured.mean()), value(captured.max()), result)
+            }
+            Outcome::Skipped => format!("{} | {} | 0 | - | - | - | skipped", name, expected(step, "")),
+            Outcome::NotRun => format!("{} | {} | 0 | - | - | - | not run", name, expected(step, "")),
+        };
+        writeln!(out, "| {} | {} |", i + 1, row)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,808 @@
+//! UT61E+ wire protocol, pure decoding with no I/O.
+//!
+//! Everything in here works on byte slices and only uses `core`, so it can be unit tested,
+//! fuzzed, or dropped into an embedded bridge that reads the meter's UART directly.
+//!
+//! A frame on the wire is `AB CD <len> <payload...> <checksum hi> <checksum lo>`, where `len`
+//! counts the payload plus the two checksum bytes and the checksum is the 16-bit sum of every
+//! byte before it. Field layout and flag bits are from ljakob/unit_ut61eplus.
+
+pub const HEADER: [u8; 2] = [0xAB, 0xCD];
+
+pub const CMD_GET_MEASUREMENT: u8 = 0x5E;
+
+/// The LIGHT key, as if pressed on the front panel. There's no command to turn auto power-off
+/// off (that's holding SELECT while switching the meter on), but a key press restarts its timer
+pub const CMD_LIGHT: u8 = 0x4B;
+
+/// Every key that can be pressed over USB, by the name `--macros` uses. `exit-` ones are a long
+/// press of the key before, and `hz` is the Hz/% key
+pub const KEYS: [(&str, u8); 11] = [
+    ("minmax", 0x41),
+    ("exit-minmax", 0x42),
+    ("range", 0x46),
+    ("auto", 0x47),
+    ("rel", 0x48),
+    ("hz", 0x49),
+    ("hold", 0x4A),
+    ("light", CMD_LIGHT),
+    ("select", 0x4C),
+    ("peak", 0x4D),
+    ("exit-peak", 0x4E),
+];
+
+/// Length of a measurement payload, checksum not included
+pub const MEASUREMENT_LEN: usize = 14;
+
+/// Why a buffer didn't contain a usable frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// No `AB CD` anywhere in the buffer
+    NoHeader,
+    /// The header was found but the buffer ends before the frame does
+    Truncated,
+    /// The length byte is too small to even hold the checksum
+    BadLength(u8),
+    BadChecksum { expected: u16, actual: u16 },
+}
+
+impl core::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FrameError::NoHeader => write!(f, "no frame header"),
+            FrameError::Truncated => write!(f, "truncated frame"),
+            FrameError::BadLength(len) => write!(f, "bad frame length {}", len),
+            FrameError::BadChecksum { expected, actual } => {
+                write!(f, "bad checksum, expected {:04x} got {:04x}", expected, actual)
+            }
+        }
+    }
+}
+
+/// 16-bit sum of all bytes, which is what the meter uses as its checksum
+pub fn checksum(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16))
+}
+
+/// Builds the 6 byte frame for a single byte command, e.g. `command(CMD_GET_MEASUREMENT)`
+pub const fn command(cmd: u8) -> [u8; 6] {
+    let sum = HEADER[0] as u16 + HEADER[1] as u16 + 3 + cmd as u16;
+    [HEADER[0], HEADER[1], 3, cmd, (sum >> 8) as u8, sum as u8]
+}
+
+/// Length of a whole measurement frame, header to checksum
+pub const MEASUREMENT_FRAME_LEN: usize = MEASUREMENT_LEN + 5;
+
+/// Wraps a measurement payload in a frame as the meter sends it, e.g. for simulating one
+pub fn measurement_frame(payload: &[u8; MEASUREMENT_LEN]) -> [u8; MEASUREMENT_FRAME_LEN] {
+    let mut frame = [0; MEASUREMENT_FRAME_LEN];
+    frame[..2].copy_from_slice(&HEADER);
+    frame[2] = MEASUREMENT_LEN as u8 + 2;
+    frame[3..3 + MEASUREMENT_LEN].copy_from_slice(payload);
+    let sum = checksum(&frame[..3 + MEASUREMENT_LEN]);
+    frame[3 + MEASUREMENT_LEN..].copy_from_slice(&sum.to_be_bytes());
+    frame
+}
+
+/// Where the first frame in `bytes` starts and how long it is, header and checksum included,
+/// without checking the checksum. For reassembling frames that arrive in pieces.
+pub fn locate_frame(bytes: &[u8]) -> Result<(usize, usize), FrameError> {
+    let start = bytes
+        .windows(2)
+        .position(|w| w == HEADER)
+        .ok_or(FrameError::NoHeader)?;
+    let len = *bytes.get(start + 2).ok_or(FrameError::Truncated)?;
+    if len < 2 {
+        return Err(FrameError::BadLength(len));
+    }
+    let total = 3 + len as usize;
+    if bytes.len() < start + total {
+        return Err(FrameError::Truncated);
+    }
+    Ok((start, total))
+}
+
+/// Finds the first frame in `bytes` and returns its payload, with the header, length and
+/// checksum stripped off once the checksum has been verified
+pub fn find_frame(bytes: &[u8]) -> Result<&[u8], FrameError> {
+    let (start, total) = locate_frame(bytes)?;
+    let frame = &bytes[start..start + total];
+
+    let expected = checksum(&frame[..total - 2]);
+    let actual = u16::from_be_bytes([frame[total - 2], frame[total - 1]]);
+    if expected != actual {
+        return Err(FrameError::BadChecksum { expected, actual });
+    }
+    Ok(&frame[3..total - 2])
+}
+
+/// The bytes in a line of a hex dump, whatever tool wrote it: `ab cd 12`, `abcd12`,
+/// `0xAB,0xCD`, or a logic analyser CSV export. If the line has any `0x` bytes only those count,
+/// so timestamps and other columns are skipped. Otherwise every run of hex digits of even length
+/// is taken as bytes.
+pub fn hex_bytes(line: &str) -> impl Iterator<Item = u8> + '_ {
+    let prefixed = line.contains("0x") || line.contains("0X");
+    line.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter_map(move |token| match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+            Some(hex) => Some(hex),
+            None if !prefixed => Some(token),
+            None => None,
+        })
+        .filter(|hex| !hex.is_empty() && hex.len() % 2 == 0 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .flat_map(|hex| {
+            hex.as_bytes()
+                .chunks(2)
+                .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).unwrap_or("00"), 16).unwrap_or(0))
+        })
+}
+
+/// The digits shown on the LCD with the padding spaces removed, kept on the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisplayDigits {
+    buf: [u8; 7],
+    len: usize,
+}
+
+impl DisplayDigits {
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("?")
+    }
+}
+
+/// What the LCD is showing, as a number where there is one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reading {
+    /// `mantissa * 10^-decimals` in the display unit, so `-0.0120` is `{ mantissa: -120,
+    /// decimals: 4 }` and the trailing zero (the resolution) survives
+    Value { mantissa: i32, decimals: u8 },
+    /// `OL`, the input is outside the range. `negative` is set for `-OL`
+    Overload { negative: bool },
+    /// `----`, shown while the meter has nothing to measure (open diode, NCV, ranging)
+    Dashes,
+    /// Anything else, kept as the raw characters
+    Unknown(DisplayDigits),
+}
+
+impl Reading {
+    /// Parses the trimmed display characters
+    pub fn parse(digits: DisplayDigits) -> Reading {
+        let text = &digits.buf[..digits.len];
+        if !text.is_empty() && text.iter().all(|&b| b == b'-') {
+            return Reading::Dashes;
+        }
+        let (negative, body) = match text.split_first() {
+            Some((b'-', rest)) => (true, rest),
+            _ => (false, text),
+        };
+
+        // Some firmware puts the decimal point inside "OL" and the O can come through as a 0
+        let mut letters = body.iter().filter(|&&b| b != b'.');
+        if let (Some(b'O' | b'0'), Some(b'L'), None) = (letters.next(), letters.next(), letters.next()) {
+            return Reading::Overload { negative };
+        }
+
+        let mut mantissa: i32 = 0;
+        let mut decimals: Option<u8> = None;
+        let mut any_digit = false;
+        for &b in body {
+            match b {
+                b'0'..=b'9' => {
+                    mantissa = mantissa * 10 + (b - b'0') as i32;
+                    any_digit = true;
+                    if let Some(d) = decimals.as_mut() {
+                        *d += 1;
+                    }
+                }
+                b'.' if decimals.is_none() => decimals = Some(0),
+                _ => return Reading::Unknown(digits),
+            }
+        }
+        if !any_digit {
+            return Reading::Unknown(digits);
+        }
+        Reading::Value {
+            mantissa: if negative { -mantissa } else { mantissa },
+            decimals: decimals.unwrap_or(0),
+        }
+    }
+
+    /// The value in the display unit, `None` for anything that isn't a number
+    pub fn value(&self) -> Option<f64> {
+        match *self {
+            Reading::Value { mantissa, decimals } => {
+                let mut scale = 1.0;
+                for _ in 0..decimals {
+                    scale *= 10.0;
+                }
+                Some(mantissa as f64 / scale)
+            }
+            _ => None,
+        }
+    }
+
+    /// The same reading times `10^places`, with the digits shown unchanged, e.g. `0.5000` moved
+    /// 3 places is `500.0`. Anything that isn't a number is returned as it is, `None` if the
+    /// result doesn't fit.
+    pub fn shifted(self, places: i32) -> Option<Reading> {
+        let Reading::Value { mantissa, decimals } = self else {
+            return Some(self);
+        };
+        let decimals = decimals as i32 - places;
+        if decimals >= 0 {
+            return Some(Reading::Value { mantissa, decimals: u8::try_from(decimals).ok()? });
+        }
+        let mantissa = mantissa.checked_mul(10i32.checked_pow(decimals.unsigned_abs())?)?;
+        Some(Reading::Value { mantissa, decimals: 0 })
+    }
+}
+
+impl core::fmt::Display for Reading {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Reading::Value { mantissa, decimals } => {
+                let sign = if mantissa < 0 { "-" } else { "" };
+                let abs = mantissa.unsigned_abs();
+                if decimals == 0 {
+                    return write!(f, "{}{}", sign, abs);
+                }
+                // u64 since a reading moved to the base unit (22.000 nF as F) can have 12 decimals
+                let scale = 10u64.pow(decimals as u32);
+                let abs = abs as u64;
+                write!(f, "{}{}.{:0width$}", sign, abs / scale, abs % scale, width = decimals as usize)
+            }
+            Reading::Overload { negative: true } => write!(f, "-OL"),
+            Reading::Overload { negative: false } => write!(f, "OL"),
+            Reading::Dashes => write!(f, "----"),
+            Reading::Unknown(digits) => write!(f, "{}", digits.as_str()),
+        }
+    }
+}
+
+/// How readings are written: as the meter shows them, or converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Units {
+    /// The unit of the range in use, `0.5000 V` on the 2.2 V range and `4.653 kΩ` on 22 kΩ
+    #[default]
+    Native,
+    /// The SI prefix that puts the number between 1 and 1000, `500.0 mV`
+    Engineering,
+    /// No prefix at all, `4653 Ω`, so a column of values has the same unit whatever the range
+    Si,
+}
+
+/// A reading converted to some [`Units`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scaled {
+    pub reading: Reading,
+    pub unit: &'static str,
+    /// Powers of ten the reading was moved by
+    pub places: i32,
+}
+
+impl Scaled {
+    /// Anything else in the range unit (full scale, resolution, uncertainty) in `unit`
+    pub fn scale(&self, value: f64) -> f64 {
+        let mut factor = 1.0;
+        for _ in 0..self.places.unsigned_abs() {
+            factor *= 10.0;
+        }
+        // Dividing rather than multiplying by 0.001 keeps 22 nF as 2.2e-8 F, not 2.2000000000000002e-8
+        if self.places >= 0 { value * factor } else { value / factor }
+    }
+}
+
+/// SI prefixes by power of ten, for the units that take one
+const PREFIXES: [(i32, [&str; 5]); 8] = [
+    (-12, ["pV", "pΩ", "pF", "pA", "pHz"]),
+    (-9, ["nV", "nΩ", "nF", "nA", "nHz"]),
+    (-6, ["μV", "μΩ", "μF", "μA", "μHz"]),
+    (-3, ["mV", "mΩ", "mF", "mA", "mHz"]),
+    (0, ["V", "Ω", "F", "A", "Hz"]),
+    (3, ["kV", "kΩ", "kF", "kA", "kHz"]),
+    (6, ["MV", "MΩ", "MF", "MA", "MHz"]),
+    (9, ["GV", "GΩ", "GF", "GA", "GHz"]),
+];
+
+/// The power of ten of `unit`'s prefix and which column of [`PREFIXES`] it's in, `None` for
+/// units without prefixes (%, β)
+fn prefix_of(unit: &str) -> Option<(i32, usize)> {
+    PREFIXES
+        .iter()
+        .find_map(|(exp, units)| units.iter().position(|&u| u == unit).map(|column| (*exp, column)))
+}
+
+/// The power of ten of `unit`'s prefix and the unit without it, so `mV` is `(-3, "V")`. `None`
+/// for units without prefixes.
+pub fn without_prefix(unit: &str) -> Option<(i32, &'static str)> {
+    let (exp, column) = prefix_of(unit)?;
+    Some((exp, with_prefix(0, column)?))
+}
+
+fn with_prefix(exp: i32, column: usize) -> Option<&'static str> {
+    PREFIXES.iter().find(|(e, _)| *e == exp).map(|(_, units)| units[column])
+}
+
+/// One decoded measurement payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Measurement {
+    pub mode: u8,
+    pub range: u8,
+    /// The 7 LCD characters as sent, padding spaces included
+    pub display: [u8; 7],
+    /// Raw bar graph bytes, not decoded yet
+    pub bar: [u8; 2],
+    pub rel: bool,
+    pub hold: bool,
+    pub min: bool,
+    pub max: bool,
+    pub auto_range: bool,
+    pub low_battery: bool,
+    pub hv_warning: bool,
+    pub dc: bool,
+    pub peak_max: bool,
+    pub peak_min: bool,
+}
+
+impl Measurement {
+    /// Decodes a payload as returned by [`find_frame`]
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() < MEASUREMENT_LEN {
+            return None;
+        }
+        let mut display = [0; 7];
+        display.copy_from_slice(&payload[2..9]);
+        let (flags1, flags2, flags3) = (payload[11], payload[12], payload[13]);
+        Some(Measurement {
+            mode: payload[0],
+            range: payload[1],
+            display,
+            bar: [payload[9], payload[10]],
+            rel: flags1 & 0x01 != 0,
+            hold: flags1 & 0x02 != 0,
+            min: flags1 & 0x04 != 0,
+            max: flags1 & 0x08 != 0,
+            hv_warning: flags2 & 0x01 != 0,
+            low_battery: flags2 & 0x02 != 0,
+            auto_range: flags2 & 0x04 == 0,
+            peak_min: flags3 & 0x02 != 0,
+            peak_max: flags3 & 0x04 != 0,
+            dc: flags3 & 0x08 != 0,
+        })
+    }
+
+    /// The payload the meter would send for this measurement, the inverse of
+    /// [`Measurement::decode`]. Flag bytes have the `0x30` the meter sets in their high nibble.
+    pub fn encode(&self) -> [u8; MEASUREMENT_LEN] {
+        let bit = |set: bool, mask: u8| if set { mask } else { 0 };
+        let mut payload = [0; MEASUREMENT_LEN];
+        payload[0] = self.mode;
+        payload[1] = self.range;
+        payload[2..9].copy_from_slice(&self.display);
+        payload[9..11].copy_from_slice(&self.bar);
+        payload[11] = 0x30 | bit(self.rel, 0x01) | bit(self.hold, 0x02) | bit(self.min, 0x04) | bit(self.max, 0x08);
+        payload[12] = 0x30 | bit(self.hv_warning, 0x01) | bit(self.low_battery, 0x02) | bit(!self.auto_range, 0x04);
+        payload[13] = 0x30 | bit(self.peak_min, 0x02) | bit(self.peak_max, 0x04) | bit(self.dc, 0x08);
+        payload
+    }
+
+    /// The display with the padding spaces removed, e.g. `"-1.2345"`
+    pub fn display_digits(&self) -> DisplayDigits {
+        let mut digits = DisplayDigits { buf: [0; 7], len: 0 };
+        for &b in self.display.iter().filter(|&&b| b != b' ') {
+            digits.buf[digits.len] = b;
+            digits.len += 1;
+        }
+        digits
+    }
+
+    /// The display decoded into a number, overload or dashes
+    pub fn reading(&self) -> Reading {
+        Reading::parse(self.display_digits())
+    }
+
+    pub fn mode_name(&self) -> &'static str {
+        parse_mode(self.mode)
+    }
+
+    pub fn unit(&self) -> &'static str {
+        parse_unit(self.mode, self.range)
+    }
+
+    /// Full scale and resolution of the active range
+    pub fn range_info(&self) -> Option<RangeInfo> {
+        range_info(self.mode, self.range)
+    }
+
+    /// Datasheet accuracy of the active range
+    pub fn accuracy(&self) -> Option<Accuracy> {
+        accuracy(self.mode, self.range)
+    }
+
+    /// ± bound on the true value in the display unit, from the datasheet accuracy
+    pub fn uncertainty(&self) -> Option<f64> {
+        let value = self.reading().value()?;
+        Some(self.accuracy()?.uncertainty(value, &self.range_info()?))
+    }
+
+    /// The reading in `units`. Overloads, dashes and units without prefixes stay as they are.
+    pub fn reading_in(&self, units: Units) -> Scaled {
+        let (reading, unit) = (self.reading(), self.unit());
+        let native = Scaled { reading, unit, places: 0 };
+        let Some((exp, column)) = prefix_of(unit) else {
+            return native;
+        };
+        let target = match (units, reading) {
+            (Units::Native, _) => return native,
+            (Units::Si, _) => 0,
+            (Units::Engineering, Reading::Value { mantissa, decimals }) if mantissa != 0 => {
+                let magnitude = mantissa.unsigned_abs().ilog10() as i32 - decimals as i32 + exp;
+                magnitude.div_euclid(3) * 3
+            }
+            (Units::Engineering, _) => return native,
+        };
+        let (Some(unit), Some(reading)) = (with_prefix(target, column), reading.shifted(exp - target)) else {
+            return native;
+        };
+        Scaled { reading, unit, places: exp - target }
+    }
+
+    pub fn range_mode(&self) -> &'static str {
+        if self.auto_range { "AUTO" } else { "MANUAL" }
+    }
+
+    /// MIN/MAX recording state as shown on the LCD
+    pub fn min_max(&self) -> &'static str {
+        match (self.min, self.max) {
+            (_, true) => "MAX",
+            (true, false) => "MIN",
+            _ => "",
+        }
+    }
+}
+
+pub fn parse_mode(mode: u8) -> &'static str {
+    match mode {
+        0 => "V_AC",
+        24 => "V_AC_LPF",
+        2 => "V_DC",
+        25 => "V_AC_DC",
+        1 => "mV_AC",
+        3 => "mV_DC",
+        6 => "Resistance Ω",
+        7 => "Continuity 🕪",
+        8 => "Diode 𜰏",
+        9 => "Capacitance 𜰓",
+        4 => "Hz",
+        5 => "%",
+        18 => "Transistor gain 𜰐 β hFE",
+        12 => "μA_DC",
+        13 => "μA_AC",
+        14 => "mA_DC",
+        15 => "mA_AC",
+        16 => "A_DC",
+        17 => "A_AC",
+        20 => "NCV",
+        _ => "?",
+    }
+}
+
+pub fn parse_unit(mode: u8, range: u8) -> &'static str {
+    match mode {
+        0 => match range { // VAC
+            0x30 => "V",
+            0x31 => "V",
+            0x32 => "V",
+            0x33 => "V",
+            _ => "?",
+        },
+        24 => match range { // VAC LPF
+            0x30 => "V",
+            0x31 => "V",
+            0x32 => "V",
+            0x33 => "V",
+            _ => "?",
+        },
+        2 => match range { // VDC
+            0x30 => "V",
+            0x31 => "V",
+            0x32 => "V",
+            0x33 => "V",
+            _ => "?",
+        },
+        25 => match range { // VACDC
+            0x30 => "V",
+            0x31 => "V",
+            0x32 => "V",
+            0x33 => "V",
+            _ => "?",
+        },
+        1 => match range { // mVAC
+            0x30 => "mV",
+            _ => "?",
+        },
+        3 => match range { // mVDC
+            0x30 => "mV",
+            _ => "?",
+        },
+        6 => match range { // Resistance
+            0x30 => "Ω",
+            0x31 => "kΩ",
+            0x32 => "kΩ",
+            0x33 => "kΩ",
+            0x34 => "MΩ",
+            0x35 => "MΩ",
+            0x36 => "MΩ",
+            _ => "?",
+        },
+        7 => match range { // Continuity
+            0x30..=0x36 => "Ω",
+            _ => "?",
+        },
+        8 => match range { // Diode
+            0x30 => "V",
+            _ => "?",
+        },
+        9 => match range { // Capacitance
+            0x30 => "nF",
+            0x31 => "nF",
+            0x32 => "μF",
+            0x33 => "μF",
+            0x34 => "μF",
+            0x35 => "mF",
+            0x36 => "mF",
+            _ => "?",
+        },
+        4 => match range { // Hz
+            0x30 => "Hz",
+            0x31 => "Hz",
+            0x32 => "kHz",
+            0x33 => "kHz",
+            0x34 => "kHz",
+            0x35 => "MHz",
+            0x36 => "MHz",
+            0x37 => "MHz",
+            _ => "?",
+        },
+        5 => match range { // %
+            0x30 => "%",
+            _ => "?",
+        },
+        18 => match range { // hFE
+            0x30 => "β",
+            _ => "?",
+        },
+        12 => match range { // μA_DC
+            0x30 => "μA",
+            0x31 => "μA",
+            _ => "?",
+        },
+        13 => match range { // μA_AC
+            0x30 => "μA",
+            0x31 => "μA",
+            _ => "?",
+        },
+        14 => match range { // mA_DC
+            0x30 => "mA",
+            0x31 => "mA",
+            _ => "?",
+        },
+        15 => match range { // mA_AC
+            0x30 => "mA",
+            0x31 => "mA",
+            _ => "?",
+        },
+        16 => match range { // A_DC
+            0x31 => "A",
+            _ => "?",
+        },
+        17 => match range { // A_AC
+            0x31 => "A",
+            _ => "?",
+        },
+        20 => match range { // NCV
+            0x30 => "NCV",
+            _ => "?",
+        },
+        _ => "?",
+    }
+}
+
+
+/// A measurement range, e.g. 22.000 V is `{ counts: 22000, decimals: 3, unit: "V" }`. One count
+/// is the resolution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeInfo {
+    pub counts: u32,
+    pub decimals: u8,
+    pub unit: &'static str,
+}
+
+impl RangeInfo {
+    const fn new(counts: u32, decimals: u8, unit: &'static str) -> Self {
+        RangeInfo { counts, decimals, unit }
+    }
+
+    pub fn full_scale(&self) -> f64 {
+        self.counts as f64 / self.scale()
+    }
+
+    /// Size of one count in the range unit
+    pub fn resolution(&self) -> f64 {
+        1.0 / self.scale()
+    }
+
+    fn scale(&self) -> f64 {
+        10u32.pow(self.decimals as u32) as f64
+    }
+
+    /// One count written with the next SI prefix down, e.g. `1 mV` for the 22.000 V range or
+    /// `100 μV` for 2.2000 V. Units without prefixes (%, β) keep the decimals, `0.01 %`.
+    pub fn resolution_prefixed(&self) -> (Reading, &'static str) {
+        let mut unit = self.unit;
+        let mut exp = -(self.decimals as i32);
+        while exp < 0 {
+            let Some(smaller) = smaller_prefix(unit) else { break };
+            unit = smaller;
+            exp += 3;
+        }
+        let value = if exp >= 0 {
+            Reading::Value { mantissa: 10i32.pow(exp as u32), decimals: 0 }
+        } else {
+            Reading::Value { mantissa: 1, decimals: (-exp) as u8 }
+        };
+        (value, unit)
+    }
+}
+
+fn smaller_prefix(unit: &str) -> Option<&'static str> {
+    Some(match unit {
+        "MΩ" => "kΩ",
+        "kΩ" => "Ω",
+        "Ω" => "mΩ",
+        "V" => "mV",
+        "mV" => "μV",
+        "mF" => "μF",
+        "μF" => "nF",
+        "nF" => "pF",
+        "A" => "mA",
+        "mA" => "μA",
+        "μA" => "nA",
+        "MHz" => "kHz",
+        "kHz" => "Hz",
+        "Hz" => "mHz",
+        _ => return None,
+    })
+}
+
+impl core::fmt::Display for RangeInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let full = Reading::Value { mantissa: self.counts as i32, decimals: self.decimals };
+        write!(f, "{} {}", full, self.unit)
+    }
+}
+
+/// Range table from the UT61E+ manual, 22000 counts on most ranges
+pub fn range_info(mode: u8, range: u8) -> Option<RangeInfo> {
+    use RangeInfo as R;
+    let info = match (mode, range) {
+        // V AC, V AC LPF and V AC+DC top out at 750 V
+        (0 | 24 | 25, 0x30) => R::new(22000, 4, "V"),
+        (0 | 24 | 25, 0x31) => R::new(22000, 3, "V"),
+        (0 | 24 | 25, 0x32) => R::new(22000, 2, "V"),
+        (0 | 24 | 25, 0x33) => R::new(7500, 1, "V"),
+        // V DC
+        (2, 0x30) => R::new(22000, 4, "V"),
+        (2, 0x31) => R::new(22000, 3, "V"),
+        (2, 0x32) => R::new(22000, 2, "V"),
+        (2, 0x33) => R::new(10000, 1, "V"),
+        // mV AC/DC
+        (1 | 3, 0x30) => R::new(22000, 2, "mV"),
+        // Resistance
+        (6, 0x30) => R::new(22000, 2, "Ω"),
+        (6, 0x31) => R::new(22000, 4, "kΩ"),
+        (6, 0x32) => R::new(22000, 3, "kΩ"),
+        (6, 0x33) => R::new(22000, 2, "kΩ"),
+        (6, 0x34) => R::new(22000, 4, "MΩ"),
+        (6, 0x35) => R::new(22000, 3, "MΩ"),
+        (6, 0x36) => R::new(22000, 2, "MΩ"),
+        // Continuity always measures on the 220 Ω range
+        (7, 0x30..=0x36) => R::new(22000, 2, "Ω"),
+        // Diode
+        (8, 0x30) => R::new(30000, 4, "V"),
+        // Capacitance
+        (9, 0x30) => R::new(22000, 3, "nF"),
+        (9, 0x31) => R::new(22000, 2, "nF"),
+        (9, 0x32) => R::new(22000, 4, "μF"),
+        (9, 0x33) => R::new(22000, 3, "μF"),
+        (9, 0x34) => R::new(22000, 2, "μF"),
+        (9, 0x35) => R::new(22000, 4, "mF"),
+        (9, 0x36) => R::new(22000, 3, "mF"),
+        // Frequency
+        (4, 0x30) => R::new(22000, 3, "Hz"),
+        (4, 0x31) => R::new(22000, 2, "Hz"),
+        (4, 0x32) => R::new(22000, 4, "kHz"),
+        (4, 0x33) => R::new(22000, 3, "kHz"),
+        (4, 0x34) => R::new(22000, 2, "kHz"),
+        (4, 0x35) => R::new(22000, 4, "MHz"),
+        (4, 0x36) => R::new(22000, 3, "MHz"),
+        (4, 0x37) => R::new(22000, 2, "MHz"),
+        // Duty cycle
+        (5, 0x30) => R::new(10000, 2, "%"),
+        // hFE
+        (18, 0x30) => R::new(1000, 0, "β"),
+        // μA
+        (12 | 13, 0x30) => R::new(22000, 2, "μA"),
+        (12 | 13, 0x31) => R::new(22000, 1, "μA"),
+        // mA
+        (14 | 15, 0x30) => R::new(22000, 3, "mA"),
+        (14 | 15, 0x31) => R::new(22000, 2, "mA"),
+        // A, 10 A continuous but the display goes to 22.000
+        (16 | 17, 0x31) => R::new(22000, 3, "A"),
+        _ => return None,
+    };
+    Some(info)
+}
+
+/// Datasheet accuracy, ±(percent of reading + counts)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Accuracy {
+    pub percent: f64,
+    pub counts: u32,
+}
+
+impl Accuracy {
+    const fn new(percent: f64, counts: u32) -> Self {
+        Accuracy { percent, counts }
+    }
+
+    /// ± bound for `value` read on `range`, in the range unit
+    pub fn uncertainty(&self, value: f64, range: &RangeInfo) -> f64 {
+        let abs = if value < 0.0 { -value } else { value };
+        abs * self.percent / 100.0 + self.counts as f64 * range.resolution()
+    }
+}
+
+impl core::fmt::Display for Accuracy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "±({}% + {})", self.percent, self.counts)
+    }
+}
+
+/// Accuracy table from the UT61E+ manual (18-28 °C, < 75 % RH, within a year of calibration).
+/// AC figures are for 45 Hz - 1 kHz. These are the published specs, not a calibration, a
+/// certificate for your own meter beats them.
+pub fn accuracy(mode: u8, range: u8) -> Option<Accuracy> {
+    use Accuracy as A;
+    let spec = match (mode, range) {
+        // V DC
+        (2, 0x30..=0x32) => A::new(0.05, 5),
+        (2, 0x33) => A::new(0.1, 8),
+        (3, 0x30) => A::new(0.1, 10),
+        // V AC, LPF and AC+DC
+        (0 | 24, 0x30..=0x32) => A::new(0.8, 10),
+        (0 | 24, 0x33) => A::new(1.0, 10),
+        (25, 0x30..=0x33) => A::new(1.2, 30),
+        (1, 0x30) => A::new(1.0, 15),
+        // Resistance
+        (6, 0x30) => A::new(0.5, 10),
+        (6, 0x31..=0x34) => A::new(0.5, 2),
+        (6, 0x35) => A::new(1.5, 5),
+        (6, 0x36) => A::new(3.0, 10),
+        // Capacitance
+        (9, 0x30) => A::new(3.0, 10),
+        (9, 0x31..=0x34) => A::new(3.0, 5),
+        (9, 0x35..=0x36) => A::new(10.0, 10),
+        // Frequency
+        (4, 0x30..=0x37) => A::new(0.01, 5),
+        // Current DC
+        (12, 0x30..=0x31) => A::new(0.5, 10),
+        (14, 0x30..=0x31) => A::new(0.5, 10),
+        (16, 0x31) => A::new(1.2, 10),
+        // Current AC
+        (13, 0x30..=0x31) => A::new(1.0, 15),
+        (15, 0x30..=0x31) => A::new(1.0, 15),
+        (17, 0x31) => A::new(1.5, 15),
+        _ => return None,
+    };
+    Some(spec)
+}
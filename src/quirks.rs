@@ -0,0 +1,77 @@
+//! Differences between the USB-UART bridges the UT61E+ cable ships with. Both are HID devices
+//! carrying the meter's UART bytes, but they frame the reports and set up the UART differently.
+
+use crate::meter::Transport;
+use hidapi::HidError;
+
+pub struct Quirks {
+    pub name: &'static str,
+    /// Most UART bytes one report can carry
+    pub max_payload: usize,
+    /// First byte of an outgoing report carrying `len` bytes
+    pub length_byte: fn(usize) -> u8,
+    /// Number of UART bytes in an incoming report, from its first byte
+    pub report_len: fn(u8) -> usize,
+    /// Baud rate and line setup
+    pub init: fn(&dyn Transport) -> Result<(), HidError>,
+    /// Whether the UART has to be set up before the meter answers, rather than only when
+    /// recovering from a stall
+    pub init_on_open: bool,
+}
+
+impl Quirks {
+    /// The UART bytes in an incoming report
+    pub fn report_data<'a>(&self, report: &'a [u8]) -> &'a [u8] {
+        match report.split_first() {
+            Some((&first, data)) => &data[..(self.report_len)(first).min(data.len())],
+            None => &[],
+        }
+    }
+}
+
+/// Silicon Labs CP2110: the report ID is the number of data bytes (1-63), the UART is set up
+/// with feature reports: enable the UART, 9600 baud 8N1, purge the FIFOs
+pub static CP2110: Quirks = Quirks {
+    name: "Silicon Labs CP2110",
+    max_payload: 63,
+    length_byte: |len| len as u8,
+    report_len: |first| first as usize,
+    init: |dev| {
+        dev.send_feature_report(&[0x41, 0x01])?;
+        dev.send_feature_report(&[0x50, 0x00, 0x00, 0x25, 0x80, 0x00, 0x00, 0x03, 0x00, 0x00])?;
+        dev.send_feature_report(&[0x43, 0x02])?;
+        Ok(())
+    },
+    // The meter answers on a CP2110 cable without it
+    init_on_open: false,
+};
+
+/// QinHeng (WCH) CH9325: 8 byte reports, the first byte is 0xF0 plus the number of data bytes
+/// (up to 7), so a measurement frame arrives spread over several reports. The UART is set with
+/// a single feature report holding the baud rate twice (little endian) and the 8N1 line
+/// setting, the same way sigrok drives this chip.
+pub static CH9325: Quirks = Quirks {
+    name: "QinHeng CH9325",
+    max_payload: 7,
+    length_byte: |len| 0xF0 | len as u8,
+    report_len: |first| (first & 0x0F) as usize,
+    init: |dev| {
+        let baud = 9600u16.to_le_bytes();
+        dev.send_feature_report(&[0x00, baud[0], baud[1], baud[0], baud[1], 0x03])?;
+        Ok(())
+    },
+    init_on_open: true,
+};
+
+/// USB IDs of the bridges the meter's cable has been seen with
+pub const DEVICE_IDS: &[(u16, u16)] = &[
+    (0x1A86, 0xE429), // QinHeng
+    (0x10C4, 0xEA80), // Silicon Labs CP2110
+];
+
+pub fn for_device(vid: u16, pid: u16) -> &'static Quirks {
+    match (vid, pid) {
+        (0x1A86, 0xE429) => &CH9325,
+        _ => &CP2110,
+    }
+}
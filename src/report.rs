@@ -0,0 +1,950 @@
+//! `report` subcommand, renders a CSV capture into a single self-contained HTML page with a
+//! plot, statistics and the events seen along the way.
+
+use crate::analysis::{self, Fit, Histogram, Shape, Window};
+use crate::output;
+use clap::ValueEnum;
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A CSV log as written by `--csv`, looked up by column name so older logs with fewer columns
+/// still load. JSON lines from `--format json` (or `--udp` dumped to a file) are turned into the
+/// same rows, see [`Log::from_json`].
+pub struct Log {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// The columns of a CSV log in full, what JSON records are laid out as
+const CSV_COLUMNS: [&str; 14] = ["seq", "time", "elapsed", "value", "unit", "mode", "range", "rel", "hold", "minmax", "full_scale", "resolution", "uncertainty", "verdict"];
+
+impl Log {
+    pub fn load(path: &Path) -> io::Result<Log> {
+        let text = fs::read_to_string(path)?;
+        if text.trim_start().starts_with('{') {
+            return Log::from_json(&text);
+        }
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let header = lines
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty file"))?
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .collect();
+        let rows = lines.map(|l| l.split(',').map(|s| s.trim().to_string()).collect()).collect();
+        Ok(Log { header, rows })
+    }
+
+    /// Each record as the CSV row it would have been. Records from before there was a `version`
+    /// are read as version 1, they're the same. Newer ones are read as far as the fields this
+    /// logger knows go, with a warning, rather than refused.
+    fn from_json(text: &str) -> io::Result<Log> {
+        let invalid = |line: usize, why: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, why));
+        let mut rows = Vec::new();
+        let mut newest = output::FORMAT_VERSION;
+        for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let fields = json_fields(line).ok_or_else(|| invalid(i + 1, "not a JSON object"))?;
+            let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+            let version = field("version").map_or(Ok(1), str::parse::<u32>).map_err(|_| invalid(i + 1, "the version isn't a number"))?;
+            newest = newest.max(version);
+            let flag = |name: &str, set: &'static str| if field(name) == Some("true") { set } else { "" };
+            let (value, unit, mode, range) = match (field("derived"), field("gap"), field("event")) {
+                (Some(name), _, _) => (field("value"), field("unit"), Some(name), Some("DERIVED")),
+                (_, Some(reason), _) => (Some("GAP"), None, Some(reason), None),
+                (_, _, Some(message)) => (Some("EVENT"), None, Some(message), None),
+                _ => (field("display").or(field("value")), field("unit"), field("mode"), field("range")),
+            };
+            let row = CSV_COLUMNS.map(|column| match column {
+                "value" => value,
+                "unit" => unit,
+                "mode" => mode,
+                "range" => range,
+                "rel" => Some(flag("rel", "REL")),
+                "hold" => Some(flag("hold", "HOLD")),
+                other => field(other),
+            });
+            rows.push(row.iter().map(|cell| cell.unwrap_or("").replace(',', ";")).collect());
+        }
+        if newest > output::FORMAT_VERSION {
+            eprintln!("Some records are format version {}, this logger only knows up to {}. Anything new in them is left out.", newest, output::FORMAT_VERSION);
+        }
+        Ok(Log { header: CSV_COLUMNS.map(str::to_owned).to_vec(), rows })
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.header.iter().position(|h| h == name)
+    }
+
+    pub fn get<'a>(&self, row: &'a [String], column: Option<usize>) -> &'a str {
+        column.and_then(|c| row.get(c)).map_or("", |s| s.as_str())
+    }
+}
+
+/// A JSON object as (name, value), strings unescaped, numbers and booleans as written, `null`
+/// and anything nested as empty. `None` if it isn't an object.
+fn json_fields(line: &str) -> Option<Vec<(String, String)>> {
+    let mut chars = line.trim().chars().peekable();
+    let mut fields = Vec::new();
+    let skip_space = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    let string = |chars: &mut std::iter::Peekable<std::str::Chars>| -> Option<String> {
+        let mut s = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(s),
+                '\\' => s.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => char::from_u32(u32::from_str_radix(&chars.by_ref().take(4).collect::<String>(), 16).ok()?).unwrap_or('\u{fffd}'),
+                    c => c,
+                }),
+                c => s.push(c),
+            }
+        }
+    };
+    (chars.next()? == '{').then_some(())?;
+    loop {
+        skip_space(&mut chars);
+        match chars.next()? {
+            '}' if fields.is_empty() => break,
+            '"' => {}
+            _ => return None,
+        }
+        let name = string(&mut chars)?;
+        skip_space(&mut chars);
+        (chars.next()? == ':').then_some(())?;
+        skip_space(&mut chars);
+        let value = match chars.peek()? {
+            '"' => {
+                chars.next();
+                string(&mut chars)?
+            }
+            // Nothing written now nests, but a later version might, left out as unknown
+            '{' | '[' => {
+                let (mut depth, mut quoted, mut escaped) = (0, false, false);
+                for c in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' if quoted => escaped = true,
+                        '"' => quoted = !quoted,
+                        '{' | '[' if !quoted => depth += 1,
+                        '}' | ']' if !quoted => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                String::new()
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(c) = chars.next_if(|&c| c != ',' && c != '}' && !c.is_whitespace()) {
+                    token.push(c);
+                }
+                if token == "null" { String::new() } else { token }
+            }
+        };
+        fields.push((name, value));
+        skip_space(&mut chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => break,
+            _ => return None,
+        }
+    }
+    Some(fields)
+}
+
+/// Count, extremes, mean and standard deviation of a series
+pub struct Stats {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl Stats {
+    pub fn of(values: &[f64]) -> Option<Stats> {
+        if values.is_empty() {
+            return None;
+        }
+        let count = values.len();
+        let mean = values.iter().sum::<f64>() / count as f64;
+        let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+        Some(Stats {
+            count,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean,
+            std_dev: var.sqrt(),
+        })
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Samples in one unit, a plot only makes sense within one of these
+struct Series {
+    unit: String,
+    mode: String,
+    /// What to call it, the mode unless renamed with `--name`
+    name: String,
+    color: String,
+    points: Vec<(usize, f64)>,
+    uncertainty: Vec<f64>,
+}
+
+/// Colours for the plots
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Palette {
+    #[default]
+    Default,
+    /// Okabe-Ito colours, told apart with any kind of colour blindness
+    Colorblind,
+    /// Black and strong colours with thicker lines, for projectors and printouts
+    HighContrast,
+}
+
+struct Colors {
+    trace: &'static str,
+    reference: &'static str,
+    difference: &'static str,
+    fit: &'static str,
+    spectrum: &'static str,
+    allan: &'static str,
+    bars: &'static str,
+    bar_edges: &'static str,
+}
+
+impl Palette {
+    fn colors(self) -> Colors {
+        match self {
+            Palette::Default => Colors {
+                trace: "#1f77b4",
+                reference: "#ff7f0e",
+                difference: "#2ca02c",
+                fit: "#d62728",
+                spectrum: "#9467bd",
+                allan: "#8c564b",
+                bars: "#aec7e8",
+                bar_edges: "#1f77b4",
+            },
+            Palette::Colorblind => Colors {
+                trace: "#0072b2",
+                reference: "#e69f00",
+                difference: "#009e73",
+                fit: "#d55e00",
+                spectrum: "#cc79a7",
+                allan: "#000000",
+                bars: "#56b4e9",
+                bar_edges: "#0072b2",
+            },
+            Palette::HighContrast => Colors {
+                trace: "#000000",
+                reference: "#d55e00",
+                difference: "#0072b2",
+                fit: "#d55e00",
+                spectrum: "#000000",
+                allan: "#000000",
+                bars: "#bbbbbb",
+                bar_edges: "#000000",
+            },
+        }
+    }
+
+    /// Extra CSS, presentation attributes in the SVGs give way to it
+    fn style(self) -> &'static str {
+        match self {
+            Palette::HighContrast => "body{color:#000}svg polyline,svg line{stroke-width:2.5}svg rect{stroke:#000}",
+            _ => "",
+        }
+    }
+}
+
+const PLOT_W: f64 = 800.0;
+const PLOT_H: f64 = 300.0;
+
+/// Plots one or more traces of (record, value) against the same axes, with the line broken
+/// wherever a record in `gaps` falls between two points
+fn svg_plot(traces: &[(&[(usize, f64)], &str)], gaps: &[usize], unit: &str, total: usize) -> String {
+    let ys: Vec<f64> = traces.iter().flat_map(|t| t.0.iter().map(|p| p.1)).collect();
+    let Some(stats) = Stats::of(&ys) else {
+        return String::new();
+    };
+    let (lo, hi) = if stats.max > stats.min {
+        (stats.min, stats.max)
+    } else {
+        (stats.min - 1.0, stats.max + 1.0)
+    };
+    let x = |i: usize| i as f64 / total.max(2).saturating_sub(1) as f64 * PLOT_W;
+    let y = |v: f64| PLOT_H - (v - lo) / (hi - lo) * PLOT_H;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg viewBox=\"-70 -10 {} {}\" width=\"{}\" height=\"{}\">",
+        PLOT_W + 80.0,
+        PLOT_H + 30.0,
+        PLOT_W + 80.0,
+        PLOT_H + 30.0
+    );
+    let _ = write!(
+        svg,
+        "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#ccc\"/>",
+        PLOT_W, PLOT_H
+    );
+    for (v, anchor) in [(hi, 0.0), (lo, PLOT_H)] {
+        let _ = write!(
+            svg,
+            "<text x=\"-5\" y=\"{}\" text-anchor=\"end\" font-size=\"12\">{} {}</text>",
+            anchor + 4.0,
+            v,
+            escape(unit)
+        );
+    }
+    for (points, color) in traces {
+        let segments = points.chunk_by(|a, b| !gaps.iter().any(|&g| a.0 < g && g < b.0));
+        for segment in segments {
+            let _ = write!(svg, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1\" points=\"", escape(color));
+            for &(i, v) in segment {
+                let _ = write!(svg, "{:.1},{:.1} ", x(i), y(v));
+            }
+            svg.push_str("\"/>");
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Things worth pointing out when reading back through a capture
+fn events(log: &Log) -> Vec<(usize, String)> {
+    let (mode, unit, value) = (log.column("mode"), log.column("unit"), log.column("value"));
+    let (rel, hold, range) = (log.column("rel"), log.column("hold"), log.column("range"));
+    let mut events = Vec::new();
+    let mut prev: Option<&Vec<String>> = None;
+    for (i, row) in log.rows.iter().enumerate() {
+        match log.get(row, value) {
+            "GAP" => {
+                events.push((i, format!("Gap ({})", log.get(row, mode))));
+                continue;
+            }
+            "EVENT" => {
+                events.push((i, log.get(row, mode).to_string()));
+                continue;
+            }
+            _ if derived(log, row) => continue,
+            _ => {}
+        }
+        let now_ol = log.get(row, value).ends_with("OL");
+        match prev {
+            None => events.push((i, format!("Started in {} ({})", log.get(row, mode), log.get(row, range)))),
+            Some(p) => {
+                if log.get(p, mode) != log.get(row, mode) || log.get(p, unit) != log.get(row, unit) {
+                    events.push((i, format!("Changed to {} ({})", log.get(row, mode), log.get(row, unit))));
+                }
+                for (col, name) in [(rel, "REL"), (hold, "HOLD")] {
+                    let (was, is) = (!log.get(p, col).is_empty(), !log.get(row, col).is_empty());
+                    if was != is {
+                        events.push((i, format!("{} {}", name, if is { "on" } else { "off" })));
+                    }
+                }
+                if now_ol && !log.get(p, value).ends_with("OL") {
+                    events.push((i, "Overload".to_string()));
+                }
+            }
+        }
+        prev = Some(row);
+    }
+    events
+}
+
+/// Values the logger worked out rather than read, see `output::write_csv_derived`. They're series
+/// of their own but not changes of mode.
+fn derived(log: &Log, row: &[String]) -> bool {
+    log.get(row, log.column("range")) == "DERIVED"
+}
+
+/// Records marking missing samples, see `output::write_csv_gap`
+fn gaps(log: &Log) -> Vec<usize> {
+    let value = log.column("value");
+    (0..log.rows.len()).filter(|&i| log.get(&log.rows[i], value) == "GAP").collect()
+}
+
+/// Splits the numeric readings by unit, and counts the ones that aren't numbers
+fn split_series(log: &Log) -> (Vec<Series>, usize) {
+    let (value, unit, mode) = (log.column("value"), log.column("unit"), log.column("mode"));
+    let uncertainty = log.column("uncertainty");
+
+    let mut series: Vec<Series> = Vec::new();
+    let mut non_numeric = 0;
+    for (i, row) in log.rows.iter().enumerate() {
+        let Ok(v) = log.get(row, value).parse::<f64>() else {
+            if !matches!(log.get(row, value), "GAP" | "EVENT") {
+                non_numeric += 1;
+            }
+            continue;
+        };
+        let u = log.get(row, unit);
+        let s = match series.iter_mut().find(|s| s.unit == u) {
+            Some(s) => s,
+            None => {
+                series.push(Series {
+                    unit: u.to_string(),
+                    mode: log.get(row, mode).to_string(),
+                    name: log.get(row, mode).to_string(),
+                    color: String::new(),
+                    points: Vec::new(),
+                    uncertainty: Vec::new(),
+                });
+                series.last_mut().unwrap()
+            }
+        };
+        s.points.push((i, v));
+        if let Ok(u) = log.get(row, uncertainty).parse::<f64>() {
+            s.uncertainty.push(u);
+        }
+    }
+    (series, non_numeric)
+}
+
+/// Stretches of `points` with nothing but events and derived values between them, no gaps or
+/// readings in other units, so they're evenly spaced as far as the logger could manage
+fn runs<'a>(log: &Log, points: &'a [(usize, f64)]) -> impl Iterator<Item = &'a [(usize, f64)]> {
+    let value = log.column("value");
+    points.chunk_by(move |a, b| (a.0 + 1..b.0).all(|i| log.get(&log.rows[i], value) == "EVENT" || derived(log, &log.rows[i])))
+}
+
+/// Seconds between readings, from the `elapsed` column if the capture has one
+fn interval(log: &Log, points: &[(usize, f64)]) -> Option<f64> {
+    let elapsed = log.column("elapsed")?;
+    let at = |i: usize| log.get(&log.rows[i], Some(elapsed)).parse::<f64>().ok();
+    let (first, last) = (points.first()?, points.last()?);
+    Some((at(last.0)? - at(first.0)?) / (points.len() - 1) as f64)
+}
+
+/// The spectrum of the longest stretch without a gap
+fn spectrum_section(log: &Log, s: &Series, options: &Options) -> String {
+    let Some(run) = runs(log, &s.points).max_by_key(|run| run.len()) else {
+        return String::new();
+    };
+    let values: Vec<f64> = run.iter().map(|p| p.1).collect();
+    let Some(spectrum) = analysis::spectrum(&values, options.window, interval(log, run)) else {
+        return String::new();
+    };
+    let unit = if spectrum.timed { "Hz" } else { "cycles/reading" };
+    let mut html = String::from("\n<h3>Spectrum</h3>\n<p>");
+    let _ = write!(
+        html,
+        "The last {} readings of the longest stretch without a gap, mean removed, {:?} window. \
+         From {:.4} to {:.4} {unit} in steps of {:.4}.",
+        spectrum.samples,
+        options.window,
+        spectrum.frequency(0),
+        spectrum.frequency(spectrum.amplitudes.len() - 1),
+        spectrum.bin_width
+    );
+    if let Some((bin, amplitude)) = spectrum.peak() {
+        let _ = write!(
+            html,
+            " Strongest at {:.4} {unit}, {:.6} {}.",
+            spectrum.frequency(bin),
+            amplitude,
+            escape(&s.unit)
+        );
+    }
+    if !spectrum.timed {
+        html.push_str(" Record with <code>--timestamp</code> for a frequency axis in Hz.");
+    }
+    html.push_str("</p>\n");
+    let points: Vec<(usize, f64)> = spectrum.amplitudes.iter().copied().enumerate().collect();
+    html.push_str(&svg_plot(&[(&points, options.palette.colors().spectrum)], &[], &s.unit, points.len()));
+    html
+}
+
+/// Bars for the histogram, with the normal distribution of the same mean and standard deviation
+/// drawn over them and the mean and ±1σ marked
+fn svg_histogram(h: &Histogram, st: &Stats, unit: &str, colors: &Colors) -> String {
+    let (lo, hi) = (h.start, h.start + h.width * h.counts.len() as f64);
+    let normal = |v: f64| {
+        let z = (v - st.mean) / st.std_dev;
+        st.count as f64 * h.width * (-z * z / 2.0).exp() / (st.std_dev * (2.0 * PI).sqrt())
+    };
+    let tallest = h.counts.iter().copied().max().unwrap_or(0) as f64;
+    let top = if st.std_dev > 0.0 { tallest.max(normal(st.mean)) } else { tallest }.max(1.0);
+    let x = |v: f64| (v - lo) / (hi - lo) * PLOT_W;
+    let y = |count: f64| PLOT_H - count / top * PLOT_H;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg viewBox=\"-70 -10 {} {}\" width=\"{}\" height=\"{}\">",
+        PLOT_W + 80.0,
+        PLOT_H + 40.0,
+        PLOT_W + 80.0,
+        PLOT_H + 40.0
+    );
+    let _ = write!(
+        svg,
+        "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#ccc\"/>",
+        PLOT_W, PLOT_H
+    );
+    let _ = write!(
+        svg,
+        "<text x=\"-5\" y=\"4\" text-anchor=\"end\" font-size=\"12\">{}</text>\
+         <text x=\"-5\" y=\"{}\" text-anchor=\"end\" font-size=\"12\">0</text>",
+        top.round(),
+        PLOT_H + 4.0
+    );
+    for (v, anchor) in [(lo, "start"), (hi, "end")] {
+        let _ = write!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"{}\" font-size=\"12\">{} {}</text>",
+            x(v),
+            PLOT_H + 16.0,
+            anchor,
+            v,
+            escape(unit)
+        );
+    }
+    for (i, &count) in h.counts.iter().enumerate() {
+        let left = h.start + h.width * i as f64;
+        let _ = write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"{}\"/>",
+            x(left),
+            y(count as f64),
+            x(left + h.width) - x(left),
+            PLOT_H - y(count as f64),
+            colors.bars,
+            colors.bar_edges
+        );
+    }
+    if st.std_dev > 0.0 {
+        let _ = write!(svg, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"", colors.fit);
+        for i in 0..=200 {
+            let v = lo + (hi - lo) * i as f64 / 200.0;
+            let _ = write!(svg, "{:.1},{:.1} ", x(v), y(normal(v)));
+        }
+        svg.push_str("\"/>");
+    }
+    for (v, dash) in [(st.mean, ""), (st.mean - st.std_dev, " stroke-dasharray=\"4\""), (st.mean + st.std_dev, " stroke-dasharray=\"4\"")] {
+        if (lo..=hi).contains(&v) {
+            let _ = write!(
+                svg,
+                "<line x1=\"{0:.1}\" y1=\"0\" x2=\"{0:.1}\" y2=\"{1}\" stroke=\"#333\"{2}/>",
+                x(v),
+                PLOT_H,
+                dash
+            );
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// How the readings of a series are spread, and how that compares to a normal distribution
+fn histogram_section(s: &Series, options: &Options) -> String {
+    let values: Vec<f64> = s.points.iter().map(|p| p.1).collect();
+    let (Some(h), Some(st), Some(shape)) = (analysis::histogram(&values, options.bins), Stats::of(&values), Shape::of(&values))
+    else {
+        return String::new();
+    };
+    let mut html = String::from("\n<h3>Histogram</h3>\n<p>");
+    let _ = writeln!(
+        html,
+        "{} readings in {} bins of {:.6} {unit}{}. The smooth curve is a normal distribution with the same \
+         mean ({:.6} {unit}, solid line) and standard deviation ({:.6} {unit}, dashed lines at ±1σ). {}</p>",
+        st.count,
+        h.counts.len(),
+        h.width,
+        if options.bins.is_some() { "" } else { ", change with <code>--bins</code>" },
+        st.mean,
+        st.std_dev,
+        shape.hint(),
+        unit = escape(&s.unit)
+    );
+    html.push_str(&svg_histogram(&h, &st, &s.unit, &options.palette.colors()));
+    html
+}
+
+/// Points joined up on logarithmic axes, both of which have to be positive
+fn svg_loglog(points: &[(f64, f64)], x_unit: &str, y_unit: &str, color: &str) -> String {
+    let decades = |v: &mut dyn Iterator<Item = f64>| {
+        let (lo, hi) = v.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let (lo, hi) = (lo.log10().floor(), hi.log10().ceil());
+        (lo, if hi > lo { hi } else { lo + 1.0 })
+    };
+    let (x_lo, x_hi) = decades(&mut points.iter().map(|p| p.0));
+    let (y_lo, y_hi) = decades(&mut points.iter().map(|p| p.1));
+    let x = |v: f64| (v.log10() - x_lo) / (x_hi - x_lo) * PLOT_W;
+    let y = |v: f64| PLOT_H - (v.log10() - y_lo) / (y_hi - y_lo) * PLOT_H;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg viewBox=\"-70 -10 {} {}\" width=\"{}\" height=\"{}\">",
+        PLOT_W + 80.0,
+        PLOT_H + 40.0,
+        PLOT_W + 80.0,
+        PLOT_H + 40.0
+    );
+    let _ = write!(
+        svg,
+        "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#ccc\"/>",
+        PLOT_W, PLOT_H
+    );
+    for decade in x_lo as i32 + 1..x_hi as i32 {
+        let _ = write!(svg, "<line x1=\"{0:.1}\" y1=\"0\" x2=\"{0:.1}\" y2=\"{1}\" stroke=\"#eee\"/>", x(10f64.powi(decade)), PLOT_H);
+    }
+    for decade in y_lo as i32 + 1..y_hi as i32 {
+        let _ = write!(svg, "<line x1=\"0\" y1=\"{0:.1}\" x2=\"{1}\" y2=\"{0:.1}\" stroke=\"#eee\"/>", y(10f64.powi(decade)), PLOT_W);
+    }
+    for (v, anchor) in [(y_hi, 0.0), (y_lo, PLOT_H)] {
+        let _ = write!(
+            svg,
+            "<text x=\"-5\" y=\"{}\" text-anchor=\"end\" font-size=\"12\">1e{} {}</text>",
+            anchor + 4.0,
+            v,
+            escape(y_unit)
+        );
+    }
+    for (v, anchor) in [(x_lo, "start"), (x_hi, "end")] {
+        let _ = write!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"{}\" font-size=\"12\">1e{} {}</text>",
+            x(10f64.powf(v)),
+            PLOT_H + 16.0,
+            anchor,
+            v,
+            escape(x_unit)
+        );
+    }
+    let _ = write!(svg, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"", color);
+    for &(px, py) in points {
+        let _ = write!(svg, "{:.1},{:.1} ", x(px), y(py));
+    }
+    svg.push_str("\"/>");
+    for &(px, py) in points {
+        let _ = write!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{}\"/>", x(px), y(py), color);
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// A link that saves `contents` as `name` without a server, the report stays a single file
+fn download_link(name: &str, contents: &str, label: &str) -> String {
+    let mut href = String::from("data:text/csv;charset=utf-8,");
+    for b in contents.bytes() {
+        if b.is_ascii_alphanumeric() || b",.-_".contains(&b) {
+            href.push(b as char);
+        } else {
+            let _ = write!(href, "%{:02X}", b);
+        }
+    }
+    format!("<a download=\"{}\" href=\"{}\">{}</a>", escape(name), href, label)
+}
+
+/// Allan deviation of the longest stretch without a gap, for judging references and how long
+/// to average
+fn allan_section(log: &Log, s: &Series, options: &Options) -> String {
+    let Some(run) = runs(log, &s.points).max_by_key(|run| run.len()) else {
+        return String::new();
+    };
+    let values: Vec<f64> = run.iter().map(|p| p.1).collect();
+    let interval = interval(log, run).filter(|&dt| dt > 0.0);
+    let points: Vec<_> = analysis::allan_deviation(&values, interval).into_iter().filter(|p| p.deviation > 0.0).collect();
+    if points.len() < 2 {
+        return String::new();
+    }
+    let tau_unit = if interval.is_some() { "s" } else { "readings" };
+    let mut csv = format!("tau_{tau_unit},adev_{},terms\n", s.unit);
+    for p in &points {
+        let _ = writeln!(csv, "{},{},{}", p.tau, p.deviation, p.terms);
+    }
+    let lowest = points.iter().min_by(|a, b| a.deviation.total_cmp(&b.deviation)).unwrap();
+    let mut html = String::from("\n<h3>Allan deviation</h3>\n<p>");
+    let _ = write!(
+        html,
+        "Overlapping Allan deviation of the {} readings in the longest stretch without a gap. Lowest \
+         at τ = {} {tau_unit}, {:.3e} {unit}: averaging for longer than that makes a reading worse, \
+         drift takes over from noise.",
+        values.len(),
+        lowest.tau,
+        lowest.deviation,
+        unit = escape(&s.unit)
+    );
+    if interval.is_none() {
+        html.push_str(" Record with <code>--timestamp</code> for τ in seconds.");
+    }
+    let _ = writeln!(html, " {}</p>", download_link(&format!("allan_{}.csv", s.unit), &csv, "Download as CSV"));
+    let xy: Vec<(f64, f64)> = points.iter().map(|p| (p.tau, p.deviation)).collect();
+    html.push_str(&svg_loglog(&xy, tau_unit, &s.unit, options.palette.colors().allan));
+    html
+}
+
+/// A line or exponential through the readings, `--fit-from`/`--fit-to` narrowing it down to
+/// part of the capture
+fn trend_section(log: &Log, s: &Series, options: &Options) -> String {
+    let elapsed = log.column("elapsed");
+    let time = |i: usize| match elapsed {
+        Some(_) => log.get(&log.rows[i], elapsed).parse::<f64>().ok(),
+        None => Some(i as f64),
+    };
+    let in_range = |t: f64| options.fit_from.is_none_or(|from| t >= from) && options.fit_to.is_none_or(|to| t <= to);
+    let selected: Vec<(usize, f64, f64)> =
+        s.points.iter().filter_map(|&(i, v)| time(i).filter(|&t| in_range(t)).map(|t| (i, t, v))).collect();
+    let times: Vec<f64> = selected.iter().map(|p| p.1).collect();
+    let values: Vec<f64> = selected.iter().map(|p| p.2).collect();
+    let Some(trend) = analysis::trend(&times, &values, options.fit) else {
+        return String::new();
+    };
+    let (per, scale) = if elapsed.is_some() { ("h", 3600.0) } else { ("reading", 1.0) };
+    let mut html = String::from("\n<h3>Trend</h3>\n<p>");
+    let span = match (times.first(), times.last()) {
+        (Some(first), Some(last)) if elapsed.is_some() => format!("from {} s to {} s", first, last),
+        (Some(first), Some(last)) => format!("from record {} to {}", first, last),
+        _ => String::new(),
+    };
+    let _ = write!(html, "{:?} fit to the {} readings {}: ", trend.fit, trend.samples, span);
+    let unit = escape(&s.unit);
+    match trend.fit {
+        Fit::Linear => {
+            let _ = write!(
+                html,
+                "{:.4e} ± {:.2e} {unit}/{per} (95% confidence)",
+                trend.slope * scale,
+                trend.slope_ci95 * scale
+            );
+        }
+        Fit::Exponential => {
+            let _ = write!(
+                html,
+                "{:+.4} ± {:.4} %/{per} (95% confidence), a time constant of {:.4e} {per}",
+                trend.slope * scale * 100.0,
+                trend.slope_ci95 * scale * 100.0,
+                1.0 / (trend.slope * scale).abs()
+            );
+        }
+    }
+    let _ = write!(
+        html,
+        ", R² {:.4}. The interval assumes the noise from one reading to the next is \
+         independent, slow wander makes the real one wider.",
+        trend.r_squared
+    );
+    if elapsed.is_none() {
+        html.push_str(" Record with <code>--timestamp</code> for a drift per hour.");
+    }
+    html.push_str("</p>\n");
+    let data: Vec<(usize, f64)> = selected.iter().map(|&(i, _, v)| (i, v)).collect();
+    let fitted: Vec<(usize, f64)> = selected.iter().map(|&(i, t, _)| (i, trend.at(t))).collect();
+    html.push_str(&svg_plot(&[(&data, &s.color), (&fitted, options.palette.colors().fit)], &gaps(log), &s.unit, log.rows.len()));
+    html
+}
+
+/// How the report is put together, from the command line
+pub struct Options {
+    pub window: Window,
+    /// Bins for the histograms, `None` to pick them from the data
+    pub bins: Option<usize>,
+    pub fit: Fit,
+    /// Part of the capture to fit the trend to, in seconds (the `elapsed` column) or records if
+    /// the capture has no timestamps
+    pub fit_from: Option<f64>,
+    pub fit_to: Option<f64>,
+    /// New names and colours for series, each keyed by unit or mode
+    pub names: Vec<(String, String)>,
+    pub colors: Vec<(String, String)>,
+    /// Series left out of the report, or if `solo` isn't empty the only ones kept
+    pub hide: Vec<String>,
+    pub solo: Vec<String>,
+    pub palette: Palette,
+}
+
+impl Options {
+    /// Applies `--name`, `--color`, `--hide` and `--solo`
+    fn style(&self, series: Vec<Series>) -> Vec<Series> {
+        let is = |s: &Series, key: &str| key == s.unit || key == s.mode;
+        series
+            .into_iter()
+            .filter(|s| !self.hide.iter().any(|k| is(s, k)) && (self.solo.is_empty() || self.solo.iter().any(|k| is(s, k))))
+            .map(|mut s| {
+                if let Some((_, name)) = self.names.iter().find(|(k, _)| is(&s, k)) {
+                    s.name = name.clone();
+                }
+                s.color = match self.colors.iter().find(|(k, _)| is(&s, k)) {
+                    Some((_, color)) => color.clone(),
+                    None => self.palette.colors().trace.to_string(),
+                };
+                s
+            })
+            .collect()
+    }
+}
+
+/// `a - b` wherever both have a reading at the same record
+fn difference(a: &[(usize, f64)], b: &[(usize, f64)]) -> Vec<(usize, f64)> {
+    let mut diff = Vec::new();
+    let mut b = b.iter().peekable();
+    for &(i, va) in a {
+        while b.next_if(|(j, _)| *j < i).is_some() {}
+        if let Some(&&(j, vb)) = b.peek()
+            && j == i
+        {
+            diff.push((i, va - vb));
+        }
+    }
+    diff
+}
+
+/// Renders the report for `log`, with `reference` (another capture and its name) overlaid on
+/// the plots for before/after comparisons
+pub fn render(log: &Log, title: &str, reference: Option<(&Log, &str)>, options: &Options) -> String {
+    let uncertainty = log.column("uncertainty");
+    let (series, non_numeric) = split_series(log);
+    let series = options.style(series);
+    let gaps = gaps(log);
+    let reference = reference.map(|(r, name)| (split_series(r).0, r.rows.len(), name));
+    let colors = options.palette.colors();
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title>\
+         <style>body{{font-family:sans-serif;max-width:900px;margin:auto}}\
+         table{{border-collapse:collapse}}td,th{{border:1px solid #ccc;padding:4px 8px;text-align:right}}{1}</style>\
+         </head><body>\n<h1>{0}</h1>\n",
+        escape(title),
+        options.palette.style()
+    );
+
+    html.push_str("<h2>Session</h2>\n<table>\n");
+    let _ = writeln!(html, "<tr><th>Source</th><td>{}</td></tr>", escape(title));
+    let _ = writeln!(html, "<tr><th>Records</th><td>{}</td></tr>", log.rows.len());
+    let _ = writeln!(html, "<tr><th>Non-numeric readings (OL, ----)</th><td>{}</td></tr>", non_numeric);
+    let _ = writeln!(html, "<tr><th>Gaps (timeouts, reconnects)</th><td>{}</td></tr>", gaps.len());
+    let _ = writeln!(html, "<tr><th>Columns</th><td>{}</td></tr>", escape(&log.header.join(", ")));
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Statistics</h2>\n<table>\n<tr><th>Series</th><th>Unit</th><th>Count</th>\
+                   <th>Min</th><th>Max</th><th>Mean</th><th>Std dev</th><th>Max uncertainty</th></tr>\n");
+    for s in &series {
+        let ys: Vec<f64> = s.points.iter().map(|p| p.1).collect();
+        let Some(st) = Stats::of(&ys) else { continue };
+        let max_u = s.uncertainty.iter().copied().fold(None, |m: Option<f64>, u| Some(m.map_or(u, |m| m.max(u))));
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.6}</td><td>{:.6}</td><td>{}</td></tr>",
+            escape(&s.name),
+            escape(&s.unit),
+            st.count,
+            st.min,
+            st.max,
+            st.mean,
+            st.std_dev,
+            max_u.map_or("".to_string(), |u| format!("±{:.6}", u))
+        );
+    }
+    html.push_str("</table>\n");
+
+    if let Some((_, _, name)) = &reference {
+        let _ = writeln!(
+            html,
+            "<p>Compared against <b>{}</b>, shown as the second trace and lined up by record number. The \
+             difference plots are this capture minus the reference.</p>",
+            escape(name)
+        );
+    }
+    for s in &series {
+        let _ = writeln!(html, "<h2>{} ({})</h2>", escape(&s.name), escape(&s.unit));
+        let matching = reference
+            .as_ref()
+            .and_then(|(r, len, _)| r.iter().find(|r| r.unit == s.unit).map(|r| (r, *len)));
+        match matching {
+            Some((r, len)) => {
+                let total = log.rows.len().max(len);
+                html.push_str(&svg_plot(&[(&s.points, &s.color), (&r.points, colors.reference)], &gaps, &s.unit, total));
+                let diff = difference(&s.points, &r.points);
+                let ys: Vec<f64> = diff.iter().map(|p| p.1).collect();
+                if let Some(st) = Stats::of(&ys) {
+                    let _ = writeln!(
+                        html,
+                        "\n<h3>Difference</h3>\n<p>Mean {:.6} {unit}, std dev {:.6} {unit}, from {:.6} to {:.6} {unit} over {} records</p>",
+                        st.mean,
+                        st.std_dev,
+                        st.min,
+                        st.max,
+                        st.count,
+                        unit = escape(&s.unit)
+                    );
+                    html.push_str(&svg_plot(&[(&diff, colors.difference)], &gaps, &s.unit, total));
+                }
+            }
+            None => html.push_str(&svg_plot(&[(&s.points, &s.color)], &gaps, &s.unit, log.rows.len())),
+        }
+        html.push_str(&trend_section(log, s, options));
+        html.push_str(&histogram_section(s, options));
+        html.push_str(&spectrum_section(log, s, options));
+        html.push_str(&allan_section(log, s, options));
+        html.push('\n');
+    }
+
+    let time = log.column("time");
+    html.push_str("<h2>Events</h2>\n<table>\n<tr><th>Record</th><th>Time</th><th>Event</th></tr>\n");
+    for (i, e) in events(log) {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td style=\"text-align:left\">{}</td></tr>",
+            i,
+            escape(log.get(&log.rows[i], time)),
+            escape(&e)
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Uncertainty</h2>\n<p>");
+    if uncertainty.is_some() {
+        html.push_str(
+            "Uncertainties are the UT61E+ datasheet accuracy, ±(% of reading + counts) for the \
+             range in use, not a calibration of this particular meter. The mean and standard \
+             deviation describe the readings only.",
+        );
+    } else {
+        html.push_str(
+            "This capture doesn't include uncertainties, record with <code>--uncertainty</code> \
+             to get the datasheet accuracy bound for every reading.",
+        );
+    }
+    html.push_str("</p>\n</body></html>\n");
+    html
+}
+
+/// Reads `input` and writes the report to `output`, or next to the input with an .html extension
+pub fn run(input: &Path, output: Option<&Path>, reference: Option<&Path>, options: &Options) -> io::Result<()> {
+    let log = Log::load(input)?;
+    let reference = match reference {
+        Some(path) => Some((Log::load(path)?, path.display().to_string())),
+        None => None,
+    };
+    let html = render(
+        &log,
+        &input.display().to_string(),
+        reference.as_ref().map(|(r, name)| (r, name.as_str())),
+        options,
+    );
+    let output = output.map_or_else(|| input.with_extension("html"), Path::to_path_buf);
+    fs::write(&output, html)?;
+    eprintln!("Wrote {}", output.display());
+    Ok(())
+}
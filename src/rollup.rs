@@ -0,0 +1,141 @@
+//! `--rollup`, a line per day (UTC) and series with the min, max and mean, for logs that run for
+//! weeks on a solar charger or a battery bank where nobody wants to wade through every reading.
+//! Series are the readings of each mode and unit, and derived values like `--power-with`'s power
+//! each by name. Series in A also get the charge that day in Ah and series in W the energy in Wh,
+//! adding up steps of at most [`MAX_STEP`] so gaps don't count.
+//!
+//! A day is written when the next one starts, and whatever there is of the last one at the end
+//! with `complete` false. The file is appended to, so a logger restarted the same day leaves two
+//! partial lines for it rather than losing one. CSV, or JSON lines if the name ends in `.json` or
+//! `.jsonl`.
+
+use crate::clock::{Stamp, Utc};
+use crate::output::{Derived, write_json_str};
+use crate::sink::Sink;
+use crate::stats::significant;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// Longer than this between two readings of a series is a gap, not a step to add up
+const MAX_STEP: Duration = Duration::from_secs(5);
+
+struct Series {
+    name: &'static str,
+    unit: &'static str,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u64,
+    /// The unit times hours, Ah for A and Wh for W
+    hours: f64,
+    last: Duration,
+}
+
+pub struct Rollup {
+    out: BufWriter<File>,
+    json: bool,
+    date: Option<String>,
+    series: Vec<Series>,
+}
+
+impl Rollup {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let json = path.extension().is_some_and(|ext| ext == "json" || ext == "jsonl");
+        let mut out = BufWriter::new(file);
+        if empty && !json {
+            writeln!(out, "date,series,unit,min,max,mean,count,ah,wh,complete")?;
+            out.flush()?;
+        }
+        Ok(Rollup { out, json, date: None, series: Vec::new() })
+    }
+
+    fn add(&mut self, stamp: &Stamp, name: &'static str, unit: &'static str, value: f64) -> io::Result<()> {
+        let date = Utc(stamp.wall).date();
+        if self.date.as_ref().is_some_and(|day| *day != date) {
+            self.write(true)?;
+        }
+        self.date = Some(date);
+        let series = match self.series.iter().position(|s| s.name == name && s.unit == unit) {
+            Some(i) => &mut self.series[i],
+            None => {
+                let series = Series { name, unit, min: value, max: value, sum: 0.0, count: 0, hours: 0.0, last: stamp.elapsed };
+                self.series.push(series);
+                self.series.last_mut().expect("just pushed")
+            }
+        };
+        let step = stamp.elapsed.saturating_sub(series.last);
+        if step <= MAX_STEP {
+            series.hours += value * step.as_secs_f64() / 3600.0;
+        }
+        series.last = stamp.elapsed;
+        series.min = series.min.min(value);
+        series.max = series.max.max(value);
+        series.sum += value;
+        series.count += 1;
+        Ok(())
+    }
+
+    /// The day so far, and starts the next
+    fn write(&mut self, complete: bool) -> io::Result<()> {
+        let Some(date) = self.date.take() else {
+            return Ok(());
+        };
+        for s in std::mem::take(&mut self.series) {
+            let (ah, wh) = match s.unit {
+                "A" => (Some(s.hours), None),
+                "W" => (None, Some(s.hours)),
+                _ => (None, None),
+            };
+            let mean = s.sum / s.count as f64;
+            if self.json {
+                write!(self.out, "{{\"date\":\"{}\",\"series\":", date)?;
+                write_json_str(&mut self.out, s.name)?;
+                write!(self.out, ",\"unit\":")?;
+                write_json_str(&mut self.out, s.unit)?;
+                write!(self.out, ",\"min\":{},\"max\":{},\"mean\":{},\"count\":{}", significant(s.min), significant(s.max), significant(mean), s.count)?;
+                let number = |n: Option<f64>| n.map_or("null".to_owned(), significant);
+                writeln!(self.out, ",\"ah\":{},\"wh\":{},\"complete\":{}}}", number(ah), number(wh), complete)?;
+            } else {
+                let number = |n: Option<f64>| n.map_or(String::new(), significant);
+                writeln!(
+                    self.out,
+                    "{},{},{},{},{},{},{},{},{},{}",
+                    date,
+                    s.name,
+                    s.unit,
+                    significant(s.min),
+                    significant(s.max),
+                    significant(mean),
+                    s.count,
+                    number(ah),
+                    number(wh),
+                    complete
+                )?;
+            }
+        }
+        self.out.flush()
+    }
+}
+
+impl Sink for Rollup {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        let scaled = m.reading_in(Units::Si);
+        match scaled.reading.value() {
+            Some(value) => self.add(stamp, m.mode_name(), scaled.unit, value),
+            None => Ok(()),
+        }
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        self.add(stamp, derived.name, derived.unit, derived.value)
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.write(false)
+    }
+}
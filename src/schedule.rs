@@ -0,0 +1,304 @@
+//! When to sample. Cron style schedules for `--schedule`, so long studies (battery
+//! self-discharge over weeks) can sample in short bursts instead of six times a second the whole
+//! time, and the bursts themselves, which can also be started on demand.
+//!
+//! The usual five fields, minute hour day-of-month month day-of-week, each `*`, a number, a
+//! range `a-b`, a step `*/n` or `a-b/n`, or a comma separated list of those. Times are local.
+//! Like cron, when both day fields are restricted either one matching is enough.
+
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone)]
+struct Field {
+    /// Bit n set if n matches
+    bits: u64,
+    /// `*`, which matters for the day fields
+    any: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Field, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("bad step in {:?}", part))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("step can't be 0 in {:?}", part));
+        }
+        let (low, high) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((low, high)) => (low.parse().map_err(|_| format!("bad number in {:?}", part))?, high.parse().map_err(|_| format!("bad number in {:?}", part))?),
+                None => {
+                    let n = range.parse().map_err(|_| format!("bad number in {:?}", part))?;
+                    // `5/15` means from 5 to the end in steps of 15
+                    (n, if step > 1 { max } else { n })
+                }
+            },
+        };
+        if low < min || high > max || low > high {
+            return Err(format!("{:?} is outside {}-{}", part, min, max));
+        }
+        for n in (low..=high).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(Field { bits, any: field == "*" })
+}
+
+#[derive(Clone)]
+pub struct Schedule {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+/// Broken down local time: minute, hour, day of month, month (1-12), day of week (0 = Sunday)
+#[cfg(unix)]
+fn local(secs: i64) -> (u32, u32, u32, u32, u32) {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let t = secs as libc::time_t;
+    unsafe { libc::localtime_r(&t, &mut tm) };
+    (tm.tm_min as u32, tm.tm_hour as u32, tm.tm_mday as u32, tm.tm_mon as u32 + 1, tm.tm_wday as u32)
+}
+
+/// No time zone database to hand, so UTC
+#[cfg(not(unix))]
+fn local(secs: i64) -> (u32, u32, u32, u32, u32) {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    let (_, month, day) = crate::clock::civil(days);
+    // 1970-01-01 was a Thursday
+    let weekday = (days + 4).rem_euclid(7);
+    ((rem % 3600 / 60) as u32, (rem / 3600) as u32, day as u32, month as u32, weekday as u32)
+}
+
+impl Schedule {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields (minute hour day month weekday), got {}", fields.len()));
+        };
+        let mut weekday = parse_field(weekday, 0, 7)?;
+        // 7 is Sunday too
+        if weekday.bits & (1 << 7) != 0 {
+            weekday.bits |= 1;
+        }
+        Ok(Schedule {
+            minute: parse_field(minute, 0, 59)?,
+            hour: parse_field(hour, 0, 23)?,
+            day: parse_field(day, 1, 31)?,
+            month: parse_field(month, 1, 12)?,
+            weekday,
+        })
+    }
+
+    fn matches(&self, secs: i64) -> bool {
+        let (minute, hour, day, month, weekday) = local(secs);
+        let day_matches = match (self.day.any, self.weekday.any) {
+            (false, false) => self.day.bits & (1 << day) != 0 || self.weekday.bits & (1 << weekday) != 0,
+            _ => self.day.bits & (1 << day) != 0 && self.weekday.bits & (1 << weekday) != 0,
+        };
+        self.minute.bits & (1 << minute) != 0 && self.hour.bits & (1 << hour) != 0 && self.month.bits & (1 << month) != 0 && day_matches
+    }
+
+    /// The first whole minute after `time` that matches, or `None` if nothing does within
+    /// 5 years (e.g. the 31st of February)
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+        let mut minute = secs - secs.rem_euclid(60) + 60;
+        for _ in 0..5 * 366 * 24 * 60 {
+            if self.matches(minute) {
+                return Some(UNIX_EPOCH + Duration::from_secs(minute as u64));
+            }
+            minute += 60;
+        }
+        None
+    }
+}
+
+/// What `--burst` grabs: everything for a while, or a number of samples at a given rate
+#[derive(Clone, Copy)]
+pub enum Burst {
+    /// `30s`, at the normal polling interval
+    For(Duration),
+    /// `100@10Hz`, `100@10` or `100@max`. A zero interval polls as fast as the meter answers.
+    Samples { count: u32, interval: Duration },
+}
+
+impl Burst {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let Some((count, rate)) = s.split_once('@') else {
+            return crate::clock::parse_duration(s)
+                .map(Burst::For)
+                .ok_or_else(|| format!("expected a duration like 30s or samples@rate like 100@10Hz, got {:?}", s));
+        };
+        let count = count.parse().map_err(|_| format!("bad sample count in {:?}", s))?;
+        let interval = match rate {
+            "max" => Duration::ZERO,
+            _ => {
+                let hz: f64 = rate.trim_end_matches("Hz").parse().map_err(|_| format!("bad rate in {:?}", s))?;
+                if !(hz > 0.0 && hz.is_finite()) {
+                    return Err(format!("rate has to be above 0 in {:?}", s));
+                }
+                // A rate so low the interval doesn't fit in a Duration
+                Duration::try_from_secs_f64(1.0 / hz).map_err(|_| format!("bad rate in {:?}", s))?
+            }
+        };
+        Ok(Burst::Samples { count, interval })
+    }
+}
+
+/// A burst that's underway
+pub struct Active {
+    burst: Burst,
+    started: Instant,
+    pub taken: u32,
+}
+
+impl Active {
+    pub fn start(burst: Burst) -> Self {
+        Active { burst, started: Instant::now(), taken: 0 }
+    }
+
+    pub fn done(&self) -> bool {
+        match self.burst {
+            Burst::For(duration) => self.started.elapsed() >= duration,
+            Burst::Samples { count, .. } => self.taken >= count,
+        }
+    }
+
+    /// Time between polls while the burst lasts
+    pub fn interval(&self, normal: Duration) -> Duration {
+        match self.burst {
+            Burst::For(_) => normal,
+            Burst::Samples { interval, .. } => interval,
+        }
+    }
+}
+
+/// `--burst-trigger`, start a burst when the value goes above or below a level
+#[derive(Clone, Copy)]
+pub struct Trigger {
+    above: bool,
+    level: f64,
+}
+
+impl Trigger {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (above, level) = match (s.strip_prefix('>'), s.strip_prefix('<')) {
+            (Some(level), _) => (true, level),
+            (_, Some(level)) => (false, level),
+            _ => return Err(format!("expected >LEVEL or <LEVEL, got {:?}", s)),
+        };
+        let level = level.trim().parse().map_err(|_| format!("bad level in {:?}", s))?;
+        Ok(Trigger { above, level })
+    }
+
+    pub fn matches(&self, value: f64) -> bool {
+        if self.above { value > self.level } else { value < self.level }
+    }
+
+    /// The level moved back by `margin` towards the side that doesn't match, `>5` to `>4.9`
+    pub fn backed_off(self, margin: f64) -> Trigger {
+        Trigger { level: if self.above { self.level - margin } else { self.level + margin }, ..self }
+    }
+}
+
+/// As it's written on the command line, `>5`
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.above { '>' } else { '<' }, self.level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The numbers a field matches
+    fn numbers(field: &str, min: u32, max: u32) -> Vec<u32> {
+        let field = parse_field(field, min, max).unwrap();
+        (min..=max).filter(|n| field.bits & (1 << n) != 0).collect()
+    }
+
+    /// Some time in 2024, a Wednesday
+    fn start() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_704_283_200)
+    }
+
+    /// The next `n` times it matches, broken down in local time like `matches` sees them
+    fn next(schedule: &str, n: usize) -> Vec<(u32, u32, u32, u32, u32)> {
+        let schedule = Schedule::parse(schedule).unwrap();
+        let times = std::iter::successors(schedule.next_after(start()), |&time| schedule.next_after(time));
+        times.take(n).map(|time| local(time.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64)).collect()
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(numbers("*/15", 0, 59), [0, 15, 30, 45]);
+        assert_eq!(numbers("10-20/5", 0, 59), [10, 15, 20]);
+        assert_eq!(numbers("5/15", 0, 59), [5, 20, 35, 50]);
+        assert_eq!(numbers("1,3,5-6", 0, 59), [1, 3, 5, 6]);
+        assert_eq!(numbers("7", 0, 59), [7]);
+        assert!(parse_field("*/0", 0, 59).is_err());
+        assert!(parse_field("20-10", 0, 59).is_err());
+        assert!(parse_field("0", 1, 31).is_err());
+        assert!(Schedule::parse("0 0 * *").is_err());
+    }
+
+    #[test]
+    fn weekday_7_is_sunday() {
+        let sunday = Schedule::parse("0 12 * * 7").unwrap();
+        assert_eq!(sunday.weekday.bits, Schedule::parse("0 12 * * 0").unwrap().weekday.bits | 1 << 7);
+        assert!(next("0 12 * * 7", 3).iter().all(|&(minute, hour, .., weekday)| (minute, hour, weekday) == (0, 12, 0)));
+    }
+
+    #[test]
+    fn next_is_the_next_whole_minute_that_matches() {
+        let schedule = Schedule::parse("*/15 * * * *").unwrap();
+        let after = start() + Duration::from_secs(30);
+        let next = schedule.next_after(after).unwrap();
+        assert!(next > after && next.duration_since(after).unwrap() <= Duration::from_secs(15 * 60));
+        assert_eq!(next.duration_since(UNIX_EPOCH).unwrap().as_secs() % 60, 0);
+        // Strictly after, so one that matches moves on
+        assert_eq!(schedule.next_after(next).unwrap().duration_since(next).unwrap(), Duration::from_secs(15 * 60));
+        assert!(self::next("*/15 * * * *", 8).iter().all(|&(minute, ..)| minute % 15 == 0));
+    }
+
+    #[test]
+    fn either_day_field_is_enough_when_both_are_given() {
+        // The 13th, and every Friday
+        let times = next("0 0 13 * 5", 12);
+        assert!(times.iter().all(|&(_, _, day, _, weekday)| day == 13 || weekday == 5));
+        assert!(times.iter().any(|&(_, _, day, _, weekday)| day == 13 && weekday != 5));
+        assert!(times.iter().any(|&(_, _, day, _, weekday)| day != 13 && weekday == 5));
+        // With one of them `*` only the other counts
+        assert!(next("0 0 13 * *", 3).iter().all(|&(_, _, day, ..)| day == 13));
+        assert!(next("0 0 * * 5", 3).iter().all(|&(.., weekday)| weekday == 5));
+    }
+
+    #[test]
+    fn bursts() {
+        let interval = |s| match Burst::parse(s) {
+            Ok(Burst::Samples { count, interval }) => Some((count, interval)),
+            _ => None,
+        };
+        assert_eq!(interval("100@10Hz"), Some((100, Duration::from_millis(100))));
+        assert_eq!(interval("100@4"), Some((100, Duration::from_millis(250))));
+        assert_eq!(interval("5@max"), Some((5, Duration::ZERO)));
+        assert!(matches!(Burst::parse("30s"), Ok(Burst::For(d)) if d == Duration::from_secs(30)));
+        assert_eq!(Burst::parse("100@1e-30Hz").err().as_deref(), Some("bad rate in \"100@1e-30Hz\""));
+        assert!(Burst::parse("100@0Hz").is_err());
+        assert!(Burst::parse("100@infHz").is_err());
+    }
+
+    #[test]
+    fn never_is_none() {
+        assert!(Schedule::parse("0 0 31 2 *").unwrap().next_after(start()).is_none());
+    }
+}
@@ -0,0 +1,263 @@
+//! `--scpi`, the latest reading over a raw SCPI socket (port 5025 by convention) like a bench
+//! multimeter on the network, for instrument software that knows nothing about USB HID. One
+//! command per line, or several separated by `;`, each query's answer on a line of its own.
+//!
+//! | command                         | answer |
+//! |---------------------------------|--------|
+//! | `*IDN?`                         | `UNI-T,UT61E+,<serial>,<logger version>` |
+//! | `READ?`, `FETCh?`, `MEASure?`   | the latest value in the base unit (V, not mV), `9.9E37` for OL |
+//! | `CONFigure?`                    | the mode and unit, `"V_DC,V"` |
+//! | `SYSTem:ERRor[:NEXT]?`          | the oldest error, `0,"No error"` once there are none |
+//! | `SYSTem:ERRor:COUNt?`           | how many errors are waiting |
+//! | `SYSTem:VERSion?`               | `1999.0`, the SCPI version |
+//! | `*OPC?`, `*TST?`                | `1` and `0`, everything is done at once and there's no self test |
+//! | `*ESR?`, `*STB?`                | the event status and status byte registers |
+//! | `*ESE`, `*SRE` and their queries | the enable masks for them |
+//! | `*CLS`, `*RST`, `*OPC`, `*WAI`  | clear the errors and event status, nothing, set OPC, nothing |
+//!
+//! `MEASure:VOLTage:DC?` and the like answer whatever the meter is set to, the mode can't be
+//! changed from here. While the meter isn't answering the value is `9.91E37`, SCPI's NaN.
+//!
+//! Every connection has its own error queue and registers, as if it were the only one. An
+//! unknown command is error -113 and sets CME in the event status register, a bad parameter
+//! -224 and EXE. The status byte has EAV (4) while there are errors, ESB (32) for enabled events
+//! and MSS (64) for enabled bits of the rest. That's what pyvisa scripts written for other
+//! meters ask, with `TCPIP::<host>::5025::SOCKET` and `read_termination = "\n"`.
+//!
+//! Together with `--web` and `--mdns` this is enough of LXI for instrument managers to list the
+//! logger: the dashboard serves the identification at `/lxi` and `/lxi/identification`, and mDNS
+//! announces `_lxi._tcp` and `_scpi-raw._tcp`.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use ut61e_plus_logger::protocol::{Measurement, Reading, Units};
+
+/// SCPI's overload
+const OVERLOAD: f64 = 9.9e37;
+/// SCPI's not a number, for dashes or no reading
+const NOT_A_NUMBER: f64 = 9.91e37;
+
+/// Older errors are kept, the newest replaced by a queue overflow
+const MAX_ERRORS: usize = 10;
+
+const UNDEFINED_HEADER: (i32, &str) = (-113, "Undefined header");
+const ILLEGAL_PARAMETER: (i32, &str) = (-224, "Illegal parameter value");
+const QUEUE_OVERFLOW: (i32, &str) = (-350, "Queue overflow");
+
+/// Event status register bits
+const ESR_OPC: u8 = 1 << 0;
+const ESR_EXE: u8 = 1 << 4;
+const ESR_CME: u8 = 1 << 5;
+
+/// Status byte bits
+const STB_EAV: u8 = 1 << 2;
+const STB_ESB: u8 = 1 << 5;
+const STB_MSS: u8 = 1 << 6;
+
+/// Who the logger says it is, on `*IDN?` and the LXI pages
+#[derive(Clone)]
+pub struct Identity {
+    pub serial: Option<String>,
+    /// Where the SCPI server ended up listening
+    pub scpi: SocketAddr,
+}
+
+impl Identity {
+    pub const MANUFACTURER: &str = "UNI-T";
+    pub const MODEL: &str = "UT61E+";
+    pub const VERSION: &str = concat!("ut61e_plus_logger ", env!("CARGO_PKG_VERSION"));
+
+    /// The cable's serial, or 0 as SCPI has it for an unknown one
+    pub fn serial(&self) -> &str {
+        self.serial.as_deref().unwrap_or("0")
+    }
+
+    /// For pyvisa and friends, as seen from a machine that reaches this one as `host`
+    pub fn visa(&self, host: &str) -> String {
+        format!("TCPIP::{}::{}::SOCKET", host, self.scpi.port())
+    }
+}
+
+pub struct Server {
+    latest: Arc<Mutex<Option<Measurement>>>,
+    identity: Identity,
+}
+
+impl Server {
+    /// Starts listening on `addr`, e.g. 0.0.0.0:5025, with every connection on its own thread
+    pub fn serve(addr: impl ToSocketAddrs, serial: Option<String>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let identity = Identity { serial, scpi: listener.local_addr()? };
+        let latest = Arc::new(Mutex::new(None));
+        let (shared, idn) = (latest.clone(), identity.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (latest, idn) = (shared.clone(), idn.clone());
+                thread::spawn(move || {
+                    let _ = handle(stream, &latest, &idn);
+                });
+            }
+        });
+        Ok(Server { latest, identity })
+    }
+
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    pub fn update(&self, m: &Measurement) {
+        *self.latest.lock().unwrap() = Some(*m);
+    }
+
+    /// The meter stopped answering
+    pub fn gap(&self) {
+        *self.latest.lock().unwrap() = None;
+    }
+}
+
+fn handle(stream: TcpStream, latest: &Mutex<Option<Measurement>>, identity: &Identity) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    let mut session = Session::default();
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let commands = line.split(';').map(str::trim).filter(|command| !command.is_empty());
+        let answers: Vec<String> = commands.filter_map(|command| session.run(command, latest, identity)).collect();
+        if !answers.is_empty() {
+            writeln!(out, "{}", answers.join(";"))?;
+        }
+    }
+    Ok(())
+}
+
+/// The status model, one per connection
+#[derive(Default)]
+struct Session {
+    errors: VecDeque<(i32, &'static str)>,
+    /// Event status register
+    esr: u8,
+    /// Event status enable
+    ese: u8,
+    /// Service request enable
+    sre: u8,
+}
+
+impl Session {
+    fn error(&mut self, error: (i32, &'static str), bit: u8) {
+        self.esr |= bit;
+        if self.errors.len() < MAX_ERRORS {
+            self.errors.push_back(error);
+        } else if let Some(last) = self.errors.back_mut() {
+            *last = QUEUE_OVERFLOW;
+        }
+    }
+
+    fn stb(&self) -> u8 {
+        let mut stb = 0;
+        if !self.errors.is_empty() {
+            stb |= STB_EAV;
+        }
+        if self.esr & self.ese != 0 {
+            stb |= STB_ESB;
+        }
+        if stb & self.sre & !STB_MSS != 0 {
+            stb |= STB_MSS;
+        }
+        stb
+    }
+
+    /// A mask for `*ESE` or `*SRE`
+    fn mask(&mut self, parameter: Option<&str>) -> Option<u8> {
+        let mask = parameter.and_then(|p| p.parse::<f64>().ok()).filter(|n| (0.0..=255.0).contains(n));
+        if mask.is_none() {
+            self.error(ILLEGAL_PARAMETER, ESR_EXE);
+        }
+        mask.map(|n| n.round() as u8)
+    }
+
+    /// Carries out one command, with what it answers if it's a query
+    fn run(&mut self, command: &str, latest: &Mutex<Option<Measurement>>, identity: &Identity) -> Option<String> {
+        let mut words = command.split_whitespace();
+        let header = words.next()?;
+        let parameter = words.next();
+        let common = |name: &str| header.eq_ignore_ascii_case(name);
+        if common("*IDN?") {
+            Some(format!("{},{},{},{}", Identity::MANUFACTURER, Identity::MODEL, identity.serial(), Identity::VERSION))
+        } else if common("*OPC?") {
+            Some("1".to_owned())
+        } else if common("*TST?") {
+            Some("0".to_owned())
+        } else if common("*ESR?") {
+            // Reading it clears it
+            Some(std::mem::take(&mut self.esr).to_string())
+        } else if common("*STB?") {
+            Some(self.stb().to_string())
+        } else if common("*ESE?") {
+            Some(self.ese.to_string())
+        } else if common("*SRE?") {
+            Some(self.sre.to_string())
+        } else if common("*ESE") {
+            self.ese = self.mask(parameter).unwrap_or(self.ese);
+            None
+        } else if common("*SRE") {
+            self.sre = self.mask(parameter).unwrap_or(self.sre);
+            None
+        } else if common("*CLS") {
+            self.errors.clear();
+            self.esr = 0;
+            None
+        } else if common("*OPC") {
+            self.esr |= ESR_OPC;
+            None
+        } else if common("*RST") || common("*WAI") {
+            None
+        } else if is(header, "READ?") || is(header, "FETCh?") || is(header, "MEASure?") || (is_under(header, "MEASure") && header.ends_with('?')) {
+            Some(number(value(latest.lock().unwrap().as_ref())))
+        } else if is(header, "CONFigure?") {
+            Some(latest.lock().unwrap().map_or("\"NONE\"".to_owned(), |m| format!("\"{},{}\"", m.mode_name(), m.unit())))
+        } else if is(header, "SYSTem:ERRor?") || is(header, "SYSTem:ERRor:NEXT?") {
+            let (code, message) = self.errors.pop_front().unwrap_or((0, "No error"));
+            Some(format!("{},\"{}\"", code, message))
+        } else if is(header, "SYSTem:ERRor:COUNt?") {
+            Some(self.errors.len().to_string())
+        } else if is(header, "SYSTem:VERSion?") {
+            Some("1999.0".to_owned())
+        } else {
+            self.error(UNDEFINED_HEADER, ESR_CME);
+            None
+        }
+    }
+}
+
+fn value(m: Option<&Measurement>) -> f64 {
+    match m.map(|m| m.reading_in(Units::Si).reading) {
+        Some(Reading::Overload { .. }) => OVERLOAD,
+        Some(reading) => reading.value().unwrap_or(NOT_A_NUMBER),
+        None => NOT_A_NUMBER,
+    }
+}
+
+/// As instruments send them, `+1.2345E-3`
+fn number(value: f64) -> String {
+    format!("{}{:E}", if value < 0.0 { "" } else { "+" }, value)
+}
+
+/// Whether `header` is `keyword`, each part in its short form (the capitals) or long form, any
+/// case, with or without a leading colon
+fn is(header: &str, keyword: &str) -> bool {
+    let header = header.strip_prefix(':').unwrap_or(header);
+    let (given, wanted) = (header.split(':'), keyword.split(':'));
+    given.clone().count() == wanted.clone().count()
+        && given.zip(wanted).all(|(given, wanted)| {
+            let short: String = wanted.chars().filter(|c| !c.is_ascii_lowercase()).collect();
+            given.eq_ignore_ascii_case(wanted) || given.eq_ignore_ascii_case(&short)
+        })
+}
+
+/// Whether `header` starts with `keyword` followed by more, `MEAS:VOLT:DC?` under `MEASure`
+fn is_under(header: &str, keyword: &str) -> bool {
+    let header = header.strip_prefix(':').unwrap_or(header);
+    header.split_once(':').is_some_and(|(first, _)| is(first, keyword))
+}
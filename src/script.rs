@@ -0,0 +1,134 @@
+//! `--script FILE.rhai`, for what the flags don't cover: a sensor with its own calibration curve,
+//! a unit nobody else uses, an alarm that depends on more than one level. The script is
+//! [Rhai](https://rhai.rs) and defines `fn reading(r)`, which is called for every reading with a
+//! map of it:
+//!
+//! ```rhai
+//! fn reading(r) {
+//!     if r.mode != "V_DC" { return false; }           // left out of the outputs
+//!     derive("Pressure", r.value * 25.0 - 12.5, "bar");
+//!     if r.value > 4.8 && !this.high { alarm("sensor over range"); }
+//!     this.high = r.value > 4.8;
+//! }
+//! ```
+//!
+//! `r` has `value` (in the unit without a prefix, `()` for OL), `unit`, `mode`, `display` (as on the
+//! meter), `range`, `rel`, `hold`, `minmax` and `elapsed` (seconds since logging started). `this` is
+//! a map that's kept from one call to the next. `derive(name, value, unit)` logs a derived value
+//! next to the reading like `--ntc` does, `alarm(text)` an alarm event, and returning `false`
+//! leaves the reading out of the outputs (still counted in the summary and checked by `--alarm`).
+//!
+//! `print` goes to stderr. A script that fails, or runs for more than a million operations on one
+//! reading, is reported once and then skipped for the rest of the run, the readings go through as
+//! they are.
+
+use crate::clock::Stamp;
+use crate::output::Derived;
+use rhai::{AST, CallFnOptions, Dynamic, Engine, ImmutableString, Map, Scope};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::Path;
+use std::rc::Rc;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// Operations a call may take before it's stopped, so a stray loop can't hang the logger
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What the calls to `derive` and `alarm` asked for while handling one reading
+#[derive(Default)]
+struct Asked {
+    derived: Vec<(String, f64, String)>,
+    alarms: Vec<String>,
+}
+
+/// What the script made of a reading
+pub struct Outcome {
+    pub keep: bool,
+    pub derived: Vec<Derived>,
+    pub alarms: Vec<String>,
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    this: Dynamic,
+    asked: Rc<RefCell<Asked>>,
+    /// Derived names and units, which are `&'static str` everywhere else. A script only ever
+    /// uses a handful, so they're leaked once each
+    names: HashSet<&'static str>,
+    /// After an error it's left alone
+    failed: bool,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let asked = Rc::new(RefCell::new(Asked::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        // stdout may well be the CSV
+        engine.on_print(|text| eprintln!("{}", text));
+        let derive = asked.clone();
+        engine.register_fn("derive", move |name: ImmutableString, value: f64, unit: ImmutableString| {
+            derive.borrow_mut().derived.push((name.to_string(), value, unit.to_string()));
+        });
+        // Whole numbers too, `derive("Count", 3, "")` shouldn't need 3.0
+        let derive = asked.clone();
+        engine.register_fn("derive", move |name: ImmutableString, value: i64, unit: ImmutableString| {
+            derive.borrow_mut().derived.push((name.to_string(), value as f64, unit.to_string()));
+        });
+        let alarm = asked.clone();
+        engine.register_fn("alarm", move |text: ImmutableString| alarm.borrow_mut().alarms.push(text.to_string()));
+
+        let ast = engine.compile_file(path.into()).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if !ast.iter_functions().any(|f| f.name == "reading" && f.params.len() == 1) {
+            return Err(format!("{} has no fn reading(r)", path.display()));
+        }
+        // Top-level statements run once, e.g. to print what it's set up for
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Script { engine, ast, scope, this: Dynamic::from_map(Map::new()), asked, names: HashSet::new(), failed: false })
+    }
+
+    /// `None` once the script has failed, the error is the first time it does
+    pub fn reading(&mut self, stamp: &Stamp, m: &Measurement) -> Option<Result<Outcome, String>> {
+        if self.failed {
+            return None;
+        }
+        let scaled = m.reading_in(Units::Si);
+        let mut r = Map::new();
+        r.insert("value".into(), scaled.reading.value().map_or(Dynamic::UNIT, Dynamic::from_float));
+        r.insert("unit".into(), scaled.unit.into());
+        r.insert("mode".into(), m.mode_name().into());
+        r.insert("display".into(), m.reading().to_string().into());
+        r.insert("range".into(), m.range_mode().into());
+        r.insert("rel".into(), m.rel.into());
+        r.insert("hold".into(), m.hold.into());
+        r.insert("minmax".into(), m.min_max().into());
+        r.insert("elapsed".into(), stamp.elapsed.as_secs_f64().into());
+
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.this);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, "reading", (r,));
+        let asked = std::mem::take(&mut *self.asked.borrow_mut());
+        let keep = match result {
+            Ok(returned) => returned.as_bool().unwrap_or(true),
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e.to_string()));
+            }
+        };
+        let derived = asked.derived.into_iter().map(|(name, value, unit)| Derived { name: self.name(name), value, unit: self.name(unit) }).collect();
+        Some(Ok(Outcome { keep, derived, alarms: asked.alarms }))
+    }
+
+    fn name(&mut self, name: String) -> &'static str {
+        match self.names.get(name.as_str()) {
+            Some(name) => name,
+            None => {
+                let name = Box::leak(name.into_boxed_str());
+                self.names.insert(name);
+                name
+            }
+        }
+    }
+}
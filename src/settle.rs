@@ -0,0 +1,95 @@
+//! `--settle`, for leakage and insulation tests: after the voltage goes on, the current into an
+//! electrolytic or the resistance of insulation creeps towards its final value for minutes.
+//! Rather than waiting it out, this fits `final + a·e^(-t/τ)` to the readings so far and logs
+//! where they're heading and the time constant as derived values, once a second. Readings
+//! start over when the mode or unit changes, or with `/reset`.
+
+use crate::analysis::{self, Settling};
+use crate::clock::Stamp;
+use crate::i18n::tr;
+use crate::output::Derived;
+use crate::stats::significant;
+use std::time::{Duration, Instant};
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// How often the curve is fitted again
+const FIT_EVERY: Duration = Duration::from_secs(1);
+
+/// Within 1% of the final value after this many time constants
+const SETTLED_AFTER: f64 = 4.6;
+
+#[derive(Default)]
+pub struct Settle {
+    mode: &'static str,
+    unit: &'static str,
+    /// When the first of `times` was read
+    start: Duration,
+    times: Vec<f64>,
+    values: Vec<f64>,
+    fit: Option<Settling>,
+    fitted: Option<Instant>,
+    /// Whether it's been said that the readings are within 1% of the final value
+    settled: bool,
+}
+
+impl Settle {
+    /// The fit now and then, and a message when it's settled or started over
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> (Vec<Derived>, Option<String>) {
+        let scaled = m.reading_in(Units::Si);
+        let Some(value) = scaled.reading.value() else {
+            return (Vec::new(), None);
+        };
+        let mut message = None;
+        if (m.mode_name(), scaled.unit) != (self.mode, self.unit) {
+            if !self.times.is_empty() {
+                message = Some(tr!("Settling: started over in {}", m.mode_name()));
+            }
+            self.reset();
+            (self.mode, self.unit) = (m.mode_name(), scaled.unit);
+        }
+        if self.times.is_empty() {
+            self.start = stamp.elapsed;
+        }
+        let t = stamp.elapsed.saturating_sub(self.start).as_secs_f64();
+        self.times.push(t);
+        self.values.push(value);
+        if self.fitted.is_some_and(|fitted| fitted.elapsed() < FIT_EVERY) {
+            return (Vec::new(), message);
+        }
+        self.fitted = Some(Instant::now());
+        self.fit = analysis::settling(&self.times, &self.values);
+        let Some(fit) = &self.fit else {
+            return (Vec::new(), message);
+        };
+        if !self.settled && t >= SETTLED_AFTER * fit.tau {
+            self.settled = true;
+            message = Some(tr!("Settled within 1% of {} {} (τ {} s)", significant(fit.asymptote), self.unit, significant(fit.tau)));
+        }
+        let derived = vec![
+            Derived { name: "Settles to", value: fit.asymptote, unit: self.unit },
+            Derived { name: "Time constant", value: fit.tau, unit: "s" },
+        ];
+        (derived, message)
+    }
+
+    /// Starts over with the next reading
+    pub fn reset(&mut self) {
+        *self = Settle::default();
+    }
+
+    /// The last fit for the end of the run, if there was one
+    pub fn summary(&self) -> Option<String> {
+        let fit = self.fit.as_ref()?;
+        let settled = SETTLED_AFTER * fit.tau;
+        Some(tr!(
+            "{} settles to {} {} with a time constant of {} s (R² {}, {} readings), within 1% after {} s",
+            self.mode,
+            significant(fit.asymptote),
+            self.unit,
+            significant(fit.tau),
+            format!("{:.4}", fit.r_squared),
+            fit.samples,
+            format!("{:.0}", settled)
+        ))
+    }
+}
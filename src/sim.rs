@@ -0,0 +1,421 @@
+//! A pretend meter for `--simulate`, so the logger, its outputs and whatever reads them can be
+//! tried out (and tested) without the hardware. It answers polls the way a CP2110 cable does,
+//! real frames split over HID reports, so everything from frame reassembly up runs exactly as
+//! it does for a meter.
+//!
+//! The reading is a sine around `value` plus noise, autoranging like the meter unless a range
+//! is given, and `OL` past the top of the range. Faults (corrupt frames, polls nobody answers)
+//! can be mixed in to try the error handling.
+//!
+//! For reproducing something that only happens on a particular sequence ("switches from mV to V
+//! at 10 s and the plot breaks") a scenario file scripts the readings instead, one CSV row per
+//! step, `time,value,mode,range,flags`, each shown until the next row's time:
+//!
+//! ```text
+//! # time,value,mode,range,flags
+//! 0,0.012,mV_DC
+//! 10s,1.5,V_DC
+//! 15s,OL,V_DC,0x30
+//! 20s,1.5,V_DC,,hold low-battery
+//! ```
+//!
+//! Values are in base units like `value=`, or `OL`, `-OL` or `----`. An empty mode keeps the
+//! one before, an empty range autoranges.
+
+use crate::meter::Transport;
+use hidapi::HidError;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::f64::consts::TAU;
+use std::time::{Duration, Instant};
+use std::{fmt, fs};
+use ut61e_plus_logger::protocol::{self, CMD_GET_MEASUREMENT, Measurement};
+
+/// What to simulate, from `--simulate mode=V_DC,value=5,amplitude=0.1,noise=0.001,hold`
+#[derive(Clone)]
+pub struct Sim {
+    pub mode: u8,
+    /// A fixed range byte like the RANGE button gives, `None` to autorange
+    pub range: Option<u8>,
+    /// Centre of the sine in base units (V, A, Ω, F, Hz, %) whatever range it lands on
+    pub value: f64,
+    pub amplitude: f64,
+    pub period: Duration,
+    /// Uniform noise of up to this much either way, in base units. By default 0.05% of the
+    /// signal, so it's there whatever the unit.
+    pub noise: Option<f64>,
+    pub flags: Flags,
+    /// Scripted readings from `scenario=file.csv`, replacing the sine
+    pub scenario: Vec<Step>,
+    /// Chance of a poll getting a frame with a bad checksum
+    pub corrupt: f64,
+    /// Chance of a poll getting no answer at all
+    pub dropout: f64,
+    pub seed: u64,
+}
+
+impl Default for Sim {
+    fn default() -> Self {
+        Sim {
+            mode: 2,
+            range: None,
+            value: 1.0,
+            amplitude: 0.5,
+            period: Duration::from_secs(10),
+            noise: None,
+            flags: Flags::default(),
+            scenario: Vec::new(),
+            corrupt: 0.0,
+            dropout: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+/// LCD annunciators that can be turned on, by the names used in the settings and scenarios
+#[derive(Clone, Copy, Default)]
+pub struct Flags {
+    pub hold: bool,
+    pub rel: bool,
+    pub min: bool,
+    pub max: bool,
+    pub low_battery: bool,
+    pub hv_warning: bool,
+}
+
+impl Flags {
+    fn set(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "hold" => self.hold = true,
+            "rel" => self.rel = true,
+            "min" => self.min = true,
+            "max" => self.max = true,
+            "low-battery" => self.low_battery = true,
+            "hv" => self.hv_warning = true,
+            _ => return Err(format!("unknown flag {:?}, expected hold, rel, min, max, low-battery or hv", name)),
+        }
+        Ok(())
+    }
+}
+
+/// What the display shows, before it's fitted to a range
+#[derive(Clone, Copy)]
+pub enum Shown {
+    /// In base units
+    Value(f64),
+    Overload { negative: bool },
+    Dashes,
+}
+
+/// One row of a scenario, shown from `at` until the next one
+#[derive(Clone)]
+pub struct Step {
+    pub at: Duration,
+    pub shown: Shown,
+    pub mode: u8,
+    pub range: Option<u8>,
+    pub flags: Flags,
+}
+
+/// The mode byte for a name as [`protocol::parse_mode`] gives it, or just its first word, so
+/// `resistance` works as well as `Resistance Ω`
+fn mode_byte(name: &str) -> Option<u8> {
+    (0..=u8::MAX).find(|&mode| {
+        let known = protocol::parse_mode(mode);
+        known != "?" && (known.eq_ignore_ascii_case(name) || known.split(' ').next().is_some_and(|w| w.eq_ignore_ascii_case(name)))
+    })
+}
+
+/// How many base units one of `unit` is, from its SI prefix
+fn prefix(unit: &str) -> f64 {
+    let mut chars = unit.chars();
+    match (chars.next(), chars.next()) {
+        (Some('M'), Some(_)) => 1e6,
+        (Some('k'), Some(_)) => 1e3,
+        (Some('m'), Some(_)) => 1e-3,
+        (Some('μ'), Some(_)) => 1e-6,
+        (Some('n'), Some(_)) => 1e-9,
+        _ => 1.0,
+    }
+}
+
+/// `0x31`, or counted from the lowest range like pressing RANGE, `1`
+fn range_byte(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse::<u8>().ok().and_then(|n| n.checked_add(0x30)),
+    }
+}
+
+/// Whether `mode` and `range` can be shown, an error saying why not otherwise
+fn check_range(mode: u8, range: Option<u8>) -> Result<(), String> {
+    if let Some(range) = range
+        && protocol::range_info(mode, range).is_none()
+    {
+        return Err(format!("{} has no range 0x{:02x}", protocol::parse_mode(mode), range));
+    }
+    if (0x30..=0x37).all(|range| protocol::range_info(mode, range).is_none()) {
+        return Err(format!("{} can't be simulated, it has no numeric ranges", protocol::parse_mode(mode)));
+    }
+    Ok(())
+}
+
+/// Reads a scenario file, see the module docs for the format
+fn load_scenario(path: &str, mut mode: u8) -> Result<Vec<Step>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let mut steps: Vec<Step> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (steps.is_empty() && line.starts_with("time")) {
+            continue;
+        }
+        let error = |e: String| format!("{} line {}: {}", path, n + 1, e);
+        let mut fields = line.split(',').map(str::trim);
+        let at = fields.next().unwrap_or_default();
+        let at = match at.parse::<f64>() {
+            Ok(secs) if secs >= 0.0 => Some(Duration::from_secs_f64(secs)),
+            _ => crate::clock::parse_duration(at),
+        }
+        .ok_or_else(|| error(format!("bad time {:?}, expected seconds or e.g. 10s", at)))?;
+        if steps.last().is_some_and(|last| at < last.at) {
+            return Err(error("times have to go up".to_owned()));
+        }
+        let shown = match fields.next().unwrap_or_default() {
+            "OL" => Shown::Overload { negative: false },
+            "-OL" => Shown::Overload { negative: true },
+            "----" => Shown::Dashes,
+            value => Shown::Value(value.parse().map_err(|_| error(format!("bad value {:?}", value)))?),
+        };
+        match fields.next().unwrap_or_default() {
+            "" => {}
+            name => mode = mode_byte(name).ok_or_else(|| error(format!("unknown mode {:?}", name)))?,
+        }
+        let range = match fields.next().unwrap_or_default() {
+            "" => None,
+            range => Some(range_byte(range).ok_or_else(|| error(format!("bad range {:?}", range)))?),
+        };
+        check_range(mode, range).map_err(error)?;
+        let mut flags = Flags::default();
+        for name in fields.next().unwrap_or_default().split_whitespace() {
+            flags.set(name).map_err(error)?;
+        }
+        steps.push(Step { at, shown, mode, range, flags });
+    }
+    if steps.is_empty() {
+        return Err(format!("{} has no steps", path));
+    }
+    Ok(steps)
+}
+
+fn probability(key: &str, value: &str) -> Result<f64, String> {
+    match value.parse() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!("{} has to be between 0 and 1, got {:?}", key, value)),
+    }
+}
+
+impl Sim {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut sim = Sim::default();
+        let mut scenario = None;
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let number = |value: &str| value.parse::<f64>().map_err(|_| format!("{:?} isn't a number in {:?}", value, part));
+            match part.split_once('=') {
+                Some(("mode", name)) => sim.mode = mode_byte(name).ok_or_else(|| format!("unknown mode {:?}", name))?,
+                Some(("range", range)) => {
+                    sim.range = Some(range_byte(range).ok_or_else(|| format!("bad range in {:?}, expected 0x31 or 1", part))?);
+                }
+                Some(("value", value)) => sim.value = number(value)?,
+                Some(("amplitude", value)) => sim.amplitude = number(value)?,
+                Some(("period", value)) => {
+                    sim.period = crate::clock::parse_duration(value)
+                        .filter(|p| !p.is_zero())
+                        .ok_or_else(|| format!("bad period in {:?}, expected e.g. 10s", part))?
+                }
+                Some(("noise", value)) => sim.noise = Some(number(value)?.abs()),
+                Some(("corrupt", value)) => sim.corrupt = probability("corrupt", value)?,
+                Some(("dropout", value)) => sim.dropout = probability("dropout", value)?,
+                Some(("scenario", path)) => scenario = Some(path),
+                Some(("seed", value)) => sim.seed = value.parse().map_err(|_| format!("bad seed in {:?}", part))?,
+                Some((key, _)) => return Err(format!("unknown setting {:?}", key)),
+                None => sim.flags.set(part)?,
+            }
+        }
+        check_range(sim.mode, sim.range)?;
+        // After the loop so mode= is the starting mode wherever it's written
+        if let Some(path) = scenario {
+            sim.scenario = load_scenario(path, sim.mode)?;
+        }
+        Ok(sim)
+    }
+
+    /// Noise amplitude around `value`, 0.05% of it unless set
+    fn noise(&self, value: f64) -> f64 {
+        self.noise.unwrap_or(0.0005 * value.abs())
+    }
+
+    /// What the meter shows `elapsed` into the run, with `noise` between -1 and 1
+    pub fn measurement(&self, elapsed: Duration, noise: f64) -> Measurement {
+        if let Some(step) = self.scenario.iter().rev().find(|step| step.at <= elapsed).or(self.scenario.first()) {
+            let shown = match step.shown {
+                Shown::Value(value) => Shown::Value(value + self.noise(value) * noise),
+                shown => shown,
+            };
+            return show(step.mode, step.range, shown, &step.flags);
+        }
+        // HOLD freezes the first reading
+        let elapsed = if self.flags.hold { Duration::ZERO } else { elapsed };
+        let phase = TAU * elapsed.as_secs_f64() / self.period.as_secs_f64();
+        let value = self.value + self.amplitude * phase.sin();
+        let value = value + self.noise(self.value.abs() + self.amplitude.abs()) * noise;
+        show(self.mode, self.range, Shown::Value(value), &self.flags)
+    }
+}
+
+/// The measurement for `shown` on the range it fits (or `range`), `OL` if it doesn't fit
+fn show(mode: u8, range: Option<u8>, shown: Shown, flags: &Flags) -> Measurement {
+    let ranges = || (0x30..=0x37).filter_map(|range| Some((range, protocol::range_info(mode, range)?)));
+    let fits = |info: &protocol::RangeInfo| match shown {
+        Shown::Value(value) => (value / prefix(info.unit)).abs() <= info.full_scale(),
+        _ => true,
+    };
+    let (range_byte, info) = match range {
+        Some(range) => (range, protocol::range_info(mode, range).unwrap()),
+        // Overloads and dashes show on the lowest range, like the meter with nothing connected
+        None => ranges().find(|(_, info)| fits(info)).or_else(|| ranges().next_back()).unwrap(),
+    };
+
+    let text = match shown {
+        Shown::Value(value) if fits(&info) => format!("{:>7.*}", info.decimals as usize, value / prefix(info.unit)),
+        Shown::Value(value) if value < 0.0 => "  -OL  ".to_owned(),
+        Shown::Overload { negative: true } => "  -OL  ".to_owned(),
+        Shown::Value(_) | Shown::Overload { negative: false } => "   OL  ".to_owned(),
+        Shown::Dashes => "  ---- ".to_owned(),
+    };
+    let mut display = [b' '; 7];
+    for (d, b) in display.iter_mut().zip(text.bytes()) {
+        *d = b;
+    }
+    let name = protocol::parse_mode(mode);
+    Measurement {
+        mode,
+        range: range_byte,
+        display,
+        bar: [0, 0],
+        rel: flags.rel,
+        hold: flags.hold,
+        min: flags.min,
+        max: flags.max,
+        auto_range: range.is_none(),
+        low_battery: flags.low_battery,
+        hv_warning: flags.hv_warning,
+        dc: name.ends_with("DC") && !name.contains("AC"),
+        peak_max: false,
+        peak_min: false,
+    }
+}
+
+impl fmt::Display for Sim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scenario.last() {
+            Some(last) => write!(f, "{} step scenario over {:?}", self.scenario.len(), last.at)?,
+            None => write!(
+                f,
+                "{} {} ± {} over {:?}, noise {}",
+                protocol::parse_mode(self.mode),
+                self.value,
+                self.amplitude,
+                self.period,
+                self.noise(self.value.abs() + self.amplitude.abs())
+            )?,
+        }
+        if self.corrupt > 0.0 || self.dropout > 0.0 {
+            write!(f, ", {}% corrupt, {}% unanswered", 100.0 * self.corrupt, 100.0 * self.dropout)?;
+        }
+        Ok(())
+    }
+}
+
+/// xorshift64, plenty for noise and repeatable from the seed
+struct Rng(u64);
+
+impl Rng {
+    /// Uniform in [0, 1)
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+struct State {
+    rng: Rng,
+    /// UART bytes the logger has sent, until they make up a command
+    received: Vec<u8>,
+    /// UART bytes waiting to be read
+    pending: VecDeque<u8>,
+}
+
+/// A [`Transport`] that behaves like a meter on a CP2110 cable
+pub struct SimTransport {
+    sim: Sim,
+    started: Instant,
+    state: RefCell<State>,
+}
+
+impl SimTransport {
+    pub fn new(sim: Sim) -> Self {
+        // xorshift gets stuck on 0
+        let rng = Rng(sim.seed.max(1));
+        SimTransport {
+            sim,
+            started: Instant::now(),
+            state: RefCell::new(State { rng, received: Vec::new(), pending: VecDeque::new() }),
+        }
+    }
+}
+
+impl Transport for SimTransport {
+    fn write(&self, data: &[u8]) -> Result<usize, HidError> {
+        let state = &mut *self.state.borrow_mut();
+        // CP2110 reports start with the number of UART bytes
+        let len = data.first().map_or(0, |&n| n as usize);
+        state.received.extend(data.iter().skip(1).take(len));
+        let poll = protocol::command(CMD_GET_MEASUREMENT);
+        if let Some(at) = state.received.windows(poll.len()).position(|w| w == poll) {
+            state.received.drain(..at + poll.len());
+            if state.rng.next() >= self.sim.dropout {
+                let noise = 2.0 * state.rng.next() - 1.0;
+                let payload = self.sim.measurement(self.started.elapsed(), noise).encode();
+                let mut frame = protocol::measurement_frame(&payload);
+                if state.rng.next() < self.sim.corrupt {
+                    // A flipped bit somewhere in the display
+                    frame[5 + (state.rng.next() * 7.0) as usize] ^= 1 << (state.rng.next() * 7.0) as u32;
+                }
+                state.pending.extend(frame);
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, HidError> {
+        let state = &mut *self.state.borrow_mut();
+        if state.pending.is_empty() {
+            // Nothing coming, the same wait a real poll would have
+            std::thread::sleep(Duration::from_millis(timeout_ms.max(0) as u64));
+            return Ok(0);
+        }
+        // A few bytes at a time like the cable, so frames have to be put back together
+        let n = (1 + (state.rng.next() * 16.0) as usize).min(state.pending.len()).min(buf.len() - 1);
+        buf[0] = n as u8;
+        for (b, byte) in buf[1..=n].iter_mut().zip(state.pending.drain(..n)) {
+            *b = byte;
+        }
+        Ok(n + 1)
+    }
+
+    fn send_feature_report(&self, _data: &[u8]) -> Result<(), HidError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,634 @@
+//! Everywhere readings go, behind one trait so the logging loop hands each record over once and
+//! every output gets it. One output failing (a full disk, a file on a share that went away) is
+//! reported and that output dropped, the rest carry on. Only stdout is allowed to end the run, so
+//! `| head` still stops the logger.
+//!
+//! Network outputs run on threads of their own behind a bounded queue (see [`Policy`]), so a slow
+//! broker or a stuck browser costs that output some readings rather than holding up the meter and
+//! the capture file.
+
+use crate::clock::Stamp;
+use crate::i18n::tr;
+use crate::output::{self, Columns, Derived, Flushing};
+use crate::stats::Tracked;
+use crate::{alarm, modbus, scpi, udp, web, zabbix};
+use colored::*;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use ut61e_plus_logger::protocol::Measurement;
+
+pub trait Sink {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()>;
+
+    /// The start of a stretch with no readings, once for the whole stretch
+    fn gap(&mut self, _stamp: &Stamp, _reason: &'static str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Every poll that got no reading, not just the first of a gap
+    fn missed(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A note, a burst starting or anything else worth keeping alongside the data
+    fn event(&mut self, _stamp: &Stamp, _message: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// An event that's already been printed on stderr, so the terminal doesn't get it twice
+    fn notice(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.event(stamp, message)
+    }
+
+    /// Something worked out from the reading just handed over, see [`Derived`]
+    fn derived(&mut self, _stamp: &Stamp, _derived: &Derived) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The logger's own min, max and mean for the mode the meter is in, now and then and after a
+    /// reset, `None` when there's nothing counted yet
+    fn stats(&mut self, _current: Option<&Tracked>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Where every alarm is at, when one changes and along with the stats
+    fn alarms(&mut self, _statuses: &[alarm::Status]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Once a poll, for anything flushed on a schedule
+    fn tick(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Exiting skips destructors, so anything that buffers or has files to tidy up does it here
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How records get to a sink, from `--queue`
+#[derive(Clone, Copy, Debug)]
+pub enum Policy {
+    /// Straight from the logging loop, so a slow sink slows logging down
+    Inline,
+    /// Through a queue of this many records, leaving out new ones for that sink while it's full
+    Drop(usize),
+    /// Through a queue of this many records, waiting for room while it's full
+    Block(usize),
+}
+
+const DEFAULT_QUEUE: usize = 1000;
+
+impl Policy {
+    /// The capture itself mustn't miss anything and stdout has to notice `| head` going away
+    /// promptly, everything else is better off losing readings than holding up the meter
+    fn default_for(kind: &str) -> Policy {
+        match kind {
+            "stdout" | "file" | "local" => Policy::Inline,
+            _ => Policy::Drop(DEFAULT_QUEUE),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    /// `inline`, `drop`, `block`, or with a queue length like `drop:100`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (policy, size) = s.split_once(':').map_or((s, None), |(policy, size)| (policy, Some(size)));
+        let size = match size.map(str::parse) {
+            None => DEFAULT_QUEUE,
+            Some(Ok(size)) if size > 0 && policy != "inline" => size,
+            Some(_) => return Err(format!("expected a queue length like drop:100, got {:?}", s)),
+        };
+        match policy {
+            "inline" => Ok(Policy::Inline),
+            "drop" => Ok(Policy::Drop(size)),
+            "block" => Ok(Policy::Block(size)),
+            _ => Err(format!("expected inline, drop, block, drop:N or block:N, got {:?}", s)),
+        }
+    }
+}
+
+/// One call on a sink, so it can be queued for another thread
+#[derive(Clone)]
+enum Record {
+    Sample(Stamp, Measurement),
+    Gap(Stamp, &'static str),
+    Missed,
+    Event(Stamp, String),
+    Notice(Stamp, String),
+    Derived(Stamp, Derived),
+    Stats(Option<Tracked>),
+    Alarms(Vec<alarm::Status>),
+    Tick,
+}
+
+impl Record {
+    fn apply(self, sink: &mut dyn Sink) -> io::Result<()> {
+        match self {
+            Record::Sample(stamp, m) => sink.sample(&stamp, &m),
+            Record::Gap(stamp, reason) => sink.gap(&stamp, reason),
+            Record::Missed => sink.missed(),
+            Record::Event(stamp, message) => sink.event(&stamp, &message),
+            Record::Notice(stamp, message) => sink.notice(&stamp, &message),
+            Record::Derived(stamp, derived) => sink.derived(&stamp, &derived),
+            Record::Stats(current) => sink.stats(current.as_ref()),
+            Record::Alarms(statuses) => sink.alarms(&statuses),
+            Record::Tick => sink.tick(),
+        }
+    }
+}
+
+/// A sink on its own thread
+struct Queue {
+    tx: SyncSender<Record>,
+    block: bool,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Queue {
+    fn start(mut sink: Box<dyn Sink + Send>, size: usize, block: bool) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<Record>(size);
+        let thread = thread::spawn(move || {
+            for record in rx {
+                record.apply(sink.as_mut())?;
+            }
+            sink.finish()
+        });
+        Queue { tx, block, thread: Some(thread) }
+    }
+
+    /// False if the queue was full and the record left out
+    fn send(&mut self, record: Record) -> io::Result<bool> {
+        let sent = if self.block {
+            self.tx.send(record).map_err(|_| ())
+        } else {
+            match self.tx.try_send(record) {
+                Err(TrySendError::Full(_)) => return Ok(false),
+                result => result.map_err(|_| ()),
+            }
+        };
+        match sent {
+            Ok(()) => Ok(true),
+            // The thread only stops early on an error
+            Err(()) => Err(self.thread.take().map(join).and_then(Result::err).unwrap_or_else(|| io::Error::other("stopped"))),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        // Closing the queue lets the thread write out what's left and finish the sink
+        let Queue { tx, thread, .. } = self;
+        drop(tx);
+        thread.map_or(Ok(()), join)
+    }
+}
+
+fn join(thread: JoinHandle<io::Result<()>>) -> io::Result<()> {
+    thread.join().unwrap_or_else(|_| Err(io::Error::other("panicked")))
+}
+
+enum Target {
+    Inline(Box<dyn Sink>),
+    Queued(Queue),
+}
+
+struct Entry {
+    name: String,
+    target: Target,
+    /// Its errors end the run instead of just dropping it
+    fatal: bool,
+    /// Records left out because its queue was full
+    dropped: u64,
+}
+
+/// Hands every record to each sink in the order they were added
+pub struct Fanout {
+    sinks: Vec<Entry>,
+    /// `--queue`, by kind (`udp`) or name (`capture.csv`)
+    policies: Vec<(String, Policy)>,
+}
+
+impl Fanout {
+    pub fn new(policies: Vec<(String, Policy)>) -> Self {
+        Fanout { sinks: Vec::new(), policies }
+    }
+
+    fn policy(&self, kind: &str, name: &str) -> Policy {
+        let given = self.policies.iter().rev().find(|(key, _)| key == name || key == kind);
+        given.map_or(Policy::default_for(kind), |&(_, policy)| policy)
+    }
+
+    /// `kind` is what `--queue` picks it by, like `udp` or `file`, and `name` is what the error
+    /// says when it fails, e.g. the file name
+    pub fn add(&mut self, kind: &str, name: impl Into<String>, sink: impl Sink + Send + 'static) {
+        let name = name.into();
+        let target = match self.policy(kind, &name) {
+            Policy::Inline => Target::Inline(Box::new(sink)),
+            Policy::Drop(size) => Target::Queued(Queue::start(Box::new(sink), size, false)),
+            Policy::Block(size) => Target::Queued(Queue::start(Box::new(sink), size, true)),
+        };
+        self.sinks.push(Entry { name, target, fatal: kind == "stdout", dropped: 0 });
+    }
+
+    /// For sinks that can't move to another thread, like the audio output
+    #[cfg_attr(not(feature = "sonify"), allow(dead_code))]
+    pub fn add_inline(&mut self, name: impl Into<String>, sink: impl Sink + 'static) {
+        self.sinks.push(Entry { name: name.into(), target: Target::Inline(Box::new(sink)), fatal: false, dropped: 0 });
+    }
+
+    fn dispatch(&mut self, record: Record) -> io::Result<()> {
+        let mut fatal = None;
+        self.sinks.retain_mut(|entry| {
+            let result = match &mut entry.target {
+                Target::Inline(sink) => record.clone().apply(sink.as_mut()),
+                Target::Queued(queue) => queue.send(record.clone()).map(|sent| {
+                    if !sent {
+                        entry.dropped += 1;
+                    }
+                }),
+            };
+            match result {
+                Ok(()) => true,
+                Err(e) if entry.fatal => {
+                    fatal.get_or_insert(e);
+                    true
+                }
+                Err(e) => {
+                    eprintln!("{}", format!("Writing to {} failed ({}), carrying on without it", entry.name, e).red());
+                    false
+                }
+            }
+        });
+        fatal.map_or(Ok(()), Err)
+    }
+
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.dispatch(Record::Sample(*stamp, *m))
+    }
+
+    pub fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        self.dispatch(Record::Gap(*stamp, reason))
+    }
+
+    pub fn missed(&mut self) -> io::Result<()> {
+        self.dispatch(Record::Missed)
+    }
+
+    pub fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.dispatch(Record::Event(*stamp, message.to_owned()))
+    }
+
+    pub fn notice(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.dispatch(Record::Notice(*stamp, message.to_owned()))
+    }
+
+    pub fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        self.dispatch(Record::Derived(*stamp, *derived))
+    }
+
+    pub fn stats(&mut self, current: Option<&Tracked>) -> io::Result<()> {
+        self.dispatch(Record::Stats(current.copied()))
+    }
+
+    pub fn alarms(&mut self, statuses: &[alarm::Status]) -> io::Result<()> {
+        self.dispatch(Record::Alarms(statuses.to_vec()))
+    }
+
+    pub fn tick(&mut self) -> io::Result<()> {
+        self.dispatch(Record::Tick)
+    }
+
+    /// Finishes every sink even if one fails, says which ones couldn't keep up, and returns the
+    /// first error
+    pub fn finish(self) -> io::Result<()> {
+        let mut result = Ok(());
+        for entry in self.sinks {
+            if entry.dropped > 0 {
+                eprintln!("{}", tr!("{} records left out for {}, it couldn't keep up", entry.dropped, entry.name).yellow());
+            }
+            let finished = match entry.target {
+                Target::Inline(sink) => sink.finish(),
+                Target::Queued(queue) => queue.finish(),
+            };
+            if let Err(e) = finished {
+                eprintln!("{}", format!("Finishing {} failed: {}", entry.name, e).red());
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Coloured lines or CSV, to stdout, a file or the local consumers
+pub struct Text {
+    out: Flushing<Box<dyn Write + Send>>,
+    columns: Columns,
+    csv: bool,
+}
+
+impl Text {
+    pub fn new(out: Flushing<Box<dyn Write + Send>>, columns: Columns, csv: bool) -> Self {
+        Text { out, columns, csv }
+    }
+}
+
+impl Sink for Text {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        if self.csv {
+            output::write_csv(&mut self.out, stamp, m, &self.columns)
+        } else {
+            output::write_pretty(&mut self.out, stamp, m, &self.columns)
+        }
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        if self.csv {
+            output::write_csv_gap(&mut self.out, stamp, &self.columns, reason)?;
+        }
+        Ok(())
+    }
+
+    fn missed(&mut self) -> io::Result<()> {
+        if !self.csv {
+            writeln!(self.out, "{}", "No response or parse error.".red())?;
+        }
+        Ok(())
+    }
+
+    fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        if self.csv {
+            output::write_csv_event(&mut self.out, stamp, &self.columns, message)
+        } else {
+            output::write_pretty_event(&mut self.out, message)
+        }
+    }
+
+    fn notice(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        if self.csv {
+            output::write_csv_event(&mut self.out, stamp, &self.columns, message)?;
+        }
+        Ok(())
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        if self.csv {
+            output::write_csv_derived(&mut self.out, stamp, &self.columns, derived)
+        } else {
+            output::write_pretty_derived(&mut self.out, derived)
+        }
+    }
+
+    fn tick(&mut self) -> io::Result<()> {
+        self.out.tick()
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl Sink for crate::parquet_file::ParquetFile {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.write(stamp, m).map_err(io::Error::other)
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        self.write_gap(stamp, reason).map_err(io::Error::other)
+    }
+
+    fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.write_event(stamp, message).map_err(io::Error::other)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.close().map_err(io::Error::other)
+    }
+}
+
+impl Sink for modbus::Server {
+    fn sample(&mut self, _stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.update(m);
+        Ok(())
+    }
+
+    fn gap(&mut self, _stamp: &Stamp, _reason: &'static str) -> io::Result<()> {
+        modbus::Server::gap(self);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "opcua")]
+impl Sink for crate::opcua::Server {
+    fn sample(&mut self, _stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.update(m);
+        Ok(())
+    }
+
+    fn gap(&mut self, _stamp: &Stamp, _reason: &'static str) -> io::Result<()> {
+        crate::opcua::Server::gap(self);
+        Ok(())
+    }
+}
+
+impl Sink for scpi::Server {
+    fn sample(&mut self, _stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.update(m);
+        Ok(())
+    }
+
+    fn gap(&mut self, _stamp: &Stamp, _reason: &'static str) -> io::Result<()> {
+        scpi::Server::gap(self);
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct Panel {
+    pub panel: crate::panel::Panel,
+    pub units: ut61e_plus_logger::protocol::Units,
+}
+
+#[cfg(target_os = "linux")]
+impl Sink for Panel {
+    fn sample(&mut self, _stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.panel.sample(m, self.units);
+        Ok(())
+    }
+
+    fn gap(&mut self, _stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        self.panel.gap(reason);
+        Ok(())
+    }
+}
+
+impl Sink for zabbix::Zabbix {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        self.send(stamp.wall, m.reading().value(), m.unit(), m.mode_name());
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        (*self).finish();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sonify")]
+pub struct Sonify {
+    pub sonify: crate::sonify::Sonify,
+    pub mapping: crate::sonify::Mapping,
+}
+
+#[cfg(feature = "sonify")]
+impl Sink for Sonify {
+    fn sample(&mut self, _stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        let full_scale = m.range_info().map(|r| r.full_scale());
+        self.sonify.set(m.reading().value().and_then(|v| self.mapping.frequency(v, full_scale)));
+        Ok(())
+    }
+
+    fn gap(&mut self, _stamp: &Stamp, _reason: &'static str) -> io::Result<()> {
+        self.sonify.set(None);
+        Ok(())
+    }
+}
+
+/// `--osc`, the value on its own as a float
+pub struct Osc {
+    pub udp: udp::Udp,
+    pub address: String,
+}
+
+impl Sink for Osc {
+    fn sample(&mut self, _stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        // OL and dashes have no number to send
+        if let Some(value) = m.reading().value() {
+            self.udp.send(&crate::osc::message(&self.address, value as f32));
+        }
+        Ok(())
+    }
+}
+
+/// `--format json`, a JSON object a line, to stdout or a file
+pub struct JsonLines {
+    out: Flushing<Box<dyn Write + Send>>,
+    columns: Columns,
+}
+
+impl JsonLines {
+    pub fn new(out: Flushing<Box<dyn Write + Send>>, columns: Columns) -> Self {
+        JsonLines { out, columns }
+    }
+}
+
+impl Sink for JsonLines {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        output::write_json(&mut self.out, stamp, m, &self.columns)
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        output::write_json_note(&mut self.out, stamp, "gap", reason)
+    }
+
+    fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        output::write_json_note(&mut self.out, stamp, "event", message)
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        output::write_json_derived(&mut self.out, stamp, derived)
+    }
+
+    fn tick(&mut self) -> io::Result<()> {
+        self.out.tick()
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// `--udp`, a JSON object a datagram
+pub struct Datagrams {
+    pub udp: udp::Udp,
+    pub columns: Columns,
+}
+
+impl Sink for Datagrams {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        let mut datagram = Vec::new();
+        output::write_json(&mut datagram, stamp, m, &self.columns)?;
+        self.udp.send(&datagram);
+        Ok(())
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        let mut datagram = Vec::new();
+        output::write_json_derived(&mut datagram, stamp, derived)?;
+        self.udp.send(&datagram);
+        Ok(())
+    }
+}
+
+impl Sink for web::Web {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        web::Web::sample(self, stamp, m);
+        Ok(())
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        web::Web::gap(self, stamp, reason);
+        Ok(())
+    }
+
+    fn event(&mut self, _stamp: &Stamp, message: &str) -> io::Result<()> {
+        web::Web::event(self, message);
+        Ok(())
+    }
+
+    fn stats(&mut self, current: Option<&Tracked>) -> io::Result<()> {
+        web::Web::stats(self, current);
+        Ok(())
+    }
+
+    fn alarms(&mut self, statuses: &[alarm::Status]) -> io::Result<()> {
+        web::Web::alarms(self, statuses);
+        Ok(())
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        web::Web::derived(self, stamp, derived);
+        Ok(())
+    }
+}
+
+/// `--kafka`, keyed by the cable's serial number
+#[cfg(feature = "kafka")]
+pub struct Kafka {
+    pub kafka: crate::kafka::Kafka,
+    pub key: String,
+    pub columns: Columns,
+}
+
+#[cfg(feature = "kafka")]
+impl Sink for Kafka {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        let mut message = Vec::new();
+        output::write_json(&mut message, stamp, m, &self.columns)?;
+        self.kafka.send(&self.key, &message);
+        Ok(())
+    }
+
+    fn derived(&mut self, stamp: &Stamp, derived: &Derived) -> io::Result<()> {
+        let mut message = Vec::new();
+        output::write_json_derived(&mut message, stamp, derived)?;
+        self.kafka.send(&self.key, &message);
+        Ok(())
+    }
+}
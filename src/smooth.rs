@@ -0,0 +1,197 @@
+//! `--smooth FILE`, smoothed versions of the reading logged next to it as derived values, so the
+//! raw readings stay as they were and the smoothed ones are traces of their own in `report` and
+//! on the dashboard. The file has a `[[stage]]` for each trace:
+//!
+//! ```toml
+//! [[stage]]
+//! name = "Despiked"
+//! kind = "mad"          # outliers replaced by the median of the window
+//! window = 15
+//! threshold = 3.5
+//!
+//! [[stage]]
+//! name = "Smoothed"
+//! kind = "ema"
+//! tau = "2s"
+//! from = "Despiked"     # the stage before's output rather than the reading
+//! ```
+//!
+//! Without a `name` it's named after what it does, e.g. `Median of 5`. `median` is the median of
+//! the last `window` readings (5 if not given). `ema` is an exponential moving average with the
+//! time constant `tau` (1 s), worked out from the time between readings so it's the same at any
+//! `--interval`. `mad` passes readings through unless they're more than
+//! `threshold` (3) scaled median absolute deviations from the median of the last `window` (15),
+//! which are replaced by that median. Values are in the unit without a prefix, overloads are
+//! skipped, and every stage starts over when the mode, unit or REL changes.
+
+use crate::clock::Stamp;
+use crate::output::Derived;
+use crate::config::{Setting, Value, sections};
+use crate::stats;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+enum Kind {
+    Median { window: usize },
+    Ema { tau: f64 },
+    Mad { window: usize, threshold: f64 },
+}
+
+struct Stage {
+    /// Leaked once when the file's loaded, derived names are `&'static str`
+    name: &'static str,
+    /// An earlier stage this one smooths instead of the reading
+    from: Option<usize>,
+    kind: Kind,
+    recent: VecDeque<f64>,
+    /// `recent` copied out to be sorted for a median, kept so that doesn't allocate each time
+    sorted: Vec<f64>,
+    /// For `ema`, when the last input was and where the average is
+    average: Option<(f64, f64)>,
+}
+
+impl Stage {
+    fn step(&mut self, elapsed: f64, input: f64) -> f64 {
+        match self.kind {
+            Kind::Median { window } => {
+                push(&mut self.recent, window, input);
+                stats::median(self.sorted_recent())
+            }
+            Kind::Ema { tau } => {
+                let average = match self.average {
+                    Some((last, average)) => average + (1.0 - (-(elapsed - last).max(0.0) / tau).exp()) * (input - average),
+                    None => input,
+                };
+                self.average = Some((elapsed, average));
+                average
+            }
+            Kind::Mad { window, threshold } => {
+                push(&mut self.recent, window, input);
+                // Too few to tell an outlier from the rest
+                if self.recent.len() < 3 {
+                    return input;
+                }
+                let middle = stats::median(self.sorted_recent());
+                let sigma = stats::robust_sigma(self.sorted_recent(), middle);
+                if sigma > 0.0 && (input - middle).abs() > threshold * sigma { middle } else { input }
+            }
+        }
+    }
+
+    fn sorted_recent(&mut self) -> &mut [f64] {
+        self.sorted.clear();
+        self.sorted.extend(&self.recent);
+        &mut self.sorted
+    }
+}
+
+fn push(recent: &mut VecDeque<f64>, window: usize, value: f64) {
+    recent.push_back(value);
+    while recent.len() > window {
+        recent.pop_front();
+    }
+}
+
+pub struct Smooth {
+    stages: Vec<Stage>,
+    /// Mode, unit and REL the stages have been fed so far
+    baseline: Option<(&'static str, &'static str, bool)>,
+}
+
+impl Smooth {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        parse(&text).map_err(|e| format!("{} {}", path.display(), e))
+    }
+
+    /// Every stage's output for this reading
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> Vec<Derived> {
+        let scaled = m.reading_in(Units::Si);
+        let Some(value) = scaled.reading.value() else {
+            return Vec::new();
+        };
+        let baseline = (m.mode_name(), scaled.unit, m.rel);
+        if self.baseline.replace(baseline) != Some(baseline) {
+            for stage in &mut self.stages {
+                stage.recent.clear();
+                stage.average = None;
+            }
+        }
+        let elapsed = stamp.elapsed.as_secs_f64();
+        let mut outputs: Vec<f64> = Vec::with_capacity(self.stages.len());
+        for stage in &mut self.stages {
+            let input = stage.from.map_or(value, |i| outputs[i]);
+            outputs.push(stage.step(elapsed, input));
+        }
+        self.stages.iter().zip(outputs).map(|(stage, value)| Derived { name: stage.name, value, unit: scaled.unit }).collect()
+    }
+}
+
+fn parse(text: &str) -> Result<Smooth, String> {
+    let mut stages: Vec<Stage> = Vec::new();
+    for section in sections(text, "stage")? {
+        if let Some(Setting { key, line, .. }) = section.settings.iter().find(|s| !matches!(s.key.as_str(), "name" | "kind" | "window" | "tau" | "threshold" | "from")) {
+            return Err(format!("line {}: unknown setting {:?}", line, key));
+        }
+        let error = |e: String| format!("line {}: {}", section.start, e);
+        // The last one counts if a key is given twice
+        let setting = |key: &str| section.settings.iter().rev().find(|s| s.key == key).map(|s| &s.value);
+        let text = |key: &str| -> Result<Option<&str>, String> {
+            match setting(key) {
+                Some(Value::Text(text)) => Ok(Some(text)),
+                Some(Value::Number(_)) => Err(error(format!("{} has to be a string", key))),
+                None => Ok(None),
+            }
+        };
+        let number = |key: &str| -> Result<Option<f64>, String> {
+            match setting(key) {
+                Some(Value::Number(number)) if *number > 0.0 => Ok(Some(*number)),
+                Some(_) => Err(error(format!("{} has to be a number above 0", key))),
+                None => Ok(None),
+            }
+        };
+        let window = |default: usize| -> Result<usize, String> {
+            match number("window")? {
+                Some(window) if window.fract() == 0.0 => Ok(window as usize),
+                Some(_) => Err(error("window is a number of readings".to_owned())),
+                None => Ok(default),
+            }
+        };
+        let kind = match text("kind")? {
+            Some("median") => Kind::Median { window: window(5)? },
+            Some("ema") => {
+                let tau = match setting("tau") {
+                    Some(Value::Text(tau)) => crate::clock::parse_duration(tau).filter(|tau| !tau.is_zero()).map(|tau| tau.as_secs_f64()).ok_or_else(|| error(format!("bad tau {:?}, expected e.g. \"2s\"", tau)))?,
+                    Some(_) => number("tau")?.unwrap_or(1.0),
+                    None => 1.0,
+                };
+                Kind::Ema { tau }
+            }
+            Some("mad") => Kind::Mad { window: window(15)?, threshold: number("threshold")?.unwrap_or(3.0) },
+            Some(other) => return Err(error(format!("no kind of stage called {:?}, there's median, ema and mad", other))),
+            None => return Err(error("the stage has no kind".to_owned())),
+        };
+        let name = match (text("name")?, &kind) {
+            (Some(name), _) if !name.trim().is_empty() => name.to_owned(),
+            (_, Kind::Median { window }) => format!("Median of {}", window),
+            (_, Kind::Ema { tau }) => format!("Average over {} s", tau),
+            (_, Kind::Mad { .. }) => "Despiked".to_owned(),
+        };
+        if stages.iter().any(|stage| stage.name == name) {
+            return Err(error(format!("there's already a stage called {}", name)));
+        }
+        let from = match text("from")? {
+            Some(from) => Some(stages.iter().position(|stage| stage.name == from).ok_or_else(|| error(format!("from {:?} has to be a stage above this one", from)))?),
+            None => None,
+        };
+        let window = match kind {
+            Kind::Median { window } | Kind::Mad { window, .. } => window,
+            Kind::Ema { .. } => 0,
+        };
+        let (recent, sorted) = (VecDeque::with_capacity(window + 1), Vec::with_capacity(window));
+        stages.push(Stage { name: Box::leak(name.into_boxed_str()), from, kind, recent, sorted, average: None });
+    }
+    Ok(Smooth { stages, baseline: None })
+}
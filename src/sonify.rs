@@ -0,0 +1,116 @@
+//! Turning the live value into a tone, so a drifting voltage can be heard while your eyes and
+//! hands are on the circuit. Higher value, higher pitch, spaced exponentially so equal steps in
+//! value sound like equal musical intervals.
+
+use clap::ValueEnum;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Scale {
+    /// Any pitch, hears the smallest change
+    Continuous,
+    /// Snapped to semitones
+    Chromatic,
+    /// Snapped to a major pentatonic scale, easier on the ears for long runs
+    Pentatonic,
+}
+
+/// How the value maps to pitch
+pub struct Mapping {
+    /// Value range, `None` for 0 to the full scale of the current range (by magnitude)
+    pub values: Option<(f64, f64)>,
+    /// Frequencies in Hz for the bottom and top of the value range
+    pub pitch: (f32, f32),
+    pub scale: Scale,
+}
+
+const PENTATONIC: [f32; 5] = [0.0, 2.0, 4.0, 7.0, 9.0];
+
+impl Mapping {
+    pub fn frequency(&self, value: f64, full_scale: Option<f64>) -> Option<f32> {
+        let t = match (self.values, full_scale) {
+            (Some((low, high)), _) => (value - low) / (high - low),
+            (None, Some(full_scale)) => value.abs() / full_scale,
+            (None, None) => return None,
+        };
+        let (low, high) = self.pitch;
+        let semitones = 12.0 * (high / low).log2() * t.clamp(0.0, 1.0) as f32;
+        let semitones = match self.scale {
+            Scale::Continuous => semitones,
+            Scale::Chromatic => semitones.round(),
+            Scale::Pentatonic => {
+                let octave = (semitones / 12.0).floor();
+                let within = semitones - 12.0 * octave;
+                let nearest = PENTATONIC
+                    .iter()
+                    .chain(&[12.0])
+                    .min_by(|a, b| (*a - within).abs().total_cmp(&(*b - within).abs()))
+                    .unwrap();
+                12.0 * octave + nearest
+            }
+        };
+        Some(low * 2f32.powf(semitones / 12.0))
+    }
+}
+
+/// A sine on the default output device. Kept alive for as long as it should play.
+pub struct Sonify {
+    _stream: cpal::Stream,
+    /// f32 bits of the target frequency, 0 for silence
+    frequency: Arc<AtomicU32>,
+}
+
+impl Sonify {
+    pub fn start() -> Result<Self, Box<dyn std::error::Error>> {
+        let device = cpal::default_host().default_output_device().ok_or("no audio output device")?;
+        let config = device.default_output_config()?;
+        let frequency = Arc::new(AtomicU32::new(0));
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build::<f32>(&device, &config.into(), frequency.clone())?,
+            cpal::SampleFormat::I16 => build::<i16>(&device, &config.into(), frequency.clone())?,
+            cpal::SampleFormat::U16 => build::<u16>(&device, &config.into(), frequency.clone())?,
+            format => return Err(format!("unsupported sample format {}", format).into()),
+        };
+        stream.play()?;
+        Ok(Sonify { _stream: stream, frequency })
+    }
+
+    /// `None` goes quiet, e.g. for overloads and gaps
+    pub fn set(&self, frequency: Option<f32>) {
+        self.frequency.store(frequency.unwrap_or(0.0).to_bits(), Ordering::Relaxed);
+    }
+}
+
+fn build<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    target: Arc<AtomicU32>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let rate = config.sample_rate.0 as f32;
+    let channels = config.channels as usize;
+    let mut phase = 0.0f32;
+    let mut frequency = 0.0f32;
+    let mut amplitude = 0.0f32;
+    // About 20 ms to glide between pitches and fade in/out, so changes don't click
+    let smoothing = 1.0 - (-1.0 / (0.02 * rate)).exp();
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let target = f32::from_bits(target.load(Ordering::Relaxed));
+            let (target_frequency, target_amplitude) = if target > 0.0 { (target, 0.2) } else { (frequency, 0.0) };
+            for frame in data.chunks_mut(channels) {
+                frequency += (target_frequency - frequency) * smoothing;
+                amplitude += (target_amplitude - amplitude) * smoothing;
+                phase = (phase + frequency / rate) % 1.0;
+                let sample = T::from_sample((phase * TAU).sin() * amplitude);
+                frame.fill(sample);
+            }
+        },
+        |e| eprintln!("Audio output error: {}", e),
+        None,
+    )
+}
@@ -0,0 +1,133 @@
+//! `--despike 6`, for single readings that are nothing to do with the input (contact bounce, a
+//! relay switching next to the leads) and would otherwise be the min or max of the whole run. A
+//! reading more than that many standard deviations from the median of the last few is held back
+//! for one poll: if the next one is back in line it was a spike, if it's out there too the input
+//! really moved and the held reading goes out late, in order, as any other.
+//!
+//! Spikes are left out of the logger's own min, max and mean and the limit test, and logged as a
+//! `Spike` derived value with the reading as the meter showed it, so nothing's hidden. With
+//! `--despike 6,remove` that's all there is of them, otherwise the reading is written as usual
+//! too. The standard deviation is worked out from the median absolute deviation, so the spikes
+//! don't inflate it, and is never taken as less than one count of the range. Values are compared
+//! in the unit without a prefix, so a spike the meter autoranged for is still one, and it all
+//! starts over when the mode, unit or REL changes.
+
+use crate::clock::Stamp;
+use crate::output::Derived;
+use crate::stats;
+use std::collections::VecDeque;
+use ut61e_plus_logger::protocol::{self, Measurement, Units};
+
+/// Readings the median and deviation are taken over
+const WINDOW: usize = 15;
+
+/// Fewer than this and nothing is a spike yet
+const ENOUGH: usize = 5;
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub sigmas: f64,
+    pub remove: bool,
+}
+
+impl Settings {
+    /// For clap, `6` or `6,remove`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (sigmas, remove) = match s.split_once(',') {
+            Some((sigmas, "remove")) => (sigmas, true),
+            Some(_) => return Err(format!("expected e.g. 6 or 6,remove, not {:?}", s)),
+            None => (s, false),
+        };
+        let sigmas = sigmas.trim().parse().ok().filter(|&n: &f64| n > 0.0 && n.is_finite()).ok_or_else(|| format!("expected a number of standard deviations like 6, got {:?}", sigmas))?;
+        Ok(Settings { sigmas, remove })
+    }
+}
+
+/// What became of a reading held back the poll before
+pub enum Held {
+    Spike(Stamp, Measurement),
+    /// The input moved, it's a reading like any other
+    Step(Stamp, Measurement),
+}
+
+/// What's logged for a spike
+pub fn derived(m: &Measurement) -> Option<Derived> {
+    let scaled = m.reading_in(Units::Si);
+    Some(Derived { name: "Spike", value: scaled.reading.value()?, unit: scaled.unit })
+}
+
+pub struct Despike {
+    pub settings: Settings,
+    /// Mode, unit and REL the readings in `recent` were in
+    key: Option<(&'static str, &'static str, bool)>,
+    recent: VecDeque<f64>,
+    held: Option<(Stamp, Measurement)>,
+}
+
+impl Despike {
+    pub fn new(settings: Settings) -> Self {
+        Despike { settings, key: None, recent: VecDeque::new(), held: None }
+    }
+
+    /// What became of the reading held back from the poll before, if there was one, and whether
+    /// this one is held back in turn
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> (Option<Held>, bool) {
+        let scaled = m.reading_in(Units::Si);
+        let key = (m.mode_name(), scaled.unit, m.rel);
+        let value = scaled.reading.value();
+        if self.key.replace(key) != Some(key) || value.is_none() {
+            // Nothing to compare either with, so the held one counts
+            self.recent.clear();
+            let held = self.held.take().map(|(stamp, m)| Held::Step(stamp, m));
+            self.push(value);
+            return (held, false);
+        }
+        let value = value.unwrap_or_default();
+        let resolution = m.range_info().map_or(0.0, |range| range.resolution() * protocol::without_prefix(range.unit).map_or(1.0, |(exp, _)| 10f64.powi(exp)));
+        let out_of_line = self.recent.len() >= ENOUGH && {
+            let mut sorted = [0.0; WINDOW];
+            let sorted = &mut sorted[..self.recent.len()];
+            for (to, from) in sorted.iter_mut().zip(&self.recent) {
+                *to = *from;
+            }
+            let middle = stats::median(sorted);
+            let sigma = stats::robust_sigma(sorted, middle).max(resolution);
+            (value - middle).abs() > self.settings.sigmas * sigma
+        };
+        match (self.held.take(), out_of_line) {
+            (Some((held_stamp, held)), false) => {
+                self.push(Some(value));
+                (Some(Held::Spike(held_stamp, held)), false)
+            }
+            // Two in a row out there is the input, start over from them
+            (Some((held_stamp, held)), true) => {
+                self.recent.clear();
+                self.push(held.reading_in(Units::Si).reading.value());
+                self.push(Some(value));
+                (Some(Held::Step(held_stamp, held)), false)
+            }
+            (None, true) => {
+                self.held = Some((*stamp, *m));
+                (None, true)
+            }
+            (None, false) => {
+                self.push(Some(value));
+                (None, false)
+            }
+        }
+    }
+
+    /// A reading still held back when logging stops, which there's nothing to judge against
+    pub fn take_held(&mut self) -> Option<(Stamp, Measurement)> {
+        self.held.take()
+    }
+
+    fn push(&mut self, value: Option<f64>) {
+        if let Some(value) = value {
+            self.recent.push_back(value);
+            if self.recent.len() > WINDOW {
+                self.recent.pop_front();
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! `--history` within a memory budget. Once the in-memory [`History`] has more than the budget
+//! allows, its oldest entries go to temporary files in chunks and are read back when asked for,
+//! so a day of readings stays available on a small machine. Chunks older than the history
+//! window are deleted along with their file.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use ut61e_plus_logger::history::History;
+
+/// Entries moved to disk at a time
+const CHUNK: usize = 4096;
+
+/// For naming the files, unique within the process
+static CHUNKS_CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// Something that can be written to disk as `SIZE` bytes and read back
+pub trait Spill: Sized {
+    const SIZE: usize;
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(bytes: &[u8]) -> Self;
+}
+
+struct Chunk {
+    first: Duration,
+    last: Duration,
+    file: File,
+}
+
+impl Chunk {
+    /// Removed from the directory straight away on Unix, so nothing is left behind however the
+    /// logger stops. Elsewhere it stays in the temp directory.
+    fn create<T: Spill>(entries: &[(Duration, T)]) -> io::Result<Self> {
+        let n = CHUNKS_CREATED.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("ut61e_plus-{}-{}.history", std::process::id(), n));
+        let mut file = File::options().read(true).write(true).create_new(true).open(&path)?;
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&path);
+        let mut bytes = Vec::with_capacity(entries.len() * (8 + T::SIZE));
+        for (time, entry) in entries {
+            bytes.extend_from_slice(&(time.as_nanos() as u64).to_le_bytes());
+            entry.write(&mut bytes);
+        }
+        file.write_all(&bytes)?;
+        Ok(Chunk { first: entries[0].0, last: entries[entries.len() - 1].0, file })
+    }
+
+    fn read<T: Spill>(&self) -> io::Result<Vec<(Duration, T)>> {
+        let mut file = &self.file;
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes
+            .chunks_exact(8 + T::SIZE)
+            .map(|record| {
+                let nanos = u64::from_le_bytes(record[..8].try_into().unwrap());
+                (Duration::from_nanos(nanos), T::read(&record[8..]))
+            })
+            .collect())
+    }
+}
+
+pub struct Store<T> {
+    memory: History<T>,
+    spilled: VecDeque<Chunk>,
+    keep: Duration,
+    /// The budget as a number of entries
+    max_in_memory: usize,
+    /// Set once writing to disk has failed, after that the oldest entries are just dropped
+    failed: bool,
+}
+
+impl<T: Spill + Clone> Store<T> {
+    /// Keeps `keep` worth of entries, with about `budget` bytes of them in memory
+    pub fn new(keep: Duration, budget: usize) -> Self {
+        Store {
+            memory: History::new(keep),
+            spilled: VecDeque::new(),
+            keep,
+            max_in_memory: (budget / std::mem::size_of::<(Duration, T)>()).max(2 * CHUNK),
+            failed: false,
+        }
+    }
+
+    pub fn push(&mut self, time: Duration, entry: T) {
+        self.memory.push(time, entry);
+        while self.spilled.front().is_some_and(|chunk| time.saturating_sub(chunk.last) > self.keep) {
+            self.spilled.pop_front();
+        }
+        if self.memory.len() > self.max_in_memory {
+            let oldest: Vec<_> = self.memory.take_oldest(CHUNK).collect();
+            if self.failed {
+                return;
+            }
+            match Chunk::create(&oldest) {
+                Ok(chunk) => self.spilled.push_back(chunk),
+                Err(e) => {
+                    eprintln!("Couldn't move history to disk, dropping the oldest instead: {}", e);
+                    self.failed = true;
+                }
+            }
+        }
+    }
+
+    /// Entries with their times in `range`, oldest first
+    pub fn range(&self, range: impl RangeBounds<Duration>) -> Vec<(Duration, T)> {
+        let mut entries = Vec::new();
+        for chunk in &self.spilled {
+            if overlaps(&range, chunk) {
+                entries.extend(self.read(chunk).into_iter().filter(|(time, _)| range.contains(time)));
+            }
+        }
+        entries.extend(self.memory.range(range).cloned());
+        entries
+    }
+
+    /// The newest `n` entries, oldest first
+    pub fn latest(&self, n: usize) -> Vec<(Duration, T)> {
+        let mut entries: Vec<_> = self.memory.latest(n).cloned().collect();
+        for chunk in self.spilled.iter().rev() {
+            if entries.len() >= n {
+                break;
+            }
+            let older = self.read(chunk);
+            let wanted = older.len().min(n - entries.len());
+            entries.splice(0..0, older[older.len() - wanted..].iter().cloned());
+        }
+        entries
+    }
+
+    /// A chunk that can't be read back is left out rather than failing the whole request
+    fn read(&self, chunk: &Chunk) -> Vec<(Duration, T)> {
+        chunk.read().unwrap_or_else(|e| {
+            eprintln!("Couldn't read history back from disk: {}", e);
+            Vec::new()
+        })
+    }
+}
+
+fn overlaps(range: &impl RangeBounds<Duration>, chunk: &Chunk) -> bool {
+    let ends_after_start = match range.start_bound() {
+        Bound::Included(t) => chunk.last >= *t,
+        Bound::Excluded(t) => chunk.last > *t,
+        Bound::Unbounded => true,
+    };
+    let starts_before_end = match range.end_bound() {
+        Bound::Included(t) => chunk.first <= *t,
+        Bound::Excluded(t) => chunk.first < *t,
+        Bound::Unbounded => true,
+    };
+    ends_after_start && starts_before_end
+}
@@ -0,0 +1,203 @@
+//! Min, max and mean kept by the logger itself, whatever the meter's own MIN/MAX button is doing,
+//! and separately for each mode since volts and ohms don't mix. Values are in the unit without
+//! a prefix, so a range change doesn't start them over. Overloads and dashes aren't counted.
+//! The min and max come with when they happened and the range the meter was on, to find them in
+//! the capture again.
+
+use crate::clock::Stamp;
+use ut61e_plus_logger::protocol::{Measurement, RangeInfo, Units};
+
+/// The lowest or highest value and where it was
+#[derive(Clone, Copy)]
+pub struct Peak {
+    pub value: f64,
+    pub stamp: Stamp,
+    pub range: Option<RangeInfo>,
+    pub auto_range: bool,
+}
+
+impl Peak {
+    fn of(value: f64, stamp: &Stamp, m: &Measurement) -> Self {
+        Peak { value, stamp: *stamp, range: m.range_info(), auto_range: m.auto_range }
+    }
+
+    /// `22.000 V range, auto`
+    pub fn context(&self) -> String {
+        let range = self.range.map_or_else(|| "unknown range".to_owned(), |r| format!("{} range", r));
+        if self.auto_range { format!("{}, auto", range) } else { range }
+    }
+}
+
+/// What's been seen in one mode since logging started or the last reset
+#[derive(Clone, Copy)]
+pub struct Tracked {
+    pub mode: &'static str,
+    pub unit: &'static str,
+    pub min: Peak,
+    pub max: Peak,
+    sum: f64,
+    pub count: u64,
+}
+
+impl Tracked {
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+/// To 6 significant digits, the mean of 0.1 and 0.2 as 0.15 rather than 0.15000000000000002
+pub fn significant(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return value.to_string();
+    }
+    let decimals = 5 - value.abs().log10().floor() as i32;
+    let factor = 10f64.powi(decimals);
+    ((value * factor).round() / factor).to_string()
+}
+
+/// The MAD of normally distributed values times this is their standard deviation
+const MAD_TO_SIGMA: f64 = 1.4826;
+
+/// The middle value, or the mean of the two middle ones. NaN for none. Sorts `values` where
+/// they are, so the poll loop can keep reusing one buffer for it.
+pub fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    match values.len() {
+        0 => f64::NAN,
+        n if n % 2 == 1 => values[n / 2],
+        n => (values[n / 2 - 1] + values[n / 2]) / 2.0,
+    }
+}
+
+/// Standard deviation of `values` from their median absolute deviation from `middle`, which a
+/// few outliers hardly move, for `--smooth`'s `mad` stage and `--despike`. Overwrites `values`
+/// with the deviations.
+pub fn robust_sigma(values: &mut [f64], middle: f64) -> f64 {
+    for value in values.iter_mut() {
+        *value = (*value - middle).abs();
+    }
+    median(values) * MAD_TO_SIGMA
+}
+
+#[derive(Default)]
+pub struct Stats {
+    /// In the order the modes were first seen
+    modes: Vec<Tracked>,
+    /// The mode of the latest reading, an index into `modes`
+    current: Option<usize>,
+}
+
+impl Stats {
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) {
+        let scaled = m.reading_in(Units::Si);
+        let Some(value) = scaled.reading.value() else {
+            return;
+        };
+        let (mode, unit) = (m.mode_name(), scaled.unit);
+        let i = match self.modes.iter().position(|t| t.mode == mode && t.unit == unit) {
+            Some(i) => i,
+            None => {
+                let peak = Peak::of(value, stamp, m);
+                self.modes.push(Tracked { mode, unit, min: peak, max: peak, sum: 0.0, count: 0 });
+                self.modes.len() - 1
+            }
+        };
+        let tracked = &mut self.modes[i];
+        // The first time it got there
+        if value < tracked.min.value {
+            tracked.min = Peak::of(value, stamp, m);
+        }
+        if value > tracked.max.value {
+            tracked.max = Peak::of(value, stamp, m);
+        }
+        tracked.sum += value;
+        tracked.count += 1;
+        self.current = Some(i);
+    }
+
+    /// Starts over, for every mode
+    pub fn reset(&mut self) {
+        *self = Stats::default();
+    }
+
+    /// Starts over for the mode and unit of `m`, leaving the others, so `m` is the first one
+    /// counted on the new baseline. That needn't be the mode of the reading before.
+    pub fn restart(&mut self, m: &Measurement) {
+        let (mode, unit) = (m.mode_name(), m.reading_in(Units::Si).unit);
+        let Some(i) = self.modes.iter().position(|t| t.mode == mode && t.unit == unit) else {
+            return;
+        };
+        self.modes.remove(i);
+        self.current = match self.current {
+            Some(current) if current == i => None,
+            Some(current) if current > i => Some(current - 1),
+            current => current,
+        };
+    }
+
+    /// For the mode the meter is in, if anything's been counted since the last reset
+    pub fn current(&self) -> Option<&Tracked> {
+        self.current.map(|i| &self.modes[i])
+    }
+
+    pub fn all(&self) -> &[Tracked] {
+        &self.modes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn reading(mode: u8, display: &[u8; 7], rel: bool) -> Measurement {
+        Measurement {
+            mode,
+            range: 0x30,
+            display: *display,
+            bar: [0; 2],
+            rel,
+            hold: false,
+            min: false,
+            max: false,
+            auto_range: true,
+            low_battery: false,
+            hv_warning: false,
+            dc: true,
+            peak_max: false,
+            peak_min: false,
+        }
+    }
+
+    fn stamp(secs: u64) -> Stamp {
+        Stamp { wall: SystemTime::UNIX_EPOCH, elapsed: Duration::from_secs(secs), seq: secs }
+    }
+
+    const V_DC: u8 = 2;
+    const OHMS: u8 = 6;
+
+    #[test]
+    fn restart_is_for_the_new_readings_mode() {
+        let mut stats = Stats::default();
+        stats.sample(&stamp(1), &reading(OHMS, b" 100.00", false));
+        stats.sample(&stamp(2), &reading(V_DC, b" 1.0000", false));
+        stats.sample(&stamp(3), &reading(V_DC, b" 2.0000", false));
+        // Into ohms with REL on in the same reading, the volts were on a baseline of their own
+        let rel = reading(OHMS, b" 000.05", true);
+        stats.restart(&rel);
+        stats.sample(&stamp(4), &rel);
+        let volts = stats.all().iter().find(|t| t.mode == "V_DC").expect("volts kept");
+        assert_eq!((volts.count, volts.min.value, volts.max.value), (2, 1.0, 2.0));
+        let ohms = stats.current().expect("ohms counted again");
+        assert_eq!((ohms.mode, ohms.count, ohms.max.value), (rel.mode_name(), 1, 0.05));
+    }
+
+    #[test]
+    fn restart_keeps_current_pointing_at_its_mode() {
+        let mut stats = Stats::default();
+        stats.sample(&stamp(1), &reading(OHMS, b" 100.00", false));
+        stats.sample(&stamp(2), &reading(V_DC, b" 1.0000", false));
+        stats.restart(&reading(OHMS, b" 000.05", true));
+        assert_eq!(stats.current().map(|t| (t.mode, t.count)), Some(("V_DC", 1)));
+    }
+}
@@ -0,0 +1,236 @@
+//! Counts of what happened during a run, printed to stderr when logging stops.
+
+use crate::clock::{Stamp, Utc};
+use crate::i18n::tr;
+use crate::output::Limits;
+use crate::stats::{self, Stats};
+use colored::*;
+use std::time::{Duration, Instant};
+use ut61e_plus_logger::protocol::Measurement;
+
+/// Why a poll didn't produce a measurement
+#[derive(Clone, Copy, Debug)]
+pub enum Error {
+    /// The meter didn't answer in time
+    Timeout,
+    BadChecksum,
+    /// A frame arrived but couldn't be found or decoded
+    Parse,
+    /// The HID read or write itself failed, even after retrying
+    Read,
+}
+
+pub struct Summary {
+    started: Instant,
+    pub samples: u64,
+    pub timeouts: u64,
+    pub bad_checksums: u64,
+    pub parse_errors: u64,
+    pub read_errors: u64,
+    pub reconnects: u64,
+    /// Errors since the last good measurement
+    pub consecutive: u32,
+    /// To tell new readings from the meter apart from the same one polled again
+    last: Option<Measurement>,
+    repeats: u64,
+    changes: u64,
+    first_change: Option<Instant>,
+    last_change: Option<Instant>,
+    /// How long the logger means to wait between polls right now
+    pub interval: Duration,
+    /// When the last poll started, None after a deliberate pause
+    last_poll: Option<Instant>,
+    /// Display updates that went by unseen because polls came late (host load, a slow disk)
+    missed: u64,
+    longest_poll_gap: Duration,
+    /// Min, max and mean of the values, by mode
+    pub stats: Stats,
+    /// For a final verdict
+    pub limits: Option<Limits>,
+    passed: u64,
+    failed: u64,
+}
+
+/// Fewer samples than this say nothing about the cadence
+const CADENCE_MIN_SAMPLES: u64 = 20;
+
+/// The UT61E+ updates its display about 3 times a second
+const METER_UPDATE: Duration = Duration::from_millis(333);
+
+impl Summary {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            samples: 0,
+            timeouts: 0,
+            bad_checksums: 0,
+            parse_errors: 0,
+            read_errors: 0,
+            reconnects: 0,
+            consecutive: 0,
+            last: None,
+            repeats: 0,
+            changes: 0,
+            first_change: None,
+            last_change: None,
+            interval: Duration::ZERO,
+            last_poll: None,
+            missed: 0,
+            longest_poll_gap: Duration::ZERO,
+            stats: Stats::default(),
+            limits: None,
+            passed: 0,
+            failed: 0,
+        }
+    }
+
+    pub fn sample(&mut self, stamp: &Stamp, m: &Measurement) {
+        self.samples += 1;
+        self.consecutive = 0;
+        self.stats.sample(stamp, m);
+        match self.limits.map(|limits| limits.pass(m)) {
+            Some(true) => self.passed += 1,
+            Some(false) => self.failed += 1,
+            None => {}
+        }
+        if self.last == Some(*m) {
+            self.repeats += 1;
+        } else {
+            let now = Instant::now();
+            self.changes += 1;
+            self.first_change.get_or_insert(now);
+            self.last_change = Some(now);
+            self.last = Some(*m);
+        }
+    }
+
+    /// Call as each poll starts. Returns how many meter updates were missed since the last one
+    /// and how far apart the two were, if the logger fell behind.
+    pub fn poll(&mut self) -> Option<(u64, Duration)> {
+        let now = Instant::now();
+        let last = self.last_poll.replace(now)?;
+        // After a timeout the late start is the meter's doing, that's counted as an error
+        if self.consecutive > 0 {
+            return None;
+        }
+        let apart = now.duration_since(last);
+        // Polling slower than the meter updates skips some on purpose, only the extra counts
+        let step = self.interval.max(METER_UPDATE);
+        let missed = (apart.as_secs_f64() / step.as_secs_f64()) as u64;
+        if missed < 2 {
+            return None;
+        }
+        self.missed += missed - 1;
+        self.longest_poll_gap = self.longest_poll_gap.max(apart);
+        Some((missed - 1, apart))
+    }
+
+    /// Nothing is expected until the next poll: waiting for a scheduled burst or reconnecting
+    pub fn pause(&mut self) {
+        self.last_poll = None;
+    }
+
+    pub fn error(&mut self, error: Error) {
+        match error {
+            Error::Timeout => self.timeouts += 1,
+            Error::BadChecksum => self.bad_checksums += 1,
+            Error::Parse => self.parse_errors += 1,
+            Error::Read => self.read_errors += 1,
+        }
+        self.consecutive += 1;
+    }
+
+    /// With limits, whether any reading was outside them, or there were none to test
+    pub fn failed(&self) -> bool {
+        self.limits.is_some() && (self.failed > 0 || self.passed == 0)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.timeouts + self.bad_checksums + self.parse_errors + self.read_errors
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "{}",
+            tr!(
+                "{} samples in {} s, {} errors ({} timeouts, {} bad checksums, {} parse errors, {} read errors), {} reconnects",
+                self.samples,
+                format!("{:.1}", self.started.elapsed().as_secs_f64()),
+                self.errors(),
+                self.timeouts,
+                self.bad_checksums,
+                self.parse_errors,
+                self.read_errors,
+                self.reconnects
+            )
+        );
+        if self.missed > 0 {
+            eprintln!(
+                "{}",
+                tr!(
+                    "{} meter updates missed because polls came late, up to {} s apart",
+                    self.missed,
+                    format!("{:.1}", self.longest_poll_gap.as_secs_f64())
+                )
+            );
+        }
+        for tracked in self.stats.all() {
+            eprintln!("{}", describe(tracked));
+            eprintln!("  {}", peaks(tracked));
+        }
+        if let Some(cadence) = self.cadence() {
+            eprintln!("{}", cadence);
+        }
+        if self.limits.is_some() {
+            let total = self.passed + self.failed;
+            match self.failed() {
+                false => eprintln!("{}", tr!("PASS: all {} readings within the limits", total).bold().green()),
+                true if total == 0 => eprintln!("{}", tr!("FAIL: no readings to test").bold().red()),
+                true => eprintln!("{}", tr!("FAIL: {} of {} readings outside the limits", self.failed, total).bold().red()),
+            }
+        }
+    }
+
+    /// How often the meter actually came up with a new reading compared to how often it was
+    /// asked, and whether that's a waste or missing updates
+    fn cadence(&self) -> Option<String> {
+        if self.samples < CADENCE_MIN_SAMPLES {
+            return None;
+        }
+        let poll_rate = self.samples as f64 / self.started.elapsed().as_secs_f64();
+        let span = self.last_change?.duration_since(self.first_change?).as_secs_f64();
+        let update_rate = if span > 0.0 { (self.changes - 1) as f64 / span } else { 0.0 };
+        let repeats = self.repeats as f64 / self.samples as f64;
+        let mut line = tr!(
+            "Polled {} times/s, the reading changed {} times/s ({}% repeats)",
+            format!("{:.1}", poll_rate),
+            format!("{:.1}", update_rate),
+            format!("{:.0}", 100.0 * repeats)
+        );
+        if repeats > 0.5 && update_rate > 0.0 {
+            line.push_str(&tr!(". Oversampling unless the input was steady, --interval {}ms would keep up", format!("{:.0}", 1000.0 / update_rate)));
+        } else if repeats < 0.05 {
+            line.push_str(tr!(". Every reading was new, the meter may be updating faster than it's polled, try a shorter --interval"));
+        }
+        Some(line)
+    }
+}
+
+/// `V_DC: min 0.1 V, max 0.5 V, mean 0.3 V over 120 readings`
+pub fn describe(tracked: &stats::Tracked) -> String {
+    let unit = tracked.unit;
+    tr!(
+        "{}: min {}, max {}, mean {} over {} readings",
+        tracked.mode,
+        format!("{} {}", tracked.min.value, unit),
+        format!("{} {}", tracked.max.value, unit),
+        format!("{} {}", stats::significant(tracked.mean()), unit),
+        tracked.count
+    )
+}
+
+/// When the min and max were, `min at 2024-05-01T12:00:01.000Z (12.3 s in, 22.000 V range, auto), max at ...`
+pub fn peaks(tracked: &stats::Tracked) -> String {
+    let at = |peak: &stats::Peak| format!("{} ({:.1} s, {})", Utc(peak.stamp.wall), peak.stamp.elapsed.as_secs_f64(), peak.context());
+    tr!("min at {}, max at {}", at(&tracked.min), at(&tracked.max))
+}
@@ -0,0 +1,189 @@
+//! `ut61e_plus sweep`, a diode's or LED's forward voltage at one current after another, for
+//! matching parts or a rough I-V curve. The meter is on diode mode or volts across the part, and
+//! the current is either set by hand on a supply (`--current 1m,2m,5m`, asked for one step at a
+//! time) or read off a second meter in series with its own logger (`--current-from`). Without
+//! either every step is just the meter's diode test current, one part after another, each with a
+//! note to say which.
+//!
+//! The data is a CSV with a row per step, and `--plot` draws it as an SVG: current against
+//! voltage when there are currents, the voltage of each step otherwise.
+
+use crate::follow::Follow;
+use crate::i18n::tr;
+use crate::meter::Meter;
+use crate::procedure::{Capture, ask, read_for};
+use crate::stats::significant;
+use colored::*;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+use ut61e_plus_logger::protocol::Units;
+
+/// A reading of the current is too old to go with the voltage after this long
+const STALE: Duration = Duration::from_secs(2);
+
+pub struct Options<'a> {
+    pub currents: &'a [f64],
+    pub current_from: Option<&'a str>,
+    pub duration: Duration,
+    pub output: Option<&'a Path>,
+    pub plot: Option<&'a Path>,
+}
+
+struct Row {
+    step: usize,
+    note: String,
+    /// A, as set or the mean of the second meter
+    current: Option<f64>,
+    /// V, or whatever the meter was on
+    mean: f64,
+    min: f64,
+    max: f64,
+    unit: &'static str,
+    readings: usize,
+}
+
+/// Amps with an optional m or u (µ works too), `20m`
+pub fn amps(s: &str) -> Result<f64, String> {
+    let (number, scale) = match s.char_indices().last() {
+        Some((i, 'm')) => (&s[..i], 1e-3),
+        Some((i, 'u' | 'µ' | 'μ')) => (&s[..i], 1e-6),
+        _ => (s, 1.0),
+    };
+    number.parse::<f64>().ok().filter(|a| a.is_finite()).map(|a| a * scale).ok_or_else(|| format!("{:?} isn't a current like 20m", s))
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+pub fn run(options: &Options, meter: &mut Meter, interval: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let follow = options.current_from.map(Follow::start);
+    let mut rows: Vec<Row> = Vec::new();
+    let mut step = 0;
+    loop {
+        let set = options.currents.get(step).copied();
+        if !options.currents.is_empty() && set.is_none() {
+            break;
+        }
+        step += 1;
+        let heading = match set {
+            Some(amps) => tr!("Step {} of {}: set the current to {} A", step, options.currents.len(), significant(amps)),
+            None => tr!("Step {}", step),
+        };
+        eprintln!("\n{}", heading.bold());
+        let note = ask(tr!("Enter to measure or type a note first, s to skip, q to stop: "))?;
+        match note.to_lowercase().as_str() {
+            "s" => continue,
+            "q" => break,
+            _ => {}
+        }
+        let (mut values, mut currents, mut unit) = (Vec::new(), Vec::new(), "");
+        read_for(meter, Capture::For(options.duration), interval, |m| {
+            let scaled = m.reading_in(Units::Si);
+            unit = scaled.unit;
+            values.extend(scaled.reading.value());
+            if let Some(latest) = follow.as_ref().and_then(|follow| follow.latest(STALE))
+                && latest.unit == "A"
+            {
+                currents.push(latest.value);
+            }
+        })?;
+        let (Some(mean_v), Some(min), Some(max)) = (mean(&values), values.iter().copied().reduce(f64::min), values.iter().copied().reduce(f64::max)) else {
+            eprintln!("{}", tr!("No reading, open or OL? Skipped.").red());
+            continue;
+        };
+        let current = set.or(mean(&currents));
+        if follow.is_some() && current.is_none() {
+            eprintln!("{}", tr!("No current in A from the other meter").yellow());
+        }
+        let at = current.map_or(String::new(), |amps| format!(" at {} A", significant(amps)));
+        eprintln!("{}", format!("{} {}{}", significant(mean_v), unit, at).green().bold());
+        rows.push(Row { step, note, current, mean: mean_v, min, max, unit, readings: values.len() });
+    }
+
+    let mut out: Box<dyn Write> = match options.output {
+        Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    writeln!(out, "step,note,current,value,unit,min,max,readings")?;
+    for row in &rows {
+        let current = row.current.map_or(String::new(), significant);
+        writeln!(out, "{},{},{},{},{},{},{},{}", row.step, row.note.replace(',', ";"), current, significant(row.mean), row.unit, significant(row.min), significant(row.max), row.readings)?;
+    }
+    out.flush()?;
+    if let Some(path) = options.plot {
+        fs::write(path, svg(&rows))?;
+        eprintln!("{}", tr!("Plot written to {}", path.display()));
+    }
+    Ok(())
+}
+
+const PLOT_W: f64 = 600.0;
+const PLOT_H: f64 = 400.0;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Low and high for an axis, a bit apart if there's only one value
+fn bounds(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (lo, hi) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    match hi > lo {
+        true => (lo, hi),
+        false if lo.is_finite() => (lo - 1.0, hi + 1.0),
+        false => (0.0, 1.0),
+    }
+}
+
+/// Current against voltage if every step has a current, each step's voltage in turn otherwise,
+/// with the notes next to the points
+fn svg(rows: &[Row]) -> String {
+    let iv = !rows.is_empty() && rows.iter().all(|row| row.current.is_some());
+    let unit = rows.first().map_or("V", |row| row.unit);
+    let point = |row: &Row| match iv {
+        true => (row.mean, row.current.unwrap_or_default()),
+        false => (row.step as f64, row.mean),
+    };
+    let (x_lo, x_hi) = bounds(rows.iter().map(|row| point(row).0));
+    let (y_lo, y_hi) = bounds(rows.iter().map(|row| point(row).1));
+    let x = |v: f64| (v - x_lo) / (x_hi - x_lo) * PLOT_W;
+    let y = |v: f64| PLOT_H - (v - y_lo) / (y_hi - y_lo) * PLOT_H;
+    let (x_unit, y_unit) = if iv { (unit, "A") } else { ("step", unit) };
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-80 -10 {} {}\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\">",
+        PLOT_W + 100.0,
+        PLOT_H + 50.0,
+        PLOT_W + 100.0,
+        PLOT_H + 50.0
+    );
+    let _ = write!(svg, "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#ccc\"/>", PLOT_W, PLOT_H);
+    for (v, at) in [(y_hi, 0.0), (y_lo, PLOT_H)] {
+        let _ = write!(svg, "<text x=\"-5\" y=\"{}\" text-anchor=\"end\" font-size=\"12\">{} {}</text>", at + 4.0, significant(v), escape(y_unit));
+    }
+    for (v, at, anchor) in [(x_lo, 0.0, "start"), (x_hi, PLOT_W, "end")] {
+        let _ = write!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"{}\" font-size=\"12\">{} {}</text>", at, PLOT_H + 18.0, anchor, significant(v), escape(x_unit));
+    }
+    let mut points: Vec<(f64, f64, &Row)> = rows.iter().map(|row| (point(row).0, point(row).1, row)).collect();
+    if iv {
+        points.sort_by(|a, b| a.1.total_cmp(&b.1));
+        svg.push_str("<polyline fill=\"none\" stroke=\"#1f77b4\" stroke-width=\"1\" points=\"");
+        for (px, py, _) in &points {
+            let _ = write!(svg, "{:.1},{:.1} ", x(*px), y(*py));
+        }
+        svg.push_str("\"/>");
+    }
+    for (px, py, row) in &points {
+        let _ = write!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"#1f77b4\"/>", x(*px), y(*py));
+        if !row.note.is_empty() {
+            let _ = write!(svg, "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"11\">{}</text>", x(*px) + 5.0, y(*py) - 5.0, escape(&row.note));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
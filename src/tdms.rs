@@ -0,0 +1,203 @@
+//! `-o capture.tdms`, for LabVIEW and DIAdem. One group for the meter with a channel of values for
+//! each mode the meter was on (`unit_string` set, which DIAdem picks up as the unit) and a channel
+//! of timestamps next to each, `V_DC` and `V_DC time`, since readings don't come at a fixed rate.
+//! Events go in `Events` with `Events time`. The session is in the file's properties.
+//!
+//! Written as a single segment when logging stops, like the other array formats (see
+//! export.rs). The layout is TDMS 2.0 as NI describes it in "TDMS File Format Internal Structure".
+
+use crate::clock::{Stamp, Utc};
+use crate::output::{FORMAT_VERSION, Session};
+use crate::sink::Sink;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+const TDS_STRING: u32 = 0x20;
+const TDS_DOUBLE: u32 = 0x0A;
+const TDS_TIMESTAMP: u32 = 0x44;
+/// Seconds from 1904-01-01, where LabVIEW counts time from, to 1970-01-01
+const LABVIEW_EPOCH: i64 = 2_082_844_800;
+
+struct Channel {
+    mode: &'static str,
+    unit: &'static str,
+    times: Vec<SystemTime>,
+    values: Vec<f64>,
+}
+
+pub struct Tdms {
+    out: BufWriter<File>,
+    session: Session,
+    units: Units,
+    channels: Vec<Channel>,
+    event_times: Vec<SystemTime>,
+    events: Vec<String>,
+}
+
+impl Tdms {
+    pub fn create(path: &Path, units: Units, session: Session) -> io::Result<Self> {
+        let out = BufWriter::new(File::create(path)?);
+        Ok(Tdms { out, session, units, channels: Vec::new(), event_times: Vec::new(), events: Vec::new() })
+    }
+}
+
+impl Sink for Tdms {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        let scaled = m.reading_in(self.units);
+        let (mode, unit) = (m.mode_name(), scaled.unit);
+        let channel = match self.channels.iter().position(|c| c.mode == mode && c.unit == unit) {
+            Some(i) => &mut self.channels[i],
+            None => {
+                self.channels.push(Channel { mode, unit, times: Vec::new(), values: Vec::new() });
+                self.channels.last_mut().expect("just pushed")
+            }
+        };
+        channel.times.push(stamp.wall);
+        channel.values.push(scaled.reading.value().unwrap_or(f64::NAN));
+        Ok(())
+    }
+
+    fn event(&mut self, stamp: &Stamp, message: &str) -> io::Result<()> {
+        self.event_times.push(stamp.wall);
+        self.events.push(message.to_owned());
+        Ok(())
+    }
+
+    fn gap(&mut self, stamp: &Stamp, reason: &'static str) -> io::Result<()> {
+        self.event(stamp, &format!("gap: {}", reason))
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        let segment = self.segment();
+        self.out.write_all(&segment)?;
+        self.out.flush()
+    }
+}
+
+fn string(out: &mut Vec<u8>, s: &str) {
+    out.extend((s.len() as u32).to_le_bytes());
+    out.extend(s.as_bytes());
+}
+
+/// `/'group'/'channel'`, quotes doubled
+fn path(names: &[&str]) -> String {
+    names.iter().map(|name| format!("/'{}'", name.replace('\'', "''"))).collect()
+}
+
+fn timestamp(out: &mut Vec<u8>, time: SystemTime) {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    // Fractions of a second in units of 2^-64
+    let fraction = ((u128::from(since.subsec_nanos()) << 64) / 1_000_000_000) as u64;
+    out.extend(fraction.to_le_bytes());
+    out.extend((since.as_secs() as i64 + LABVIEW_EPOCH).to_le_bytes());
+}
+
+/// An object with its properties, and where its raw data is in the segment if it has any
+struct Object {
+    path: String,
+    properties: Vec<(&'static str, String)>,
+    /// Data type, number of values and the data
+    raw: Option<(u32, usize, Vec<u8>)>,
+}
+
+impl Tdms {
+    fn objects(&self) -> Vec<Object> {
+        let group = match self.session.meter.as_str() {
+            "no serial number" => "UT61E+".to_owned(),
+            serial => format!("UT61E+ {}", serial),
+        };
+        let mut objects = vec![
+            Object {
+                path: "/".to_owned(),
+                properties: vec![
+                    ("name", "UT61E+ capture".to_owned()),
+                    ("author", format!("ut61e_plus_logger {}", env!("CARGO_PKG_VERSION"))),
+                    ("description", self.session.command.clone()),
+                    ("started", Utc(self.session.started).to_string()),
+                    ("format_version", FORMAT_VERSION.to_string()),
+                ],
+                raw: None,
+            },
+            Object { path: path(&[&group]), properties: vec![("meter", self.session.meter.clone())], raw: None },
+        ];
+        let times = |times: &[SystemTime]| {
+            let mut raw = Vec::with_capacity(times.len() * 16);
+            times.iter().for_each(|&time| timestamp(&mut raw, time));
+            Some((TDS_TIMESTAMP, times.len(), raw))
+        };
+        let mut names: Vec<String> = Vec::new();
+        for channel in &self.channels {
+            // The same mode in another unit, after a range change without --units si
+            let name = match names.iter().any(|name| name == channel.mode) {
+                true => format!("{} ({})", channel.mode, channel.unit),
+                false => channel.mode.to_owned(),
+            };
+            let values = channel.values.iter().flat_map(|v| v.to_le_bytes()).collect();
+            objects.push(Object {
+                path: path(&[&group, &name]),
+                properties: vec![("unit_string", channel.unit.to_owned()), ("mode", channel.mode.to_owned())],
+                raw: Some((TDS_DOUBLE, channel.values.len(), values)),
+            });
+            objects.push(Object { path: path(&[&group, &format!("{} time", name)]), properties: Vec::new(), raw: times(&channel.times) });
+            names.push(name);
+        }
+        if !self.events.is_empty() {
+            // Strings are the offsets of where each ends, then all of them one after another
+            let mut raw = Vec::new();
+            let mut end = 0;
+            for event in &self.events {
+                end += event.len() as u32;
+                raw.extend(end.to_le_bytes());
+            }
+            self.events.iter().for_each(|event| raw.extend(event.as_bytes()));
+            objects.push(Object { path: path(&[&group, "Events"]), properties: Vec::new(), raw: Some((TDS_STRING, self.events.len(), raw)) });
+            objects.push(Object { path: path(&[&group, "Events time"]), properties: Vec::new(), raw: times(&self.event_times) });
+        }
+        objects
+    }
+
+    /// Lead in, metadata and raw data
+    fn segment(&self) -> Vec<u8> {
+        let objects = self.objects();
+        let mut meta = Vec::new();
+        let mut data = Vec::new();
+        meta.extend((objects.len() as u32).to_le_bytes());
+        for object in &objects {
+            string(&mut meta, &object.path);
+            match &object.raw {
+                None => meta.extend(u32::MAX.to_le_bytes()),
+                Some((data_type, count, raw)) => {
+                    let strings = *data_type == TDS_STRING;
+                    meta.extend(if strings { 28u32 } else { 20 }.to_le_bytes());
+                    meta.extend(data_type.to_le_bytes());
+                    meta.extend(1u32.to_le_bytes());
+                    meta.extend((*count as u64).to_le_bytes());
+                    if strings {
+                        meta.extend((raw.len() as u64).to_le_bytes());
+                    }
+                    data.extend_from_slice(raw);
+                }
+            }
+            meta.extend((object.properties.len() as u32).to_le_bytes());
+            for (name, value) in &object.properties {
+                string(&mut meta, name);
+                meta.extend(TDS_STRING.to_le_bytes());
+                string(&mut meta, value);
+            }
+        }
+
+        // Metadata, a new list of objects and raw data
+        const TOC: u32 = 1 << 1 | 1 << 2 | 1 << 3;
+        let mut segment = b"TDSm".to_vec();
+        segment.extend(TOC.to_le_bytes());
+        segment.extend(4713u32.to_le_bytes());
+        segment.extend(((meta.len() + data.len()) as u64).to_le_bytes());
+        segment.extend((meta.len() as u64).to_le_bytes());
+        segment.extend(meta);
+        segment.extend(data);
+        segment
+    }
+}
@@ -0,0 +1,141 @@
+//! Min/max summaries of a series at every zoom level, built up as readings arrive, the way audio
+//! editors draw waveforms. A plot of a day (half a million readings) then needs a few hundred
+//! tiles whatever the zoom, instead of going through every reading each time it's drawn.
+//!
+//! Level 0 tiles cover [`FANOUT`] readings each, level 1 tiles cover [`FANOUT`] level 0 tiles,
+//! and so on.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeBounds};
+use core::time::Duration;
+
+pub const FANOUT: usize = 16;
+
+/// Enough for years of readings at the meter's rate
+const MAX_LEVELS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    /// Time of the first and last reading covered
+    pub start: Duration,
+    pub end: Duration,
+    /// NaN if none of the readings were numbers (overloads, gaps)
+    pub min: f64,
+    pub max: f64,
+    /// Readings that were numbers
+    pub count: u32,
+}
+
+impl Tile {
+    fn merge(&mut self, other: &Tile) {
+        self.end = other.end;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+    }
+}
+
+#[derive(Default)]
+struct Level {
+    done: VecDeque<Tile>,
+    /// The tile being filled and how many readings or tiles from the level below are in it
+    partial: Option<(Tile, usize)>,
+}
+
+impl Level {
+    /// Adds a reading or a finished tile from the level below, returning this level's tile if
+    /// that finished it
+    fn add(&mut self, tile: Tile) -> Option<Tile> {
+        let (partial, filled) = self.partial.get_or_insert((Tile { count: 0, min: f64::NAN, max: f64::NAN, ..tile }, 0));
+        partial.merge(&tile);
+        *filled += 1;
+        if *filled < FANOUT {
+            return None;
+        }
+        let (done, _) = self.partial.take()?;
+        self.done.push_back(done);
+        Some(done)
+    }
+
+    /// The tiles overlapping `range`, the one still being filled included
+    fn overlapping(&self, range: &impl RangeBounds<Duration>) -> impl Iterator<Item = &Tile> {
+        let from = match range.start_bound() {
+            Bound::Included(t) | Bound::Excluded(t) => self.done.partition_point(|tile| tile.end < *t),
+            Bound::Unbounded => 0,
+        };
+        let to = match range.end_bound() {
+            Bound::Included(t) | Bound::Excluded(t) => self.done.partition_point(|tile| tile.start <= *t),
+            Bound::Unbounded => self.done.len(),
+        };
+        let partial = self.partial.as_ref().map(|(tile, _)| tile).filter(|tile| overlaps(range, tile));
+        self.done.range(from..to.max(from)).chain(partial)
+    }
+}
+
+fn overlaps(range: &impl RangeBounds<Duration>, tile: &Tile) -> bool {
+    let after_start = match range.start_bound() {
+        Bound::Included(t) | Bound::Excluded(t) => tile.end >= *t,
+        Bound::Unbounded => true,
+    };
+    let before_end = match range.end_bound() {
+        Bound::Included(t) | Bound::Excluded(t) => tile.start <= *t,
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+#[derive(Default)]
+pub struct Tiles {
+    levels: Vec<Level>,
+}
+
+impl Tiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a reading, `None` for ones without a number. Times have to come in order.
+    pub fn push(&mut self, time: Duration, value: Option<f64>) {
+        let value = value.filter(|v| v.is_finite());
+        let mut tile = Some(Tile {
+            start: time,
+            end: time,
+            min: value.unwrap_or(f64::NAN),
+            max: value.unwrap_or(f64::NAN),
+            count: value.is_some() as u32,
+        });
+        for level in 0..MAX_LEVELS {
+            let Some(finished) = tile else {
+                break;
+            };
+            if level == self.levels.len() {
+                self.levels.push(Level::default());
+            }
+            tile = self.levels[level].add(finished);
+        }
+    }
+
+    /// Drops tiles that end before `time`, to keep up with a history that forgets old readings
+    pub fn forget_before(&mut self, time: Duration) {
+        for level in &mut self.levels {
+            while level.done.front().is_some_and(|tile| tile.end < time) {
+                level.done.pop_front();
+            }
+        }
+    }
+
+    /// The finest tiles covering `range` that come to at most `points`, oldest first. The
+    /// coarsest level there is if even that has more.
+    pub fn query(&self, range: impl RangeBounds<Duration>, points: usize) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for level in &self.levels {
+            tiles.clear();
+            tiles.extend(level.overlapping(&range).copied());
+            if tiles.len() <= points {
+                break;
+            }
+        }
+        tiles
+    }
+}
@@ -0,0 +1,46 @@
+//! Sending datagrams (JSON measurements, OSC messages) to one host, a broadcast address or a
+//! multicast group. Fire and forget: nobody listening is not an error.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+pub struct Udp {
+    socket: UdpSocket,
+    target: SocketAddr,
+    /// So a dead network doesn't print an error three times a second
+    warned: bool,
+}
+
+impl Udp {
+    /// `target` is `host:port`, e.g. `192.168.1.255:5005` or `239.1.2.3:5005`
+    pub fn connect(target: &str) -> io::Result<Self> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} didn't resolve", target)))?;
+        let socket = match target {
+            SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+            SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+        };
+        if let SocketAddr::V4(v4) = target {
+            // Needed for broadcast addresses, harmless for anything else
+            socket.set_broadcast(true)?;
+            if v4.ip().is_multicast() {
+                // Stay on the lab network unless the routers are set up for more
+                socket.set_multicast_ttl_v4(1)?;
+            }
+        }
+        Ok(Udp { socket, target, warned: false })
+    }
+
+    pub fn send(&mut self, datagram: &[u8]) {
+        match self.socket.send_to(datagram, self.target) {
+            Ok(_) => self.warned = false,
+            Err(e) if !self.warned => {
+                eprintln!("UDP send to {} failed: {}", self.target, e);
+                self.warned = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
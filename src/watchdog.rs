@@ -0,0 +1,47 @@
+//! Notices when the meter has stopped producing valid frames even though the device is still
+//! there (reads time out or come back garbled) and decides how hard to kick it.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    Nothing,
+    /// First try, re-send the UART setup and carry on polling
+    Reinit,
+    /// Re-initialising didn't help, close and reopen the device
+    Reopen,
+}
+
+pub struct Watchdog {
+    timeout: Duration,
+    last_valid: Instant,
+    reinit_tried: bool,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration) -> Self {
+        Watchdog { timeout, last_valid: Instant::now(), reinit_tried: false }
+    }
+
+    /// Call for every valid frame
+    pub fn feed(&mut self) {
+        self.last_valid = Instant::now();
+        self.reinit_tried = false;
+    }
+
+    /// Call once per poll, escalates one step every `timeout` without a valid frame
+    pub fn check(&mut self) -> Action {
+        if self.last_valid.elapsed() < self.timeout {
+            return Action::Nothing;
+        }
+        // Give each recovery step a full timeout to work before escalating
+        self.last_valid = Instant::now();
+        if self.reinit_tried {
+            self.reinit_tried = false;
+            Action::Reopen
+        } else {
+            self.reinit_tried = true;
+            Action::Reinit
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! `-o capture.wav`, the readings as a sound file so Audacity, sox and friends can be pointed at
+//! slow wobbles: spectrograms of a mains voltage over a night, filters, looking for a 0.1 Hz
+//! oscillation by ear after speeding it up. The meter answers at an uneven few times a second, so
+//! the readings are interpolated onto an even grid at the rate they actually came in (rounded to
+//! whole Hz, at least 1 Hz, which is all WAV can say).
+//!
+//! The samples are 32-bit float and the values as they are, in the unit without a prefix, so a
+//! 230 V mains reading is 230.0 and not squeezed into ±1. Only the mode the meter was on first is
+//! used. OL holds the value before it. Written when logging stops, with what it is in the comment.
+
+use crate::clock::{Stamp, Utc};
+use crate::output::Session;
+use crate::sink::Sink;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use ut61e_plus_logger::protocol::{Measurement, Units};
+
+/// WAVE_FORMAT_IEEE_FLOAT
+const IEEE_FLOAT: u16 = 3;
+
+pub struct Wav {
+    out: BufWriter<File>,
+    session: Session,
+    /// The mode and unit of the first reading
+    series: Option<(&'static str, &'static str)>,
+    /// (elapsed s, value)
+    readings: Vec<(f64, f64)>,
+}
+
+impl Wav {
+    pub fn create(path: &Path, session: Session) -> io::Result<Self> {
+        let out = BufWriter::new(File::create(path)?);
+        Ok(Wav { out, session, series: None, readings: Vec::new() })
+    }
+
+    /// The readings on an even grid and its rate
+    fn resampled(&self) -> (u32, Vec<f32>) {
+        let (Some(&(first, _)), Some(&(last, _))) = (self.readings.first(), self.readings.last()) else {
+            return (1, Vec::new());
+        };
+        let duration = last - first;
+        if duration <= 0.0 {
+            return (1, self.readings.iter().map(|&(_, v)| v as f32).collect());
+        }
+        let rate = ((self.readings.len() - 1) as f64 / duration).round().max(1.0);
+        let count = (duration * rate) as usize + 1;
+        let mut samples = Vec::with_capacity(count);
+        let mut i = 0;
+        for k in 0..count {
+            let t = first + k as f64 / rate;
+            while i + 2 < self.readings.len() && self.readings[i + 1].0 < t {
+                i += 1;
+            }
+            let (t0, v0) = self.readings[i];
+            let (t1, v1) = self.readings[(i + 1).min(self.readings.len() - 1)];
+            let v = if t1 > t0 { v0 + (v1 - v0) * ((t - t0) / (t1 - t0)).clamp(0.0, 1.0) } else { v0 };
+            samples.push(v as f32);
+        }
+        (rate as u32, samples)
+    }
+}
+
+impl Sink for Wav {
+    fn sample(&mut self, stamp: &Stamp, m: &Measurement) -> io::Result<()> {
+        let scaled = m.reading_in(Units::Si);
+        let series = *self.series.get_or_insert((m.mode_name(), scaled.unit));
+        if series != (m.mode_name(), scaled.unit) {
+            return Ok(());
+        }
+        let value = scaled.reading.value().or(self.readings.last().map(|&(_, v)| v));
+        if let Some(value) = value {
+            self.readings.push((stamp.elapsed.as_secs_f64(), value));
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        let (rate, samples) = self.resampled();
+        let (mode, unit) = self.series.unwrap_or(("nothing", ""));
+        let comment = format!("{} in {} from {} readings, started {}", mode, unit, self.readings.len(), Utc(self.session.started));
+        let software = format!("ut61e_plus_logger {}", env!("CARGO_PKG_VERSION"));
+
+        let mut info = b"INFO".to_vec();
+        for (id, text) in [(b"ICMT", comment), (b"ISFT", software)] {
+            let mut text = text.into_bytes();
+            text.push(0);
+            info.extend(id);
+            info.extend((text.len() as u32).to_le_bytes());
+            info.extend(&text);
+            if text.len() % 2 == 1 {
+                info.push(0);
+            }
+        }
+        let data_len = samples.len() as u32 * 4;
+        let riff_len = 4 + (8 + 18) + (8 + 4) + (8 + info.len() as u32) + (8 + data_len);
+
+        let out = &mut self.out;
+        out.write_all(b"RIFF")?;
+        out.write_all(&riff_len.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&18u32.to_le_bytes())?;
+        out.write_all(&IEEE_FLOAT.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // mono
+        out.write_all(&rate.to_le_bytes())?;
+        out.write_all(&(rate * 4).to_le_bytes())?; // bytes a second
+        out.write_all(&4u16.to_le_bytes())?; // block align
+        out.write_all(&32u16.to_le_bytes())?; // bits
+        out.write_all(&0u16.to_le_bytes())?; // no extension
+        out.write_all(b"fact")?;
+        out.write_all(&4u32.to_le_bytes())?;
+        out.write_all(&(samples.len() as u32).to_le_bytes())?;
+        out.write_all(b"LIST")?;
+        out.write_all(&(info.len() as u32).to_le_bytes())?;
+        out.write_all(&info)?;
+        out.write_all(b"data")?;
+        out.write_all(&data_len.to_le_bytes())?;
+        for sample in samples {
+            out.write_all(&sample.to_le_bytes())?;
+        }
+        out.flush()
+    }
+}
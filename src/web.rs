@@ -0,0 +1,783 @@
+//! `--web`, a dashboard any browser on the network can open without installing anything. The
+//! page is built in and served at `/`, and gets its data from a WebSocket at `/ws` that streams
+//! every reading as the same JSON `--udp` sends, plus `{"gap":"reason"}` when the meter stops
+//! answering, `{"event":"message"}` for notes and other events and, about once a second,
+//! `{"stats":{"mode":..,"unit":..,"min":..,"max":..,"mean":..,"count":..,"min_at":..,"max_at":..}}`
+//! with the logger's own figures for the mode the meter is in (`null` just after a reset), the
+//! `_at`s being `{"time":..,"elapsed":..,"range":..}` for when the min and max were, and
+//! `{"alarms":[{"name":..,"severity":..,"state":..}]}` with every alarm and whether it's `clear`,
+//! `on`, `latched` or `done` (see alarm.rs). Derived values (`--smooth`, `--power-with` and the
+//! like) come as `--format json` has them, right after the reading they go with. With `--web-control` and `--macros` a page starts
+//! with `{"macros":[..]}`, the names of the macros it can run. Anything else that speaks WebSocket
+//! can use the stream too.
+//!
+//! With `--history`, readings and gaps from the last while are kept and served as a JSON array
+//! from `/history`: `?from=60&to=120` for those between 60 and 120 s after logging started
+//! (`elapsed`), `?last=100` for the newest 100, or everything without either. Past
+//! `--history-memory` the older part of it is kept on disk, see spill.rs.
+//!
+//! `/tiles?from=60&to=3600&points=500` summarises the values over the same range as at most 500
+//! min/max tiles (tiles.rs in the library), for plotting hours of readings at a glance. Each
+//! tile covers at least 16 readings, zoomed in further than that `/history` has them all.
+//!
+//! `--web-config FILE` is where the dashboard's settings go, in a `[[web]]` section:
+//!
+//! ```toml
+//! [[web]]
+//! token = "b3f9c2e07d4a"   # letters, digits and -._~
+//! ```
+//!
+//! With a `token` every request needs it, as `Authorization: Bearer <token>` or
+//! `?token=<token>`. The page opened with `?token=` gets it back as a cookie, so its own requests
+//! for `/ws` and `/history` carry it without the token showing up in them. There's no TLS, put a
+//! reverse proxy in front for https; the page switches to `wss://` by itself.
+//!
+//! With `--web-control` the capture can be run from elsewhere too, with the commands in
+//! control.rs as the body of `POST /control` or as text messages on `/ws`. Commands that don't
+//! parse get a 400, or `{"error":"message"}` back on the WebSocket.
+//!
+//! With `--scpi` as well, `/lxi` is an LXI style identification page (make, model, serial and
+//! the VISA resource for the SCPI socket) and `/lxi/identification` the same as XML, for
+//! instrument managers.
+
+use crate::alarm::Status;
+use crate::clock::{Stamp, Utc};
+use crate::control::Command as Control;
+use crate::output::{self, Columns, Derived, write_json_str};
+use crate::config::{Setting, Value, sections};
+use crate::scpi::Identity;
+use crate::spill::{Spill, Store};
+use crate::stats::{self, Tracked};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use std::{fs, thread};
+use ut61e_plus_logger::protocol::{MEASUREMENT_LEN, Measurement, Units};
+use ut61e_plus_logger::tiles::Tiles;
+
+const PAGE: &str = include_str!("web.html");
+
+/// From RFC 6455, appended to the client's key for the handshake
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// A browser that stops reading gets dropped rather than holding up logging
+const WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// For `/tiles` without `points`
+const DEFAULT_POINTS: usize = 500;
+
+/// Where the page keeps the token it was opened with
+const TOKEN_COOKIE: &str = "ut61e_token";
+
+/// Longer commands than this aren't commands
+const MAX_COMMAND: usize = 4096;
+
+/// The `[[web]]` section of `--web-config`
+#[derive(Default)]
+pub struct Config {
+    pub token: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{} {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        let [section] = &sections(text, "web")?[..] else {
+            return Err("has more than one [[web]]".to_owned());
+        };
+        for Setting { key, value, line } in &section.settings {
+            let error = |e: String| format!("line {}: {}", line, e);
+            match (key.as_str(), value) {
+                ("token", Value::Text(token)) if !token.is_empty() && token.bytes().all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b)) => {
+                    config.token = Some(token.clone())
+                }
+                ("token", _) => return Err(error("the token has to be a string of letters, digits and -._~".to_owned())),
+                _ => return Err(error(format!("unknown setting {:?}", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// What `/history` keeps
+#[derive(Clone, Copy)]
+enum Record {
+    Sample(Measurement),
+    Gap(&'static str),
+}
+
+/// Gaps are written to disk as an index into this, anything else comes back as just "gap"
+const GAP_REASONS: [&str; 5] = ["no response", "parse error", "reconnect", "schedule", "stopped"];
+
+/// seq, wall clock and elapsed nanoseconds, then 0 and the payload for a sample or the gap
+/// reason's index + 1
+impl Spill for (Stamp, Record) {
+    const SIZE: usize = 8 + 8 + 8 + 1 + MEASUREMENT_LEN;
+
+    fn write(&self, out: &mut Vec<u8>) {
+        let (stamp, record) = self;
+        let wall = stamp.wall.duration_since(UNIX_EPOCH).unwrap_or_default();
+        out.extend_from_slice(&stamp.seq.to_le_bytes());
+        out.extend_from_slice(&(wall.as_nanos() as u64).to_le_bytes());
+        out.extend_from_slice(&(stamp.elapsed.as_nanos() as u64).to_le_bytes());
+        match record {
+            Record::Sample(m) => {
+                out.push(0);
+                out.extend_from_slice(&m.encode());
+            }
+            Record::Gap(reason) => {
+                out.push(GAP_REASONS.iter().position(|r| r == reason).map_or(u8::MAX, |i| i as u8 + 1));
+                out.extend_from_slice(&[0; MEASUREMENT_LEN]);
+            }
+        }
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        let stamp = Stamp {
+            seq: u64_at(0),
+            wall: UNIX_EPOCH + Duration::from_nanos(u64_at(8)),
+            elapsed: Duration::from_nanos(u64_at(16)),
+        };
+        let record = match bytes[24] {
+            0 => Measurement::decode(&bytes[25..]).map_or(Record::Gap("gap"), Record::Sample),
+            i => Record::Gap(GAP_REASONS.get(i as usize - 1).copied().unwrap_or("gap")),
+        };
+        (stamp, record)
+    }
+}
+
+type Shared<T> = Arc<Mutex<T>>;
+
+/// Set up after the server started, for whoever asks
+#[derive(Default)]
+struct Known {
+    /// For `/lxi`, once there's a SCPI server to tell about
+    identity: Option<Identity>,
+    /// The `--macros`, for the dashboard's buttons
+    macros: Vec<String>,
+}
+
+/// What `--history` keeps
+struct Kept {
+    records: Store<(Stamp, Record)>,
+    tiles: Tiles,
+    keep: Duration,
+    /// The tiles are in the same units as the JSON
+    units: Units,
+}
+
+impl Kept {
+    fn push(&mut self, stamp: &Stamp, record: Record) {
+        self.records.push(stamp.elapsed, (*stamp, record));
+        if let Record::Sample(m) = record {
+            self.tiles.push(stamp.elapsed, m.reading_in(self.units).reading.value());
+        }
+        self.tiles.forget_before(stamp.elapsed.saturating_sub(self.keep));
+    }
+}
+
+pub struct Web {
+    clients: Shared<Vec<TcpStream>>,
+    history: Option<Shared<Kept>>,
+    columns: Columns,
+    addr: SocketAddr,
+    token: Option<Arc<str>>,
+    known: Shared<Known>,
+}
+
+impl Web {
+    /// Starts listening on `addr`, e.g. `0.0.0.0:8080`, with every connection on its own thread.
+    /// `history` is how far back `/history` goes, if at all, and how many bytes of that to keep
+    /// in memory. `token`, if any, is what every request has to come with, and `control` is where
+    /// commands go if they're taken.
+    pub fn serve(
+        addr: impl ToSocketAddrs,
+        columns: Columns,
+        history: Option<(Duration, usize)>,
+        token: Option<String>,
+        control: Option<Sender<Control>>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        eprintln!("Dashboard at http://{}/", addr);
+        let web = Web {
+            clients: Arc::new(Mutex::new(Vec::new())),
+            history: history.map(|(keep, budget)| {
+                Arc::new(Mutex::new(Kept { records: Store::new(keep, budget), tiles: Tiles::new(), keep, units: columns.units }))
+            }),
+            columns,
+            addr,
+            token: token.map(Arc::from),
+            known: Arc::new(Mutex::new(Known::default())),
+        };
+        let (clients, history, token, known) = (web.clients.clone(), web.history.clone(), web.token.clone(), web.known.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (clients, history, token, control, known) = (clients.clone(), history.clone(), token.clone(), control.clone(), known.clone());
+                thread::spawn(move || {
+                    let _ = handle(stream, &clients, history.as_deref(), &columns, token.as_deref(), control.as_ref(), &known);
+                });
+            }
+        });
+        Ok(web)
+    }
+
+    /// Where it ended up listening, with the port filled in if it was 0
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Serves `/lxi` for the SCPI server with this identity
+    pub fn identify(&self, identity: Identity) {
+        self.known.lock().unwrap().identity = Some(identity);
+    }
+
+    /// Buttons on the dashboard for these `--macros`, sent to each page as it connects
+    pub fn offer_macros(&self, names: Vec<String>) {
+        self.known.lock().unwrap().macros = names;
+    }
+
+    /// Opens the dashboard's kiosk layout fullscreen in a browser, without tabs, address bar or
+    /// window borders, for a touchscreen on the bench. Chromium first, it's what Raspberry Pi OS
+    /// comes with.
+    pub fn kiosk(&self) -> io::Result<()> {
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(Ipv4Addr::LOCALHOST.into());
+        }
+        let mut url = format!("http://{}/?kiosk", addr);
+        if let Some(token) = &self.token {
+            url.push_str(&format!("&token={}", token));
+        }
+        let chromium: &[&str] = &["--kiosk", "--noerrdialogs", "--disable-infobars"];
+        for (browser, flags) in [("chromium-browser", chromium), ("chromium", chromium), ("google-chrome", chromium), ("firefox", &["--kiosk"])] {
+            match Command::new(browser).args(flags).arg(&url).stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("no Chromium or Firefox found, open {} yourself", url)))
+    }
+
+    /// Sends one JSON message to every connected WebSocket
+    fn send(&self, json: &[u8]) {
+        let json = json.strip_suffix(b"\n").unwrap_or(json);
+        let frame = frame(json);
+        self.clients.lock().unwrap().retain_mut(|client| client.write_all(&frame).is_ok());
+    }
+
+    fn record(&self, stamp: &Stamp, record: Record) {
+        let mut json = Vec::new();
+        let _ = write_record(&mut json, stamp, &record, &self.columns);
+        self.send(&json);
+        if let Some(history) = &self.history {
+            history.lock().unwrap().push(stamp, record);
+        }
+    }
+
+    pub fn sample(&self, stamp: &Stamp, m: &Measurement) {
+        self.record(stamp, Record::Sample(*m));
+    }
+
+    pub fn gap(&self, stamp: &Stamp, reason: &'static str) {
+        self.record(stamp, Record::Gap(reason));
+    }
+
+    pub fn stats(&self, current: Option<&Tracked>) {
+        let mut json = b"{\"stats\":".to_vec();
+        match current {
+            Some(t) => {
+                json.extend_from_slice(b"{\"mode\":");
+                let _ = write_json_str(&mut json, t.mode);
+                json.extend_from_slice(b",\"unit\":");
+                let _ = write_json_str(&mut json, t.unit);
+                let _ = write!(json, ",\"min\":{},\"max\":{},\"mean\":{},\"count\":{}", t.min.value, t.max.value, stats::significant(t.mean()), t.count);
+                for (name, peak) in [("min_at", &t.min), ("max_at", &t.max)] {
+                    let _ = write!(json, ",\"{}\":{{\"time\":\"{}\",\"elapsed\":{:.3},\"range\":", name, Utc(peak.stamp.wall), peak.stamp.elapsed.as_secs_f64());
+                    let _ = write_json_str(&mut json, &peak.context());
+                    json.push(b'}');
+                }
+                json.push(b'}');
+            }
+            None => json.extend_from_slice(b"null"),
+        }
+        json.push(b'}');
+        self.send(&json);
+    }
+
+    pub fn alarms(&self, statuses: &[Status]) {
+        let mut json = b"{\"alarms\":[".to_vec();
+        for (i, status) in statuses.iter().enumerate() {
+            json.extend_from_slice(if i == 0 { b"{\"name\":" } else { b",{\"name\":" });
+            let _ = write_json_str(&mut json, &status.name);
+            let _ = write!(json, ",\"severity\":\"{}\",\"state\":\"{}\"}}", status.severity, status.state);
+        }
+        json.extend_from_slice(b"]}");
+        self.send(&json);
+    }
+
+    /// Not kept for `/history`, the page draws them over the readings it saw itself
+    pub fn derived(&self, stamp: &Stamp, derived: &Derived) {
+        let mut json = Vec::new();
+        let _ = output::write_json_derived(&mut json, stamp, derived);
+        self.send(&json);
+    }
+
+    pub fn event(&self, message: &str) {
+        let mut json = b"{\"event\":".to_vec();
+        let _ = write_json_str(&mut json, message);
+        json.push(b'}');
+        self.send(&json);
+    }
+}
+
+/// Readings as `--udp` has them, gaps with the same time fields
+fn write_record(out: &mut Vec<u8>, stamp: &Stamp, record: &Record, columns: &Columns) -> io::Result<()> {
+    match record {
+        Record::Sample(m) => output::write_json(out, stamp, m, columns),
+        Record::Gap(reason) => output::write_json_note(out, stamp, "gap", reason),
+    }
+}
+
+/// `?from=..&to=..&last=..&points=..`, see the top of the file
+#[derive(Default)]
+struct Query {
+    from: Option<Duration>,
+    to: Option<Duration>,
+    last: Option<usize>,
+    points: Option<usize>,
+}
+
+impl Query {
+    fn parse(query: &str) -> Result<Self, String> {
+        let mut parsed = Query::default();
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            let seconds = || match value.parse::<f64>() {
+                Ok(s) if s.is_finite() && s >= 0.0 => Ok(Duration::from_secs_f64(s)),
+                _ => Err(format!("{} has to be seconds since logging started, got {:?}", name, value)),
+            };
+            let count = || value.parse::<usize>().map_err(|_| format!("{} has to be a count, got {:?}", name, value));
+            match name {
+                "from" => parsed.from = Some(seconds()?),
+                "to" => parsed.to = Some(seconds()?),
+                "last" => parsed.last = Some(count()?),
+                "points" => parsed.points = Some(count()?),
+                // Checked before getting here
+                "token" => {}
+                _ => return Err(format!("Unknown parameter {:?}, try from, to, last or points", name)),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn range(&self) -> std::ops::RangeInclusive<Duration> {
+        self.from.unwrap_or(Duration::ZERO)..=self.to.unwrap_or(Duration::MAX)
+    }
+}
+
+fn history_json(history: &Store<(Stamp, Record)>, query: &Query, columns: &Columns) -> Vec<u8> {
+    let records = match query.last {
+        Some(n) => history.latest(n),
+        None => history.range(query.range()),
+    };
+    let mut json = b"[".to_vec();
+    for (i, (_, (stamp, record))) in records.into_iter().enumerate() {
+        if i > 0 {
+            json.push(b',');
+        }
+        let _ = write_record(&mut json, &stamp, &record, columns);
+    }
+    json.extend_from_slice(b"]\n");
+    json
+}
+
+fn json_number(n: f64) -> String {
+    if n.is_finite() { n.to_string() } else { "null".to_owned() }
+}
+
+/// Times in seconds like `elapsed`, min and max null for tiles without a number in them
+fn tiles_json(tiles: &Tiles, query: &Query) -> Vec<u8> {
+    let mut json = b"[".to_vec();
+    for (i, tile) in tiles.query(query.range(), query.points.unwrap_or(DEFAULT_POINTS)).iter().enumerate() {
+        if i > 0 {
+            json.push(b',');
+        }
+        let _ = writeln!(
+            json,
+            "{{\"start\":{:.3},\"end\":{:.3},\"min\":{},\"max\":{},\"count\":{}}}",
+            tile.start.as_secs_f64(),
+            tile.end.as_secs_f64(),
+            json_number(tile.min),
+            json_number(tile.max),
+            tile.count
+        );
+    }
+    json.extend_from_slice(b"]\n");
+    json
+}
+
+fn handle(
+    stream: TcpStream,
+    clients: &Mutex<Vec<TcpStream>>,
+    history: Option<&Mutex<Kept>>,
+    columns: &Columns,
+    token: Option<&str>,
+    control: Option<&Sender<Control>>,
+    known: &Mutex<Known>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let (mut key, mut bearer, mut cookie, mut length, mut host) = (None, None, None, 0, None);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "sec-websocket-key" => key = Some(value.to_owned()),
+                "content-length" => length = value.parse().unwrap_or(0),
+                "host" => host = Some(value.to_owned()),
+                "authorization" => bearer = value.strip_prefix("Bearer ").map(str::to_owned),
+                "cookie" => {
+                    cookie = value.split(';').find_map(|c| c.trim().strip_prefix(&format!("{}=", TOKEN_COOKIE)).map(str::to_owned));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut stream = stream;
+    let method = request.split_whitespace().next().unwrap_or("GET");
+    let target = request.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let in_query = query.split('&').find_map(|param| param.strip_prefix("token="));
+    if let Some(token) = token {
+        let given = bearer.as_deref().or(in_query).or(cookie.as_deref());
+        if !given.is_some_and(|given| same(given.as_bytes(), token.as_bytes())) {
+            return respond_with(
+                &mut stream,
+                "401 Unauthorized",
+                "WWW-Authenticate: Bearer\r\n",
+                "text/plain",
+                b"Needs the token from --web-config, open the page with ?token=...\n",
+            );
+        }
+    }
+    match (path, key) {
+        ("/ws", Some(key)) => {
+            let accept = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept
+            )?;
+            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            let macros = known.lock().unwrap().macros.clone();
+            if !macros.is_empty() {
+                let mut json = b"{\"macros\":[".to_vec();
+                for (i, name) in macros.iter().enumerate() {
+                    if i > 0 {
+                        json.push(b',');
+                    }
+                    let _ = write_json_str(&mut json, name);
+                }
+                json.extend_from_slice(b"]}");
+                stream.write_all(&frame(&json))?;
+            }
+            clients.lock().unwrap().push(stream.try_clone()?);
+            // Read until it closes so the socket is noticed going away even between readings,
+            // and the only messages that matter are commands
+            while let Some(message) = read_frame(&mut reader)? {
+                if let Some(control) = control
+                    && let Err(e) = command(control, &message)
+                {
+                    let mut json = b"{\"error\":".to_vec();
+                    let _ = write_json_str(&mut json, &e);
+                    json.push(b'}');
+                    // Under the lock, so it doesn't land in the middle of a reading being sent
+                    let clients = clients.lock().unwrap();
+                    let _ = stream.write_all(&frame(&json));
+                    drop(clients);
+                }
+            }
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            Ok(())
+        }
+        ("/control", _) => {
+            let Some(control) = control else {
+                return respond(&mut stream, "403 Forbidden", "text/plain", b"Start the logger with --web-control to take commands\n");
+            };
+            if method != "POST" {
+                return respond_with(&mut stream, "405 Method Not Allowed", "Allow: POST\r\n", "text/plain", b"POST the command\n");
+            }
+            if length > MAX_COMMAND {
+                return respond(&mut stream, "413 Content Too Large", "text/plain", b"That's too long for a command\n");
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            match command(control, &body) {
+                Ok(()) => respond(&mut stream, "202 Accepted", "text/plain", b"OK\n"),
+                Err(e) => respond(&mut stream, "400 Bad Request", "text/plain", format!("{}\n", e).as_bytes()),
+            }
+        }
+        ("/", _) => {
+            // For the page's own requests, which can't add a header to a WebSocket
+            let cookie = match (token, in_query) {
+                (Some(token), Some(_)) => format!("Set-Cookie: {}={}; HttpOnly; SameSite=Strict\r\n", TOKEN_COOKIE, token),
+                _ => String::new(),
+            };
+            respond_with(&mut stream, "200 OK", &cookie, "text/html; charset=utf-8", PAGE.as_bytes())
+        }
+        ("/history" | "/tiles", _) => {
+            let Some(history) = history else {
+                return respond(&mut stream, "404 Not Found", "text/plain", b"Start the logger with --history to keep readings\n");
+            };
+            let query = match Query::parse(query) {
+                Ok(query) => query,
+                Err(e) => return respond(&mut stream, "400 Bad Request", "text/plain", format!("{}\n", e).as_bytes()),
+            };
+            let kept = history.lock().unwrap();
+            let json = match path {
+                "/history" => history_json(&kept.records, &query, columns),
+                _ => tiles_json(&kept.tiles, &query),
+            };
+            drop(kept);
+            respond(&mut stream, "200 OK", "application/json", &json)
+        }
+        ("/lxi" | "/lxi/identification", _) => {
+            let Some(identity) = known.lock().unwrap().identity.clone() else {
+                return respond(&mut stream, "404 Not Found", "text/plain", b"Start the logger with --scpi for LXI identification\n");
+            };
+            // As the client reached this machine, which is what it can reach the SCPI port on too
+            let authority = host.map_or_else(|| stream.local_addr().map(|a| a.to_string()).unwrap_or_default(), |h| h.to_owned());
+            let host = match authority.rsplit_once(':') {
+                Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+                _ => &authority,
+            };
+            match path {
+                "/lxi" => respond(&mut stream, "200 OK", "text/html; charset=utf-8", lxi_page(&identity, host).as_bytes()),
+                _ => respond(&mut stream, "200 OK", "text/xml", lxi_xml(&identity, host, &authority).as_bytes()),
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"Not found\n"),
+    }
+}
+
+/// Name and value of each row on the page
+fn lxi_fields(identity: &Identity, host: &str) -> [(&'static str, String); 6] {
+    [
+        ("Manufacturer", Identity::MANUFACTURER.to_owned()),
+        ("Model", Identity::MODEL.to_owned()),
+        ("Serial number", identity.serial().to_owned()),
+        ("Firmware", Identity::VERSION.to_owned()),
+        ("Host", host.to_owned()),
+        ("VISA resource", identity.visa(host)),
+    ]
+}
+
+fn lxi_page(identity: &Identity, host: &str) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0} {1}</title></head><body>\n<h1>{0} {1}</h1>\n<table>\n",
+        Identity::MANUFACTURER,
+        Identity::MODEL
+    );
+    for (name, value) in lxi_fields(identity, host) {
+        page.push_str(&format!("<tr><th align=\"left\">{}</th><td>{}</td></tr>\n", name, escape(&value)));
+    }
+    page.push_str("</table>\n<p><a href=\"/\">Dashboard</a></p>\n</body></html>\n");
+    page
+}
+
+/// The LXI identification document, the parts of it that mean something here. `authority` is
+/// the host with the dashboard's port.
+fn lxi_xml(identity: &Identity, host: &str, authority: &str) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<LXIDevice xmlns=\"http://www.lxistandard.org/InstrumentIdentification/1.0\">\n",
+            "  <Manufacturer>{}</Manufacturer>\n",
+            "  <Model>{}</Model>\n",
+            "  <SerialNumber>{}</SerialNumber>\n",
+            "  <FirmwareRevision>{}</FirmwareRevision>\n",
+            "  <ManufacturerDescription>Digital multimeter on a USB logger</ManufacturerDescription>\n",
+            "  <HomepageURL>http://{}/lxi</HomepageURL>\n",
+            "  <Interface InterfaceType=\"LXI\" IPType=\"IPv4\" InterfaceName=\"eth0\">\n",
+            "    <InstrumentAddressString>{}</InstrumentAddressString>\n",
+            "    <Hostname>{}</Hostname>\n",
+            "  </Interface>\n",
+            "</LXIDevice>\n"
+        ),
+        Identity::MANUFACTURER,
+        Identity::MODEL,
+        escape(identity.serial()),
+        Identity::VERSION,
+        escape(authority),
+        escape(&identity.visa(host)),
+        escape(host)
+    )
+}
+
+/// For HTML and XML text
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Hands a command to the logging loop, which carries it out before its next poll
+fn command(control: &Sender<Control>, text: &[u8]) -> Result<(), String> {
+    let command = Control::parse(std::str::from_utf8(text).map_err(|e| e.to_string())?)?;
+    // Only once logging has finished
+    control.send(command).map_err(|_| "Not logging any more".to_owned())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    respond_with(stream, status, "", content_type, body)
+}
+
+/// `headers` are extra header lines, each ending in `\r\n`
+fn respond_with(stream: &mut TcpStream, status: &str, headers: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        headers,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+/// Compares in the same time however much of the token is right, so it can't be guessed a
+/// character at a time
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The payload of the next text message from a client, `None` once it closes. Anything but text
+/// is skipped, pings included, browsers don't send them.
+fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut head = [0; 2];
+        if let Err(e) = reader.read_exact(&mut head) {
+            return if e.kind() == io::ErrorKind::UnexpectedEof { Ok(None) } else { Err(e) };
+        }
+        let mut len = (head[1] & 0x7f) as u64;
+        if len == 126 {
+            let mut bytes = [0; 2];
+            reader.read_exact(&mut bytes)?;
+            len = u16::from_be_bytes(bytes) as u64;
+        } else if len == 127 {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            len = u64::from_be_bytes(bytes);
+        }
+        if len > MAX_COMMAND as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "message too long"));
+        }
+        // Clients always mask
+        let mut mask = [0; 4];
+        if head[1] & 0x80 != 0 {
+            reader.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; len as usize];
+        reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        match head[0] & 0x0f {
+            0x1 => return Ok(Some(payload)),
+            0x8 => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// An unmasked text frame, as servers send them
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Only needed for the handshake, so the plain textbook version
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (out, h) in digest.chunks_mut(4).zip(h) {
+        out.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
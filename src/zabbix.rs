@@ -0,0 +1,140 @@
+//! Pushing readings to a Zabbix server or proxy with the sender (trapper) protocol, as
+//! `zabbix_sender` would. Set up trapper items on the host with these keys:
+//!
+//! - `ut61e.value`, numeric (float), every reading
+//! - `ut61e.unit` and `ut61e.mode`, text, when they change
+//!
+//! Readings are batched on a background thread and sent every `interval`, each with its own
+//! timestamp, so a slow or missing server never holds up logging.
+
+use crate::output::write_json_str;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_PORT: u16 = 10051;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Item {
+    key: &'static str,
+    value: String,
+    time: SystemTime,
+}
+
+pub struct Zabbix {
+    tx: Sender<Item>,
+    thread: JoinHandle<()>,
+    last_unit: &'static str,
+    last_mode: &'static str,
+}
+
+impl Zabbix {
+    /// `server` is `host` or `host:port`, `host` is the host name as configured in Zabbix
+    pub fn start(server: &str, host: &str, interval: Duration) -> Self {
+        let server = if server.contains(':') { server.to_owned() } else { format!("{}:{}", server, DEFAULT_PORT) };
+        let host = host.to_owned();
+        let (tx, rx) = mpsc::channel::<Item>();
+        let thread = thread::spawn(move || {
+            let mut batch = Vec::new();
+            let mut last_send = Instant::now();
+            let mut warned = false;
+            loop {
+                match rx.recv_timeout(interval.saturating_sub(last_send.elapsed())) {
+                    Ok(item) => batch.push(item),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        if !batch.is_empty() {
+                            let _ = send(&server, &host, &batch);
+                        }
+                        return;
+                    }
+                }
+                if last_send.elapsed() < interval {
+                    continue;
+                }
+                last_send = Instant::now();
+                if batch.is_empty() {
+                    continue;
+                }
+                match send(&server, &host, &batch) {
+                    Ok(()) => warned = false,
+                    Err(e) if !warned => {
+                        eprintln!("Zabbix send to {} failed: {}", server, e);
+                        warned = true;
+                    }
+                    Err(_) => {}
+                }
+                // Dropped rather than retried, Zabbix wouldn't want them late and out of order
+                batch.clear();
+            }
+        });
+        Zabbix { tx, thread, last_unit: "", last_mode: "" }
+    }
+
+    pub fn send(&mut self, time: SystemTime, value: Option<f64>, unit: &'static str, mode: &'static str) {
+        // Text items are only worth sending when they change, the first one always goes
+        if unit != self.last_unit {
+            let _ = self.tx.send(Item { key: "ut61e.unit", value: unit.to_owned(), time });
+            self.last_unit = unit;
+        }
+        if mode != self.last_mode {
+            let _ = self.tx.send(Item { key: "ut61e.mode", value: mode.to_owned(), time });
+            self.last_mode = mode;
+        }
+        if let Some(value) = value {
+            let _ = self.tx.send(Item { key: "ut61e.value", value: value.to_string(), time });
+        }
+    }
+
+    /// Sends what's left in the batch and waits for it
+    pub fn finish(self) {
+        drop(self.tx);
+        let _ = self.thread.join();
+    }
+}
+
+/// One sender request: `ZBXD\x01`, the JSON length as a little endian u64, then the JSON
+fn send(server: &str, host: &str, items: &[Item]) -> io::Result<()> {
+    let mut json = Vec::new();
+    write!(json, "{{\"request\":\"sender data\",\"data\":[")?;
+    for (i, item) in items.iter().enumerate() {
+        let since_epoch = item.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        if i > 0 {
+            write!(json, ",")?;
+        }
+        write!(json, "{{\"host\":")?;
+        write_json_str(&mut json, host)?;
+        write!(json, ",\"key\":\"{}\",\"value\":", item.key)?;
+        write_json_str(&mut json, &item.value)?;
+        write!(json, ",\"clock\":{},\"ns\":{}}}", since_epoch.as_secs(), since_epoch.subsec_nanos())?;
+    }
+    write!(json, "]}}")?;
+
+    let addr = server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} didn't resolve", server)))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut request = b"ZBXD\x01".to_vec();
+    request.extend_from_slice(&(json.len() as u64).to_le_bytes());
+    request.extend_from_slice(&json);
+    stream.write_all(&request)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    // {"response":"success","info":"processed: 3; failed: 0; ..."} after a 13 byte header
+    let body = String::from_utf8_lossy(response.get(13..).unwrap_or_default());
+    if !body.contains("\"response\":\"success\"") {
+        return Err(io::Error::other(format!("server said {}", body)));
+    }
+    if let Some(info) = body.split("\"info\":\"").nth(1).and_then(|s| s.split('"').next())
+        && !info.contains("failed: 0")
+    {
+        return Err(io::Error::other(format!("{} (are the trapper items set up for {}?)", info, host)));
+    }
+    Ok(())
+}
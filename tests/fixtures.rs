@@ -0,0 +1,130 @@
+//! Runs every capture in `tests/fixtures/` through the decoder, so a new mode or range mapping
+//! for one meter can't quietly break what another meter sends.
+//!
+//! A fixture is a JSON file with the raw bytes as the meter sends them, written as hex (spaces
+//! optional, anything `ut61e_plus decode` accepts), either a whole frame or just the payload as
+//! `--show-raw` prints it, and what they should decode to:
+//!
+//! ```json
+//! {
+//!   "description": "22 V DC range selected by hand",
+//!   "frame": "ab cd 10 02 31 20 20 31 2e 32 33 34 00 12 30 34 38 03 a1",
+//!   "expected": { "mode": "V_DC", "unit": "V", "reading": "1.234", "value": 1.234 }
+//! }
+//! ```
+//!
+//! Only the fields listed under `expected` are checked, so a fixture from a meter nobody has
+//! fully mapped yet can leave out what isn't known. For frames that should be rejected, give
+//! `"error"` with the message instead of `expected`.
+
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use ut61e_plus_logger::protocol::{FrameError, MEASUREMENT_LEN, Measurement, find_frame, hex_bytes};
+
+struct Fixture {
+    name: String,
+    description: String,
+    frame: Vec<u8>,
+    expected: Result<Map<String, Value>, String>,
+}
+
+fn load(path: &Path) -> Result<Fixture, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let json: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    let field = |key: &str| json.get(key).ok_or_else(|| format!("missing {:?}", key));
+    let frame = field("frame")?.as_str().ok_or("\"frame\" isn't a string")?;
+    let expected = match (json.get("expected"), json.get("error")) {
+        (Some(Value::Object(expected)), None) => Ok(expected.clone()),
+        (None, Some(Value::String(error))) => Err(error.clone()),
+        _ => return Err("needs either an \"expected\" object or an \"error\" string".into()),
+    };
+    Ok(Fixture {
+        name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+        description: field("description")?.as_str().unwrap_or_default().to_owned(),
+        frame: hex_bytes(frame).collect(),
+        expected,
+    })
+}
+
+/// One field of a decoded measurement, named as in the fixtures
+fn field(m: &Measurement, key: &str) -> Option<Value> {
+    let range = m.range_info();
+    Some(match key {
+        "mode" => m.mode_name().into(),
+        "unit" => m.unit().into(),
+        "reading" => m.reading().to_string().into(),
+        "value" => m.reading().value().into(),
+        "range" => m.range_mode().into(),
+        "min_max" => m.min_max().into(),
+        "full_scale" => range.map(|r| r.full_scale()).into(),
+        "resolution" => range.map(|r| r.resolution()).into(),
+        "rel" => m.rel.into(),
+        "hold" => m.hold.into(),
+        "low_battery" => m.low_battery.into(),
+        "hv_warning" => m.hv_warning.into(),
+        "dc" => m.dc.into(),
+        "peak_max" => m.peak_max.into(),
+        "peak_min" => m.peak_min.into(),
+        _ => return None,
+    })
+}
+
+/// Numbers compare with a little slack since they went through decimal text
+fn same(actual: &Value, expected: &Value) -> bool {
+    match (actual.as_f64(), expected.as_f64()) {
+        (Some(a), Some(e)) => (a - e).abs() <= 1e-9 * e.abs().max(1.0),
+        _ => actual == expected,
+    }
+}
+
+/// Everything wrong with one fixture, empty if it passes
+fn check(fixture: &Fixture) -> Vec<String> {
+    let payload = match find_frame(&fixture.frame) {
+        // A bare payload as printed by `--show-raw`
+        Err(FrameError::NoHeader) if fixture.frame.len() >= MEASUREMENT_LEN => Ok(&fixture.frame[..]),
+        payload => payload,
+    };
+    let decoded = payload
+        .map_err(|e| e.to_string())
+        .and_then(|payload| Measurement::decode(payload).ok_or_else(|| "payload too short".to_owned()));
+    match (&fixture.expected, decoded) {
+        (Ok(expected), Ok(m)) => expected
+            .iter()
+            .filter_map(|(key, want)| match field(&m, key) {
+                None => Some(format!("unknown field {:?}", key)),
+                Some(got) if !same(&got, want) => Some(format!("{}: expected {}, got {}", key, want, got)),
+                Some(_) => None,
+            })
+            .collect(),
+        (Ok(_), Err(e)) => vec![format!("didn't decode: {}", e)],
+        (Err(want), Ok(m)) => vec![format!("expected {:?} but it decoded to {} {}", want, m.reading(), m.unit())],
+        (Err(want), Err(got)) if *want != got => vec![format!("expected {:?}, got {:?}", want, got)],
+        (Err(_), Err(_)) => Vec::new(),
+    }
+}
+
+#[test]
+fn fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no fixtures in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        match load(path) {
+            Ok(fixture) => {
+                for problem in check(&fixture) {
+                    failures.push(format!("{} ({}): {}", fixture.name, fixture.description, problem));
+                }
+            }
+            Err(e) => failures.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    assert!(failures.is_empty(), "{} of {} fixtures failed:\n{}", failures.len(), paths.len(), failures.join("\n"));
+}
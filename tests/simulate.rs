@@ -0,0 +1,63 @@
+//! Runs the logger against `--simulate`, so polling, frame reassembly, decoding and the CSV
+//! output are exercised together the way they are with a meter plugged in.
+#![cfg(unix)]
+
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Logs for `run_for` then stops it with Ctrl-C, returning the exit code and the CSV rows
+fn log(settings: &str, run_for: Duration) -> (Option<i32>, Vec<Vec<String>>) {
+    let child = Command::new(env!("CARGO_BIN_EXE_ut61e_plus_logger"))
+        .args(["--csv", "--interval", "20ms", "--simulate", settings])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    thread::sleep(run_for);
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let output = child.wait_with_output().unwrap();
+    let rows = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| line.split(',').map(str::to_owned).collect())
+        .collect();
+    (output.status.code(), rows)
+}
+
+#[test]
+fn steady_value() {
+    let (code, rows) = log("mode=V_DC,value=5,amplitude=0,noise=0", Duration::from_secs(1));
+    assert_eq!(code, Some(130));
+    assert_eq!(rows[0][..3], ["value", "unit", "mode"]);
+    let readings = &rows[1..];
+    assert!(readings.len() > 10, "only {} rows", readings.len());
+    for row in readings {
+        assert_eq!(row[..4], ["5.000", "V", "V_DC", "AUTO"]);
+    }
+}
+
+#[test]
+fn overload_and_bad_frames() {
+    let (code, rows) = log("mode=V_DC,value=2000,amplitude=0,corrupt=0.3,seed=7", Duration::from_secs(1));
+    assert_eq!(code, Some(130));
+    // Corrupt frames leave gaps, everything that gets through is the overload
+    let readings: Vec<_> = rows[1..].iter().filter(|row| row[0] != "GAP").collect();
+    assert!(!readings.is_empty());
+    assert!(readings.len() < rows.len() - 1, "no gaps from corrupt frames");
+    for row in readings {
+        assert_eq!(row[..3], ["OL", "V", "V_DC"], "{:?}", row);
+    }
+}
+
+#[test]
+fn scenario() {
+    let path = std::env::temp_dir().join(format!("ut61e-scenario-{}.csv", std::process::id()));
+    std::fs::write(&path, "time,value,mode,range,flags\n0,0.012,mV_DC\n0.5,1.5,V_DC\n0.8,----,Diode,,hold\n").unwrap();
+    let (code, rows) = log(&format!("scenario={},noise=0", path.display()), Duration::from_millis(1200));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(code, Some(130));
+    let mut shown: Vec<_> = rows[1..].iter().map(|row| (row[0].as_str(), row[1].as_str(), row[5].as_str())).collect();
+    shown.dedup();
+    assert_eq!(shown, [("12.00", "mV", ""), ("1.5000", "V", ""), ("----", "V", "HOLD")]);
+}
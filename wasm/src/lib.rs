@@ -0,0 +1,156 @@
+//! The decoder for the WebHID page in `index.html`, built for wasm32 without any bindings crate
+//! so the page is one HTML file and one `.wasm`. The page does the USB side (WebHID can only be
+//! reached from JavaScript) and hands the UART bytes from every input report over:
+//!
+//! 1. copy them to [`ut61e_input`] and call [`ut61e_feed`] with their count
+//! 2. while it returns more than 0, read that many bytes of JSON from [`ut61e_output`] and call
+//!    `ut61e_feed(0)` for the next frame
+//!
+//! ```text
+//! cargo build --release -p ut61e_plus_wasm --target wasm32-unknown-unknown
+//! ```
+
+#![cfg_attr(target_arch = "wasm32", no_std)]
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use ut61e_plus_logger::protocol::{self, CMD_GET_MEASUREMENT, FrameError, HEADER, Measurement};
+
+#[cfg(target_arch = "wasm32")]
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    core::arch::wasm32::unreachable()
+}
+
+const POLL: [u8; 6] = protocol::command(CMD_GET_MEASUREMENT);
+
+/// Room for the JSON of one measurement, mode names and all
+struct Output {
+    buf: [u8; 512],
+    len: usize,
+}
+
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+struct State {
+    input: [u8; 64],
+    /// UART bytes that aren't part of a complete frame yet
+    rx: [u8; 256],
+    rx_len: usize,
+    output: Output,
+}
+
+impl State {
+    fn consume(&mut self, n: usize) {
+        self.rx.copy_within(n..self.rx_len, 0);
+        self.rx_len -= n;
+    }
+}
+
+/// The page calls in one at a time, wasm has a single thread
+struct Global(UnsafeCell<State>);
+
+unsafe impl Sync for Global {}
+
+static STATE: Global = Global(UnsafeCell::new(State {
+    input: [0; 64],
+    rx: [0; 256],
+    rx_len: 0,
+    output: Output { buf: [0; 512], len: 0 },
+}));
+
+fn state() -> &'static mut State {
+    unsafe { &mut *STATE.0.get() }
+}
+
+/// The 6 byte frame that asks the meter for a measurement, to send in an output report
+#[unsafe(no_mangle)]
+pub extern "C" fn ut61e_poll_command() -> *const u8 {
+    POLL.as_ptr()
+}
+
+/// Where to copy an input report's UART bytes before [`ut61e_feed`], 64 bytes
+#[unsafe(no_mangle)]
+pub extern "C" fn ut61e_input() -> *mut u8 {
+    state().input.as_mut_ptr()
+}
+
+/// JSON for the frame [`ut61e_feed`] last returned
+#[unsafe(no_mangle)]
+pub extern "C" fn ut61e_output() -> *const u8 {
+    state().output.buf.as_ptr()
+}
+
+/// Adds `len` bytes from [`ut61e_input`] and takes the next complete frame, returning the
+/// length of its JSON in [`ut61e_output`], or 0 if no frame is complete yet
+#[unsafe(no_mangle)]
+pub extern "C" fn ut61e_feed(len: usize) -> usize {
+    let state = state();
+    let len = len.min(state.input.len());
+    if state.rx_len + len > state.rx.len() {
+        // Nothing sensible fills this much without a frame, start over
+        state.rx_len = 0;
+    }
+    state.rx[state.rx_len..state.rx_len + len].copy_from_slice(&state.input[..len]);
+    state.rx_len += len;
+
+    loop {
+        let header = state.rx[..state.rx_len].windows(2).position(|w| w == HEADER).unwrap_or(0);
+        match protocol::locate_frame(&state.rx[..state.rx_len]) {
+            Ok((start, total)) => {
+                state.output.len = 0;
+                let frame = &state.rx[start..start + total];
+                let written = match protocol::find_frame(frame).map(Measurement::decode) {
+                    Ok(Some(m)) => write_json(&mut state.output, &m),
+                    Ok(None) => write!(state.output, "{{\"error\":\"not a measurement\"}}"),
+                    Err(e) => write!(state.output, "{{\"error\":\"{}\"}}", e),
+                };
+                state.consume(start + total);
+                if written.is_ok() {
+                    return state.output.len;
+                }
+            }
+            // Keep a trailing 0xAB, it might be the start of the next header
+            Err(FrameError::NoHeader) => {
+                let keep = state.rx_len > 0 && state.rx[state.rx_len - 1] == HEADER[0];
+                state.consume(state.rx_len - keep as usize);
+                return 0;
+            }
+            Err(FrameError::BadLength(_)) => state.consume(header + HEADER.len()),
+            // Wait for the rest, dropping whatever came before the header
+            Err(_) => {
+                state.consume(header);
+                return 0;
+            }
+        }
+    }
+}
+
+/// The fields the page shows. Mode names and units have no quotes or backslashes to escape.
+fn write_json(out: &mut Output, m: &Measurement) -> fmt::Result {
+    let reading = m.reading();
+    write!(out, "{{\"value\":")?;
+    match reading.value() {
+        // The display's own digits, so 1.2300 keeps its resolution
+        Some(_) => write!(out, "{}", reading)?,
+        None => write!(out, "null")?,
+    }
+    write!(out, ",\"display\":\"{}\",\"unit\":\"{}\",\"mode\":\"{}\"", reading, m.unit(), m.mode_name())?;
+    write!(out, ",\"range\":\"{}\",\"min_max\":\"{}\"", m.range_mode(), m.min_max())?;
+    match m.range_info() {
+        Some(info) => write!(out, ",\"full_scale\":{},\"resolution\":{}", info.full_scale(), info.resolution())?,
+        None => write!(out, ",\"full_scale\":null,\"resolution\":null")?,
+    }
+    write!(
+        out,
+        ",\"hold\":{},\"rel\":{},\"low_battery\":{},\"hv_warning\":{},\"dc\":{}}}",
+        m.hold, m.rel, m.low_battery, m.hv_warning, m.dc
+    )
+}